@@ -0,0 +1,162 @@
+//! Just enough DER parsing to read a certificate's expiry and public key.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SEQUENCE: u8 = 0x30;
+const EXPLICIT_VERSION: u8 = 0xa0;
+const BIT_STRING: u8 = 0x03;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+
+/// Reads the `notAfter` time from a DER-encoded X.509 certificate.
+pub fn not_after(crt: &[u8]) -> Option<SystemTime> {
+    let validity = validity(crt)?;
+    let validity = expect(SEQUENCE, validity)?;
+    let (_, _, validity) = tlv(validity)?; // notBefore
+    let (tag, time, _) = tlv(validity)?;
+    time_from_der(tag, time)
+}
+
+/// Reads the encoded `subjectPublicKey` from a DER-encoded X.509
+/// certificate.
+pub fn subject_public_key(crt: &[u8]) -> Option<&[u8]> {
+    let (_, _, rest) = tlv(validity(crt)?)?; // validity
+    let (_, _, rest) = tlv(rest)?; // subject
+
+    let spki = expect(SEQUENCE, rest)?;
+    let (_, _, spki) = tlv(spki)?; // algorithm
+    match expect(BIT_STRING, spki)?.split_first()? {
+        // Keys are always a whole number of bytes.
+        (0, key) => Some(key),
+        _ => None,
+    }
+}
+
+/// Returns the portion of the `TBSCertificate` that begins with the validity.
+fn validity(crt: &[u8]) -> Option<&[u8]> {
+    let crt = expect(SEQUENCE, crt)?;
+    let tbs = expect(SEQUENCE, crt)?;
+
+    // The version is optional; the serial number is not.
+    let (tag, _, mut rest) = tlv(tbs)?;
+    if tag == EXPLICIT_VERSION {
+        let (_, _, r) = tlv(rest)?;
+        rest = r;
+    }
+    let (_, _, rest) = tlv(rest)?; // signature
+    let (_, _, rest) = tlv(rest)?; // issuer
+    Some(rest)
+}
+
+/// Splits a tag-length-value from the front of `input`, returning the tag,
+/// the value, and the remaining input.
+fn tlv(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&len, rest) = rest.split_first()?;
+    let (len, rest) = if len < 0x80 {
+        (len as usize, rest)
+    } else {
+        let n = (len & 0x7f) as usize;
+        if n == 0 || n > 4 || rest.len() < n {
+            return None;
+        }
+        let (len, rest) = rest.split_at(n);
+        let len = len.iter().fold(0, |len, b| (len << 8) | *b as usize);
+        (len, rest)
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (value, rest) = rest.split_at(len);
+    Some((tag, value, rest))
+}
+
+fn expect(tag: u8, input: &[u8]) -> Option<&[u8]> {
+    match tlv(input)? {
+        (t, value, _) if t == tag => Some(value),
+        _ => None,
+    }
+}
+
+fn time_from_der(tag: u8, time: &[u8]) -> Option<SystemTime> {
+    let time = ::std::str::from_utf8(time).ok()?;
+    let (year, rest) = match tag {
+        // UTCTime years are in the range 1950-2049.
+        UTC_TIME => match time.get(0..2)?.parse::<u64>().ok()? {
+            yy if yy < 50 => (2000 + yy, time.get(2..)?),
+            yy => (1900 + yy, time.get(2..)?),
+        },
+        GENERALIZED_TIME => (time.get(0..4)?.parse::<u64>().ok()?, time.get(4..)?),
+        _ => return None,
+    };
+
+    // Certificates must encode times as `MMDDHHMMSSZ`.
+    if rest.len() != 11 || !rest.ends_with('Z') {
+        return None;
+    }
+    let field = |i: usize| rest.get(i..i + 2).and_then(|f| f.parse::<u64>().ok());
+    let (month, day) = (field(0)?, field(2)?);
+    let (hour, minute, second) = (field(4)?, field(6)?, field(8)?);
+    if year < 1970
+        || month < 1
+        || month > 12
+        || day < 1
+        || day > 31
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+
+    let days = days_since_epoch(year, month, day);
+    let secs = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Counts the days between 1970-01-01 and the given date in the proleptic
+/// Gregorian calendar.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Years begin in March so that leap days fall at the end of the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utc_and_generalized_times() {
+        let expiry = UNIX_EPOCH + Duration::from_secs(1_584_122_520);
+        assert_eq!(time_from_der(UTC_TIME, b"200313180200Z"), Some(expiry));
+        assert_eq!(
+            time_from_der(GENERALIZED_TIME, b"20200313180200Z"),
+            Some(expiry)
+        );
+        assert_eq!(
+            time_from_der(GENERALIZED_TIME, b"20240229000000Z"),
+            Some(UNIX_EPOCH + Duration::from_secs(1_709_164_800))
+        );
+        assert_eq!(time_from_der(UTC_TIME, b"2003131802Z"), None);
+        assert_eq!(time_from_der(UTC_TIME, b"201313180200Z"), None);
+    }
+
+    #[test]
+    fn subject_public_keys() {
+        use crate::test_util::*;
+        use ring::signature::KeyPair;
+
+        let key = FOO_NS1.key();
+        let foo = Strings::read(FOO_NS1.crt);
+        assert_eq!(subject_public_key(&foo), Some(key.0.public_key().as_ref()));
+
+        let bar = Strings::read(BAR_NS1.crt);
+        assert_ne!(subject_public_key(&bar), Some(key.0.public_key().as_ref()));
+        assert_eq!(subject_public_key(&bar[..bar.len() / 2]), None);
+    }
+}
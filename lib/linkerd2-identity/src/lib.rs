@@ -1,7 +1,7 @@
 use linkerd2_dns_name;
 pub use ring::error::KeyRejected;
 use ring::rand;
-use ring::signature::EcdsaKeyPair;
+use ring::signature::{EcdsaKeyPair, KeyPair};
 use std::convert::TryFrom;
 use std::error::Error;
 use std::sync::Arc;
@@ -9,6 +9,7 @@ use std::time::SystemTime;
 use std::{fmt, fs, io};
 use tracing::{debug, warn};
 
+mod der;
 #[cfg(test)]
 pub mod test_util;
 
@@ -34,7 +35,17 @@ struct SigningKey(Arc<EcdsaKeyPair>);
 struct Signer(Arc<EcdsaKeyPair>);
 
 #[derive(Clone)]
-pub struct TrustAnchors(Arc<rustls::ClientConfig>);
+pub struct TrustAnchors {
+    config: Arc<rustls::ClientConfig>,
+    anchors: Arc<Vec<TrustAnchor>>,
+}
+
+/// Describes a root certificate in a set of `TrustAnchors`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrustAnchor {
+    fingerprint: String,
+    expiry: Option<SystemTime>,
+}
 
 #[derive(Clone, Debug)]
 pub struct TokenSource(Arc<String>);
@@ -87,6 +98,15 @@ impl Csr {
 // === impl Key ===
 
 impl Key {
+    /// Reads the first PEM-encoded PKCS#8 key.
+    pub fn from_pem(pem: &[u8]) -> Option<Self> {
+        let keys = rustls::internal::pemfile::pkcs8_private_keys(&mut io::Cursor::new(pem)).ok()?;
+        let key = keys.first()?;
+        Self::from_pkcs8(&key.0)
+            .map_err(|e| warn!("invalid private key: {}", e))
+            .ok()
+    }
+
     pub fn from_pkcs8(b: &[u8]) -> Result<Self, KeyRejected> {
         let k = EcdsaKeyPair::from_pkcs8(SIGNATURE_ALG_RING_SIGNING, b)?;
         Ok(Key(Arc::new(k)))
@@ -187,18 +207,33 @@ impl TokenSource {
 impl TrustAnchors {
     #[cfg(test)]
     fn empty() -> Self {
-        TrustAnchors(Arc::new(rustls::ClientConfig::new()))
+        TrustAnchors {
+            config: Arc::new(rustls::ClientConfig::new()),
+            anchors: Arc::new(Vec::new()),
+        }
     }
 
+    /// Reads one or more PEM-encoded root certificates.
+    ///
+    /// Multiple roots may be trusted at once so that a new root can be
+    /// introduced before the old one is retired.
     pub fn from_pem(s: &str) -> Option<Self> {
         use std::io::Cursor;
 
+        let certs = rustls::internal::pemfile::certs(&mut Cursor::new(s)).ok()?;
         let mut roots = rustls::RootCertStore::empty();
-        let (added, skipped) = roots.add_pem_file(&mut Cursor::new(s)).ok()?;
+        let mut anchors = Vec::with_capacity(certs.len());
+        for crt in &certs {
+            match roots.add(crt) {
+                Ok(()) => anchors.push(TrustAnchor::new(crt.as_ref())),
+                Err(e) => debug!("invalid trust anchor: {:?}", e),
+            }
+        }
+        let skipped = certs.len() - anchors.len();
         if skipped != 0 {
             warn!("skipped {} trust anchors in trust anchors file", skipped);
         }
-        if added == 0 {
+        if anchors.is_empty() {
             return None;
         }
 
@@ -215,11 +250,18 @@ impl TrustAnchors {
         // more tested.
         c.enable_tickets = false;
 
-        Some(TrustAnchors(Arc::new(c)))
+        Some(TrustAnchors {
+            config: Arc::new(c),
+            anchors: Arc::new(anchors),
+        })
+    }
+
+    pub fn anchors(&self) -> &[TrustAnchor] {
+        &self.anchors
     }
 
     pub fn certify(&self, key: Key, crt: Crt) -> Result<CrtKey, InvalidCrt> {
-        let mut client = self.0.as_ref().clone();
+        let mut client = self.config.as_ref().clone();
 
         // Ensure the certificate is valid for the services we terminate for
         // TLS. This assumes that server cert validation does the same or
@@ -243,6 +285,18 @@ impl TrustAnchors {
                 NO_OCSP,
             )
             .map_err(InvalidCrt)?;
+
+        // The verifier only checks the certificate, so ensure that the key is
+        // the one it was issued for.
+        let leaf = crt
+            .chain
+            .first()
+            .and_then(|c| der::subject_public_key(c.as_ref()));
+        if leaf != Some(key.0.public_key().as_ref()) {
+            return Err(InvalidCrt(rustls::TLSError::General(
+                "private key does not match certificate".into(),
+            )));
+        }
         debug!("certified {}", crt.name.as_ref());
 
         let k = SigningKey(key.0.clone());
@@ -262,7 +316,7 @@ impl TrustAnchors {
         //
         // TODO: Change Rustls's API to Avoid needing to clone `root_cert_store`.
         let mut server = rustls::ServerConfig::new(
-            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(self.0.root_store.clone()),
+            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(self.config.root_store.clone()),
         );
         server.versions = TLS_VERSIONS.to_vec();
         server.cert_resolver = resolver;
@@ -276,13 +330,43 @@ impl TrustAnchors {
     }
 
     pub fn tls_client_config(&self) -> Arc<rustls::ClientConfig> {
-        self.0.clone()
+        self.config.clone()
     }
 }
 
 impl fmt::Debug for TrustAnchors {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("TrustAnchors").finish()
+        f.debug_struct("TrustAnchors")
+            .field("anchors", &self.anchors)
+            .finish()
+    }
+}
+
+// === impl TrustAnchor ===
+
+impl TrustAnchor {
+    fn new(der: &[u8]) -> Self {
+        use std::fmt::Write;
+
+        let digest = ring::digest::digest(&ring::digest::SHA256, der);
+        let mut fingerprint = String::with_capacity(digest.as_ref().len() * 2);
+        for b in digest.as_ref() {
+            let _ = write!(fingerprint, "{:02x}", b);
+        }
+
+        Self {
+            fingerprint,
+            expiry: der::not_after(der),
+        }
+    }
+
+    /// The hex-encoded SHA-256 digest of the DER-encoded certificate.
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    pub fn expiry(&self) -> Option<SystemTime> {
+        self.expiry
     }
 }
 
@@ -300,11 +384,32 @@ impl Crt {
             expiry,
         }
     }
+
+    /// Reads a PEM-encoded certificate chain, leaf first.
+    ///
+    /// The certificate expires when its leaf does.
+    pub fn from_pem(name: Name, pem: &[u8]) -> Option<Self> {
+        let chain = rustls::internal::pemfile::certs(&mut io::Cursor::new(pem)).ok()?;
+        let expiry = der::not_after(chain.first()?.as_ref())?;
+        Some(Self {
+            name,
+            chain,
+            expiry,
+        })
+    }
+
+    pub fn expiry(&self) -> SystemTime {
+        self.expiry
+    }
 }
 
 // === CrtKey ===
 
 impl CrtKey {
+    pub fn expiry(&self) -> SystemTime {
+        self.expiry
+    }
+
     pub fn tls_client_config(&self) -> Arc<rustls::ClientConfig> {
         self.client_config.clone()
     }
@@ -411,12 +516,36 @@ impl Error for InvalidCrt {
 #[cfg(test)]
 mod tests {
     use super::test_util::*;
+    use super::TrustAnchors;
 
     #[test]
     fn can_construct_client_and_server_config_from_valid_settings() {
         FOO_NS1.validate().expect("foo.ns1 must be valid");
     }
 
+    #[test]
+    fn reads_pem_encoded_crt_and_key() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let _ = FOO_NS1.key_pem();
+        let expiry = UNIX_EPOCH + Duration::from_secs(1_584_122_520);
+        assert_eq!(FOO_NS1.crt_pem().expiry(), expiry);
+    }
+
+    #[test]
+    fn trust_anchors_may_overlap() {
+        let mut pem = String::from_utf8(Strings::read("ca1.pem")).unwrap();
+        pem.push_str(std::str::from_utf8(&Strings::read("ca2.pem")).unwrap());
+        let anchors = TrustAnchors::from_pem(&pem).expect("anchors must be valid");
+
+        let ca1 = FOO_NS1.trust_anchors();
+        assert_eq!(anchors.anchors().len(), 2);
+        assert_eq!(anchors.anchors()[0], ca1.anchors()[0]);
+        assert_ne!(anchors.anchors()[0], anchors.anchors()[1]);
+        assert_eq!(ca1.anchors()[0].fingerprint().len(), 64);
+        assert!(ca1.anchors()[0].expiry().is_some());
+    }
+
     #[test]
     fn recognize_ca_did_not_issue_cert() {
         let s = Strings {
@@ -437,7 +566,6 @@ mod tests {
     }
 
     #[test]
-    #[ignore] // XXX this doesn't fail because we don't actually check the key against the cert...
     fn recognize_private_key_is_not_valid_for_cert() {
         let s = Strings {
             key: BAR_NS1.key,
@@ -8,6 +8,8 @@ pub struct Strings {
     pub trust_anchors: &'static str,
     pub crt: &'static str,
     pub key: &'static str,
+    pub crt_pem: &'static str,
+    pub key_pem: &'static str,
     //pub csr: &'static str,
 }
 
@@ -16,6 +18,8 @@ pub static FOO_NS1: Strings = Strings {
     trust_anchors: "ca1.pem",
     crt: "foo-ns1-ca1/crt.der",
     key: "foo-ns1-ca1/key.p8",
+    crt_pem: "foo-ns1-ca1/crt.pem",
+    key_pem: "foo-ns1-ca1/key.pem",
     //csr: "foo-ns1-ca1/csr.der",
 };
 
@@ -24,11 +28,13 @@ pub static BAR_NS1: Strings = Strings {
     trust_anchors: "ca1.pem",
     crt: "bar-ns1-ca1/crt.der",
     key: "bar-ns1-ca1/key.p8",
+    crt_pem: "bar-ns1-ca1/crt.pem",
+    key_pem: "bar-ns1-ca1/key.pem",
     //csr: "bar-ns1-ca1/csr.der",
 };
 
 impl Strings {
-    fn read(n: &str) -> Vec<u8> {
+    pub fn read(n: &str) -> Vec<u8> {
         let dir = PathBuf::from("src/testdata");
         let p = dir.join(n);
         match fs::read(&p) {
@@ -58,6 +64,15 @@ impl Strings {
         Crt::new(n, der, vec![], SystemTime::now() + HOUR)
     }
 
+    pub fn crt_pem(&self) -> Crt {
+        let n = Name::from_hostname(self.name.as_bytes()).expect("name must be valid");
+        Crt::from_pem(n, &Self::read(&self.crt_pem)).expect("crt must be valid")
+    }
+
+    pub fn key_pem(&self) -> Key {
+        Key::from_pem(&Self::read(&self.key_pem)).expect("key must be valid")
+    }
+
     pub fn validate(&self) -> Result<CrtKey, InvalidCrt> {
         let k = self.key();
         let c = self.crt();
@@ -1,13 +1,14 @@
 use std::fmt;
 use std::marker::PhantomData;
-use std::{cmp, iter, slice};
+use std::sync::Arc;
+use std::{cmp, error, iter, slice};
 
 use super::{Counter, FmtLabels, FmtMetric};
 
 /// A series of latency values and counts.
 #[derive(Debug, Clone)]
 pub struct Histogram<V: Into<u64>> {
-    bounds: &'static Bounds,
+    bounds: Bounds,
     buckets: Box<[Counter]>,
 
     /// The total sum of all observed latency values.
@@ -42,8 +43,30 @@ pub enum Bucket {
 }
 
 /// A series of increasing Buckets values.
-#[derive(Debug)]
-pub struct Bounds(pub &'static [Bucket]);
+///
+/// Bounds are cheaply cloneable so that they may be configured at runtime and
+/// shared by all of the histograms in a metric family.
+#[derive(Clone, Debug)]
+pub struct Bounds {
+    buckets: Arc<[Bucket]>,
+
+    /// Observed values are divided by `divisor` before they are recorded.
+    divisor: u64,
+
+    /// Recorded values (and bucket bounds) are formatted as a fraction of
+    /// `unit`.
+    unit: u64,
+}
+
+/// Indicates that buckets are not strictly increasing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidBounds(());
+
+/// Helper that formats a value as a fraction of a power-of-ten unit.
+struct Scaled<T> {
+    value: T,
+    unit: u64,
+}
 
 /// Helper that lazily formats metric keys as {0}_{1}.
 struct Key<A: fmt::Display, B: fmt::Display>(A, B);
@@ -54,18 +77,11 @@ struct Label<K: fmt::Display, V: fmt::Display>(K, V);
 // ===== impl Histogram =====
 
 impl<V: Into<u64>> Histogram<V> {
-    pub fn new(bounds: &'static Bounds) -> Self {
-        let mut buckets = Vec::with_capacity(bounds.0.len());
-        let mut prior = &Bucket::Le(0);
-        for bound in bounds.0.iter() {
-            assert!(prior < bound);
-            buckets.push(Counter::default());
-            prior = bound;
-        }
-
+    pub fn new(bounds: &Bounds) -> Self {
+        let buckets = bounds.buckets.iter().map(|_| Counter::default()).collect();
         Self {
-            bounds,
-            buckets: buckets.into_boxed_slice(),
+            bounds: bounds.clone(),
+            buckets,
             sum: Counter::default(),
             _p: PhantomData,
         }
@@ -73,11 +89,11 @@ impl<V: Into<u64>> Histogram<V> {
 
     pub fn add<U: Into<V>>(&mut self, u: U) {
         let v: V = u.into();
-        let value: u64 = v.into();
+        let value: u64 = v.into() / self.bounds.divisor;
 
         let idx = self
             .bounds
-            .0
+            .buckets
             .iter()
             .position(|b| match *b {
                 Bucket::Le(ceiling) => value <= ceiling,
@@ -122,14 +138,14 @@ impl<V: Into<u64>> Histogram<V> {
     /// Assert all buckets less than the one containing `value` have
     /// counts of exactly `exactly`.
     pub fn assert_lt_exactly(&self, value: u64, exactly: u64) -> &Self {
-        for (i, &bucket) in self.bounds.0.iter().enumerate() {
+        for (i, &bucket) in self.bounds.buckets.iter().enumerate() {
             let ceiling = match bucket {
                 Bucket::Le(c) => c,
                 Bucket::Inf => break,
             };
             let next = self
                 .bounds
-                .0
+                .buckets
                 .get(i + 1)
                 .expect("Bucket::Le may not be the last in `bounds`!");
 
@@ -173,7 +189,7 @@ impl<'a, V: Into<u64>> IntoIterator for &'a Histogram<V> {
     type IntoIter = iter::Zip<slice::Iter<'a, Bucket>, slice::Iter<'a, Counter>>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.bounds.0.iter().zip(self.buckets.iter())
+        self.bounds.buckets.iter().zip(self.buckets.iter())
     }
 }
 
@@ -184,10 +200,12 @@ impl<V: Into<u64>> FmtMetric for Histogram<V> {
         let mut total = Counter::default();
         for (le, count) in self {
             total += *count;
+            let le = self.bounds.fmt_value(le);
             total.fmt_metric_labeled(f, Key(&name, "bucket"), Label("le", le))?;
         }
         total.fmt_metric(f, Key(&name, "count"))?;
-        self.sum.fmt_metric(f, Key(&name, "sum"))?;
+        let sum = self.bounds.fmt_value(self.sum.value());
+        writeln!(f, "{} {}", Key(&name, "sum"), sum)?;
 
         Ok(())
     }
@@ -205,15 +223,127 @@ impl<V: Into<u64>> FmtMetric for Histogram<V> {
         let mut total = Counter::default();
         for (le, count) in self {
             total += *count;
+            let le = self.bounds.fmt_value(le);
             total.fmt_metric_labeled(f, Key(&name, "bucket"), (&labels, Label("le", le)))?;
         }
         total.fmt_metric_labeled(f, Key(&name, "count"), &labels)?;
-        self.sum.fmt_metric_labeled(f, Key(&name, "sum"), &labels)?;
+        write!(f, "{}{{", Key(&name, "sum"))?;
+        labels.fmt_labels(f)?;
+        writeln!(f, "}} {}", self.bounds.fmt_value(self.sum.value()))?;
 
         Ok(())
     }
 }
 
+// ===== impl Bounds =====
+
+impl Bounds {
+    /// Builds bounds from a series of strictly increasing buckets.
+    ///
+    /// A final `Bucket::Inf` is added if it is not already present.
+    pub fn new(mut buckets: Vec<Bucket>) -> Result<Self, InvalidBounds> {
+        if buckets.last() != Some(&Bucket::Inf) {
+            buckets.push(Bucket::Inf);
+        }
+
+        let mut prior = &Bucket::Le(0);
+        for bound in buckets.iter() {
+            if prior >= bound {
+                return Err(InvalidBounds(()));
+            }
+            prior = bound;
+        }
+
+        Ok(Self {
+            buckets: buckets.into(),
+            divisor: 1,
+            unit: 1,
+        })
+    }
+
+    /// Configures these bounds to record observations in units of `divisor`
+    /// and to format recorded values as fractions of `unit`.
+    ///
+    /// For example, a histogram of microsecond observations that is reported
+    /// in milliseconds with microsecond precision uses a `unit` of 1,000,
+    /// while one reported with millisecond precision uses a `divisor` of
+    /// 1,000.
+    ///
+    /// # Panics
+    ///
+    /// If `divisor` is zero or `unit` is not a power of ten.
+    pub fn scaled(self, divisor: u64, unit: u64) -> Self {
+        assert!(divisor > 0, "divisor must be positive");
+        let mut u = unit;
+        while u > 1 && u % 10 == 0 {
+            u /= 10;
+        }
+        assert!(u == 1, "unit must be a power of ten");
+
+        Self {
+            divisor,
+            unit,
+            ..self
+        }
+    }
+
+    pub fn buckets(&self) -> &[Bucket] {
+        &self.buckets
+    }
+
+    fn fmt_value<T>(&self, value: T) -> Scaled<T> {
+        Scaled {
+            value,
+            unit: self.unit,
+        }
+    }
+}
+
+impl fmt::Display for InvalidBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "histogram buckets must be positive and strictly increasing"
+        )
+    }
+}
+
+impl error::Error for InvalidBounds {}
+
+// ===== impl Scaled =====
+
+impl fmt::Display for Scaled<u64> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let whole = self.value / self.unit;
+        let frac = self.value % self.unit;
+        if frac == 0 {
+            return write!(f, "{}", whole);
+        }
+
+        let mut width = 0;
+        let mut u = self.unit;
+        while u > 1 {
+            u /= 10;
+            width += 1;
+        }
+        let frac = format!("{:0width$}", frac, width = width);
+        write!(f, "{}.{}", whole, frac.trim_end_matches('0'))
+    }
+}
+
+impl<'a> fmt::Display for Scaled<&'a Bucket> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self.value {
+            Bucket::Le(v) => Scaled {
+                value: v,
+                unit: self.unit,
+            }
+            .fmt(f),
+            Bucket::Inf => write!(f, "+Inf"),
+        }
+    }
+}
+
 // ===== impl Key =====
 
 impl<A: fmt::Display, B: fmt::Display> fmt::Display for Key<A, B> {
@@ -288,7 +418,7 @@ mod tests {
     use std::collections::HashMap;
     use std::u64;
 
-    static BOUNDS: &'static Bounds = &Bounds(&[
+    static BUCKETS: &[Bucket] = &[
         Bucket::Le(10),
         Bucket::Le(20),
         Bucket::Le(30),
@@ -336,11 +466,76 @@ mod tests {
         Bucket::Le(900_000),
         Bucket::Le(1_000_000),
         Bucket::Inf,
-    ]);
+    ];
+
+    fn bounds() -> Bounds {
+        Bounds::new(BUCKETS.to_vec()).expect("buckets must be valid")
+    }
+
+    struct Fmt<'a>(&'a Histogram<u64>);
+
+    impl<'a> fmt::Display for Fmt<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt_metric(f, "latency")
+        }
+    }
+
+    #[test]
+    fn bounds_must_increase() {
+        let bounds = Bounds::new(vec![Bucket::Le(1), Bucket::Le(2)]).unwrap();
+        assert_eq!(
+            bounds.buckets(),
+            &[Bucket::Le(1), Bucket::Le(2), Bucket::Inf][..]
+        );
+
+        assert!(Bounds::new(vec![Bucket::Le(2), Bucket::Le(2)]).is_err());
+        assert!(Bounds::new(vec![Bucket::Le(2), Bucket::Le(1)]).is_err());
+        assert!(Bounds::new(vec![Bucket::Le(0)]).is_err());
+    }
+
+    #[test]
+    fn ms_resolution_rejects_sub_ms_bounds() {
+        use crate::latency::{InvalidResolution, Resolution};
+
+        assert_eq!(
+            Resolution::Ms.ms_bounds(&[500, 1_000]).unwrap_err(),
+            InvalidResolution::SubMillisecond
+        );
+        assert!(Resolution::Us.ms_bounds(&[500, 1_000]).is_ok());
+        assert!(Resolution::Ms.ms_bounds(&[1_000, 2_000]).is_ok());
+    }
+
+    #[test]
+    fn scaled_bounds() {
+        // Recorded in thousandths and formatted in whole units.
+        let bounds = Bounds::new(vec![Bucket::Le(500), Bucket::Le(1_000)])
+            .unwrap()
+            .scaled(1, 1_000);
+        let mut hist = Histogram::<u64>::new(&bounds);
+        hist.add(250u64);
+        hist.add(1_250u64);
+        assert_eq!(
+            Fmt(&hist).to_string(),
+            "latency_bucket{le=\"0.5\"} 1\n\
+             latency_bucket{le=\"1\"} 1\n\
+             latency_bucket{le=\"+Inf\"} 2\n\
+             latency_count 2\n\
+             latency_sum 1.5\n"
+        );
+
+        // Recorded in whole units from thousandths.
+        let bounds = Bounds::new(vec![Bucket::Le(1), Bucket::Le(2)])
+            .unwrap()
+            .scaled(1_000, 1);
+        let mut hist = Histogram::<u64>::new(&bounds);
+        hist.add(1_999u64);
+        hist.assert_bucket_exactly(1, 1);
+        assert_eq!(hist.sum.value(), 1);
+    }
 
     quickcheck! {
         fn bucket_incremented(obs: u64) -> bool {
-            let mut hist = Histogram::<u64>::new(&BOUNDS);
+            let mut hist = Histogram::<u64>::new(&bounds());
             hist.add(obs);
             // The bucket containing `obs` must have count 1.
             hist.assert_bucket_exactly(obs, 1)
@@ -354,7 +549,7 @@ mod tests {
         }
 
         fn sum_equals_total_of_observations(observations: Vec<u64>) -> bool {
-            let mut hist = Histogram::<u64>::new(&BOUNDS);
+            let mut hist = Histogram::<u64>::new(&bounds());
 
             let mut expected_sum = Counter::default();
             for obs in observations {
@@ -366,7 +561,7 @@ mod tests {
         }
 
         fn count_equals_number_of_observations(observations: Vec<u64>) -> bool {
-            let mut hist = Histogram::<u64>::new(&BOUNDS);
+            let mut hist = Histogram::<u64>::new(&bounds());
 
             for obs in &observations {
                 hist.add(*obs);
@@ -381,10 +576,10 @@ mod tests {
 
         fn multiple_observations_increment_buckets(observations: Vec<u64>) -> bool {
             let mut buckets_and_counts: HashMap<usize, u64> = HashMap::new();
-            let mut hist = Histogram::<u64>::new(&BOUNDS);
+            let mut hist = Histogram::<u64>::new(&bounds());
 
             for obs in observations {
-                let incremented_bucket = &BOUNDS.0.iter()
+                let incremented_bucket = &BUCKETS.iter()
                     .position(|bucket| match *bucket {
                         Bucket::Le(ceiling) => obs <= ceiling,
                         Bucket::Inf => true,
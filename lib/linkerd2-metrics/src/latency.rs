@@ -1,10 +1,10 @@
-use std::time::Duration;
+use std::{error, fmt, time::Duration};
 
-use super::histogram::{Bounds, Bucket, Histogram};
+use super::histogram::{Bounds, Bucket, Histogram, InvalidBounds};
 
 /// The maximum value (inclusive) for each latency bucket in
 /// milliseconds.
-pub const BOUNDS: &Bounds = &Bounds(&[
+pub const BUCKETS: &[Bucket] = &[
     Bucket::Le(1),
     Bucket::Le(2),
     Bucket::Le(3),
@@ -32,7 +32,22 @@ pub const BOUNDS: &Bounds = &Bounds(&[
     Bucket::Le(50_000),
     // A final upper bound.
     Bucket::Inf,
-]);
+];
+
+/// The precision with which latencies are reported in milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    Ms,
+    Us,
+}
+
+/// Indicates that latency buckets can't be reported at a `Resolution`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvalidResolution {
+    /// Sub-millisecond buckets were configured at millisecond resolution.
+    SubMillisecond,
+    Bounds(InvalidBounds),
+}
 
 /// A duration in milliseconds.
 #[derive(Debug, Default, Clone)]
@@ -42,6 +57,69 @@ pub struct Ms(Duration);
 #[derive(Debug, Default, Clone)]
 pub struct Us(Duration);
 
+// ===== impl Resolution =====
+
+impl Resolution {
+    /// Builds bounds for a histogram of `Us` values that is reported in
+    /// milliseconds.
+    ///
+    /// Bucket bounds are given in microseconds. At millisecond resolution,
+    /// observations are truncated to whole milliseconds, so every bound must
+    /// be a whole number of milliseconds.
+    pub fn ms_bounds(self, buckets_us: &[u64]) -> Result<Bounds, InvalidResolution> {
+        match self {
+            Resolution::Ms => {
+                if buckets_us.iter().any(|us| us % 1_000 != 0) {
+                    return Err(InvalidResolution::SubMillisecond);
+                }
+                let buckets = buckets_us.iter().map(|us| Bucket::Le(us / 1_000));
+                Ok(Bounds::new(buckets.collect())?.scaled(1_000, 1))
+            }
+            Resolution::Us => {
+                let buckets = buckets_us.iter().map(|us| Bucket::Le(*us));
+                Ok(Bounds::new(buckets.collect())?.scaled(1, 1_000))
+            }
+        }
+    }
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Resolution::Ms
+    }
+}
+
+// ===== impl InvalidResolution =====
+
+impl From<InvalidBounds> for InvalidResolution {
+    fn from(e: InvalidBounds) -> Self {
+        InvalidResolution::Bounds(e)
+    }
+}
+
+impl fmt::Display for InvalidResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidResolution::SubMillisecond => {
+                write!(f, "sub-millisecond buckets require microsecond resolution")
+            }
+            InvalidResolution::Bounds(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl error::Error for InvalidResolution {}
+
+/// Returns the default bounds for a histogram of `Us` values that is reported
+/// in milliseconds.
+pub fn default_ms_bounds() -> Bounds {
+    Bounds::new(BUCKETS.to_vec())
+        .expect("default buckets must be valid")
+        .scaled(1_000, 1)
+}
+
+// ===== impl Us =====
+
 impl Into<u64> for Us {
     fn into(self) -> u64 {
         use std::convert::TryInto;
@@ -61,10 +139,12 @@ impl From<Duration> for Us {
 
 impl Default for Histogram<Us> {
     fn default() -> Self {
-        Histogram::new(BOUNDS)
+        Histogram::new(&Bounds::new(BUCKETS.to_vec()).expect("default buckets must be valid"))
     }
 }
 
+// ===== impl Ms =====
+
 impl Into<u64> for Ms {
     fn into(self) -> u64 {
         self.0
@@ -82,6 +162,6 @@ impl From<Duration> for Ms {
 
 impl Default for Histogram<Ms> {
     fn default() -> Self {
-        Histogram::new(BOUNDS)
+        Histogram::new(&Bounds::new(BUCKETS.to_vec()).expect("default buckets must be valid"))
     }
 }
@@ -153,6 +153,11 @@ impl Metadata {
         &self.labels
     }
 
+    /// Returns the endpoint's relative weight, where 10,000 is the default.
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
     pub fn protocol_hint(&self) -> ProtocolHint {
         self.protocol_hint
     }
@@ -80,6 +80,10 @@ where
         self.capacity
     }
 
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.values.keys()
+    }
+
     pub fn can_insert(&self) -> bool {
         self.values.len() < self.capacity
     }
@@ -56,6 +56,19 @@ where
     }
 }
 
+/// A handle that lists the targets held in a `Router`'s cache.
+///
+/// Keys are read without affecting the cache's expirations.
+pub struct CacheKeys<K, V>(Lock<Cache<K, V>>)
+where
+    K: Clone + Eq + Hash;
+
+/// The `CacheKeys` of a `Router`.
+pub type RouterCacheKeys<Req, Rec, Mk> = CacheKeys<
+    <Rec as Recognize<Req>>::Target,
+    LoadShed<<Mk as Make<<Rec as Recognize<Req>>::Target>>::Value>,
+>;
+
 /// A map of known routes and services used when creating a fixed router.
 #[derive(Clone, Debug)]
 pub struct FixedMake<T: Clone + Eq + Hash, Svc>(IndexMap<T, Svc>);
@@ -158,6 +171,19 @@ where
     }
 }
 
+impl<Req, Rec, Mk> Router<Req, Rec, Mk>
+where
+    Rec: Recognize<Req>,
+    Mk: Make<Rec::Target>,
+    Mk::Value: svc::Service<Req>,
+{
+    /// Returns a handle that lists the targets currently cached by this
+    /// router (and all of its clones).
+    pub fn cache_keys(&self) -> RouterCacheKeys<Req, Rec, Mk> {
+        CacheKeys(self.inner.cache.clone())
+    }
+}
+
 impl<Req, Rec, Svc> Router<Req, Rec, FixedMake<Rec::Target, Svc>>
 where
     Rec: Recognize<Req>,
@@ -237,6 +263,31 @@ where
     }
 }
 
+// ===== impl CacheKeys =====
+
+impl<K, V> CacheKeys<K, V>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+{
+    /// Lists the cached keys once the cache's lock is acquired.
+    pub fn poll_keys(&mut self) -> Async<Vec<K>> {
+        match self.0.poll_lock() {
+            Async::Ready(cache) => Async::Ready(cache.keys().cloned().collect()),
+            Async::NotReady => Async::NotReady,
+        }
+    }
+}
+
+impl<K, V> Clone for CacheKeys<K, V>
+where
+    K: Clone + Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        CacheKeys(self.0.clone())
+    }
+}
+
 // ===== impl ResponseFuture =====
 
 impl<Req, Rec, Mk> ResponseFuture<Req, Rec, Mk>
@@ -489,7 +540,7 @@ mod tests {
     use super::Make;
     use super::{error, Router};
     use crate::test_util::*;
-    use futures::Future;
+    use futures::{Async, Future};
     use std::time::Duration;
     use std::usize;
     use tower_service::{self as svc, Service};
@@ -556,6 +607,27 @@ mod tests {
         assert_eq!(rsp, 4);
     }
 
+    #[test]
+    fn cache_keys_lists_targets() {
+        use futures::future;
+
+        future::lazy(|| {
+            let (mut router, _cache_bg) =
+                Router::new(Recognize, Recognize, 2, Duration::from_secs(60));
+            let mut keys = router.cache_keys();
+            assert_eq!(keys.poll_keys(), Async::Ready(vec![]));
+
+            router.call_ok(2);
+            router.call_ok(3);
+            router.call_ok(2);
+            assert_eq!(keys.poll_keys(), Async::Ready(vec![2, 3]));
+
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
     #[test]
     fn poll_ready_is_called_first() {
         let (mut router, _cache_bg) = Router::new(
@@ -2,6 +2,8 @@
 //!
 //! * `/metrics` -- reports prometheus-formatted metrics.
 //! * `/ready` -- returns 200 when the proxy is ready to participate in meshed traffic.
+//! * `/debug/state` -- reports router caches, resolutions, profile routes, the
+//!   local identity, and tap sessions as JSON.
 
 use crate::metrics;
 use futures::future::{self, Future};
@@ -11,11 +13,13 @@ use std::io;
 
 mod readiness;
 mod serve_http;
+mod state;
 mod trace_level;
 
 pub use self::readiness::{Latch, Readiness};
 pub use self::serve_http::serve_http;
 use self::serve_http::ClientAddr;
+pub use self::state::State;
 use self::trace_level::TraceLevel;
 
 #[derive(Debug, Clone)]
@@ -26,6 +30,7 @@ where
     metrics: metrics::Serve<M>,
     trace_level: TraceLevel,
     ready: Readiness,
+    state: State,
 }
 
 pub type ResponseFuture =
@@ -35,11 +40,12 @@ impl<M> Admin<M>
 where
     M: metrics::FmtMetrics,
 {
-    pub fn new(m: M, ready: Readiness, trace_level: TraceLevel) -> Self {
+    pub fn new(m: M, ready: Readiness, trace_level: TraceLevel, state: State) -> Self {
         Self {
             metrics: metrics::Serve::new(m),
             trace_level,
             ready,
+            state,
         }
     }
 
@@ -72,6 +78,7 @@ where
             "/metrics" => Box::new(self.metrics.call(req)),
             "/proxy-log-level" => self.trace_level.call(req),
             "/ready" => Box::new(future::ok(self.ready_rsp())),
+            "/debug/state" => Box::new(self.state.report()),
             _ => Box::new(future::ok(rsp(StatusCode::NOT_FOUND, Body::empty()))),
         }
     }
@@ -100,7 +107,7 @@ mod tests {
         let l1 = l0.clone();
 
         let mut rt = Runtime::new().unwrap();
-        let mut srv = Admin::new((), r, TraceLevel::dangling());
+        let mut srv = Admin::new((), r, TraceLevel::dangling(), State::default());
         macro_rules! call {
             () => {{
                 let r = Request::builder()
@@ -121,4 +128,55 @@ mod tests {
         drop(l1);
         assert_eq!(call!().status(), StatusCode::OK);
     }
+
+    #[test]
+    fn debug_state_reports_resolutions() {
+        use crate::proxy::resolve::Resolved;
+        use futures::Stream;
+        use indexmap::IndexMap;
+        use std::sync::{Arc, Mutex};
+
+        let state = State::default();
+        let mut labels = IndexMap::new();
+        labels.insert("pod".to_owned(), "web-0".to_owned());
+        let mut endpoints = IndexMap::new();
+        endpoints.insert(([10, 1, 1, 1], 8080).into(), labels);
+        let resolved = Arc::new(Mutex::new(Resolved {
+            target: "web.ns.svc.cluster.local:8080".to_owned(),
+            endpoints,
+        }));
+        state.resolutions().register(&resolved);
+
+        let mut rt = Runtime::new().unwrap();
+        let (r, _) = Readiness::new();
+        let mut srv = Admin::new((), r, TraceLevel::dangling(), state);
+        macro_rules! call {
+            () => {{
+                let r = Request::builder()
+                    .method(Method::GET)
+                    .uri("http://4.3.2.1:5678/debug/state")
+                    .body(Body::empty())
+                    .unwrap();
+                let f = srv.call(r).and_then(|rsp| {
+                    assert_eq!(rsp.status(), StatusCode::OK);
+                    rsp.into_body().concat2().map_err(|e| panic!("{}", e))
+                });
+                let body = rt.block_on_for(TIMEOUT, f).expect("call");
+                String::from_utf8(body.to_vec()).unwrap()
+            };};
+        }
+
+        assert_eq!(
+            call!(),
+            "{\"routers\":{},\"resolutions\":[{\"target\":\"web.ns.svc.cluster.local:8080\",\
+             \"endpoints\":[{\"addr\":\"10.1.1.1:8080\",\"labels\":{\"pod\":\"web-0\"}}]}],\
+             \"profiles\":[],\"identity\":null,\"taps\":[]}\n"
+        );
+
+        drop(resolved);
+        assert_eq!(
+            call!(),
+            "{\"routers\":{},\"resolutions\":[],\"profiles\":[],\"identity\":null,\"taps\":[]}\n"
+        );
+    }
 }
@@ -0,0 +1,379 @@
+use super::rsp;
+use crate::app::identity;
+use crate::proxy::http::{profiles::router::ActiveRoutes, router::CacheKeys};
+use crate::proxy::resolve::Resolutions;
+use crate::tap;
+use futures::{Async, Future, Poll};
+use http::{header, StatusCode};
+use hyper::{Body, Response};
+use indexmap::IndexMap;
+use std::fmt::{self, Write};
+use std::hash::Hash;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+/// Describes the proxy's live state as JSON.
+///
+/// Routers are registered as they are built; resolutions, profile routes, and
+/// tap sessions are reported while they are active.
+#[derive(Clone, Default)]
+pub struct State {
+    routers: Arc<Mutex<Vec<Router>>>,
+    resolutions: Resolutions,
+    active_routes: ActiveRoutes,
+    identity: Option<identity::Local>,
+    taps: tap::Sessions,
+}
+
+/// Renders the state once the keys of all router caches have been read.
+pub struct Report {
+    state: State,
+    router_keys: Vec<Option<Vec<String>>>,
+}
+
+struct Router {
+    name: &'static str,
+    keys: Box<dyn PollKeys + Send>,
+}
+
+/// Lists a router's cached targets, regardless of its target type.
+trait PollKeys {
+    fn poll_keys(&mut self) -> Async<Vec<String>>;
+}
+
+// === impl State ===
+
+impl State {
+    pub fn new(identity: Option<identity::Local>, taps: tap::Sessions) -> Self {
+        Self {
+            identity,
+            taps,
+            ..Self::default()
+        }
+    }
+
+    pub fn register_router<K, V>(&self, name: &'static str, keys: CacheKeys<K, V>)
+    where
+        K: Clone + Eq + Hash + fmt::Display + Send + 'static,
+        V: Clone + Send + 'static,
+    {
+        let router = Router {
+            name,
+            keys: Box::new(keys),
+        };
+        if let Ok(mut routers) = self.routers.lock() {
+            routers.push(router);
+        }
+    }
+
+    pub fn resolutions(&self) -> Resolutions {
+        self.resolutions.clone()
+    }
+
+    pub fn active_routes(&self) -> ActiveRoutes {
+        self.active_routes.clone()
+    }
+
+    pub fn report(&self) -> Report {
+        Report {
+            state: self.clone(),
+            router_keys: Vec::new(),
+        }
+    }
+
+    fn write_json(&self, routers: Vec<(&'static str, Vec<String>)>) -> String {
+        let mut buf = String::new();
+
+        buf.push_str("{\"routers\":{");
+        for (i, (name, keys)) in routers.iter().enumerate() {
+            if i > 0 {
+                buf.push(',');
+            }
+            write_str(&mut buf, name);
+            buf.push(':');
+            write_strs(&mut buf, keys);
+        }
+
+        buf.push_str("},\"resolutions\":[");
+        let resolutions = self.resolutions.live();
+        let resolutions = resolutions.iter().filter_map(|r| r.lock().ok());
+        for (i, resolved) in resolutions.enumerate() {
+            if i > 0 {
+                buf.push(',');
+            }
+            buf.push_str("{\"target\":");
+            write_str(&mut buf, &resolved.target);
+            buf.push_str(",\"endpoints\":[");
+            for (j, (addr, labels)) in resolved.endpoints.iter().enumerate() {
+                if j > 0 {
+                    buf.push(',');
+                }
+                buf.push_str("{\"addr\":");
+                write_str(&mut buf, &addr.to_string());
+                buf.push_str(",\"labels\":");
+                write_labels(&mut buf, labels);
+                buf.push('}');
+            }
+            buf.push_str("]}");
+        }
+
+        buf.push_str("],\"profiles\":[");
+        let profiles = self.active_routes.live();
+        let profiles = profiles.iter().filter_map(|p| p.lock().ok());
+        for (i, profile) in profiles.enumerate() {
+            if i > 0 {
+                buf.push(',');
+            }
+            buf.push_str("{\"dst\":");
+            write_str(&mut buf, &profile.dst.to_string());
+            buf.push_str(",\"routes\":[");
+            for (j, route) in profile.routes.iter().enumerate() {
+                if j > 0 {
+                    buf.push(',');
+                }
+                buf.push_str("{\"labels\":");
+                write_labels(&mut buf, route.labels());
+                buf.push_str(",\"mirrors\":");
+                let mirrors = route.mirrors().iter().map(|m| m.to_string());
+                write_strs(&mut buf, &mirrors.collect::<Vec<_>>());
+                buf.push('}');
+            }
+            buf.push_str("],\"dst_overrides\":[");
+            for (j, dst) in profile.dst_overrides.iter().enumerate() {
+                if j > 0 {
+                    buf.push(',');
+                }
+                buf.push_str("{\"addr\":");
+                write_str(&mut buf, &dst.addr.to_string());
+                let _ = write!(buf, ",\"weight\":{}}}", dst.weight);
+            }
+            buf.push_str("]}");
+        }
+
+        buf.push_str("],\"identity\":");
+        match self.identity {
+            None => buf.push_str("null"),
+            Some(ref local) => {
+                buf.push_str("{\"name\":");
+                write_str(&mut buf, local.name().as_ref());
+                buf.push_str(",\"crt_expiry\":");
+                match local
+                    .crt_expiry()
+                    .and_then(|e| e.duration_since(UNIX_EPOCH).ok())
+                {
+                    Some(expiry) => {
+                        let _ = write!(buf, "{}", expiry.as_secs());
+                    }
+                    None => buf.push_str("null"),
+                }
+                buf.push('}');
+            }
+        }
+
+        buf.push_str(",\"taps\":[");
+        for (i, session) in self.taps.snapshot().iter().enumerate() {
+            if i > 0 {
+                buf.push(',');
+            }
+            let _ = write!(buf, "{{\"id\":{},\"match\":", session.id);
+            write_str(&mut buf, &session.match_);
+            let _ = write!(
+                buf,
+                ",\"limit\":{},\"tapped\":{}}}",
+                session.limit, session.tapped
+            );
+        }
+        buf.push_str("]}\n");
+
+        buf
+    }
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("State")
+            .field("identity", &self.identity)
+            .finish()
+    }
+}
+
+// === impl Report ===
+
+impl Future for Report {
+    type Item = Response<Body>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // If the state's lock is poisoned, the report omits routers.
+        let routers = match self.state.routers.lock() {
+            Err(_) => Vec::new(),
+            Ok(mut routers) => {
+                self.router_keys.resize(routers.len(), None);
+
+                let mut ready = true;
+                for (router, keys) in routers.iter_mut().zip(self.router_keys.iter_mut()) {
+                    if keys.is_none() {
+                        match router.keys.poll_keys() {
+                            Async::Ready(k) => *keys = Some(k),
+                            Async::NotReady => ready = false,
+                        }
+                    }
+                }
+                if !ready {
+                    return Ok(Async::NotReady);
+                }
+
+                routers
+                    .iter()
+                    .zip(self.router_keys.iter_mut())
+                    .map(|(router, keys)| (router.name, keys.take().unwrap_or_default()))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let mut response = rsp(StatusCode::OK, self.state.write_json(routers));
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        Ok(Async::Ready(response))
+    }
+}
+
+// === impl PollKeys ===
+
+impl<K, V> PollKeys for CacheKeys<K, V>
+where
+    K: Clone + Eq + Hash + fmt::Display,
+    V: Clone,
+{
+    fn poll_keys(&mut self) -> Async<Vec<String>> {
+        match CacheKeys::poll_keys(self) {
+            Async::Ready(keys) => Async::Ready(keys.iter().map(ToString::to_string).collect()),
+            Async::NotReady => Async::NotReady,
+        }
+    }
+}
+
+// === JSON ===
+
+fn write_str(buf: &mut String, s: &str) {
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(buf, "\\u{:04x}", c as u32);
+            }
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+fn write_strs(buf: &mut String, strs: &[String]) {
+    buf.push('[');
+    for (i, s) in strs.iter().enumerate() {
+        if i > 0 {
+            buf.push(',');
+        }
+        write_str(buf, s);
+    }
+    buf.push(']');
+}
+
+fn write_labels(buf: &mut String, labels: &IndexMap<String, String>) {
+    buf.push('{');
+    for (i, (k, v)) in labels.iter().enumerate() {
+        if i > 0 {
+            buf.push(',');
+        }
+        write_str(buf, k);
+        buf.push(':');
+        write_str(buf, v);
+    }
+    buf.push('}');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::http::profiles::{router::Profile, Route, WeightedAddr};
+    use crate::NameAddr;
+    use futures::Stream;
+    use std::str::FromStr;
+    use std::thread;
+
+    fn profile() -> Arc<Mutex<Profile>> {
+        let labels = vec![("route".to_owned(), "GET /\"quoted\"".to_owned())];
+        let mut route = Route::new(labels.into_iter(), Vec::new());
+        route.set_mirrors(vec![NameAddr::from_str(
+            "web-shadow.ns.svc.cluster.local:8080",
+        )
+        .unwrap()]);
+        Arc::new(Mutex::new(Profile {
+            dst: NameAddr::from_str("web.ns.svc.cluster.local:8080").unwrap(),
+            routes: vec![route],
+            dst_overrides: vec![WeightedAddr {
+                addr: NameAddr::from_str("web-v2.ns.svc.cluster.local:8080").unwrap(),
+                weight: 10_000,
+            }],
+        }))
+    }
+
+    fn render(state: &State) -> String {
+        let rsp = state.report().wait().expect("report");
+        assert_eq!(rsp.headers()[header::CONTENT_TYPE], "application/json");
+        let body = rsp.into_body().concat2().wait().expect("body");
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn renders_profiles() {
+        let state = State::default();
+        let profile = profile();
+        state.active_routes().register(&profile);
+
+        assert_eq!(
+            render(&state),
+            "{\"routers\":{},\"resolutions\":[],\"profiles\":[{\
+             \"dst\":\"web.ns.svc.cluster.local:8080\",\
+             \"routes\":[{\"labels\":{\"route\":\"GET /\\\"quoted\\\"\"},\
+             \"mirrors\":[\"web-shadow.ns.svc.cluster.local:8080\"]}],\
+             \"dst_overrides\":[{\"addr\":\"web-v2.ns.svc.cluster.local:8080\",\"weight\":10000}]}],\
+             \"identity\":null,\"taps\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn omits_poisoned_state() {
+        let state = State::default();
+        let profile = profile();
+        state.active_routes().register(&profile);
+
+        let poisoned = profile.clone();
+        let _ = thread::spawn(move || {
+            let _lock = poisoned.lock().unwrap();
+            panic!("poison the profile lock");
+        })
+        .join();
+        assert!(profile.is_poisoned());
+
+        assert_eq!(
+            render(&state),
+            "{\"routers\":{},\"resolutions\":[],\"profiles\":[],\"identity\":null,\"taps\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let mut buf = String::new();
+        write_str(&mut buf, "a\"b\\c\nd\u{1}");
+        assert_eq!(buf, "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+}
@@ -1,5 +1,5 @@
 pub use crate::proxy::http::metrics::classify::{self, layer, CanClassify};
-use crate::proxy::http::{profiles, timeout, HasH2Reason};
+use crate::proxy::http::{outlier, profiles, timeout, trace_context, HasH2Reason};
 use crate::Error;
 use http;
 use std::borrow::Cow;
@@ -23,9 +23,20 @@ pub enum Eos {
     Default(http::StatusCode),
     Grpc(GrpcEos),
     Profile(Class),
+    ProfileEos(ProfileEos),
     Error(&'static str),
 }
 
+/// Defers profile classification until the end of the response stream, when
+/// a response class matches on the gRPC status sent in the trailers.
+#[derive(Clone, Debug)]
+pub struct ProfileEos {
+    classes: profiles::ResponseClasses,
+    status: http::StatusCode,
+    /// Only the response headers that the classes match on.
+    headers: http::HeaderMap,
+}
+
 #[derive(Clone, Debug)]
 pub enum GrpcEos {
     NoBody(Class),
@@ -104,18 +115,18 @@ impl Response {
         rsp: &http::Response<B>,
         classes: &[profiles::ResponseClass],
     ) -> Option<Class> {
-        for class in classes {
-            if class.is_match(rsp) {
-                let result = if class.is_failure() {
-                    SuccessOrFailure::Failure
-                } else {
-                    SuccessOrFailure::Success
-                };
-                return Some(Class::Default(result));
-            }
-        }
+        classes
+            .iter()
+            .find(|class| class.is_match(rsp))
+            .map(profile_class)
+    }
 
-        None
+    /// Classifies a response that no profile response class matches, as if
+    /// the route had no response classes.
+    fn unmatched(status: http::StatusCode, headers: &http::HeaderMap) -> Eos {
+        grpc_class(headers)
+            .map(|c| Eos::Grpc(GrpcEos::NoBody(c)))
+            .unwrap_or_else(|| Eos::Default(status))
     }
 }
 
@@ -135,13 +146,23 @@ impl classify::ClassifyResponse for Response {
             Response::Grpc => grpc_class(rsp.headers())
                 .map(|c| Eos::Grpc(GrpcEos::NoBody(c)))
                 .unwrap_or(Eos::Grpc(GrpcEos::Open)),
+            Response::Profile(ref classes)
+                if !rsp.headers().contains_key("grpc-status")
+                    && classes.iter().any(|c| c.is_eos_dependent()) =>
+            {
+                let mut headers = http::HeaderMap::new();
+                for class in classes.iter() {
+                    class.copy_matched_headers(rsp.headers(), &mut headers);
+                }
+                Eos::ProfileEos(ProfileEos {
+                    classes: classes.clone(),
+                    status: rsp.status(),
+                    headers,
+                })
+            }
             Response::Profile(ref classes) => Self::match_class(rsp, classes.as_ref())
                 .map(Eos::Profile)
-                .unwrap_or_else(|| {
-                    grpc_class(rsp.headers())
-                        .map(|c| Eos::Grpc(GrpcEos::NoBody(c)))
-                        .unwrap_or_else(|| Eos::Default(rsp.status()))
-                }),
+                .unwrap_or_else(|| Self::unmatched(rsp.status(), rsp.headers())),
         }
     }
 
@@ -168,6 +189,7 @@ impl classify::ClassifyEos for Eos {
                 .and_then(grpc_class)
                 .unwrap_or_else(|| Class::Grpc(SuccessOrFailure::Success, 0)),
             Eos::Profile(class) => class,
+            Eos::ProfileEos(eos) => eos.eos(trailers),
             Eos::Error(msg) => Class::Stream(SuccessOrFailure::Failure, msg.into()),
         }
     }
@@ -177,6 +199,33 @@ impl classify::ClassifyEos for Eos {
     }
 }
 
+// === impl ProfileEos ===
+
+impl ProfileEos {
+    fn eos(self, trailers: Option<&http::HeaderMap>) -> Class {
+        let ProfileEos {
+            classes,
+            status,
+            headers,
+        } = self;
+
+        classes
+            .iter()
+            .find(|class| class.is_match_eos(status, &headers, trailers))
+            .map(profile_class)
+            .unwrap_or_else(|| Response::unmatched(status, &headers).eos(trailers))
+    }
+}
+
+fn profile_class(class: &profiles::ResponseClass) -> Class {
+    let result = if class.is_failure() {
+        SuccessOrFailure::Failure
+    } else {
+        SuccessOrFailure::Success
+    };
+    Class::Default(result)
+}
+
 fn grpc_class(headers: &http::HeaderMap) -> Option<Class> {
     headers
         .get("grpc-status")
@@ -216,10 +265,35 @@ impl Class {
     }
 }
 
+impl outlier::IsFailure for Class {
+    fn is_failure(&self) -> bool {
+        Class::is_failure(self)
+    }
+}
+
+impl trace_context::SpanLabels for Class {
+    fn span_labels(&self, labels: &mut trace_context::Labels) {
+        match self {
+            Class::Default(result) => {
+                labels.insert("classification".into(), result.to_string());
+            }
+            Class::Grpc(result, status) => {
+                labels.insert("classification".into(), result.to_string());
+                labels.insert("grpc_status".into(), status.to_string());
+            }
+            Class::Stream(result, error) => {
+                labels.insert("classification".into(), result.to_string());
+                labels.insert("error".into(), error.to_string());
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Class, SuccessOrFailure};
     use crate::proxy::http::metrics::classify::{ClassifyEos as _CE, ClassifyResponse as _CR};
+    use crate::proxy::http::profiles;
     use http::{HeaderMap, Response, StatusCode};
 
     #[test]
@@ -310,4 +384,131 @@ mod tests {
             .eos(Some(&trailers));
         assert_eq!(class, Class::Grpc(SuccessOrFailure::Failure, 3));
     }
+
+    fn grpc_unavailable_is_failure() -> profiles::ResponseClasses {
+        let class = profiles::ResponseClass::new(
+            true,
+            profiles::ResponseMatch::GrpcStatus { min: 14, max: 14 },
+        );
+        profiles::Route::new(std::iter::empty(), vec![class])
+            .response_classes()
+            .clone()
+    }
+
+    #[test]
+    fn profile_grpc_status_header() {
+        let rsp = Response::builder()
+            .header("grpc-status", "14")
+            .status(StatusCode::OK)
+            .body(())
+            .unwrap();
+
+        let class = super::Response::Profile(grpc_unavailable_is_failure())
+            .start(&rsp)
+            .eos(None);
+        assert_eq!(class, Class::Default(SuccessOrFailure::Failure));
+    }
+
+    #[test]
+    fn profile_grpc_status_trailer() {
+        let rsp = Response::builder().status(StatusCode::OK).body(()).unwrap();
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", 14.into());
+
+        let class = super::Response::Profile(grpc_unavailable_is_failure())
+            .start(&rsp)
+            .eos(Some(&trailers));
+        assert_eq!(class, Class::Default(SuccessOrFailure::Failure));
+    }
+
+    #[test]
+    fn profile_grpc_status_trailer_unmatched() {
+        let rsp = Response::builder().status(StatusCode::OK).body(()).unwrap();
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", 0.into());
+
+        let class = super::Response::Profile(grpc_unavailable_is_failure())
+            .start(&rsp)
+            .eos(Some(&trailers));
+        assert_eq!(class, Class::Grpc(SuccessOrFailure::Success, 0));
+    }
+
+    fn classes(classes: Vec<profiles::ResponseClass>) -> profiles::ResponseClasses {
+        profiles::Route::new(std::iter::empty(), classes)
+            .response_classes()
+            .clone()
+    }
+
+    fn header_is_failure() -> profiles::ResponseClass {
+        profiles::ResponseClass::new(
+            true,
+            profiles::ResponseMatch::Header {
+                name: http::header::HeaderName::from_static("x-error"),
+                value: profiles::ValueMatch::Exact("fatal".into()),
+            },
+        )
+    }
+
+    #[test]
+    fn profile_eos_retains_only_matched_headers() {
+        let rsp = Response::builder()
+            .header("x-error", "fatal")
+            .header("x-other", "value")
+            .status(StatusCode::OK)
+            .body(())
+            .unwrap();
+        let classes = classes(vec![
+            header_is_failure(),
+            profiles::ResponseClass::new(
+                true,
+                profiles::ResponseMatch::GrpcStatus { min: 14, max: 14 },
+            ),
+        ]);
+
+        match super::Response::Profile(classes).start(&rsp) {
+            super::Eos::ProfileEos(eos) => {
+                assert_eq!(eos.headers.len(), 1);
+                assert_eq!(eos.headers["x-error"], "fatal");
+                let mut trailers = HeaderMap::new();
+                trailers.insert("grpc-status", 0.into());
+                assert_eq!(
+                    eos.eos(Some(&trailers)),
+                    Class::Default(SuccessOrFailure::Failure)
+                );
+            }
+            eos => panic!("unexpected eos: {:?}", eos),
+        }
+    }
+
+    #[test]
+    fn profile_unmatched_responses_are_classified_alike() {
+        // The first set of classes may only be matched at the end of the
+        // stream; the second may be matched on the response headers.
+        let eos_dependent = classes(vec![profiles::ResponseClass::new(
+            true,
+            profiles::ResponseMatch::GrpcStatus { min: 14, max: 14 },
+        )]);
+        let headers_only = classes(vec![header_is_failure()]);
+
+        for &status in &[StatusCode::OK, StatusCode::INTERNAL_SERVER_ERROR] {
+            for grpc_status in &[None, Some(0), Some(3)] {
+                let rsp = Response::builder().status(status).body(()).unwrap();
+                let trailers = grpc_status.map(|code: u32| {
+                    let mut trailers = HeaderMap::new();
+                    trailers.insert("grpc-status", code.into());
+                    trailers
+                });
+
+                let a = super::Response::Profile(eos_dependent.clone())
+                    .start(&rsp)
+                    .eos(trailers.as_ref());
+                let b = super::Response::Profile(headers_only.clone())
+                    .start(&rsp)
+                    .eos(trailers.as_ref());
+                let c = super::Response::Default.start(&rsp).eos(trailers.as_ref());
+                assert_eq!(a, b, "status={}; grpc-status={:?}", status, grpc_status);
+                assert_eq!(a, c, "status={}; grpc-status={:?}", status, grpc_status);
+            }
+        }
+    }
 }
@@ -1,10 +1,17 @@
 use super::control::ControlAddr;
 use super::identity;
+use super::static_discovery;
 use crate::addr::{self, Addr};
+use crate::metrics::{latency, Bounds, Bucket};
+use crate::proxy::authz;
+use crate::proxy::http::{
+    adaptive_concurrency, consistent_hash, health_check, jwt, locality, outlier, profiles,
+    rate_limit, route_authz,
+};
 use crate::proxy::reconnect::Backoff;
 use crate::transport::tls;
-use crate::{dns, Conditional};
-use indexmap::IndexSet;
+use crate::{dns, Conditional, NameAddr};
+use indexmap::{IndexMap, IndexSet};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::iter::FromIterator;
@@ -86,11 +93,87 @@ pub struct Config {
 
     pub inbound_max_requests_in_flight: usize,
 
+    /// Adapts the concurrency limit of each inbound destination, if
+    /// configured by `ENV_INBOUND_ADAPTIVE_CONCURRENCY_MAX_LIMIT`.
+    pub inbound_adaptive_concurrency: Option<adaptive_concurrency::Config>,
+
+    /// Limits the rate of inbound requests, if configured by
+    /// `ENV_INBOUND_RATE_LIMIT`.
+    pub inbound_rate_limit: Option<rate_limit::Config>,
+
+    /// Authorizes inbound connections by client identity, if configured by
+    /// `ENV_INBOUND_AUTHZ_POLICY`.
+    pub inbound_authz_policy: Option<authz::Policy>,
+
+    /// Authorizes inbound requests by route and client identity, if
+    /// configured by `ENV_INBOUND_ROUTE_AUTHZ_POLICY_FILE`.
+    pub inbound_route_authz_policy: Option<route_authz::Policy>,
+
+    /// Validates JWTs on inbound requests to routes that require them, if
+    /// configured by `ENV_INBOUND_JWT_JWKS_FILE`.
+    pub inbound_jwt: Option<jwt::Config>,
+
     pub outbound_max_requests_in_flight: usize,
 
+    /// Adapts the concurrency limit of each outbound destination, if
+    /// configured by `ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_MAX_LIMIT`.
+    pub outbound_adaptive_concurrency: Option<adaptive_concurrency::Config>,
+
+    /// The maximum number of request body bytes copied to each of a
+    /// route's mirrors. Requests with larger bodies fail to be mirrored.
+    pub outbound_mirror_max_body_bytes: usize,
+
+    /// Maps the original destination addresses of opaque outbound TCP
+    /// connections to the logical names used to resolve and balance them.
+    ///
+    /// Configured by `ENV_OUTBOUND_TCP_DESTINATIONS`.
+    pub outbound_tcp_destinations: IndexMap<SocketAddr, NameAddr>,
+
+    /// Configures the ejection of failing endpoints from outbound balancers.
+    pub outbound_outlier_detection: outlier::Config,
+
+    /// Configures outbound balancers to prefer endpoints in the proxy's zone
+    /// and region. Enabled when `ENV_OUTBOUND_LOCALITY_ZONE` or
+    /// `ENV_OUTBOUND_LOCALITY_REGION` is set.
+    pub outbound_locality: Option<locality::Config>,
+
+    /// Configures outbound balancers to dispatch requests by a consistent
+    /// hash of this part of each request, rather than by load, unless a
+    /// destination's profile describes its own hash key.
+    pub outbound_balance_hash_key: Option<consistent_hash::HashKey>,
+
+    /// The amount of time over which outbound balancers ramp up the share of
+    /// requests dispatched to newly discovered endpoints.
+    pub outbound_balance_slow_start: Option<Duration>,
+
+    /// Configures how often and how strictly outbound endpoints are actively
+    /// health checked.
+    pub outbound_health_check: health_check::Config,
+
+    /// The health checks of outbound destinations, which a destination's
+    /// profile may override.
+    ///
+    /// Configured by `ENV_OUTBOUND_HEALTH_CHECKS`.
+    pub outbound_health_checks: IndexMap<NameAddr, health_check::Check>,
+
+    /// Names that are resolved via DNS when the destination service cannot
+    /// resolve them.
+    ///
+    /// Configured by `ENV_OUTBOUND_DNS_FALLBACK_SUFFIXES`.
+    pub outbound_dns_fallback_suffixes: Vec<dns::Suffix>,
+
     /// Age after which metrics may be dropped.
     pub metrics_retain_idle: Duration,
 
+    /// Bounds for HTTP response latency histograms, in milliseconds.
+    pub response_latency_bounds: Bounds,
+
+    /// Bounds for TCP connection duration histograms, in milliseconds.
+    pub tcp_connection_duration_bounds: Bounds,
+
+    /// Bounds for request handle time histograms, in microseconds.
+    pub handle_time_bounds: Bounds,
+
     /// Settings for the back-off used to determine the amount of time to wait
     /// between when encountering errors talking to control plane before
     /// a new connection is attempted.
@@ -104,8 +187,8 @@ pub struct Config {
     //
     // Destination Config
     //
-    /// Where to talk to the control plane.
-    pub destination_addr: ControlAddr,
+    /// Where destinations' endpoints and profiles are discovered.
+    pub destination: Destination,
 
     /// The maximum number of queries to the Destination service which may be
     /// active concurrently.
@@ -122,6 +205,16 @@ pub struct Config {
     /// call.
     pub destination_context: String,
 
+    //
+    // Tracing Config
+    //
+    /// Where spans are exported, if configured by
+    /// `ENV_TRACE_COLLECTOR_SVC_BASE`.
+    pub trace_collector_addr: Option<ControlAddr>,
+
+    /// Identifies this proxy to the trace collector.
+    pub trace_service_name: String,
+
     //
     // DNS Config
     //
@@ -161,6 +254,17 @@ pub struct Listener {
     pub addr: SocketAddr,
 }
 
+/// Where destinations' endpoints and profiles are discovered.
+#[derive(Clone, Debug)]
+pub enum Destination {
+    /// Discovers destinations via the control plane's Destination service.
+    Service(ControlAddr),
+
+    /// Discovers destinations from a file configured by
+    /// `ENV_DESTINATION_STATIC_FILE`.
+    File(static_discovery::Config),
+}
+
 /// Errors produced when loading a `Config` struct.
 #[derive(Clone, Debug)]
 pub enum Error {
@@ -178,8 +282,21 @@ pub enum ParseError {
     NotUnicode,
     AddrError(addr::Error),
     NameError,
+    NotATcpDestination,
+    NotAPercentage,
+    NotARateLimitKey,
+    NotAnAuthzPolicy,
+    NotARouteAuthzPolicy,
+    NotAClaimHeader,
+    InvalidJwks,
+    NotAHeaderName,
+    NotALatencyResolution,
+    InvalidHistogramBuckets,
     InvalidTokenSource,
     InvalidTrustAnchors,
+    InvalidDiscoveryFile,
+    NotAHashKey,
+    NotAHealthCheck,
 }
 
 /// The strings used to build a configuration.
@@ -205,6 +322,20 @@ pub const ENV_INBOUND_LISTEN_ADDR: &str = "LINKERD2_PROXY_INBOUND_LISTEN_ADDR";
 pub const ENV_CONTROL_LISTEN_ADDR: &str = "LINKERD2_PROXY_CONTROL_LISTEN_ADDR";
 pub const ENV_ADMIN_LISTEN_ADDR: &str = "LINKERD2_PROXY_ADMIN_LISTEN_ADDR";
 pub const ENV_METRICS_RETAIN_IDLE: &str = "LINKERD2_PROXY_METRICS_RETAIN_IDLE";
+
+/// Configures whether latencies are reported in milliseconds with `ms` (the
+/// default) or `us` precision. Sub-millisecond buckets require `us`.
+pub const ENV_METRICS_LATENCY_RESOLUTION: &str = "LINKERD2_PROXY_METRICS_LATENCY_RESOLUTION";
+
+/// Comma-separated lists of histogram bucket bounds, in milliseconds.
+pub const ENV_METRICS_RESPONSE_LATENCY_BUCKETS: &str =
+    "LINKERD2_PROXY_METRICS_RESPONSE_LATENCY_BUCKETS";
+pub const ENV_METRICS_TCP_CONNECTION_DURATION_BUCKETS: &str =
+    "LINKERD2_PROXY_METRICS_TCP_CONNECTION_DURATION_BUCKETS";
+
+/// A comma-separated list of handle time histogram bucket bounds, in
+/// microseconds.
+pub const ENV_METRICS_HANDLE_TIME_BUCKETS: &str = "LINKERD2_PROXY_METRICS_HANDLE_TIME_BUCKETS";
 const ENV_INBOUND_DISPATCH_TIMEOUT: &str = "LINKERD2_PROXY_INBOUND_DISPATCH_TIMEOUT";
 const ENV_OUTBOUND_DISPATCH_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_DISPATCH_TIMEOUT";
 const ENV_INBOUND_CONNECT_TIMEOUT: &str = "LINKERD2_PROXY_INBOUND_CONNECT_TIMEOUT";
@@ -230,6 +361,186 @@ pub const ENV_OUTBOUND_ROUTER_MAX_IDLE_AGE: &str = "LINKERD2_PROXY_OUTBOUND_ROUT
 pub const ENV_INBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_INBOUND_MAX_IN_FLIGHT";
 pub const ENV_OUTBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_OUTBOUND_MAX_IN_FLIGHT";
 
+/// The upper bound of each destination's adaptive concurrency limit. If
+/// unset, the concurrency of requests to each destination is not limited
+/// (beyond the proxy-wide `*_MAX_IN_FLIGHT`).
+pub const ENV_INBOUND_ADAPTIVE_CONCURRENCY_MAX_LIMIT: &str =
+    "LINKERD2_PROXY_INBOUND_ADAPTIVE_CONCURRENCY_MAX_LIMIT";
+pub const ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_MAX_LIMIT: &str =
+    "LINKERD2_PROXY_OUTBOUND_ADAPTIVE_CONCURRENCY_MAX_LIMIT";
+
+/// The lower bound of each destination's adaptive concurrency limit, at
+/// which each destination starts.
+pub const ENV_INBOUND_ADAPTIVE_CONCURRENCY_MIN_LIMIT: &str =
+    "LINKERD2_PROXY_INBOUND_ADAPTIVE_CONCURRENCY_MIN_LIMIT";
+pub const ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_MIN_LIMIT: &str =
+    "LINKERD2_PROXY_OUTBOUND_ADAPTIVE_CONCURRENCY_MIN_LIMIT";
+
+pub const ENV_OUTBOUND_MIRROR_MAX_BODY_BYTES: &str =
+    "LINKERD2_PROXY_OUTBOUND_MIRROR_MAX_BODY_BYTES";
+
+/// Limits the number of inbound requests permitted per second. If unset,
+/// inbound requests are not rate limited.
+pub const ENV_INBOUND_RATE_LIMIT: &str = "LINKERD2_PROXY_INBOUND_RATE_LIMIT";
+
+/// The number of inbound requests that may be permitted in a burst. Defaults to
+/// the value of `ENV_INBOUND_RATE_LIMIT`.
+pub const ENV_INBOUND_RATE_LIMIT_BURST: &str = "LINKERD2_PROXY_INBOUND_RATE_LIMIT_BURST";
+
+/// A comma-separated list of the properties by which inbound requests are
+/// rate limited: `route` limits each profile route independently and
+/// `client_id` limits each TLS client identity independently. If empty, a
+/// single limit applies to all inbound requests.
+///
+/// If unspecified, requests are limited by both route and client identity.
+pub const ENV_INBOUND_RATE_LIMIT_KEY: &str = "LINKERD2_PROXY_INBOUND_RATE_LIMIT_KEY";
+
+/// Authorizes inbound connections by port and client identity.
+///
+/// The value is a semicolon-separated list of `PORT=RULE` pairs, where `PORT`
+/// is an inbound port or `*` for all other ports. `RULE` is one of:
+///
+/// - `plaintext`: all connections are authorized;
+/// - `identity`: only clients that authenticate via mTLS are authorized;
+/// - `identity:ID,...`: only clients that authenticate via mTLS with one of
+///   the listed identities are authorized. An identity of the form
+///   `*.SUFFIX` matches all identities in that domain.
+///
+/// Unauthorized TCP connections are refused and unauthorized HTTP requests
+/// fail with a 403 response. If unset, all connections are authorized.
+pub const ENV_INBOUND_AUTHZ_POLICY: &str = "LINKERD2_PROXY_INBOUND_AUTHZ_POLICY";
+
+/// The path to a file of rules that authorize inbound requests by route and
+/// client identity.
+///
+/// Each line of the file is a rule of the form `NAME METHOD PATH ID[,ID...]`,
+/// where `METHOD` is an HTTP method or `*` for all methods, `PATH` is a
+/// regular expression that must match the entire request path, and each `ID`
+/// is formatted as in `ENV_INBOUND_AUTHZ_POLICY`. Blank lines and lines
+/// starting with `#` are ignored.
+///
+/// The first rule that matches a request determines whether it is authorized.
+/// Unauthorized requests fail with a 403 response; requests that match no
+/// rule are authorized. The file is read once, at startup.
+pub const ENV_INBOUND_ROUTE_AUTHZ_POLICY_FILE: &str =
+    "LINKERD2_PROXY_INBOUND_ROUTE_AUTHZ_POLICY_FILE";
+
+/// The path to a JWKS file holding the keys that sign JWTs on inbound
+/// requests. If set, requests to profile routes with a
+/// `config.linkerd.io/jwt` label of `required` must carry a valid
+/// `Authorization: Bearer` token; requests without one fail with a 401
+/// response. The file is read once, at startup.
+pub const ENV_INBOUND_JWT_JWKS_FILE: &str = "LINKERD2_PROXY_INBOUND_JWT_JWKS_FILE";
+
+/// A comma-separated list of accepted JWT issuers. If unset, any `iss` claim
+/// is accepted.
+pub const ENV_INBOUND_JWT_ISSUERS: &str = "LINKERD2_PROXY_INBOUND_JWT_ISSUERS";
+
+/// A comma-separated list of accepted JWT audiences. If unset, any `aud`
+/// claim is accepted.
+pub const ENV_INBOUND_JWT_AUDIENCES: &str = "LINKERD2_PROXY_INBOUND_JWT_AUDIENCES";
+
+/// A comma-separated list of `CLAIM=HEADER` pairs naming the verified claims
+/// that are forwarded to the application as request headers.
+///
+/// If unspecified, the `sub` claim is forwarded as `l5d-jwt-sub`.
+pub const ENV_INBOUND_JWT_CLAIM_HEADERS: &str = "LINKERD2_PROXY_INBOUND_JWT_CLAIM_HEADERS";
+
+/// The clock skew tolerated when checking a JWT's `exp` and `nbf` claims.
+pub const ENV_INBOUND_JWT_LEEWAY: &str = "LINKERD2_PROXY_INBOUND_JWT_LEEWAY";
+
+/// Configures opaque TCP connections to be balanced over the endpoints of a
+/// logical destination.
+///
+/// The value is a comma-separated list of `IP:PORT=NAME:PORT` pairs. Non-HTTP
+/// connections whose original destination matches an `IP:PORT` are balanced
+/// over the endpoints that the destination service returns for `NAME:PORT`.
+/// All other non-HTTP connections are forwarded to their original destination.
+pub const ENV_OUTBOUND_TCP_DESTINATIONS: &str = "LINKERD2_PROXY_OUTBOUND_TCP_DESTINATIONS";
+
+/// Ejects an endpoint from outbound load balancers after this many
+/// consecutive failed requests. If unset, endpoints are not ejected for
+/// consecutive failures.
+pub const ENV_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES";
+
+/// Ejects an endpoint from outbound load balancers when at least this
+/// percentage of its requests fail within a window. If unset, endpoints are
+/// not ejected for their failure rate.
+pub const ENV_OUTBOUND_OUTLIER_FAILURE_PERCENT: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_FAILURE_PERCENT";
+pub const ENV_OUTBOUND_OUTLIER_FAILURE_MIN_REQUESTS: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_FAILURE_MIN_REQUESTS";
+pub const ENV_OUTBOUND_OUTLIER_FAILURE_WINDOW: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_FAILURE_WINDOW";
+
+pub const ENV_OUTBOUND_OUTLIER_EJECTION_TIME: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_EJECTION_TIME";
+pub const ENV_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT";
+
+/// The zone in which the proxy runs. Outbound balancers prefer endpoints
+/// whose `zone` label matches this value.
+pub const ENV_OUTBOUND_LOCALITY_ZONE: &str = "LINKERD2_PROXY_OUTBOUND_LOCALITY_ZONE";
+
+/// The region in which the proxy runs. Outbound balancers prefer endpoints
+/// whose `region` label matches this value over endpoints in other regions.
+pub const ENV_OUTBOUND_LOCALITY_REGION: &str = "LINKERD2_PROXY_OUTBOUND_LOCALITY_REGION";
+
+/// The minimum number of ready endpoints in the proxy's locality. When fewer
+/// endpoints are ready, requests spill over to endpoints in other localities.
+pub const ENV_OUTBOUND_LOCALITY_MIN_ENDPOINTS: &str =
+    "LINKERD2_PROXY_OUTBOUND_LOCALITY_MIN_ENDPOINTS";
+
+/// Configures outbound balancers to provide session affinity by dispatching
+/// each request to an endpoint selected by a consistent hash of part of the
+/// request.
+///
+/// The value is one of `header:<NAME>`, `cookie:<NAME>`, or `source-ip`.
+/// Requests that do not have the configured header or cookie are balanced
+/// by load. If unset, all requests are balanced by load. Destinations in the
+/// static discovery file may override this with their `balance_hash_key`.
+pub const ENV_OUTBOUND_BALANCE_HASH_KEY: &str = "LINKERD2_PROXY_OUTBOUND_BALANCE_HASH_KEY";
+
+/// Configures outbound balancers to ramp up the share of requests dispatched
+/// to each newly discovered endpoint over this amount of time, so that cold
+/// endpoints are not flooded with requests. If unset, new endpoints are used
+/// immediately.
+pub const ENV_OUTBOUND_BALANCE_SLOW_START: &str = "LINKERD2_PROXY_OUTBOUND_BALANCE_SLOW_START";
+
+/// Configures active health checks of the endpoints of outbound destinations.
+///
+/// The value is a comma-separated list of `NAME:PORT=CHECK` pairs, where
+/// `CHECK` is one of `http:<PATH>`, `grpc`, or `grpc:<SERVICE>`. Endpoints
+/// that fail their checks are not used by outbound balancers until they pass
+/// again. A destination's profile may also configure its check.
+pub const ENV_OUTBOUND_HEALTH_CHECKS: &str = "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECKS";
+
+/// Constrains which outbound destination names are resolved via DNS when the
+/// destination service cannot resolve them.
+///
+/// The value is a comma-separated list of domain name suffixes. A value of
+/// `.` indicates that all names may be resolved via DNS. Ports are discovered
+/// from each name's `_http._tcp` SRV records, if it has any.
+///
+/// If unspecified or empty, unresolvable names are not resolved via DNS, and
+/// requests are forwarded to their original destination.
+pub const ENV_OUTBOUND_DNS_FALLBACK_SUFFIXES: &str =
+    "LINKERD2_PROXY_OUTBOUND_DNS_FALLBACK_SUFFIXES";
+
+pub const ENV_OUTBOUND_HEALTH_CHECK_INTERVAL: &str =
+    "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECK_INTERVAL";
+pub const ENV_OUTBOUND_HEALTH_CHECK_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECK_TIMEOUT";
+
+/// Marks an endpoint unhealthy after this many consecutive failed checks.
+pub const ENV_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD: &str =
+    "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD";
+
+/// Marks an unhealthy endpoint healthy after this many consecutive
+/// successful checks.
+pub const ENV_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD: &str =
+    "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD";
+
 /// Constrains which destination names are resolved through the destination
 /// service.
 ///
@@ -263,11 +574,24 @@ pub const ENV_OUTBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str =
 pub const ENV_IDENTITY_DISABLED: &str = "LINKERD2_PROXY_IDENTITY_DISABLED";
 pub const ENV_IDENTITY_DIR: &str = "LINKERD2_PROXY_IDENTITY_DIR";
 pub const ENV_IDENTITY_TRUST_ANCHORS: &str = "LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS";
+/// A file of PEM-encoded trust anchors, as an alternative to
+/// `LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS`. The file is re-read whenever it
+/// changes so that trust anchors may be rotated.
+pub const ENV_IDENTITY_TRUST_ANCHORS_FILE: &str = "LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS_FILE";
 pub const ENV_IDENTITY_IDENTITY_LOCAL_NAME: &str = "LINKERD2_PROXY_IDENTITY_LOCAL_NAME";
 pub const ENV_IDENTITY_TOKEN_FILE: &str = "LINKERD2_PROXY_IDENTITY_TOKEN_FILE";
 pub const ENV_IDENTITY_MIN_REFRESH: &str = "LINKERD2_PROXY_IDENTITY_MIN_REFRESH";
 pub const ENV_IDENTITY_MAX_REFRESH: &str = "LINKERD2_PROXY_IDENTITY_MAX_REFRESH";
 
+// When set, the local identity's PEM-encoded certificate and key are read from
+// these files instead of being issued by the Identity service. The files are
+// re-read whenever they change, as is the trust anchors file, at the configured
+// poll interval.
+pub const ENV_IDENTITY_CRT_FILE: &str = "LINKERD2_PROXY_IDENTITY_CRT_FILE";
+pub const ENV_IDENTITY_KEY_FILE: &str = "LINKERD2_PROXY_IDENTITY_KEY_FILE";
+pub const ENV_IDENTITY_BUNDLE_FILE: &str = "LINKERD2_PROXY_IDENTITY_BUNDLE_FILE";
+pub const ENV_IDENTITY_FILE_POLL_INTERVAL: &str = "LINKERD2_PROXY_IDENTITY_FILE_POLL_INTERVAL";
+
 pub const ENV_IDENTITY_SVC_BASE: &str = "LINKERD2_PROXY_IDENTITY_SVC";
 
 pub const ENV_DESTINATION_SVC_BASE: &str = "LINKERD2_PROXY_DESTINATION_SVC";
@@ -275,6 +599,21 @@ pub const ENV_DESTINATION_SVC_ADDR: &str = "LINKERD2_PROXY_DESTINATION_SVC_ADDR"
 
 pub const ENV_DESTINATION_CONTEXT: &str = "LINKERD2_PROXY_DESTINATION_CONTEXT";
 
+/// The path to a JSON or YAML file describing destinations' endpoints and
+/// routes.
+///
+/// If set, destinations are discovered from this file instead of the
+/// Destination service, so `ENV_DESTINATION_SVC_ADDR` must not be set. The
+/// file is polled for changes.
+pub const ENV_DESTINATION_STATIC_FILE: &str = "LINKERD2_PROXY_DESTINATION_STATIC_FILE";
+pub const ENV_DESTINATION_STATIC_FILE_POLL_INTERVAL: &str =
+    "LINKERD2_PROXY_DESTINATION_STATIC_FILE_POLL_INTERVAL";
+
+/// Configures the OpenCensus agent to which spans are exported. If unset,
+/// spans are not recorded.
+pub const ENV_TRACE_COLLECTOR_SVC_BASE: &str = "LINKERD2_PROXY_TRACE_COLLECTOR_SVC";
+pub const ENV_TRACE_SERVICE_NAME: &str = "LINKERD2_PROXY_TRACE_SERVICE_NAME";
+
 pub const ENV_CONTROL_EXP_BACKOFF_MIN: &str = "LINKERD2_PROXY_CONTROL_EXP_BACKOFF_MIN";
 pub const ENV_CONTROL_EXP_BACKOFF_MAX: &str = "LINKERD2_PROXY_CONTROL_EXP_BACKOFF_MAX";
 pub const ENV_CONTROL_EXP_BACKOFF_JITTER: &str = "LINKERD2_PROXY_CONTROL_EXP_BACKOFF_JITTER";
@@ -346,13 +685,36 @@ const DEFAULT_OUTBOUND_ROUTER_MAX_IDLE_AGE: Duration = Duration::from_secs(60);
 const DEFAULT_INBOUND_MAX_IN_FLIGHT: usize = 10_000;
 const DEFAULT_OUTBOUND_MAX_IN_FLIGHT: usize = 10_000;
 
+const DEFAULT_ADAPTIVE_CONCURRENCY_MIN_LIMIT: usize = 10;
+
+const DEFAULT_OUTBOUND_MIRROR_MAX_BODY_BYTES: usize = 64 * 1024;
+
+const DEFAULT_INBOUND_JWT_CLAIM_HEADERS: &str = "sub=l5d-jwt-sub";
+const DEFAULT_INBOUND_JWT_LEEWAY: Duration = Duration::from_secs(60);
+
+const DEFAULT_TRACE_SERVICE_NAME: &str = "linkerd-proxy";
+
+const DEFAULT_OUTBOUND_OUTLIER_FAILURE_MIN_REQUESTS: u64 = 10;
+const DEFAULT_OUTBOUND_OUTLIER_FAILURE_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_OUTBOUND_OUTLIER_EJECTION_TIME: Duration = Duration::from_secs(30);
+const DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT: u32 = 50;
+
+const DEFAULT_OUTBOUND_LOCALITY_MIN_ENDPOINTS: usize = 1;
+
+const DEFAULT_OUTBOUND_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_OUTBOUND_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD: u32 = 3;
+const DEFAULT_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD: u32 = 2;
+
 const DEFAULT_DESTINATION_BUFFER_CAPACITY: usize = 100;
 
 const DEFAULT_DESTINATION_GET_SUFFIXES: &str = "svc.cluster.local.";
 const DEFAULT_DESTINATION_PROFILE_SUFFIXES: &str = "svc.cluster.local.";
+const DEFAULT_DESTINATION_STATIC_FILE_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 const DEFAULT_IDENTITY_MIN_REFRESH: Duration = Duration::from_secs(10);
 const DEFAULT_IDENTITY_MAX_REFRESH: Duration = Duration::from_secs(60 * 60 * 24);
+const DEFAULT_IDENTITY_FILE_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 // By default, we keep a list of known assigned ports of server-first protocols.
 //
@@ -428,7 +790,132 @@ impl Config {
         let inbound_max_in_flight = parse(strings, ENV_INBOUND_MAX_IN_FLIGHT, parse_number);
         let outbound_max_in_flight = parse(strings, ENV_OUTBOUND_MAX_IN_FLIGHT, parse_number);
 
+        let inbound_adaptive_concurrency_min = parse(
+            strings,
+            ENV_INBOUND_ADAPTIVE_CONCURRENCY_MIN_LIMIT,
+            parse_number,
+        );
+        let inbound_adaptive_concurrency_max = parse(
+            strings,
+            ENV_INBOUND_ADAPTIVE_CONCURRENCY_MAX_LIMIT,
+            parse_number,
+        );
+        let outbound_adaptive_concurrency_min = parse(
+            strings,
+            ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_MIN_LIMIT,
+            parse_number,
+        );
+        let outbound_adaptive_concurrency_max = parse(
+            strings,
+            ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_MAX_LIMIT,
+            parse_number,
+        );
+
+        let outbound_mirror_max_body_bytes =
+            parse(strings, ENV_OUTBOUND_MIRROR_MAX_BODY_BYTES, parse_number);
+
+        let inbound_rate_limit = parse(strings, ENV_INBOUND_RATE_LIMIT, parse_number);
+        let inbound_rate_limit_burst = parse(strings, ENV_INBOUND_RATE_LIMIT_BURST, parse_number);
+        let inbound_rate_limit_key =
+            parse(strings, ENV_INBOUND_RATE_LIMIT_KEY, parse_rate_limit_key);
+        let inbound_authz_policy = parse(strings, ENV_INBOUND_AUTHZ_POLICY, parse_authz_policy);
+        let inbound_route_authz_policy = parse(strings, ENV_INBOUND_ROUTE_AUTHZ_POLICY_FILE, |s| {
+            let policy = fs::read_to_string(s).map_err(|e| {
+                error!("Failed to read {}: {}", s, e);
+                ParseError::NotARouteAuthzPolicy
+            })?;
+            parse_route_authz_policy(&policy)
+        });
+        let inbound_jwt_keys = parse(strings, ENV_INBOUND_JWT_JWKS_FILE, |s| {
+            let jwks = fs::read(s).map_err(|e| {
+                error!("Failed to read {}: {}", s, e);
+                ParseError::InvalidJwks
+            })?;
+            jwt::KeySet::from_json(&jwks).ok_or(ParseError::InvalidJwks)
+        });
+        let inbound_jwt_issuers = parse(strings, ENV_INBOUND_JWT_ISSUERS, parse_strings);
+        let inbound_jwt_audiences = parse(strings, ENV_INBOUND_JWT_AUDIENCES, parse_strings);
+        let inbound_jwt_claim_headers =
+            parse(strings, ENV_INBOUND_JWT_CLAIM_HEADERS, parse_claim_headers);
+        let inbound_jwt_leeway = parse(strings, ENV_INBOUND_JWT_LEEWAY, parse_duration);
+
+        let outbound_outlier_consecutive_failures = parse(
+            strings,
+            ENV_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES,
+            parse_number,
+        );
+        let outbound_outlier_failure_percent =
+            parse(strings, ENV_OUTBOUND_OUTLIER_FAILURE_PERCENT, parse_percent);
+        let outbound_outlier_failure_min_requests = parse(
+            strings,
+            ENV_OUTBOUND_OUTLIER_FAILURE_MIN_REQUESTS,
+            parse_number,
+        );
+        let outbound_outlier_failure_window =
+            parse(strings, ENV_OUTBOUND_OUTLIER_FAILURE_WINDOW, parse_duration);
+        let outbound_outlier_ejection_time =
+            parse(strings, ENV_OUTBOUND_OUTLIER_EJECTION_TIME, parse_duration);
+        let outbound_outlier_max_ejection_percent = parse(
+            strings,
+            ENV_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT,
+            parse_percent,
+        );
+
+        let outbound_balance_hash_key =
+            parse(strings, ENV_OUTBOUND_BALANCE_HASH_KEY, parse_hash_key);
+        let outbound_balance_slow_start =
+            parse(strings, ENV_OUTBOUND_BALANCE_SLOW_START, parse_duration);
+
+        let outbound_health_checks =
+            parse(strings, ENV_OUTBOUND_HEALTH_CHECKS, parse_health_checks);
+        let outbound_dns_fallback_suffixes = parse(
+            strings,
+            ENV_OUTBOUND_DNS_FALLBACK_SUFFIXES,
+            parse_dns_suffixes,
+        );
+        let outbound_health_check_interval =
+            parse(strings, ENV_OUTBOUND_HEALTH_CHECK_INTERVAL, parse_duration);
+        let outbound_health_check_timeout =
+            parse(strings, ENV_OUTBOUND_HEALTH_CHECK_TIMEOUT, parse_duration);
+        let outbound_health_check_unhealthy_threshold = parse(
+            strings,
+            ENV_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD,
+            parse_number,
+        );
+        let outbound_health_check_healthy_threshold = parse(
+            strings,
+            ENV_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD,
+            parse_number,
+        );
+
+        let outbound_locality_zone = strings.get(ENV_OUTBOUND_LOCALITY_ZONE);
+        let outbound_locality_region = strings.get(ENV_OUTBOUND_LOCALITY_REGION);
+        let outbound_locality_min_endpoints =
+            parse(strings, ENV_OUTBOUND_LOCALITY_MIN_ENDPOINTS, parse_number);
+
+        let outbound_tcp_destinations = parse(
+            strings,
+            ENV_OUTBOUND_TCP_DESTINATIONS,
+            parse_tcp_destinations,
+        );
+
         let metrics_retain_idle = parse(strings, ENV_METRICS_RETAIN_IDLE, parse_duration);
+        let latency_resolution = parse(
+            strings,
+            ENV_METRICS_LATENCY_RESOLUTION,
+            parse_latency_resolution,
+        );
+        let response_latency_buckets = parse(
+            strings,
+            ENV_METRICS_RESPONSE_LATENCY_BUCKETS,
+            parse_ms_buckets,
+        );
+        let tcp_connection_duration_buckets = parse(
+            strings,
+            ENV_METRICS_TCP_CONNECTION_DURATION_BUCKETS,
+            parse_ms_buckets,
+        );
+        let handle_time_buckets = parse(strings, ENV_METRICS_HANDLE_TIME_BUCKETS, parse_us_buckets);
 
         // DNS
 
@@ -453,6 +940,29 @@ impl Config {
         };
 
         let dst_token = strings.get(ENV_DESTINATION_CONTEXT);
+        let dst_static_file = parse(strings, ENV_DESTINATION_STATIC_FILE, |s| {
+            let doc = fs::read(s).map_err(|e| {
+                error!("Failed to read {}: {}", s, e);
+                ParseError::InvalidDiscoveryFile
+            })?;
+            let table = static_discovery::Table::parse(&doc).map_err(|e| {
+                error!("Invalid discovery file {}: {}", s, e);
+                ParseError::InvalidDiscoveryFile
+            })?;
+            Ok((PathBuf::from(s), table))
+        });
+        let dst_static_file_poll_interval = parse(
+            strings,
+            ENV_DESTINATION_STATIC_FILE_POLL_INTERVAL,
+            parse_duration,
+        );
+
+        let trace_collector_addr = if id_disabled {
+            parse_control_addr_disable_identity(strings, ENV_TRACE_COLLECTOR_SVC_BASE)
+        } else {
+            parse_control_addr(strings, ENV_TRACE_COLLECTOR_SVC_BASE)
+        };
+        let trace_service_name = strings.get(ENV_TRACE_SERVICE_NAME);
 
         let dst_get_suffixes = parse(strings, ENV_DESTINATION_GET_SUFFIXES, parse_dns_suffixes);
         let dst_profile_suffixes = parse(
@@ -472,6 +982,8 @@ impl Config {
             .unwrap_or(false);
         let control_listener = parse_control_listener(strings, id_disabled, tap_disabled);
 
+        let latency_resolution = latency_resolution?.unwrap_or_default();
+
         Ok(Config {
             outbound_listener: Listener {
                 addr: outbound_listener_addr?
@@ -540,6 +1052,99 @@ impl Config {
             outbound_max_requests_in_flight: outbound_max_in_flight?
                 .unwrap_or(DEFAULT_OUTBOUND_MAX_IN_FLIGHT),
 
+            inbound_adaptive_concurrency: adaptive_concurrency_config(
+                inbound_adaptive_concurrency_min?,
+                inbound_adaptive_concurrency_max?,
+            )?,
+            outbound_adaptive_concurrency: adaptive_concurrency_config(
+                outbound_adaptive_concurrency_min?,
+                outbound_adaptive_concurrency_max?,
+            )?,
+
+            outbound_mirror_max_body_bytes: outbound_mirror_max_body_bytes?
+                .unwrap_or(DEFAULT_OUTBOUND_MIRROR_MAX_BODY_BYTES),
+
+            inbound_rate_limit: {
+                let burst = inbound_rate_limit_burst?;
+                let (by_target, by_client_id) = inbound_rate_limit_key?.unwrap_or((true, true));
+                inbound_rate_limit?.map(|rate| rate_limit::Config {
+                    rate,
+                    burst: burst.unwrap_or(rate),
+                    by_target,
+                    by_client_id,
+                })
+            },
+
+            inbound_authz_policy: inbound_authz_policy?,
+
+            inbound_route_authz_policy: inbound_route_authz_policy?,
+
+            inbound_jwt: {
+                let issuers = inbound_jwt_issuers?.unwrap_or_default();
+                let audiences = inbound_jwt_audiences?.unwrap_or_default();
+                let claim_headers = match inbound_jwt_claim_headers? {
+                    Some(headers) => headers,
+                    None => parse_claim_headers(DEFAULT_INBOUND_JWT_CLAIM_HEADERS)
+                        .expect("default claim headers must be valid"),
+                };
+                let leeway = inbound_jwt_leeway?.unwrap_or(DEFAULT_INBOUND_JWT_LEEWAY);
+                inbound_jwt_keys?.map(|keys| jwt::Config {
+                    keys,
+                    issuers,
+                    audiences,
+                    claim_headers,
+                    leeway,
+                })
+            },
+
+            outbound_tcp_destinations: outbound_tcp_destinations?.unwrap_or_default(),
+
+            outbound_outlier_detection: {
+                let failure_min_requests = outbound_outlier_failure_min_requests?
+                    .unwrap_or(DEFAULT_OUTBOUND_OUTLIER_FAILURE_MIN_REQUESTS);
+                let failure_window = outbound_outlier_failure_window?
+                    .unwrap_or(DEFAULT_OUTBOUND_OUTLIER_FAILURE_WINDOW);
+                outlier::Config {
+                    consecutive_failures: outbound_outlier_consecutive_failures?,
+                    failure_rate: outbound_outlier_failure_percent?.map(|percent| {
+                        outlier::FailureRate {
+                            percent,
+                            min_requests: failure_min_requests,
+                            window: failure_window,
+                        }
+                    }),
+                    ejection_time: outbound_outlier_ejection_time?
+                        .unwrap_or(DEFAULT_OUTBOUND_OUTLIER_EJECTION_TIME),
+                    max_ejection_percent: outbound_outlier_max_ejection_percent?
+                        .unwrap_or(DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT),
+                }
+            },
+
+            outbound_locality: locality_config(
+                outbound_locality_zone?,
+                outbound_locality_region?,
+                outbound_locality_min_endpoints?,
+            )?,
+
+            outbound_balance_hash_key: outbound_balance_hash_key?,
+
+            outbound_balance_slow_start: outbound_balance_slow_start?,
+
+            outbound_health_check: health_check::Config {
+                interval: outbound_health_check_interval?
+                    .unwrap_or(DEFAULT_OUTBOUND_HEALTH_CHECK_INTERVAL),
+                timeout: outbound_health_check_timeout?
+                    .unwrap_or(DEFAULT_OUTBOUND_HEALTH_CHECK_TIMEOUT),
+                unhealthy_threshold: outbound_health_check_unhealthy_threshold?
+                    .unwrap_or(DEFAULT_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD),
+                healthy_threshold: outbound_health_check_healthy_threshold?
+                    .unwrap_or(DEFAULT_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD),
+            },
+
+            outbound_health_checks: outbound_health_checks?.unwrap_or_default(),
+
+            outbound_dns_fallback_suffixes: outbound_dns_fallback_suffixes?.unwrap_or_default(),
+
             destination_buffer_capacity: DEFAULT_DESTINATION_BUFFER_CAPACITY,
 
             destination_get_suffixes: dst_get_suffixes?
@@ -548,9 +1153,29 @@ impl Config {
             destination_profile_suffixes: dst_profile_suffixes?
                 .unwrap_or(parse_dns_suffixes(DEFAULT_DESTINATION_PROFILE_SUFFIXES).unwrap()),
 
-            destination_addr: dst_addr?.ok_or(Error::NoDestinationAddress)?,
+            destination: match (dst_addr?, dst_static_file?) {
+                (Some(addr), None) => Destination::Service(addr),
+                (None, Some((path, table))) => Destination::File(static_discovery::Config {
+                    path,
+                    poll_interval: dst_static_file_poll_interval?
+                        .unwrap_or(DEFAULT_DESTINATION_STATIC_FILE_POLL_INTERVAL),
+                    table,
+                }),
+                (None, None) => return Err(Error::NoDestinationAddress),
+                (Some(_), Some(_)) => {
+                    error!(
+                        "{} and {} must not both be set.",
+                        ENV_DESTINATION_SVC_ADDR, ENV_DESTINATION_STATIC_FILE
+                    );
+                    return Err(Error::InvalidEnvVar);
+                }
+            },
             destination_context: dst_token?.unwrap_or_default(),
 
+            trace_collector_addr: trace_collector_addr?,
+            trace_service_name: trace_service_name?
+                .unwrap_or_else(|| DEFAULT_TRACE_SERVICE_NAME.to_owned()),
+
             identity_config: identity_config?
                 .map(Conditional::Some)
                 .unwrap_or_else(|| Conditional::None(tls::ReasonForNoIdentity::Disabled)),
@@ -561,6 +1186,20 @@ impl Config {
 
             metrics_retain_idle: metrics_retain_idle?.unwrap_or(DEFAULT_METRICS_RETAIN_IDLE),
 
+            response_latency_bounds: latency_bounds(
+                ENV_METRICS_RESPONSE_LATENCY_BUCKETS,
+                latency_resolution,
+                response_latency_buckets?,
+            )?,
+            tcp_connection_duration_bounds: latency_bounds(
+                ENV_METRICS_TCP_CONNECTION_DURATION_BUCKETS,
+                latency_resolution,
+                tcp_connection_duration_buckets?,
+            )?,
+            handle_time_bounds: handle_time_buckets?.unwrap_or_else(|| {
+                Bounds::new(latency::BUCKETS.to_vec()).expect("default buckets must be valid")
+            }),
+
             dns_min_ttl: dns_min_ttl?,
 
             dns_max_ttl: dns_max_ttl?,
@@ -690,7 +1329,213 @@ where
     s.parse().map_err(|_| ParseError::NotANumber)
 }
 
-fn parse_duration(s: &str) -> Result<Duration, ParseError> {
+/// Parses a list of rate limit keys into a tuple indicating whether requests
+/// are limited by route and by client identity, respectively.
+fn parse_rate_limit_key(s: &str) -> Result<(bool, bool), ParseError> {
+    let mut by_route = false;
+    let mut by_client_id = false;
+    for key in s.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+        match key {
+            "route" => by_route = true,
+            "client_id" => by_client_id = true,
+            _ => {
+                error!("Not a rate limit key: {}", key);
+                return Err(ParseError::NotARateLimitKey);
+            }
+        }
+    }
+    Ok((by_route, by_client_id))
+}
+
+fn parse_authz_policy(s: &str) -> Result<authz::Policy, ParseError> {
+    let mut default = authz::PortPolicy::AllowPlaintext;
+    let mut ports = IndexMap::new();
+    for entry in s.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let mut parts = entry.splitn(2, '=');
+        let (port, rule) = match (parts.next(), parts.next()) {
+            (Some(port), Some(rule)) => (port.trim(), rule.trim()),
+            _ => {
+                error!("Expected PORT=RULE; found: {}", entry);
+                return Err(ParseError::NotAnAuthzPolicy);
+            }
+        };
+
+        let mut rule_parts = rule.splitn(2, ':');
+        let policy = match (rule_parts.next(), rule_parts.next()) {
+            (Some("plaintext"), None) => authz::PortPolicy::AllowPlaintext,
+            (Some("identity"), None) => authz::PortPolicy::RequireIdentity(vec![]),
+            (Some("identity"), Some(ids)) => {
+                let matches = ids
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(parse_authz_match)
+                    .collect::<Result<Vec<_>, _>>()?;
+                authz::PortPolicy::RequireIdentity(matches)
+            }
+            _ => {
+                error!("Not an authorization rule: {}", rule);
+                return Err(ParseError::NotAnAuthzPolicy);
+            }
+        };
+
+        if port == "*" {
+            default = policy;
+        } else {
+            ports.insert(parse_number(port)?, policy);
+        }
+    }
+    Ok(authz::Policy::new(default, ports))
+}
+
+fn parse_authz_match(s: &str) -> Result<authz::Match, ParseError> {
+    if s == "*" {
+        return Ok(authz::Match::Suffix(dns::Suffix::Root));
+    }
+    if s.starts_with("*.") {
+        return parse_dns_suffix(&s[2..]).map(authz::Match::Suffix);
+    }
+    parse_identity(s).map(authz::Match::Exact)
+}
+
+fn parse_route_authz_policy(s: &str) -> Result<route_authz::Policy, ParseError> {
+    use regex::Regex;
+
+    let mut rules = Vec::new();
+    for line in s.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        let (name, method, path, ids) = match parts.as_slice() {
+            [name, method, path, ids] => (*name, *method, *path, *ids),
+            _ => {
+                error!("Expected NAME METHOD PATH ID[,ID...]; found: {}", line);
+                return Err(ParseError::NotARouteAuthzPolicy);
+            }
+        };
+
+        let path = Regex::new(&format!("^(?:{})$", path)).map_err(|e| {
+            error!("Invalid path regex in rule {}: {}", name, e);
+            ParseError::NotARouteAuthzPolicy
+        })?;
+        let mut matches = vec![profiles::RequestMatch::Path(path)];
+        if method != "*" {
+            let method = http::Method::from_bytes(method.as_bytes()).map_err(|_| {
+                error!("Invalid method in rule {}: {}", name, method);
+                ParseError::NotARouteAuthzPolicy
+            })?;
+            matches.insert(0, profiles::RequestMatch::Method(method));
+        }
+
+        let identities = ids
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(parse_authz_match)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rules.push(route_authz::Rule::new(
+            name.to_owned(),
+            profiles::RequestMatch::All(matches),
+            identities,
+        ));
+    }
+    Ok(route_authz::Policy::new(rules))
+}
+
+fn parse_strings(s: &str) -> Result<Vec<String>, ParseError> {
+    Ok(s.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+fn parse_claim_headers(s: &str) -> Result<Vec<(String, http::header::HeaderName)>, ParseError> {
+    let mut headers = Vec::new();
+    for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let mut parts = pair.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(claim), Some(name)) if !claim.trim().is_empty() => {
+                let name =
+                    http::header::HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| {
+                        error!("Not a header name: {}", name);
+                        ParseError::NotAHeaderName
+                    })?;
+                headers.push((claim.trim().to_owned(), name));
+            }
+            _ => {
+                error!("Expected CLAIM=HEADER; found: {}", pair);
+                return Err(ParseError::NotAClaimHeader);
+            }
+        }
+    }
+    Ok(headers)
+}
+
+fn adaptive_concurrency_config(
+    min_limit: Option<usize>,
+    max_limit: Option<usize>,
+) -> Result<Option<adaptive_concurrency::Config>, Error> {
+    let max_limit = match max_limit {
+        Some(max) => max,
+        None => return Ok(None),
+    };
+    let min_limit = min_limit.unwrap_or(DEFAULT_ADAPTIVE_CONCURRENCY_MIN_LIMIT.min(max_limit));
+    if min_limit == 0 || min_limit > max_limit {
+        error!(
+            "Adaptive concurrency limits must satisfy 0 < min <= max; found min={} max={}",
+            min_limit, max_limit
+        );
+        return Err(Error::InvalidEnvVar);
+    }
+    Ok(Some(adaptive_concurrency::Config {
+        min_limit,
+        max_limit,
+    }))
+}
+
+fn parse_hash_key(s: &str) -> Result<consistent_hash::HashKey, ParseError> {
+    s.parse().map_err(|e| {
+        error!("Not a valid hash key: {}; {}", s, e);
+        ParseError::NotAHashKey
+    })
+}
+
+fn locality_config(
+    zone: Option<String>,
+    region: Option<String>,
+    min_endpoints: Option<usize>,
+) -> Result<Option<locality::Config>, Error> {
+    if zone.is_none() && region.is_none() {
+        return Ok(None);
+    }
+    let min_endpoints = min_endpoints.unwrap_or(DEFAULT_OUTBOUND_LOCALITY_MIN_ENDPOINTS);
+    if min_endpoints == 0 {
+        error!(
+            "{} must be greater than 0",
+            ENV_OUTBOUND_LOCALITY_MIN_ENDPOINTS
+        );
+        return Err(Error::InvalidEnvVar);
+    }
+    Ok(Some(locality::Config {
+        zone,
+        region,
+        min_endpoints,
+    }))
+}
+
+pub(super) fn parse_percent(s: &str) -> Result<u32, ParseError> {
+    let n = parse_number(s)?;
+    if n > 100 {
+        error!("Expected a percentage between 0 and 100; found: {}", n);
+        return Err(ParseError::NotAPercentage);
+    }
+    Ok(n)
+}
+
+pub(super) fn parse_duration(s: &str) -> Result<Duration, ParseError> {
     use regex::Regex;
 
     let re = Regex::new(r"^\s*(\d+)(ms|s|m|h|d)?\s*$").expect("duration regex");
@@ -734,6 +1579,140 @@ fn parse_port_set(s: &str) -> Result<IndexSet<u16>, ParseError> {
     Ok(set)
 }
 
+fn parse_latency_resolution(s: &str) -> Result<latency::Resolution, ParseError> {
+    match s.trim() {
+        "ms" => Ok(latency::Resolution::Ms),
+        "us" => Ok(latency::Resolution::Us),
+        _ => {
+            error!("Expected a latency resolution of ms or us; found: {}", s);
+            Err(ParseError::NotALatencyResolution)
+        }
+    }
+}
+
+/// Parses a list of millisecond bucket bounds, which may have up to three
+/// decimal places, into microseconds.
+fn parse_ms_buckets(s: &str) -> Result<Vec<u64>, ParseError> {
+    let mut buckets = Vec::new();
+    for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        let mut parts = item.splitn(2, '.');
+        let ms = parse_number::<u64>(parts.next().unwrap_or(""))?;
+        let us = match parts.next() {
+            None => 0,
+            Some(frac) if !frac.is_empty() && frac.len() <= 3 => {
+                let frac = format!("{:0<3}", frac);
+                parse_number::<u64>(&frac)?
+            }
+            Some(_) => {
+                error!(
+                    "Expected milliseconds with at most 3 decimal places: {}",
+                    item
+                );
+                return Err(ParseError::NotANumber);
+            }
+        };
+        let bucket = ms
+            .checked_mul(1_000)
+            .and_then(|ms| ms.checked_add(us))
+            .ok_or(ParseError::NotANumber)?;
+        buckets.push(bucket);
+    }
+    Ok(buckets)
+}
+
+fn parse_us_buckets(s: &str) -> Result<Bounds, ParseError> {
+    let mut buckets = Vec::new();
+    for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        buckets.push(Bucket::Le(parse_number(item)?));
+    }
+    Bounds::new(buckets).map_err(|e| {
+        error!("{}: {}", s, e);
+        ParseError::InvalidHistogramBuckets
+    })
+}
+
+/// Builds bounds for a histogram reported in milliseconds from buckets in
+/// microseconds, or from the default buckets.
+fn latency_bounds(
+    env: &str,
+    resolution: latency::Resolution,
+    buckets_us: Option<Vec<u64>>,
+) -> Result<Bounds, Error> {
+    let buckets_us = match buckets_us {
+        Some(buckets) => buckets,
+        None => latency::BUCKETS
+            .iter()
+            .filter_map(|b| match *b {
+                Bucket::Le(ms) => Some(ms * 1_000),
+                Bucket::Inf => None,
+            })
+            .collect(),
+    };
+
+    resolution.ms_bounds(&buckets_us).map_err(|e| {
+        match e {
+            latency::InvalidResolution::SubMillisecond => error!(
+                "{} is invalid: {}; set {}=us",
+                env, e, ENV_METRICS_LATENCY_RESOLUTION
+            ),
+            latency::InvalidResolution::Bounds(_) => error!("{} is invalid: {}", env, e),
+        }
+        Error::InvalidEnvVar
+    })
+}
+
+fn parse_tcp_destinations(s: &str) -> Result<IndexMap<SocketAddr, NameAddr>, ParseError> {
+    let mut dsts = IndexMap::new();
+    for item in s.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let mut parts = item.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(addr), Some(name)) => {
+                let addr = parse_socket_addr(addr.trim())?;
+                let name = NameAddr::from_str(name.trim()).map_err(|e| {
+                    error!("Not a valid name: {}", name);
+                    ParseError::AddrError(e)
+                })?;
+                dsts.insert(addr, name);
+            }
+            _ => {
+                error!("Expected IP:PORT=NAME:PORT; found: {}", item);
+                return Err(ParseError::NotATcpDestination);
+            }
+        }
+    }
+    Ok(dsts)
+}
+
+fn parse_health_checks(s: &str) -> Result<IndexMap<NameAddr, health_check::Check>, ParseError> {
+    let mut checks = IndexMap::new();
+    for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        let mut parts = item.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(check)) => {
+                let name = NameAddr::from_str(name.trim()).map_err(|e| {
+                    error!("Not a valid name: {}", name);
+                    ParseError::AddrError(e)
+                })?;
+                let check = check.trim().parse().map_err(|e| {
+                    error!("Not a valid health check: {}; {}", check, e);
+                    ParseError::NotAHealthCheck
+                })?;
+                checks.insert(name, check);
+            }
+            _ => {
+                error!("Expected NAME:PORT=CHECK; found: {}", item);
+                return Err(ParseError::NotAHealthCheck);
+            }
+        }
+    }
+    Ok(checks)
+}
+
 pub(super) fn parse_identity(s: &str) -> Result<identity::Name, ParseError> {
     identity::Name::from_hostname(s.as_bytes()).map_err(|identity::InvalidName| {
         error!("Not a valid identity name: {}", s);
@@ -889,6 +1868,9 @@ pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<identity:
     let ta = parse(strings, ENV_IDENTITY_TRUST_ANCHORS, |ref s| {
         identity::TrustAnchors::from_pem(s).ok_or(ParseError::InvalidTrustAnchors)
     });
+    let ta_file = parse(strings, ENV_IDENTITY_TRUST_ANCHORS_FILE, |ref s| {
+        Ok(PathBuf::from(s))
+    });
     let dir = parse(strings, ENV_IDENTITY_DIR, |ref s| Ok(PathBuf::from(s)));
     let tok = parse(strings, ENV_IDENTITY_TOKEN_FILE, |ref s| {
         identity::TokenSource::if_nonempty_file(s.to_string()).map_err(|e| {
@@ -899,16 +1881,109 @@ pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<identity:
     let li = parse(strings, ENV_IDENTITY_IDENTITY_LOCAL_NAME, parse_identity);
     let min_refresh = parse(strings, ENV_IDENTITY_MIN_REFRESH, parse_duration);
     let max_refresh = parse(strings, ENV_IDENTITY_MAX_REFRESH, parse_duration);
+    let crt = parse(strings, ENV_IDENTITY_CRT_FILE, |ref s| Ok(PathBuf::from(s)));
+    let key = parse(strings, ENV_IDENTITY_KEY_FILE, |ref s| Ok(PathBuf::from(s)));
+    let bundle = parse(strings, ENV_IDENTITY_BUNDLE_FILE, |ref s| {
+        Ok(PathBuf::from(s))
+    });
+    let poll_interval = parse(strings, ENV_IDENTITY_FILE_POLL_INTERVAL, parse_duration);
 
     let disabled = strings
         .get(ENV_IDENTITY_DISABLED)?
         .map(|d| !d.is_empty())
         .unwrap_or(false);
 
+    let poll_interval = poll_interval?.unwrap_or(DEFAULT_IDENTITY_FILE_POLL_INTERVAL);
+    let (ta, trust_anchors_file) = match (ta?, ta_file?) {
+        (ta, None) => (ta, None),
+        (None, Some(path)) => {
+            let ta = fs::read_to_string(&path)
+                .map_err(|e| error!("Failed to read {}: {}", path.display(), e))
+                .ok()
+                .and_then(|s| identity::TrustAnchors::from_pem(&s))
+                .ok_or_else(|| {
+                    error!("No valid trust anchors in {}", path.display());
+                    Error::InvalidEnvVar
+                })?;
+            let file = identity::TrustAnchorsFile {
+                path,
+                poll_interval,
+            };
+            (Some(ta), Some(file))
+        }
+        (Some(_), Some(_)) => {
+            error!(
+                "{} and {} must not both be set.",
+                ENV_IDENTITY_TRUST_ANCHORS, ENV_IDENTITY_TRUST_ANCHORS_FILE
+            );
+            return Err(Error::InvalidEnvVar);
+        }
+    };
+
+    let (crt, key, bundle) = (crt?, key?, bundle?);
+    if crt.is_some() || key.is_some() || bundle.is_some() {
+        return match (disabled, ta, li?, crt, key, sa?, dir?, tok?) {
+            (
+                false,
+                Some(trust_anchors),
+                Some(local_name),
+                Some(crt),
+                Some(key),
+                None,
+                None,
+                None,
+            ) => Ok(Some(identity::Config::Files(identity::FilesConfig {
+                trust_anchors,
+                local_name,
+                crt,
+                key,
+                bundle,
+                poll_interval,
+                trust_anchors_file,
+            }))),
+            (disabled, trust_anchors, local_id, crt, key, svc, end_entity_dir, token) => {
+                if disabled {
+                    error!(
+                        "{} must be unset when other identity variables are set.",
+                        ENV_IDENTITY_DISABLED,
+                    );
+                }
+                for (unset, name) in &[
+                    (trust_anchors.is_none(), ENV_IDENTITY_TRUST_ANCHORS),
+                    (local_id.is_none(), ENV_IDENTITY_IDENTITY_LOCAL_NAME),
+                    (crt.is_none(), ENV_IDENTITY_CRT_FILE),
+                    (key.is_none(), ENV_IDENTITY_KEY_FILE),
+                ] {
+                    if *unset {
+                        error!(
+                            "{} must be set when other identity variables are set.",
+                            name,
+                        );
+                    }
+                }
+                let s = format!("{0}_ADDR and {0}_NAME", ENV_IDENTITY_SVC_BASE);
+                let svc_env: &str = &s.as_str();
+                for (set, name) in &[
+                    (svc.is_some(), svc_env),
+                    (end_entity_dir.is_some(), ENV_IDENTITY_DIR),
+                    (token.is_some(), ENV_IDENTITY_TOKEN_FILE),
+                ] {
+                    if *set {
+                        error!(
+                            "{} must be unset when {} is set.",
+                            name, ENV_IDENTITY_CRT_FILE,
+                        );
+                    }
+                }
+                Err(Error::InvalidEnvVar)
+            }
+        };
+    }
+
     match (
         disabled,
         sa?,
-        ta?,
+        ta,
         dir?,
         li?,
         tok?,
@@ -972,7 +2047,7 @@ pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<identity:
                     })
             };
 
-            Ok(Some(identity::Config {
+            Ok(Some(identity::Config::Service(identity::ServiceConfig {
                 svc,
                 local_name,
                 token,
@@ -981,7 +2056,8 @@ pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<identity:
                 key: key?,
                 min_refresh: min_refresh.unwrap_or(DEFAULT_IDENTITY_MIN_REFRESH),
                 max_refresh: max_refresh.unwrap_or(DEFAULT_IDENTITY_MAX_REFRESH),
-            }))
+                trust_anchors_file,
+            })))
         }
         (disabled, svc, trust_anchors, end_entity_dir, local_id, token, _minr, _maxr) => {
             if disabled {
@@ -1097,6 +2173,354 @@ mod tests {
         assert_eq!(parse_duration("1"), Err(ParseError::NotADuration));
     }
 
+    #[test]
+    fn rate_limit_keys() {
+        assert_eq!(parse_rate_limit_key(""), Ok((false, false)));
+        assert_eq!(parse_rate_limit_key("route"), Ok((true, false)));
+        assert_eq!(parse_rate_limit_key("client_id"), Ok((false, true)));
+        assert_eq!(parse_rate_limit_key(" client_id, route "), Ok((true, true)));
+        assert_eq!(
+            parse_rate_limit_key("route,path"),
+            Err(ParseError::NotARateLimitKey)
+        );
+    }
+
+    #[test]
+    fn adaptive_concurrency_bounds() {
+        assert!(adaptive_concurrency_config(Some(5), None)
+            .unwrap()
+            .is_none());
+        let config = adaptive_concurrency_config(None, Some(4)).unwrap().unwrap();
+        assert_eq!((config.min_limit, config.max_limit), (4, 4));
+        let config = adaptive_concurrency_config(None, Some(100))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (config.min_limit, config.max_limit),
+            (DEFAULT_ADAPTIVE_CONCURRENCY_MIN_LIMIT, 100)
+        );
+        assert!(adaptive_concurrency_config(Some(0), Some(100)).is_err());
+        assert!(adaptive_concurrency_config(Some(101), Some(100)).is_err());
+    }
+
+    #[test]
+    fn parse_hash_keys() {
+        use consistent_hash::HashKey;
+        assert_eq!(parse_hash_key("source-ip"), Ok(HashKey::SourceIp));
+        assert_eq!(
+            parse_hash_key("header:x-user-id"),
+            Ok(HashKey::Header(http::header::HeaderName::from_static(
+                "x-user-id"
+            )))
+        );
+        assert_eq!(
+            parse_hash_key("cookie:session"),
+            Ok(HashKey::Cookie("session".into()))
+        );
+        assert_eq!(parse_hash_key("cookie:"), Err(ParseError::NotAHashKey));
+        assert_eq!(
+            parse_hash_key("header:bad header"),
+            Err(ParseError::NotAHashKey)
+        );
+        assert_eq!(parse_hash_key("path"), Err(ParseError::NotAHashKey));
+    }
+
+    #[test]
+    fn parse_health_check_destinations() {
+        use health_check::Check;
+        let checks = parse_health_checks(
+            "web.ns.svc.cluster.local:8080=http:/healthz, api.ns.svc.cluster.local:9090=grpc",
+        )
+        .unwrap();
+        let web = NameAddr::from_str("web.ns.svc.cluster.local:8080").unwrap();
+        let api = NameAddr::from_str("api.ns.svc.cluster.local:9090").unwrap();
+        assert_eq!(
+            checks.get(&web),
+            Some(&Check::Http(http::uri::PathAndQuery::from_static(
+                "/healthz"
+            )))
+        );
+        assert_eq!(checks.get(&api), Some(&Check::Grpc(String::new())));
+
+        assert_eq!(
+            parse_health_checks("web.ns.svc.cluster.local:8080=tcp"),
+            Err(ParseError::NotAHealthCheck)
+        );
+        assert_eq!(
+            parse_health_checks("web.ns.svc.cluster.local:8080"),
+            Err(ParseError::NotAHealthCheck)
+        );
+    }
+
+    #[test]
+    fn locality_requires_zone_or_region() {
+        assert!(locality_config(None, None, Some(3)).unwrap().is_none());
+        let config = locality_config(Some("a".into()), None, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.zone, Some("a".into()));
+        assert_eq!(
+            config.min_endpoints,
+            DEFAULT_OUTBOUND_LOCALITY_MIN_ENDPOINTS
+        );
+        assert!(locality_config(None, Some("r".into()), Some(0)).is_err());
+    }
+
+    #[test]
+    fn parse_percent_bounds() {
+        assert_eq!(parse_percent("0"), Ok(0));
+        assert_eq!(parse_percent("100"), Ok(100));
+        assert_eq!(parse_percent("101"), Err(ParseError::NotAPercentage));
+        assert_eq!(parse_percent("-1"), Err(ParseError::NotANumber));
+    }
+
+    #[test]
+    fn ms_buckets() {
+        assert_eq!(
+            parse_ms_buckets("1, 2.5,10"),
+            Ok(vec![1_000, 2_500, 10_000])
+        );
+        assert_eq!(parse_ms_buckets("0.001,0.25"), Ok(vec![1, 250]));
+        assert_eq!(parse_ms_buckets(""), Ok(vec![]));
+        assert_eq!(parse_ms_buckets("0.0001"), Err(ParseError::NotANumber));
+        assert_eq!(parse_ms_buckets("1."), Err(ParseError::NotANumber));
+        assert_eq!(parse_ms_buckets("ms"), Err(ParseError::NotANumber));
+    }
+
+    #[test]
+    fn latency_bounds_require_us_resolution_for_sub_ms_buckets() {
+        let env = ENV_METRICS_RESPONSE_LATENCY_BUCKETS;
+        assert!(latency_bounds(env, latency::Resolution::Ms, Some(vec![500, 1_000])).is_err());
+        assert!(latency_bounds(env, latency::Resolution::Ms, Some(vec![2_000, 1_000])).is_err());
+
+        let bounds = latency_bounds(env, latency::Resolution::Us, Some(vec![500, 1_000])).unwrap();
+        assert_eq!(
+            bounds.buckets(),
+            &[Bucket::Le(500), Bucket::Le(1_000), Bucket::Inf][..]
+        );
+
+        let bounds = latency_bounds(env, latency::Resolution::Ms, None).unwrap();
+        assert_eq!(bounds.buckets(), latency::BUCKETS);
+    }
+
+    #[test]
+    fn tcp_destinations() {
+        fn p(s: &str) -> Result<Vec<(String, String)>, ParseError> {
+            let dsts = parse_tcp_destinations(s)?
+                .into_iter()
+                .map(|(a, n)| (a.to_string(), n.to_string()))
+                .collect();
+
+            Ok(dsts)
+        }
+
+        assert_eq!(p(""), Ok(vec![]), "empty string");
+        assert_eq!(p(",,"), Ok(vec![]), "empty list components are ignored");
+        assert_eq!(
+            p("10.1.1.1:6379=redis.ns.svc.cluster.local:6379"),
+            Ok(vec![(
+                "10.1.1.1:6379".to_owned(),
+                "redis.ns.svc.cluster.local:6379".to_owned()
+            )]),
+            "a single mapping"
+        );
+        assert_eq!(
+            p(" 10.1.1.1:6379 = redis.ns:6379 , 10.1.1.2:5432=pg.ns:5432 "),
+            Ok(vec![
+                ("10.1.1.1:6379".to_owned(), "redis.ns:6379".to_owned()),
+                ("10.1.1.2:5432".to_owned(), "pg.ns:5432".to_owned()),
+            ]),
+            "whitespace is ignored"
+        );
+        assert_eq!(
+            p("10.1.1.1:6379"),
+            Err(ParseError::NotATcpDestination),
+            "a name is required"
+        );
+        assert_eq!(
+            p("redis.ns:6379=redis.ns:6379"),
+            Err(ParseError::HostIsNotAnIpAddress),
+            "the original destination must be an IP address"
+        );
+    }
+
+    #[test]
+    fn identity_files() {
+        let mut env = TestEnv::new();
+        env.put(
+            ENV_IDENTITY_TRUST_ANCHORS,
+            include_str!("../../tests/support/data/ca1.pem").to_owned(),
+        );
+        env.put(
+            ENV_IDENTITY_IDENTITY_LOCAL_NAME,
+            "foo.ns1.serviceaccount.identity.linkerd.cluster.local".to_owned(),
+        );
+        env.put(ENV_IDENTITY_CRT_FILE, "/var/run/linkerd/crt.pem".to_owned());
+        env.put(ENV_IDENTITY_KEY_FILE, "/var/run/linkerd/key.pem".to_owned());
+
+        match parse_identity_config(&env) {
+            Ok(Some(identity::Config::Files(c))) => {
+                assert_eq!(c.crt, PathBuf::from("/var/run/linkerd/crt.pem"));
+                assert_eq!(c.key, PathBuf::from("/var/run/linkerd/key.pem"));
+                assert_eq!(c.bundle, None);
+                assert_eq!(c.poll_interval, DEFAULT_IDENTITY_FILE_POLL_INTERVAL);
+            }
+            c => panic!("unexpected identity config: {:?}", c),
+        }
+
+        env.put(ENV_IDENTITY_DIR, "/var/run/linkerd/identity".to_owned());
+        assert!(
+            parse_identity_config(&env).is_err(),
+            "the identity service may not be configured with identity files"
+        );
+
+        env.remove(ENV_IDENTITY_DIR);
+        env.remove(ENV_IDENTITY_KEY_FILE);
+        assert!(
+            parse_identity_config(&env).is_err(),
+            "a key file is required"
+        );
+    }
+
+    #[test]
+    fn identity_trust_anchors_file() {
+        let mut env = TestEnv::new();
+        env.put(
+            ENV_IDENTITY_TRUST_ANCHORS_FILE,
+            "tests/support/data/ca1.pem".to_owned(),
+        );
+        env.put(
+            ENV_IDENTITY_IDENTITY_LOCAL_NAME,
+            "foo.ns1.serviceaccount.identity.linkerd.cluster.local".to_owned(),
+        );
+        env.put(ENV_IDENTITY_CRT_FILE, "/var/run/linkerd/crt.pem".to_owned());
+        env.put(ENV_IDENTITY_KEY_FILE, "/var/run/linkerd/key.pem".to_owned());
+        env.put(ENV_IDENTITY_FILE_POLL_INTERVAL, "1s".to_owned());
+
+        match parse_identity_config(&env) {
+            Ok(Some(identity::Config::Files(c))) => {
+                assert_eq!(c.trust_anchors.anchors().len(), 1);
+                let file = c.trust_anchors_file.expect("trust anchors file");
+                assert_eq!(file.path, PathBuf::from("tests/support/data/ca1.pem"));
+                assert_eq!(file.poll_interval, Duration::from_secs(1));
+            }
+            c => panic!("unexpected identity config: {:?}", c),
+        }
+
+        env.put(
+            ENV_IDENTITY_TRUST_ANCHORS,
+            include_str!("../../tests/support/data/ca1.pem").to_owned(),
+        );
+        assert!(
+            parse_identity_config(&env).is_err(),
+            "trust anchors may not be configured twice"
+        );
+    }
+
+    #[test]
+    fn authz_policies() {
+        use authz::{Match, PortPolicy};
+
+        fn name(s: &str) -> identity::Name {
+            identity::Name::from_hostname(s.as_bytes()).unwrap()
+        }
+
+        let policy = parse_authz_policy(
+            " *=identity ; 8080=identity:foo.ns1.serviceaccount.identity.linkerd.cluster.local,\
+             *.ns2.serviceaccount.identity.linkerd.cluster.local; 9990=plaintext",
+        )
+        .expect("policy must be valid");
+        let mut ports = IndexMap::new();
+        ports.insert(
+            8080,
+            PortPolicy::RequireIdentity(vec![
+                Match::Exact(name(
+                    "foo.ns1.serviceaccount.identity.linkerd.cluster.local",
+                )),
+                Match::Suffix(
+                    parse_dns_suffix("ns2.serviceaccount.identity.linkerd.cluster.local").unwrap(),
+                ),
+            ]),
+        );
+        ports.insert(9990, PortPolicy::AllowPlaintext);
+        assert_eq!(
+            policy,
+            authz::Policy::new(PortPolicy::RequireIdentity(vec![]), ports)
+        );
+
+        assert_eq!(
+            parse_authz_policy("8080").unwrap_err(),
+            ParseError::NotAnAuthzPolicy
+        );
+        assert_eq!(
+            parse_authz_policy("8080=tls").unwrap_err(),
+            ParseError::NotAnAuthzPolicy
+        );
+        assert_eq!(
+            parse_authz_policy("http=plaintext").unwrap_err(),
+            ParseError::NotANumber
+        );
+    }
+
+    #[test]
+    fn route_authz_policies() {
+        let policy = parse_route_authz_policy(
+            "# billing may charge\n\
+             charge POST /charge billing.ns.serviceaccount.identity.linkerd.cluster.local\n\
+             \n\
+             admin * /admin/.* *.admin.serviceaccount.identity.linkerd.cluster.local,*\n",
+        )
+        .expect("policy must be valid");
+        let names = policy.rules().iter().map(|r| r.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["charge", "admin"]);
+
+        assert_eq!(
+            parse_route_authz_policy("charge POST /charge").unwrap_err(),
+            ParseError::NotARouteAuthzPolicy
+        );
+        assert_eq!(
+            parse_route_authz_policy("charge POST /charge( foo.ns").unwrap_err(),
+            ParseError::NotARouteAuthzPolicy
+        );
+        assert_eq!(
+            parse_route_authz_policy("charge P{ST /charge foo.ns").unwrap_err(),
+            ParseError::NotARouteAuthzPolicy
+        );
+    }
+
+    #[test]
+    fn jwt_claim_headers() {
+        let headers = parse_claim_headers(" sub=l5d-jwt-sub, scope = l5d-jwt-scope ,")
+            .expect("claim headers must be valid");
+        assert_eq!(
+            headers,
+            vec![
+                (
+                    "sub".to_owned(),
+                    http::header::HeaderName::from_static("l5d-jwt-sub")
+                ),
+                (
+                    "scope".to_owned(),
+                    http::header::HeaderName::from_static("l5d-jwt-scope")
+                ),
+            ]
+        );
+        assert!(parse_claim_headers(DEFAULT_INBOUND_JWT_CLAIM_HEADERS).is_ok());
+
+        assert_eq!(
+            parse_claim_headers("sub").unwrap_err(),
+            ParseError::NotAClaimHeader
+        );
+        assert_eq!(
+            parse_claim_headers("=l5d-jwt-sub").unwrap_err(),
+            ParseError::NotAClaimHeader
+        );
+        assert_eq!(
+            parse_claim_headers("sub=l5d jwt").unwrap_err(),
+            ParseError::NotAHeaderName
+        );
+    }
+
     #[test]
     fn dns_suffixes() {
         fn p(s: &str) -> Result<Vec<String>, ParseError> {
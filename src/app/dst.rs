@@ -1,7 +1,8 @@
-use super::classify;
+use super::{classify, metric_labels::RouteLabels};
 use crate::proxy::http::{
+    fault, header_rewrite, jwt,
     metrics::classify::{CanClassify, Classify, ClassifyEos, ClassifyResponse},
-    profiles, retry, settings, timeout,
+    profiles, rate_limit, retry, settings, timeout,
 };
 use crate::{Addr, NameAddr};
 use http;
@@ -63,6 +64,38 @@ impl timeout::HasTimeout for Route {
     }
 }
 
+impl header_rewrite::HasHeaderRewrite for Route {
+    fn header_rewrite(&self) -> Option<header_rewrite::Rewrite> {
+        self.route.header_rewrite().cloned()
+    }
+}
+
+impl fault::CanInjectFault for Route {
+    type Key = RouteLabels;
+
+    fn fault(&self) -> Option<fault::Fault> {
+        self.route.fault().cloned()
+    }
+
+    fn fault_key(&self) -> Self::Key {
+        self.clone().into()
+    }
+}
+
+impl rate_limit::CanRateLimit for Route {
+    type Key = RouteLabels;
+
+    fn rate_limit_key(&self) -> Self::Key {
+        self.clone().into()
+    }
+}
+
+impl jwt::CanRequireJwt for Route {
+    fn requires_jwt(&self) -> bool {
+        self.route.requires_jwt()
+    }
+}
+
 // === impl Retry ===
 
 impl retry::Retry for Retry {
@@ -3,7 +3,7 @@
 use crate::{proxy::http::HasH2Reason, svc, Error};
 use futures::{Future, Poll};
 use http::{header, Request, Response, StatusCode, Version};
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
 /// Layer to map HTTP service errors into appropriate `http::Response`s.
 pub fn layer() -> Layer {
@@ -108,8 +108,9 @@ where
 
 fn map_err_to_5xx(e: Error) -> StatusCode {
     use crate::app::outbound;
-    use crate::proxy::buffer;
     use crate::proxy::http::router::error as router;
+    use crate::proxy::http::{jwt, rate_limit, route_authz};
+    use crate::proxy::{authz, buffer};
     use tower::load_shed::error as shed;
 
     if let Some(ref c) = e.downcast_ref::<router::NoCapacity>() {
@@ -118,6 +119,9 @@ fn map_err_to_5xx(e: Error) -> StatusCode {
     } else if let Some(_) = e.downcast_ref::<shed::Overloaded>() {
         warn!("server overloaded, max-in-flight reached");
         http::StatusCode::SERVICE_UNAVAILABLE
+    } else if let Some(_) = e.downcast_ref::<rate_limit::RateLimited>() {
+        debug!("request rate limited");
+        http::StatusCode::TOO_MANY_REQUESTS
     } else if let Some(_) = e.downcast_ref::<buffer::Aborted>() {
         warn!("request aborted because it reached the configured dispatch deadline");
         http::StatusCode::SERVICE_UNAVAILABLE
@@ -127,9 +131,46 @@ fn map_err_to_5xx(e: Error) -> StatusCode {
     } else if let Some(err) = e.downcast_ref::<outbound::RequireIdentityError>() {
         error!("{}", err);
         http::StatusCode::FORBIDDEN
+    } else if let Some(err) = e.downcast_ref::<authz::Unauthorized>() {
+        info!("{}", err);
+        http::StatusCode::FORBIDDEN
+    } else if let Some(err) = e.downcast_ref::<route_authz::Unauthorized>() {
+        info!("{}", err);
+        http::StatusCode::FORBIDDEN
+    } else if let Some(err) = e.downcast_ref::<jwt::Unauthenticated>() {
+        debug!("{}", err);
+        http::StatusCode::UNAUTHORIZED
     } else {
         // we probably should have handled this before?
         error!("unexpected error: {}", e);
         http::StatusCode::BAD_GATEWAY
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::authz;
+    use crate::proxy::http::rate_limit;
+    use crate::transport::tls;
+    use crate::Conditional;
+
+    #[test]
+    fn rate_limited_requests_are_too_many_requests() {
+        let e = Error::from(rate_limit::RateLimited::for_test());
+        assert_eq!(map_err_to_5xx(e), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn unauthorized_requests_are_forbidden() {
+        let tls_peer = Conditional::None(tls::ReasonForNoPeerName::NotProvidedByRemote.into());
+        let e = Error::from(authz::Unauthorized::for_test(8080, tls_peer));
+        assert_eq!(map_err_to_5xx(e), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn unexpected_errors_are_bad_gateway() {
+        let e = Error::from("unexpected");
+        assert_eq!(map_err_to_5xx(e), StatusCode::BAD_GATEWAY);
+    }
+}
@@ -0,0 +1,122 @@
+//! Polls files on disk for changes to their modification times.
+//!
+//! The identity and static discovery daemons reload their files when a watch
+//! reports a change. A change is only acknowledged once its files have been
+//! loaded successfully, so a file that fails to load (e.g. because it was
+//! only partially written) is retried on each poll until it loads.
+
+use futures::{try_ready, Async, Future, Poll};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio_timer::{clock, Delay};
+
+pub use tokio_timer::Error;
+
+/// Watches a set of files, polling them every `poll_interval`.
+#[derive(Debug)]
+pub struct Watch {
+    paths: Vec<PathBuf>,
+    poll_interval: Duration,
+    loaded: Option<Modified>,
+    delay: Option<Delay>,
+}
+
+/// The modification time of each watched file, if it can be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Modified(Vec<Option<SystemTime>>);
+
+// === impl Watch ===
+
+impl Watch {
+    pub fn new(paths: Vec<PathBuf>, poll_interval: Duration) -> Self {
+        Self {
+            paths,
+            poll_interval,
+            loaded: None,
+            delay: None,
+        }
+    }
+
+    /// Returns the current modification times of the watched files.
+    pub fn modified(&self) -> Modified {
+        Modified(self.paths.iter().map(|p| modified(p)).collect())
+    }
+
+    /// Records that the files were loaded as of `modified`, so that they are
+    /// not reported again until they change.
+    pub fn loaded(&mut self, modified: Modified) {
+        self.loaded = Some(modified);
+    }
+
+    /// Forgets the last load, so that the files are reported on the next
+    /// poll without waiting for the poll interval.
+    pub fn reset(&mut self) {
+        self.loaded = None;
+        self.delay = None;
+    }
+
+    /// Polls until the files differ from those last loaded.
+    ///
+    /// Fails if the timer is unavailable, in which case the files can no
+    /// longer be watched.
+    pub fn poll(&mut self) -> Poll<Modified, Error> {
+        loop {
+            if let Some(ref mut d) = self.delay {
+                try_ready!(d.poll());
+            }
+            self.delay = Some(Delay::new(clock::now() + self.poll_interval));
+
+            let modified = self.modified();
+            if self.loaded.as_ref() != Some(&modified) {
+                return Ok(Async::Ready(modified));
+            }
+        }
+    }
+
+    /// Skips the remainder of the poll interval.
+    #[cfg(test)]
+    pub fn expire(&mut self) {
+        self.delay = None;
+    }
+}
+
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use tokio::runtime::current_thread::Runtime;
+
+    /// Polls the watch once, as if its poll interval had elapsed.
+    fn poll(rt: &mut Runtime, watch: &mut Watch) -> Option<Modified> {
+        watch.expire();
+        let poll = rt
+            .block_on(future::lazy(|| watch.poll()))
+            .expect("timer must be available");
+        match poll {
+            Async::Ready(modified) => Some(modified),
+            Async::NotReady => None,
+        }
+    }
+
+    #[test]
+    fn reports_changes_until_they_are_loaded() {
+        let mut rt = Runtime::new().unwrap();
+        let path = ::std::env::temp_dir().join("linkerd2-proxy-file-watch-missing");
+        let mut watch = Watch::new(vec![path], Duration::from_secs(60 * 60));
+
+        // A change that fails to load is reported again on the next poll.
+        assert!(poll(&mut rt, &mut watch).is_some());
+        let modified = poll(&mut rt, &mut watch).expect("unloaded change must be reported");
+
+        watch.loaded(modified);
+        assert!(poll(&mut rt, &mut watch).is_none());
+
+        watch.reset();
+        assert!(poll(&mut rt, &mut watch).is_some());
+    }
+}
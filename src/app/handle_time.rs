@@ -1,5 +1,5 @@
 use super::metric_labels::Direction;
-use crate::metrics::{FmtMetrics, Metric};
+use crate::metrics::{Bounds, FmtMetrics, Metric};
 use crate::proxy::http::metrics::handle_time;
 use std::{fmt, iter};
 
@@ -14,10 +14,10 @@ impl Metrics {
         "A histogram of the time in microseconds between when a request is received and when it is sent upstream.";
     pub const NAME: &'static str = "request_handle_us";
 
-    pub fn new() -> Self {
+    pub fn new(bounds: &Bounds) -> Self {
         Self {
-            inbound: handle_time::Scope::new(),
-            outbound: handle_time::Scope::new(),
+            inbound: handle_time::Scope::new(bounds),
+            outbound: handle_time::Scope::new(bounds),
         }
     }
 
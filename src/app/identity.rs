@@ -1,18 +1,39 @@
+use super::file_watch::{self, Watch};
 use crate::api::identity as api;
-pub use crate::identity::{Crt, CrtKey, Csr, InvalidName, Key, Name, TokenSource, TrustAnchors};
+pub use crate::identity::{
+    Crt, CrtKey, Csr, InvalidName, Key, Name, TokenSource, TrustAnchor, TrustAnchors,
+};
+use crate::metrics::{metrics, FmtLabels, FmtMetric, FmtMetrics, Gauge};
 use crate::transport::tls;
 use crate::Never;
 use futures::{try_ready, Async, Future, Poll};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fmt, fs};
 use tokio::sync::watch;
 use tokio_timer::{clock, Delay};
 use tower_grpc::{self as grpc, generic::client::GrpcService, BoxBody};
 use tracing::{debug, error, trace};
 
+metrics! {
+    identity_trust_anchor_expiry_timestamp_seconds: Gauge {
+        "Time at which a trust anchor expires (in seconds since the UNIX epoch)"
+    }
+}
+
+/// Configures the local identity and how its certificates are provisioned.
+#[derive(Clone, Debug)]
+pub enum Config {
+    /// Certificates are issued by the Identity service.
+    Service(ServiceConfig),
+    /// Certificates are written to disk by an external agent.
+    Files(FilesConfig),
+}
+
 /// Configures the Identity service and local identity.
 #[derive(Clone, Debug)]
-pub struct Config {
+pub struct ServiceConfig {
     pub svc: super::control::ControlAddr,
     pub trust_anchors: TrustAnchors,
     pub key: Key,
@@ -21,14 +42,38 @@ pub struct Config {
     pub local_name: Name,
     pub min_refresh: Duration,
     pub max_refresh: Duration,
+    pub trust_anchors_file: Option<TrustAnchorsFile>,
+}
+
+/// Configures a local identity whose PEM-encoded certificate and key are
+/// read from disk.
+#[derive(Clone, Debug)]
+pub struct FilesConfig {
+    pub trust_anchors: TrustAnchors,
+    pub local_name: Name,
+    pub crt: PathBuf,
+    pub key: PathBuf,
+    /// Intermediate certificates, if they are not included in `crt`.
+    pub bundle: Option<PathBuf>,
+    /// How often the files are checked for changes.
+    pub poll_interval: Duration,
+    pub trust_anchors_file: Option<TrustAnchorsFile>,
+}
+
+/// Configures a PEM-encoded trust anchors file that is reloaded as it changes.
+#[derive(Clone, Debug)]
+pub struct TrustAnchorsFile {
+    pub path: PathBuf,
+    pub poll_interval: Duration,
 }
 
 /// Holds the process's local TLS identity state.
 ///
-/// Updates dynamically as certificates are provisioned from the Identity service.
+/// Updates dynamically as certificates are provisioned from the Identity service
+/// or read from disk.
 #[derive(Clone, Debug)]
 pub struct Local {
-    trust_anchors: TrustAnchors,
+    trust_anchors: TrustAnchorsWatch,
     name: Name,
     crt_key: watch::Receiver<Option<CrtKey>>,
 }
@@ -42,15 +87,27 @@ pub struct LostDaemon;
 
 pub type CrtKeySender = watch::Sender<Option<CrtKey>>;
 
+pub type TrustAnchorsSender = watch::Sender<TrustAnchors>;
+
+/// Observes the trust anchors as they are rotated.
+#[derive(Clone, Debug)]
+pub struct TrustAnchorsWatch(watch::Receiver<TrustAnchors>);
+
+/// Reports the expiry of each trust anchor, labeled by its fingerprint.
+#[derive(Clone, Debug, Default)]
+pub struct Report(Option<TrustAnchorsWatch>);
+
 /// Drives updates.
 pub struct Daemon<T>
 where
     T: GrpcService<BoxBody>,
     T::ResponseBody: grpc::Body,
 {
-    config: Config,
+    config: ServiceConfig,
     client: api::client::Identity<T>,
     crt_key: watch::Sender<Option<CrtKey>>,
+    trust_anchors: TrustAnchorsWatch,
+    crt: Option<Crt>,
     expiry: SystemTime,
     inner: Inner<T>,
 }
@@ -65,9 +122,51 @@ where
     Pending(grpc::client::unary::ResponseFuture<api::CertifyResponse, T::Future, T::ResponseBody>),
 }
 
+/// Loads certificates from disk, reloading them as the files change.
+pub struct FileDaemon {
+    config: FilesConfig,
+    crt_key: CrtKeySender,
+    trust_anchors: TrustAnchorsWatch,
+    watch: Watch,
+}
+
+/// Loads trust anchors from disk, reloading them as the file changes.
+pub struct TrustAnchorsDaemon {
+    file: TrustAnchorsFile,
+    trust_anchors: TrustAnchorsSender,
+    watch: Watch,
+}
+
+struct Fingerprint<'a>(&'a str);
+
 // === impl Config ===
 
 impl Config {
+    pub fn local_name(&self) -> &Name {
+        match self {
+            Config::Service(c) => &c.local_name,
+            Config::Files(c) => &c.local_name,
+        }
+    }
+
+    pub fn trust_anchors(&self) -> &TrustAnchors {
+        match self {
+            Config::Service(c) => &c.trust_anchors,
+            Config::Files(c) => &c.trust_anchors,
+        }
+    }
+
+    pub fn trust_anchors_file(&self) -> Option<&TrustAnchorsFile> {
+        match self {
+            Config::Service(c) => c.trust_anchors_file.as_ref(),
+            Config::Files(c) => c.trust_anchors_file.as_ref(),
+        }
+    }
+}
+
+// === impl ServiceConfig ===
+
+impl ServiceConfig {
     /// Returns a future that fires when a refresh should occur.
     ///
     /// A refresh is scheduled at 70% of the current certificate's lifetime;
@@ -93,20 +192,31 @@ impl Config {
 // === impl Local ===
 
 impl Local {
-    pub fn new(config: &Config) -> (Self, CrtKeySender) {
+    pub fn new(config: &Config) -> (Self, CrtKeySender, TrustAnchorsSender) {
         let (s, w) = watch::channel(None);
+        let (ta_s, ta_w) = watch::channel(config.trust_anchors().clone());
         let l = Local {
-            name: config.local_name.clone(),
-            trust_anchors: config.trust_anchors.clone(),
+            name: config.local_name().clone(),
+            trust_anchors: TrustAnchorsWatch(ta_w),
             crt_key: w,
         };
-        (l, s)
+        (l, s, ta_s)
     }
 
     pub fn name(&self) -> &Name {
         &self.name
     }
 
+    pub fn trust_anchors(&self) -> TrustAnchorsWatch {
+        self.trust_anchors.clone()
+    }
+
+    /// Returns the expiry of the current certificate, if one has been
+    /// provisioned.
+    pub fn crt_expiry(&self) -> Option<SystemTime> {
+        self.crt_key.get_ref().as_ref().map(|c| c.expiry())
+    }
+
     pub fn await_crt(self) -> AwaitCrt {
         AwaitCrt(Some(self))
     }
@@ -118,7 +228,7 @@ impl tls::client::HasConfig for Local {
             return c.tls_client_config();
         }
 
-        self.trust_anchors.tls_client_config()
+        self.trust_anchors.get().tls_client_config()
     }
 }
 
@@ -142,10 +252,17 @@ impl<T> Daemon<T>
 where
     T: GrpcService<BoxBody> + Clone,
 {
-    pub fn new(config: Config, crt_key: CrtKeySender, client: T) -> Self {
+    pub fn new(
+        config: ServiceConfig,
+        crt_key: CrtKeySender,
+        trust_anchors: TrustAnchorsWatch,
+        client: T,
+    ) -> Self {
         Self {
             config,
             crt_key,
+            trust_anchors,
+            crt: None,
             inner: Inner::ShouldRefresh,
             expiry: UNIX_EPOCH,
             client: api::client::Identity::new(client),
@@ -161,6 +278,23 @@ where
     type Error = Never;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // When the trust anchors are rotated, the current certificate is
+        // validated against the new anchors so that new connections use them.
+        if self.trust_anchors.poll_changed() {
+            if let Some(crt) = self.crt.clone() {
+                let key = self.config.key.clone();
+                match self.trust_anchors.get().certify(key, crt) {
+                    Err(e) => error!("Certificate is not valid for new trust anchors: {}", e),
+                    Ok(crt_key) => {
+                        debug!("daemon recertified with new trust anchors");
+                        if self.crt_key.broadcast(Some(crt_key)).is_err() {
+                            return Ok(Async::Ready(()));
+                        }
+                    }
+                }
+            }
+        }
+
         loop {
             self.inner = match self.inner {
                 Inner::Waiting(ref mut d) => {
@@ -219,7 +353,7 @@ where
                                         expiry,
                                     );
 
-                                    match self.config.trust_anchors.certify(key, crt) {
+                                    match self.trust_anchors.get().certify(key, crt.clone()) {
                                         Err(e) => {
                                             error!("Received invalid ceritficate: {}", e);
                                         }
@@ -231,6 +365,7 @@ where
                                                 return Ok(Async::Ready(()));
                                             }
 
+                                            self.crt = Some(crt);
                                             self.expiry = expiry;
                                         }
                                     }
@@ -250,6 +385,222 @@ where
     }
 }
 
+// === impl FileDaemon ===
+
+impl FileDaemon {
+    pub fn new(
+        config: FilesConfig,
+        crt_key: CrtKeySender,
+        trust_anchors: TrustAnchorsWatch,
+    ) -> Self {
+        let paths = Some(config.crt.clone())
+            .into_iter()
+            .chain(Some(config.key.clone()))
+            .chain(config.bundle.clone())
+            .collect();
+        let watch = Watch::new(paths, config.poll_interval);
+        Self {
+            config,
+            crt_key,
+            trust_anchors,
+            watch,
+        }
+    }
+
+    fn load(&self) -> Option<CrtKey> {
+        let mut crt = read(&self.config.crt)?;
+        if let Some(ref bundle) = self.config.bundle {
+            crt.push(b'\n');
+            crt.extend(read(bundle)?);
+        }
+        let crt = match Crt::from_pem(self.config.local_name.clone(), &crt) {
+            Some(crt) => crt,
+            None => {
+                error!("No valid certificate in {}", self.config.crt.display());
+                return None;
+            }
+        };
+        let key = match Key::from_pem(&read(&self.config.key)?) {
+            Some(key) => key,
+            None => {
+                error!("No valid PKCS#8 key in {}", self.config.key.display());
+                return None;
+            }
+        };
+
+        let expiry = crt.expiry();
+        match self.trust_anchors.get().certify(key, crt) {
+            Ok(crt_key) => {
+                debug!("certified until {:?}", expiry);
+                Some(crt_key)
+            }
+            Err(e) => {
+                error!("Read invalid certificate: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl Future for FileDaemon {
+    type Item = ();
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // When the trust anchors are rotated, the certificate is reloaded so
+        // that it is validated against the new anchors.
+        if self.trust_anchors.poll_changed() {
+            self.watch.reset();
+        }
+
+        loop {
+            let modified = match self.watch.poll() {
+                Ok(Async::Ready(modified)) => modified,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => {
+                    error!("Identity files can no longer be watched: {}", e);
+                    return Ok(Async::Ready(()));
+                }
+            };
+
+            trace!("identity files changed");
+            if let Some(crt_key) = self.load() {
+                if self.crt_key.broadcast(Some(crt_key)).is_err() {
+                    // If we can't store a value, than all observations
+                    // have been dropped and we can stop reloading.
+                    return Ok(Async::Ready(()));
+                }
+                self.watch.loaded(modified);
+            }
+        }
+    }
+}
+
+// === impl TrustAnchorsDaemon ===
+
+impl TrustAnchorsDaemon {
+    pub fn new(file: TrustAnchorsFile, trust_anchors: TrustAnchorsSender) -> Self {
+        // The file was read as the proxy was configured.
+        let mut watch = Watch::new(vec![file.path.clone()], file.poll_interval);
+        let modified = watch.modified();
+        watch.loaded(modified);
+        Self {
+            file,
+            trust_anchors,
+            watch,
+        }
+    }
+
+    fn load(&self) -> Option<TrustAnchors> {
+        let pem = read(&self.file.path)?;
+        let anchors = ::std::str::from_utf8(&pem)
+            .ok()
+            .and_then(TrustAnchors::from_pem);
+        if anchors.is_none() {
+            error!("No valid trust anchors in {}", self.file.path.display());
+        }
+        anchors
+    }
+}
+
+impl Future for TrustAnchorsDaemon {
+    type Item = ();
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let modified = match self.watch.poll() {
+                Ok(Async::Ready(modified)) => modified,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => {
+                    error!("Trust anchors file can no longer be watched: {}", e);
+                    return Ok(Async::Ready(()));
+                }
+            };
+
+            trace!("trust anchors file changed");
+            if let Some(anchors) = self.load() {
+                debug!("loaded {} trust anchors", anchors.anchors().len());
+                if self.trust_anchors.broadcast(anchors).is_err() {
+                    return Ok(Async::Ready(()));
+                }
+                self.watch.loaded(modified);
+            }
+        }
+    }
+}
+
+// === impl TrustAnchorsWatch ===
+
+impl TrustAnchorsWatch {
+    pub fn get(&self) -> TrustAnchors {
+        self.0.get_ref().clone()
+    }
+
+    /// Returns true if the trust anchors have changed since this was last
+    /// polled.
+    fn poll_changed(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(Async::Ready(Some(_))) = self.0.poll_ref() {
+            changed = true;
+        }
+        changed
+    }
+}
+
+impl tls::client::HasConfig for TrustAnchorsWatch {
+    fn tls_client_config(&self) -> Arc<tls::client::Config> {
+        self.0.get_ref().tls_client_config()
+    }
+}
+
+// === impl Report ===
+
+impl Report {
+    pub fn new(trust_anchors: Option<TrustAnchorsWatch>) -> Self {
+        Report(trust_anchors)
+    }
+}
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let trust_anchors = match self.0 {
+            Some(ref ta) => ta.get(),
+            None => return Ok(()),
+        };
+
+        identity_trust_anchor_expiry_timestamp_seconds.fmt_help(f)?;
+        for anchor in trust_anchors.anchors() {
+            let expiry = match anchor
+                .expiry()
+                .and_then(|e| e.duration_since(UNIX_EPOCH).ok())
+            {
+                Some(expiry) => expiry,
+                None => continue,
+            };
+            Gauge::from(expiry.as_secs()).fmt_metric_labeled(
+                f,
+                identity_trust_anchor_expiry_timestamp_seconds.name,
+                Fingerprint(anchor.fingerprint()),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> FmtLabels for Fingerprint<'a> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fingerprint=\"{}\"", self.0)
+    }
+}
+
+fn read(path: &Path) -> Option<Vec<u8>> {
+    fs::read(path)
+        .map_err(|e| error!("Failed to read {}: {}", path.display(), e))
+        .ok()
+}
+
 // === impl AwaitCrt ===
 
 impl Future for AwaitCrt {
@@ -275,3 +626,329 @@ impl Future for AwaitCrt {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::runtime::current_thread::Runtime;
+
+    const FOO_NS1: &str = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
+    const POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    fn testdata(name: &str) -> Vec<u8> {
+        let path = Path::new("lib/linkerd2-identity/src/testdata").join(name);
+        fs::read(&path).unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e))
+    }
+
+    fn trust_anchors(name: &str) -> TrustAnchors {
+        let pem = String::from_utf8(testdata(name)).expect("utf-8");
+        TrustAnchors::from_pem(&pem).expect("trust anchors must be valid")
+    }
+
+    /// A directory of identity files that is removed when dropped.
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new() -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let dir = ::std::env::temp_dir().join(format!(
+                "linkerd2-proxy-identity-{}-{}",
+                ::std::process::id(),
+                NEXT.fetch_add(1, Ordering::SeqCst)
+            ));
+            fs::create_dir_all(&dir).expect("must create temporary directory");
+            Dir(dir)
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+
+        /// Writes a file, ensuring that its modification time changes.
+        fn write(&self, name: &str, contents: &[u8]) {
+            let path = self.path(name);
+            let prior = file_watch::modified(&path);
+            loop {
+                fs::write(&path, contents).expect("must write file");
+                if file_watch::modified(&path) != prior {
+                    return;
+                }
+                ::std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn files_config(dir: &Dir, bundle: Option<&str>) -> FilesConfig {
+        FilesConfig {
+            trust_anchors: trust_anchors("ca1.pem"),
+            local_name: Name::from_hostname(FOO_NS1.as_bytes()).unwrap(),
+            crt: dir.path("crt.pem"),
+            key: dir.path("key.pem"),
+            bundle: bundle.map(|b| dir.path(b)),
+            poll_interval: POLL_INTERVAL,
+            trust_anchors_file: None,
+        }
+    }
+
+    /// Polls a daemon once, as if its poll interval had elapsed.
+    fn poll_file_daemon(rt: &mut Runtime, daemon: &mut FileDaemon) {
+        daemon.watch.expire();
+        let poll = rt
+            .block_on(future::lazy(|| daemon.poll()))
+            .expect("daemon must not fail");
+        assert!(poll.is_not_ready(), "daemon must not complete");
+    }
+
+    /// Returns true if a new certificate has been published since this was
+    /// last called.
+    fn updated(rt: &mut Runtime, local: &mut Local) -> bool {
+        rt.block_on(future::lazy(|| {
+            let mut updated = false;
+            while let Ok(Async::Ready(Some(_))) = local.crt_key.poll_ref() {
+                updated = true;
+            }
+            Ok::<_, ()>(updated)
+        }))
+        .unwrap()
+    }
+
+    fn file_daemon(config: FilesConfig) -> (Local, FileDaemon, TrustAnchorsSender) {
+        let (local, crt_key, ta) = Local::new(&Config::Files(config.clone()));
+        let daemon = FileDaemon::new(config, crt_key, local.trust_anchors());
+        (local, daemon, ta)
+    }
+
+    #[test]
+    fn reloads_files_as_they_change() {
+        let mut rt = Runtime::new().unwrap();
+        let dir = Dir::new();
+        dir.write("crt.pem", &testdata("foo-ns1-ca1/crt.pem"));
+        dir.write("key.pem", &testdata("foo-ns1-ca1/key.pem"));
+        let (mut local, mut daemon, _ta) = file_daemon(files_config(&dir, None));
+
+        poll_file_daemon(&mut rt, &mut daemon);
+        assert!(updated(&mut rt, &mut local), "files must be loaded");
+        assert!(local.crt_expiry().is_some());
+
+        // Files are only reloaded once they are modified.
+        poll_file_daemon(&mut rt, &mut daemon);
+        assert!(
+            !updated(&mut rt, &mut local),
+            "unmodified files were reloaded"
+        );
+
+        dir.write("crt.pem", &testdata("foo-ns1-ca1/crt.pem"));
+        poll_file_daemon(&mut rt, &mut daemon);
+        assert!(
+            updated(&mut rt, &mut local),
+            "modified files must be reloaded"
+        );
+    }
+
+    #[test]
+    fn ignores_keys_that_do_not_match_the_certificate() {
+        let mut rt = Runtime::new().unwrap();
+        let dir = Dir::new();
+        dir.write("crt.pem", &testdata("foo-ns1-ca1/crt.pem"));
+        dir.write("key.pem", &testdata("bar-ns1-ca1/key.pem"));
+        let (mut local, mut daemon, _ta) = file_daemon(files_config(&dir, None));
+
+        poll_file_daemon(&mut rt, &mut daemon);
+        assert!(!updated(&mut rt, &mut local), "mismatched key was loaded");
+        assert!(local.crt_expiry().is_none());
+
+        dir.write("key.pem", &testdata("foo-ns1-ca1/key.pem"));
+        poll_file_daemon(&mut rt, &mut daemon);
+        assert!(updated(&mut rt, &mut local), "matching key must be loaded");
+        assert!(local.crt_expiry().is_some());
+    }
+
+    #[test]
+    fn ignores_certificates_for_other_identities() {
+        let mut rt = Runtime::new().unwrap();
+        let dir = Dir::new();
+        dir.write("crt.pem", &testdata("bar-ns1-ca1/crt.pem"));
+        dir.write("key.pem", &testdata("bar-ns1-ca1/key.pem"));
+        let (mut local, mut daemon, _ta) = file_daemon(files_config(&dir, None));
+
+        poll_file_daemon(&mut rt, &mut daemon);
+        assert!(
+            !updated(&mut rt, &mut local),
+            "certificate for bar was loaded"
+        );
+        assert!(local.crt_expiry().is_none());
+    }
+
+    #[test]
+    fn waits_for_a_missing_bundle() {
+        let mut rt = Runtime::new().unwrap();
+        let dir = Dir::new();
+        dir.write("crt.pem", &testdata("foo-ns1-ca1/crt.pem"));
+        dir.write("key.pem", &testdata("foo-ns1-ca1/key.pem"));
+        let config = files_config(&dir, Some("bundle.pem"));
+        let (mut local, mut daemon, _ta) = file_daemon(config);
+
+        poll_file_daemon(&mut rt, &mut daemon);
+        assert!(!updated(&mut rt, &mut local), "loaded without a bundle");
+        assert!(local.crt_expiry().is_none());
+
+        // The bundle's appearance is detected as a modification.
+        dir.write("bundle.pem", &testdata("ca1.pem"));
+        poll_file_daemon(&mut rt, &mut daemon);
+        assert!(updated(&mut rt, &mut local), "bundle must be loaded");
+        assert!(local.crt_expiry().is_some());
+    }
+
+    fn anchors_file(dir: &Dir) -> TrustAnchorsFile {
+        TrustAnchorsFile {
+            path: dir.path("anchors.pem"),
+            poll_interval: POLL_INTERVAL,
+        }
+    }
+
+    /// Polls a daemon once, as if its poll interval had elapsed.
+    fn poll_anchors_daemon(rt: &mut Runtime, daemon: &mut TrustAnchorsDaemon) {
+        daemon.watch.expire();
+        let poll = rt
+            .block_on(future::lazy(|| daemon.poll()))
+            .expect("daemon must not fail");
+        assert!(poll.is_not_ready(), "daemon must not complete");
+    }
+
+    fn anchors_changed(rt: &mut Runtime, watch: &mut TrustAnchorsWatch) -> bool {
+        rt.block_on(future::lazy(|| Ok::<_, ()>(watch.poll_changed())))
+            .unwrap()
+    }
+
+    #[test]
+    fn reloads_trust_anchors_as_they_change() {
+        let mut rt = Runtime::new().unwrap();
+        let dir = Dir::new();
+        dir.write("anchors.pem", &testdata("ca1.pem"));
+        let (tx, rx) = watch::channel(trust_anchors("ca1.pem"));
+        let mut anchors = TrustAnchorsWatch(rx);
+        let mut daemon = TrustAnchorsDaemon::new(anchors_file(&dir), tx);
+
+        // The file was read at startup, so it's not reloaded until it changes.
+        poll_anchors_daemon(&mut rt, &mut daemon);
+        assert!(!anchors_changed(&mut rt, &mut anchors));
+
+        dir.write("anchors.pem", &testdata("ca2.pem"));
+        poll_anchors_daemon(&mut rt, &mut daemon);
+        assert!(anchors_changed(&mut rt, &mut anchors));
+        assert_eq!(anchors.get().anchors(), trust_anchors("ca2.pem").anchors());
+
+        // Invalid anchors are ignored.
+        dir.write("anchors.pem", b"-----BEGIN CERTIFICATE-----\n");
+        poll_anchors_daemon(&mut rt, &mut daemon);
+        assert!(!anchors_changed(&mut rt, &mut anchors));
+        assert_eq!(anchors.get().anchors(), trust_anchors("ca2.pem").anchors());
+    }
+
+    #[test]
+    fn file_daemon_recertifies_with_new_trust_anchors() {
+        let mut rt = Runtime::new().unwrap();
+        let dir = Dir::new();
+        dir.write("crt.pem", &testdata("foo-ns1-ca2/crt.pem"));
+        dir.write("key.pem", &testdata("foo-ns1-ca2/key.pem"));
+        let (mut local, mut daemon, ta) = file_daemon(files_config(&dir, None));
+
+        poll_file_daemon(&mut rt, &mut daemon);
+        assert!(!updated(&mut rt, &mut local), "ca1 must not certify ca2");
+
+        // The unmodified files are reloaded when the anchors change, without
+        // waiting for the poll interval.
+        ta.broadcast(trust_anchors("ca2.pem")).unwrap();
+        let poll = rt
+            .block_on(future::lazy(|| daemon.poll()))
+            .expect("daemon must not fail");
+        assert!(poll.is_not_ready());
+        assert!(updated(&mut rt, &mut local), "ca2 must certify ca2");
+        assert!(local.crt_expiry().is_some());
+    }
+
+    #[test]
+    fn daemon_recertifies_with_new_trust_anchors() {
+        let mut rt = Runtime::new().unwrap();
+        let dir = Dir::new();
+        dir.write("token", b"token");
+
+        let key = Key::from_pem(&testdata("foo-ns1-ca2/key.pem")).unwrap();
+        let name = Name::from_hostname(FOO_NS1.as_bytes()).unwrap();
+        let config = ServiceConfig {
+            svc: super::super::control::ControlAddr {
+                addr: crate::Addr::from_str("identity.linkerd.svc.cluster.local:8080").unwrap(),
+                identity: crate::Conditional::None(tls::ReasonForNoIdentity::Disabled),
+            },
+            trust_anchors: trust_anchors("ca1.pem"),
+            key,
+            csr: Csr::from_der(b"csr".to_vec()).unwrap(),
+            token: TokenSource::if_nonempty_file(dir.path("token").display().to_string()).unwrap(),
+            local_name: name.clone(),
+            min_refresh: POLL_INTERVAL,
+            max_refresh: POLL_INTERVAL,
+            trust_anchors_file: None,
+        };
+        let (mut local, crt_key, ta) = Local::new(&Config::Service(config.clone()));
+
+        // The Identity service is never ready, so the certificate is only
+        // updated as the trust anchors change.
+        let client = crate::svc::mk(|_: http::Request<BoxBody>| {
+            future::empty::<http::Response<BoxBody>, Never>()
+        });
+        let mut daemon = Daemon::new(config, crt_key, local.trust_anchors(), client);
+        let crt = Crt::from_pem(name, &testdata("foo-ns1-ca2/crt.pem")).unwrap();
+        daemon.crt = Some(crt);
+        daemon.inner = Inner::Waiting(Delay::new(clock::now() + POLL_INTERVAL));
+
+        ta.broadcast(trust_anchors("ca2.pem")).unwrap();
+        let poll = rt
+            .block_on(future::lazy(|| daemon.poll()))
+            .expect("daemon must not fail");
+        assert!(poll.is_not_ready());
+        assert!(updated(&mut rt, &mut local), "ca2 must certify ca2");
+
+        ta.broadcast(trust_anchors("ca1.pem")).unwrap();
+        let poll = rt
+            .block_on(future::lazy(|| daemon.poll()))
+            .expect("daemon must not fail");
+        assert!(poll.is_not_ready());
+        assert!(!updated(&mut rt, &mut local), "ca1 must not certify ca2");
+    }
+
+    #[test]
+    fn reports_trust_anchor_expiry() {
+        let (tx, rx) = watch::channel(trust_anchors("ca1.pem"));
+        let report = Report::new(Some(TrustAnchorsWatch(rx)));
+        assert_eq!(
+            report.as_display().to_string(),
+            "# HELP identity_trust_anchor_expiry_timestamp_seconds Time at which a trust anchor \
+             expires (in seconds since the UNIX epoch)\n\
+             # TYPE identity_trust_anchor_expiry_timestamp_seconds gauge\n\
+             identity_trust_anchor_expiry_timestamp_seconds{fingerprint=\"\
+             3231ffde41be24df947187823f57536565709a81a008b9c3252954eeb79feffd\"} 1710266520\n",
+        );
+
+        // The report reflects rotated anchors.
+        tx.broadcast(trust_anchors("ca2.pem")).unwrap();
+        let text = report.as_display().to_string();
+        assert!(
+            text.contains(
+                "fingerprint=\"7e04754d83829b26220b22ff4db3318354c05b4bafbd96bc7efc19c460cd972e\"}"
+            ),
+            "{}",
+            text
+        );
+        assert!(!text.contains("3231ffde"), "{}", text);
+
+        assert_eq!(Report::default().as_display().to_string(), "");
+    }
+}
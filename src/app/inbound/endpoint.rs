@@ -1,6 +1,6 @@
 use super::super::dst::{DstAddr, Route};
 use super::super::{classify, identity};
-use crate::proxy::http::{router, settings};
+use crate::proxy::http::{router, settings, trace_context};
 use crate::proxy::server::Source;
 use crate::transport::{connect, tls};
 use crate::{tap, Conditional, NameAddr};
@@ -102,6 +102,19 @@ impl tap::Inspect for Endpoint {
     }
 }
 
+impl trace_context::SpanLabels for Endpoint {
+    fn span_labels(&self, labels: &mut trace_context::Labels) {
+        labels.insert("direction".into(), "inbound".into());
+        labels.insert("peer.addr".into(), self.addr.to_string());
+        if let Some(ref dst) = self.dst_name {
+            labels.insert("dst_logical".into(), dst.to_string());
+        }
+        if let Conditional::Some(ref id) = self.tls_client_id {
+            labels.insert("client_id".into(), id.as_ref().to_owned());
+        }
+    }
+}
+
 impl fmt::Display for Endpoint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.addr.fmt(f)
@@ -1,13 +1,14 @@
-use super::{classify, config::Config, dst::DstAddr, identity, DispatchDeadline};
+use super::metric_labels::{BalancerLabels, RouteLabels};
+use super::{classify, config::Config, dst::DstAddr, identity, spans, DispatchDeadline};
 use crate::proxy::http::{
-    client, insert, metrics as http_metrics, normalize_uri, profiles, router, settings,
-    strip_header,
+    adaptive_concurrency, client, header_rewrite, insert, jwt, metrics as http_metrics,
+    normalize_uri, profiles, rate_limit, route_authz, router, settings, strip_header,
+    trace_context,
 };
-use crate::proxy::{accept, reconnect, Server};
+use crate::proxy::{accept, authz, reconnect, server::ForwardConnect, Server};
 use crate::transport::{self, connect, keepalive, tls, Connection};
 use crate::{core::listen::ServeConnection, svc, Addr};
 use std::net::SocketAddr;
-use tower_grpc::{self as grpc, generic::client::GrpcService};
 use tracing::debug;
 
 mod endpoint;
@@ -20,22 +21,26 @@ mod set_remote_ip_on_req;
 
 pub use self::endpoint::{Endpoint, RecognizeEndpoint};
 
-pub fn server<P>(
+pub fn server<G>(
     config: &Config,
     local_identity: tls::Conditional<identity::Local>,
     local_addr: SocketAddr,
-    profiles_client: super::profiles::Client<P>,
+    profiles_client: G,
     tap_layer: crate::tap::Layer,
     handle_time: http_metrics::handle_time::Scope,
     endpoint_http_metrics: super::HttpEndpointMetricsRegistry,
     route_http_metrics: super::HttpRouteMetricsRegistry,
     transport_metrics: transport::metrics::Registry,
+    rate_limits: rate_limit::Registry<RouteLabels>,
+    authz_metrics: authz::Registry,
+    jwt_metrics: jwt::Registry,
+    concurrency_metrics: adaptive_concurrency::Registry<BalancerLabels>,
+    span_sink: Option<spans::SpanConverter>,
+    debug_state: super::admin::State,
 ) -> impl ServeConnection<Connection>
 where
-    P: GrpcService<grpc::BoxBody> + Clone + Send + Sync + 'static,
-    P::ResponseBody: Send,
-    <P::ResponseBody as grpc::Body>::Data: Send,
-    P::Future: Send,
+    G: profiles::GetRoutes + Clone + Send + Sync + 'static,
+    G::Stream: Send + 'static,
 {
     let capacity = config.inbound_router_capacity;
     let max_idle_age = config.inbound_router_max_idle_age;
@@ -75,6 +80,10 @@ where
         .layer(http_metrics::layer::<_, classify::Response>(
             endpoint_http_metrics,
         ))
+        .layer(trace_context::layer::<_, classify::Response>(
+            trace_context::Kind::Server,
+            span_sink,
+        ))
         .layer(tap_layer)
         .service(client_stack)
         .make();
@@ -82,11 +91,24 @@ where
     // A per-`dst::Route` layer that uses profile data to configure
     // a per-route layer.
     //
-    // The `classify` module installs a `classify::Response`
-    // extension into each request so that all lower metrics
-    // implementations can use the route-specific configuration.
+    // 1. Requests are rate limited by route and client identity, if
+    //    configured. Rejected requests fail before they are buffered or
+    //    recorded by route metrics.
+    // 2. Requests to routes that require a JWT are authenticated, if
+    //    configured.
+    // 3. Request and response headers are rewritten as configured by
+    //    the target `dst::Route`.
+    // 4. The `classify` module installs a `classify::Response`
+    //    extension into each request so that all lower metrics
+    //    implementations can use the route-specific configuration.
     let dst_route_stack = svc::builder()
+        .layer(rate_limit::layer(
+            config.inbound_rate_limit.clone(),
+            rate_limits,
+        ))
         .buffer_pending(max_in_flight, DispatchDeadline::extract)
+        .layer(jwt::layer(config.inbound_jwt.clone(), jwt_metrics))
+        .layer(header_rewrite::layer())
         .layer(classify::layer())
         .layer(http_metrics::layer::<_, classify::Response>(
             route_http_metrics,
@@ -97,14 +119,27 @@ where
     //
     // 1. Determines the profile of the destination and applies
     //    per-route policy.
-    // 2. Annotates the request with the `DstAddr` so that
+    // 2. Authorizes the request by its route and client identity.
+    // 3. Limits the concurrency of requests to each `DstAddr`, if
+    //    configured, adapting the limit to the observed latency.
+    // 4. Annotates the request with the `DstAddr` so that
     //    `RecognizeEndpoint` can use the value.
     let dst_stack = svc::builder()
         .layer(strip_header::request::layer(super::DST_OVERRIDE_HEADER))
-        .layer(profiles::router::layer(
-            profile_suffixes,
-            profiles_client,
-            dst_route_stack.into_inner(),
+        .layer(
+            profiles::router::layer(
+                profile_suffixes,
+                profiles_client,
+                dst_route_stack.into_inner(),
+            )
+            .with_active_routes(debug_state.active_routes()),
+        )
+        .layer(route_authz::layer(
+            config.inbound_route_authz_policy.clone(),
+        ))
+        .layer(adaptive_concurrency::layer(
+            config.inbound_adaptive_concurrency.clone(),
+            concurrency_metrics,
         ))
         .buffer_pending(max_in_flight, DispatchDeadline::extract)
         .layer(insert::target::layer())
@@ -162,6 +197,7 @@ where
         .buffer_pending(max_in_flight, DispatchDeadline::extract)
         .service(dst_stack)
         .make();
+    debug_state.register_router("in dst", dst_router.cache_keys());
 
     // Share a single semaphore across all requests to signal when
     // the proxy is overloaded.
@@ -173,12 +209,19 @@ where
     // As HTTP requests are accepted, the `Source` connection
     // metadata is stored on each request's extensions.
     //
+    // Requests on connections that are not authorized by the inbound
+    // policy fail with a 403.
+    //
     // Furthermore, HTTP/2 requests may be downgraded to HTTP/1.1 per
     // `orig-proto` headers. This happens in the source stack so that
     // the router need not detect whether a request _will be_ downgraded.
     let source_stack = svc::builder()
         .layer(handle_time.layer())
         .layer(super::errors::layer())
+        .layer(authz::layer(
+            config.inbound_authz_policy.clone(),
+            authz_metrics.clone(),
+        ))
         .layer(insert::layer(move || {
             DispatchDeadline::after(dispatch_timeout)
         }))
@@ -192,6 +235,14 @@ where
         //.push(set_client_id_on_req::layer())
         .service(svc::shared(admission_control));
 
+    // Unauthorized TCP connections are refused before they are forwarded.
+    let forward = svc::builder()
+        .layer(authz::connect_layer(
+            config.inbound_authz_policy.clone(),
+            authz_metrics,
+        ))
+        .service(ForwardConnect::<Endpoint, _>::new(connect));
+
     // As the inbound proxy accepts connections, we don't do any
     // special transport-level handling.
     let accept = accept::builder()
@@ -202,7 +253,7 @@ where
         "out",
         local_addr,
         accept,
-        connect,
+        forward,
         source_stack,
         config.h2_settings,
     )
@@ -1,10 +1,22 @@
 use super::admin::{self, Admin, Readiness};
 use super::classify::{self, Class};
-use super::metric_labels::{ControlLabels, EndpointLabels, RouteLabels};
+use super::metric_labels::{BalancerLabels, ControlLabels, EndpointLabels, RouteLabels};
 use super::profiles::Client as ProfilesClient;
-use super::{config::Config, identity};
-use super::{handle_time, inbound, outbound, tap::serve_tap};
-use crate::proxy::{self, http::metrics as http_metrics, reconnect};
+use super::static_discovery::{self, Discover};
+use super::{
+    config::{self, Config},
+    identity,
+};
+use super::{handle_time, inbound, outbound, spans, tap::serve_tap};
+use crate::opencensus::{self, proto::common as oc};
+use crate::proxy::{
+    self, authz,
+    http::{
+        adaptive_concurrency, fault, health_check, jwt, metrics as http_metrics, outlier,
+        rate_limit, route_authz, weight,
+    },
+    reconnect,
+};
 use crate::svc::{self, LayerExt};
 use crate::transport::{self, connect, keepalive, tls, GetOriginalDst, Listen};
 use crate::{dns, drain, logging, metrics::FmtMetrics, tap, task, telemetry, trace, Conditional};
@@ -34,7 +46,11 @@ pub struct Main<G> {
 
 struct ProxyParts<G> {
     config: Config,
-    identity: tls::Conditional<(identity::Local, identity::CrtKeySender)>,
+    identity: tls::Conditional<(
+        identity::Local,
+        identity::CrtKeySender,
+        identity::TrustAnchorsSender,
+    )>,
 
     start_time: SystemTime,
     trace_level: trace::LevelHandle,
@@ -62,7 +78,7 @@ where
         let start_time = SystemTime::now();
 
         let identity = config.identity_config.as_ref().map(identity::Local::new);
-        let local_identity = identity.as_ref().map(|(l, _)| l.clone());
+        let local_identity = identity.as_ref().map(|(l, _, _)| l.clone());
 
         let control_listener = config.control_listener.as_ref().map(|cl| {
             let listener = Listen::bind(cl.listener.addr, local_identity.clone())
@@ -173,9 +189,19 @@ where
             admin_listener,
         } = self;
 
-        info!("using destination service at {:?}", config.destination_addr);
+        match config.destination {
+            config::Destination::Service(ref addr) => {
+                info!("using destination service at {:?}", addr)
+            }
+            config::Destination::File(ref c) => info!("using destinations from {:?}", c.path),
+        }
         match config.identity_config.as_ref() {
-            Conditional::Some(config) => info!("using identity service at {:?}", config.svc.addr),
+            Conditional::Some(identity::Config::Service(config)) => {
+                info!("using identity service at {:?}", config.svc.addr)
+            }
+            Conditional::Some(identity::Config::Files(config)) => {
+                info!("using identity from {:?}", config.crt)
+            }
             Conditional::None(reason) => info!("identity is DISABLED: {}", reason),
         }
         info!("routing on {:?}", outbound_listener.local_addr());
@@ -204,62 +230,203 @@ where
             });
 
         let (ctl_http_metrics, ctl_http_report) = {
-            let (m, r) = http_metrics::new::<ControlLabels, Class>(config.metrics_retain_idle);
+            let (m, r) = http_metrics::new::<ControlLabels, Class>(
+                config.metrics_retain_idle,
+                config.response_latency_bounds.clone(),
+            );
             (m, r.with_prefix("control"))
         };
 
         let (endpoint_http_metrics, endpoint_http_report) =
-            http_metrics::new::<EndpointLabels, Class>(config.metrics_retain_idle);
+            http_metrics::new::<EndpointLabels, Class>(
+                config.metrics_retain_idle,
+                config.response_latency_bounds.clone(),
+            );
 
         let (route_http_metrics, route_http_report) = {
-            let (m, r) = http_metrics::new::<RouteLabels, Class>(config.metrics_retain_idle);
+            let (m, r) = http_metrics::new::<RouteLabels, Class>(
+                config.metrics_retain_idle,
+                config.response_latency_bounds.clone(),
+            );
             (m, r.with_prefix("route"))
         };
 
         let (retry_http_metrics, retry_http_report) = {
-            let (m, r) = http_metrics::new::<RouteLabels, Class>(config.metrics_retain_idle);
+            let (m, r) = http_metrics::new::<RouteLabels, Class>(
+                config.metrics_retain_idle,
+                config.response_latency_bounds.clone(),
+            );
             (m, r.with_prefix("route_actual"))
         };
 
-        let handle_time_report = handle_time::Metrics::new();
+        let (mirror_http_metrics, mirror_http_report) = {
+            let (m, r) = http_metrics::new::<RouteLabels, Class>(
+                config.metrics_retain_idle,
+                config.response_latency_bounds.clone(),
+            );
+            (m, r.with_prefix("route_mirror"))
+        };
+
+        let (mirror_retry_http_metrics, mirror_retry_http_report) = {
+            let (m, r) = http_metrics::new::<RouteLabels, Class>(
+                config.metrics_retain_idle,
+                config.response_latency_bounds.clone(),
+            );
+            (m, r.with_prefix("route_mirror_actual"))
+        };
+
+        let handle_time_report = handle_time::Metrics::new(&config.handle_time_bounds);
         let inbound_handle_time = handle_time_report.inbound();
         let outbound_handle_time = handle_time_report.outbound();
 
-        let (transport_metrics, transport_report) = transport::metrics::new();
+        let (transport_metrics, transport_report) =
+            transport::metrics::new(config.tcp_connection_duration_bounds.clone());
+
+        let (outlier_metrics, outlier_report) =
+            outlier::new::<BalancerLabels>(config.metrics_retain_idle);
+
+        let (weight_metrics, weight_report) =
+            weight::new::<EndpointLabels>(config.metrics_retain_idle);
+
+        let (health_check_metrics, health_check_report) =
+            health_check::new::<EndpointLabels>(config.metrics_retain_idle);
+
+        let (concurrency_metrics, concurrency_report) =
+            adaptive_concurrency::new::<BalancerLabels>(config.metrics_retain_idle);
+
+        let (rate_limits, rate_limit_report) =
+            rate_limit::new::<RouteLabels>(config.metrics_retain_idle);
+
+        let (fault_metrics, fault_report) = fault::new::<RouteLabels>(config.metrics_retain_idle);
+
+        let (authz_metrics, authz_report) = authz::new(config.metrics_retain_idle);
+        let route_authz_report =
+            route_authz::Report::new(config.inbound_route_authz_policy.clone());
+
+        let (jwt_metrics, jwt_report) = jwt::new();
+
+        let identity_report =
+            identity::Report::new(identity.value().map(|(l, _, _)| l.trust_anchors()));
 
         let report = endpoint_http_report
             .and_then(route_http_report)
             .and_then(retry_http_report)
+            .and_then(mirror_http_report)
+            .and_then(mirror_retry_http_report)
             .and_then(transport_report)
+            .and_then(outlier_report)
+            .and_then(weight_report)
+            .and_then(health_check_report)
+            .and_then(concurrency_report)
+            .and_then(rate_limit_report)
+            .and_then(fault_report)
+            .and_then(authz_report)
+            .and_then(route_authz_report)
+            .and_then(jwt_report)
             //.and_then(tls_config_report)
             .and_then(ctl_http_report)
             .and_then(handle_time_report)
+            .and_then(identity_report)
             .and_then(telemetry::process::Report::new(start_time));
 
         let mut identity_daemon = None;
+        let mut trust_anchors_daemon = None;
         let (readiness, ready_latch) = Readiness::new();
         let local_identity = match identity {
             Conditional::None(r) => {
                 ready_latch.release();
                 Conditional::None(r)
             }
-            Conditional::Some((local_identity, crt_store)) => {
+            Conditional::Some((local_identity, crt_store, trust_anchors_store)) => {
                 use super::control;
 
-                let id_config = match config.identity_config.as_ref() {
-                    Conditional::Some(c) => c.clone(),
+                let trust_anchors = local_identity.trust_anchors();
+                if let Some(file) = config
+                    .identity_config
+                    .value()
+                    .and_then(|c| c.trust_anchors_file())
+                {
+                    let daemon =
+                        identity::TrustAnchorsDaemon::new(file.clone(), trust_anchors_store);
+                    trust_anchors_daemon = Some(daemon);
+                }
+
+                let daemon = match config.identity_config.as_ref() {
+                    Conditional::Some(identity::Config::Service(id_config)) => {
+                        let id_config = id_config.clone();
+
+                        // If the service is on localhost, use the inbound keepalive.
+                        // If the service. is remote, use the outbound keepalive.
+                        let keepalive = if id_config.svc.addr.is_loopback() {
+                            config.inbound_connect_keepalive
+                        } else {
+                            config.outbound_connect_keepalive
+                        };
+
+                        let svc = svc::builder()
+                            .buffer_pending(
+                                config.destination_buffer_capacity,
+                                config.control_dispatch_timeout,
+                            )
+                            .layer(control::add_origin::layer())
+                            .layer(proxy::grpc::req_body_as_payload::layer().per_make())
+                            .layer(http_metrics::layer::<_, classify::Response>(
+                                ctl_http_metrics.clone(),
+                            ))
+                            .layer(reconnect::layer().with_backoff(config.control_backoff.clone()))
+                            .layer(control::resolve::layer(dns_resolver.clone()))
+                            .layer(control::client::layer())
+                            .timeout(config.control_connect_timeout)
+                            .layer(keepalive::connect::layer(keepalive))
+                            .layer(tls::client::layer(Conditional::Some(trust_anchors.clone())))
+                            .service(connect::svc())
+                            .make(id_config.svc.clone());
+
+                        future::Either::A(identity::Daemon::new(
+                            id_config,
+                            crt_store,
+                            trust_anchors,
+                            svc,
+                        ))
+                    }
+                    Conditional::Some(identity::Config::Files(c)) => future::Either::B(
+                        identity::FileDaemon::new(c.clone(), crt_store, trust_anchors),
+                    ),
                     Conditional::None(_) => unreachable!(),
                 };
+                identity_daemon = Some(daemon);
 
-                // If the service is on localhost, use the inbound keepalive.
-                // If the service. is remote, use the outbound keepalive.
-                let keepalive = if id_config.svc.addr.is_loopback() {
+                task::spawn(
+                    local_identity
+                        .clone()
+                        .await_crt()
+                        .map(move |id| {
+                            ready_latch.release();
+                            info!("Certified identity: {}", id.name().as_ref());
+                        })
+                        .map_err(|_| {
+                            // The daemon task was lost?!
+                            panic!("Failed to certify identity!");
+                        }),
+                );
+
+                Conditional::Some(local_identity)
+            }
+        };
+
+        let (resolver, profiles_client, discovery_daemon) = match config.destination {
+            config::Destination::Service(ref dst_addr) => {
+                use super::control;
+
+                // If the dst_svc is on localhost, use the inbound keepalive.
+                // If the dst_svc is remote, use the outbound keepalive.
+                let keepalive = if dst_addr.addr.is_loopback() {
                     config.inbound_connect_keepalive
                 } else {
                     config.outbound_connect_keepalive
                 };
 
-                let svc = svc::builder()
+                let dst_svc = svc::builder()
                     .buffer_pending(
                         config.destination_buffer_capacity,
                         config.control_dispatch_timeout,
@@ -274,73 +441,92 @@ where
                     .layer(control::client::layer())
                     .timeout(config.control_connect_timeout)
                     .layer(keepalive::connect::layer(keepalive))
-                    .layer(tls::client::layer(Conditional::Some(
-                        id_config.trust_anchors.clone(),
-                    )))
+                    .layer(tls::client::layer(local_identity.clone()))
                     .service(connect::svc())
-                    .make(id_config.svc.clone());
+                    .make(dst_addr.clone());
 
-                identity_daemon = Some(identity::Daemon::new(id_config, crt_store, svc));
+                let resolver = crate::resolve::Resolver::new(
+                    dst_svc.clone(),
+                    config.destination_get_suffixes.clone(),
+                    config.destination_context.clone(),
+                );
 
-                task::spawn(
-                    local_identity
-                        .clone()
-                        .await_crt()
-                        .map(move |id| {
-                            ready_latch.release();
-                            info!("Certified identity: {}", id.name().as_ref());
-                        })
-                        .map_err(|_| {
-                            // The daemon task was lost?!
-                            panic!("Failed to certify identity!");
-                        }),
+                let profiles_client = ProfilesClient::new(
+                    dst_svc,
+                    Duration::from_secs(3),
+                    config.destination_context.clone(),
                 );
 
-                Conditional::Some(local_identity)
+                (
+                    Discover::Control(resolver),
+                    Discover::Control(profiles_client),
+                    None,
+                )
             }
-        };
-
-        let dst_svc = {
-            use super::control;
-
-            // If the dst_svc is on localhost, use the inbound keepalive.
-            // If the dst_svc is remote, use the outbound keepalive.
-            let keepalive = if config.destination_addr.addr.is_loopback() {
-                config.inbound_connect_keepalive
-            } else {
-                config.outbound_connect_keepalive
-            };
-
-            svc::builder()
-                .buffer_pending(
-                    config.destination_buffer_capacity,
-                    config.control_dispatch_timeout,
+            config::Destination::File(ref c) => {
+                let (discovery, daemon) = static_discovery::new(c.clone());
+                (
+                    Discover::Static(discovery.clone()),
+                    Discover::Static(discovery),
+                    Some(daemon),
                 )
-                .layer(control::add_origin::layer())
-                .layer(proxy::grpc::req_body_as_payload::layer().per_make())
-                .layer(http_metrics::layer::<_, classify::Response>(
-                    ctl_http_metrics.clone(),
-                ))
-                .layer(reconnect::layer().with_backoff(config.control_backoff.clone()))
-                .layer(control::resolve::layer(dns_resolver.clone()))
-                .layer(control::client::layer())
-                .timeout(config.control_connect_timeout)
-                .layer(keepalive::connect::layer(keepalive))
-                .layer(tls::client::layer(local_identity.clone()))
-                .service(connect::svc())
-                .make(config.destination_addr.clone())
+            }
         };
 
-        let resolver = crate::resolve::Resolver::new(
-            dst_svc.clone(),
-            config.destination_get_suffixes.clone(),
-            config.destination_context.clone(),
-        );
+        let (span_sink, span_exporter) = match config.trace_collector_addr.as_ref() {
+            None => (None, None),
+            Some(addr) => {
+                use super::control;
+
+                info!("exporting spans to {:?}", addr.addr);
+
+                // If the collector is on localhost, use the inbound keepalive.
+                // If the collector is remote, use the outbound keepalive.
+                let keepalive = if addr.addr.is_loopback() {
+                    config.inbound_connect_keepalive
+                } else {
+                    config.outbound_connect_keepalive
+                };
+
+                let svc = svc::builder()
+                    .buffer_pending(
+                        config.destination_buffer_capacity,
+                        config.control_dispatch_timeout,
+                    )
+                    .layer(control::add_origin::layer())
+                    .layer(proxy::grpc::req_body_as_payload::layer().per_make())
+                    .layer(http_metrics::layer::<_, classify::Response>(
+                        ctl_http_metrics.clone(),
+                    ))
+                    .layer(reconnect::layer().with_backoff(config.control_backoff.clone()))
+                    .layer(control::resolve::layer(dns_resolver.clone()))
+                    .layer(control::client::layer())
+                    .timeout(config.control_connect_timeout)
+                    .layer(keepalive::connect::layer(keepalive))
+                    .layer(tls::client::layer(local_identity.clone()))
+                    .service(connect::svc())
+                    .make(addr.clone());
+
+                let node = oc::Node {
+                    service_info: Some(oc::ServiceInfo {
+                        name: config.trace_service_name.clone(),
+                    }),
+                    attributes: Default::default(),
+                };
+                let (sink, spans) = spans::channel();
+                let exporter =
+                    opencensus::SpanExporter::new(svc, node, Duration::from_secs(3), spans);
+                (Some(sink), Some(exporter))
+            }
+        };
 
         let (tap_layer, tap_grpc, tap_daemon) = tap::new();
 
+        let debug_state = admin::State::new(local_identity.value().cloned(), tap_grpc.sessions());
+
         // Spawn a separate thread to handle the admin stuff.
         {
+            let debug_state = debug_state.clone();
             let (tx, admin_shutdown_signal) = futures::sync::oneshot::channel::<()>();
             thread::Builder::new()
                 .name("admin".into())
@@ -353,7 +539,7 @@ where
                     rt.spawn(admin::serve_http(
                         "admin",
                         admin_listener,
-                        Admin::new(report, readiness, trace_level),
+                        Admin::new(report, readiness, trace_level, debug_state),
                     ));
 
                     if let Some((listener, tap_svc_name)) = control_listener {
@@ -371,6 +557,30 @@ where
                         );
                     }
 
+                    if let Some(d) = discovery_daemon {
+                        rt.spawn(
+                            logging::admin()
+                                .bg("static-discovery")
+                                .future(d.map_err(|_| error!("static discovery task failed"))),
+                        );
+                    }
+
+                    if let Some(d) = trust_anchors_daemon {
+                        rt.spawn(
+                            logging::admin()
+                                .bg("trust-anchors")
+                                .future(d.map_err(|_| error!("trust anchors task failed"))),
+                        );
+                    }
+
+                    if let Some(e) = span_exporter {
+                        rt.spawn(
+                            logging::admin()
+                                .bg("span-exporter")
+                                .future(e.map_err(|_| error!("span exporter task failed"))),
+                        );
+                    }
+
                     let shutdown = admin_shutdown_signal.then(|_| Ok::<(), ()>(()));
                     rt.block_on(shutdown).expect("admin");
                     trace!("admin shutdown finished");
@@ -390,13 +600,7 @@ where
             task::spawn(admin_shutdown);
         }
 
-        // Build the outbound and inbound proxies using the dst_svc client.
-
-        let profiles_client = ProfilesClient::new(
-            dst_svc,
-            Duration::from_secs(3),
-            config.destination_context.clone(),
-        );
+        // Build the outbound and inbound proxies using the discovery clients.
 
         let outbound_server = outbound::server(
             &config,
@@ -410,7 +614,16 @@ where
             endpoint_http_metrics.clone(),
             route_http_metrics.clone(),
             retry_http_metrics,
+            mirror_http_metrics,
+            mirror_retry_http_metrics,
             transport_metrics.clone(),
+            outlier_metrics,
+            weight_metrics,
+            health_check_metrics,
+            concurrency_metrics.clone(),
+            fault_metrics,
+            span_sink.clone(),
+            debug_state.clone(),
         );
 
         let inbound_server = inbound::server(
@@ -423,6 +636,12 @@ where
             endpoint_http_metrics,
             route_http_metrics,
             transport_metrics,
+            rate_limits,
+            authz_metrics,
+            jwt_metrics,
+            concurrency_metrics,
+            span_sink,
+            debug_state,
         );
 
         super::proxy::spawn(outbound_listener, outbound_server, drain_rx.clone());
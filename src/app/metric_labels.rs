@@ -1,3 +1,4 @@
+use crate::proxy::http::locality::Locality;
 use crate::{identity, metrics::FmtLabels, transport::tls, Addr, Conditional, NameAddr};
 use std::fmt::{self, Write};
 
@@ -16,6 +17,7 @@ pub struct EndpointLabels {
     dst_logical: Option<NameAddr>,
     dst_concrete: Option<NameAddr>,
     labels: Option<String>,
+    locality: Option<Locality>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -24,6 +26,13 @@ pub struct RouteLabels {
     labels: Option<String>,
 }
 
+/// Labels a load balancer by its concrete destination.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BalancerLabels {
+    direction: Direction,
+    dst_concrete: Addr,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub(in crate::app) enum Direction {
     In,
@@ -82,6 +91,28 @@ impl FmtLabels for RouteLabels {
     }
 }
 
+// === impl BalancerLabels ===
+
+impl From<dst::DstAddr> for BalancerLabels {
+    fn from(dst: dst::DstAddr) -> Self {
+        let direction = match dst.direction() {
+            dst::Direction::In => Direction::In,
+            dst::Direction::Out => Direction::Out,
+        };
+        BalancerLabels {
+            direction,
+            dst_concrete: dst.as_ref().clone(),
+        }
+    }
+}
+
+impl FmtLabels for BalancerLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.direction.fmt_labels(f)?;
+        write!(f, ",dst=\"{}\"", self.dst_concrete)
+    }
+}
+
 // === impl EndpointLabels ===
 
 impl From<inbound::Endpoint> for EndpointLabels {
@@ -92,6 +123,7 @@ impl From<inbound::Endpoint> for EndpointLabels {
             direction: Direction::In,
             tls_id: ep.tls_client_id.map(TlsId::ClientId),
             labels: None,
+            locality: None,
         }
     }
 }
@@ -117,6 +149,7 @@ impl From<outbound::Endpoint> for EndpointLabels {
             direction: Direction::Out,
             tls_id: ep.identity.as_ref().map(|id| TlsId::ServerId(id.clone())),
             labels: prefix_labels("dst", ep.metadata.labels().into_iter()),
+            locality: ep.locality,
         }
     }
 }
@@ -130,6 +163,10 @@ impl FmtLabels for EndpointLabels {
             write!(f, ",{}", labels)?;
         }
 
+        if let Some(locality) = self.locality {
+            write!(f, ",locality=\"{}\"", locality)?;
+        }
+
         write!(f, ",")?;
         tls::Status::from(self.tls_id.as_ref()).fmt_labels(f)?;
 
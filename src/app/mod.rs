@@ -6,6 +6,7 @@ pub mod config;
 mod control;
 mod dst;
 mod errors;
+mod file_watch;
 mod handle_time;
 mod identity;
 mod inbound;
@@ -14,6 +15,8 @@ mod metric_labels;
 mod outbound;
 mod profiles;
 mod proxy;
+mod spans;
+mod static_discovery;
 mod tap;
 
 pub use self::main::Main;
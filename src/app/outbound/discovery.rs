@@ -1,20 +1,25 @@
 use super::super::dst::DstAddr;
 use super::Endpoint;
 use crate::core::resolve;
-use crate::proxy::http::settings;
+use crate::proxy::http::{locality, settings};
 use crate::resolve::{Metadata, Unresolvable};
 use crate::transport::tls;
 use crate::{Addr, Conditional, NameAddr};
 use futures::{future::Future, try_ready, Async, Poll};
+use std::sync::Arc;
 use tracing::debug;
 
 #[derive(Clone, Debug)]
-pub struct Resolve<R: resolve::Resolve<NameAddr>>(R);
+pub struct Resolve<R: resolve::Resolve<NameAddr>> {
+    resolve: R,
+    locality: Option<Arc<locality::Config>>,
+}
 
 #[derive(Debug)]
 pub struct Resolution<R> {
     resolving: Resolving<R>,
     http_settings: settings::Settings,
+    locality: Option<Arc<locality::Config>>,
 }
 
 #[derive(Debug)]
@@ -34,7 +39,18 @@ where
     R: resolve::Resolve<NameAddr, Endpoint = Metadata>,
 {
     pub fn new(resolve: R) -> Self {
-        Resolve(resolve)
+        Resolve {
+            resolve,
+            locality: None,
+        }
+    }
+
+    /// Classifies each endpoint by its locality relative to the proxy.
+    pub fn with_locality(self, locality: Option<locality::Config>) -> Self {
+        Resolve {
+            locality: locality.map(Arc::new),
+            ..self
+        }
     }
 }
 
@@ -52,13 +68,14 @@ where
             Addr::Name(ref name) => Resolving::Name {
                 dst_logical: dst.dst_logical().name_addr().cloned(),
                 dst_concrete: name.clone(),
-                resolution: self.0.resolve(&name),
+                resolution: self.resolve.resolve(&name),
             },
             Addr::Socket(_) => Resolving::Unresolvable,
         };
 
         Resolution {
             http_settings: dst.http_settings,
+            locality: self.locality.clone(),
             resolving,
         }
     }
@@ -93,6 +110,7 @@ where
             resolving,
             // TODO: get rid of unnecessary clone
             http_settings: self.http_settings.clone(),
+            locality: self.locality.clone(),
         }))
     }
 }
@@ -125,13 +143,21 @@ where
                                 tls::ReasonForNoPeerName::NotProvidedByServiceDiscovery.into(),
                             )
                         });
-                    debug!("adding addr={}; identity={:?}", addr, identity);
+                    let locality = self
+                        .locality
+                        .as_ref()
+                        .map(|l| l.locality(metadata.labels()));
+                    debug!(
+                        "adding addr={}; identity={:?}; locality={:?}",
+                        addr, identity, locality
+                    );
                     let ep = Endpoint {
                         dst_logical: dst_logical.clone(),
                         dst_concrete: Some(dst_concrete.clone()),
                         addr,
                         identity,
                         metadata,
+                        locality,
                         http_settings: self.http_settings,
                     };
                     Ok(Async::Ready(resolve::Update::Add(addr, ep)))
@@ -0,0 +1,600 @@
+//! Resolves destinations via DNS when the destination service cannot.
+//!
+//! Only names within one of the configured suffixes are resolved via DNS, so
+//! the fallback is disabled unless suffixes are configured. When the inner
+//! resolver fails with `Unresolvable`, the name's `_http._tcp` SRV records
+//! are looked up to discover the ports on which it is served. If the name has
+//! SRV records, each target's A/AAAA records are resolved and paired with the
+//! record's port; records on the requested port are preferred when there are
+//! several. Targets that fail to resolve are ignored unless all of them
+//! fail. Otherwise, all of the name's A/AAAA records are used with the
+//! requested port.
+//!
+//! The lookup is repeated whenever the shortest TTL in the answer expires,
+//! and the resolution publishes the difference from the previous answer. If
+//! the initial lookup fails or returns no addresses, the name remains
+//! `Unresolvable`, so that requests are forwarded to their original
+//! destination.
+
+use crate::core::resolve::{self, Update};
+use crate::dns;
+use crate::resolve::{Metadata, Unresolvable};
+use crate::{NameAddr, Never};
+use futures::{future, Async, Future, Poll};
+use indexmap::IndexSet;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_timer::{clock, Delay};
+use tracing::{debug, trace};
+
+/// Bounds how frequently a name is re-resolved, regardless of its TTL.
+const MIN_TTL: Duration = Duration::from_secs(5);
+
+/// How long to wait before retrying a lookup that failed without a TTL.
+const ERROR_TTL: Duration = Duration::from_secs(3);
+
+/// The service and protocol labels of the SRV records that are looked up.
+const SRV_SERVICE: &str = "_http._tcp";
+
+#[derive(Clone, Debug)]
+pub struct Resolve<R> {
+    inner: R,
+    dns: dns::Resolver,
+    suffixes: Arc<Vec<dns::Suffix>>,
+}
+
+pub struct ResolveFuture<F> {
+    name: NameAddr,
+    /// Set if the name may be resolved via DNS.
+    dns: Option<dns::Resolver>,
+    state: Resolving<F>,
+}
+
+enum Resolving<F> {
+    Inner(F),
+    Dns(Lookup),
+}
+
+pub enum Resolution<R> {
+    Inner(R),
+    Dns(DnsResolution),
+}
+
+/// Publishes the endpoints of a DNS name, re-resolving it as its TTL expires.
+pub struct DnsResolution {
+    name: NameAddr,
+    dns: dns::Resolver,
+    endpoints: IndexSet<SocketAddr>,
+    updates: VecDeque<Update<Metadata>>,
+    state: Refresh,
+}
+
+enum Refresh {
+    ValidUntil(Delay),
+    Lookup(Lookup),
+}
+
+/// The addresses resolved for a name.
+struct Addrs {
+    addrs: IndexSet<SocketAddr>,
+    valid_until: Instant,
+}
+
+/// Resolves the addresses of a name, first via SRV and then via A/AAAA.
+struct Lookup {
+    name: NameAddr,
+    dns: dns::Resolver,
+    state: LookupState,
+}
+
+enum LookupState {
+    Srv(dns::SrvFuture),
+    Targets {
+        targets: future::JoinAll<Vec<Target>>,
+        valid_until: Option<Instant>,
+    },
+}
+
+/// Resolves all of the addresses of a single target.
+struct Target {
+    port: u16,
+    future: dns::IpListFuture,
+}
+
+// === impl Resolve ===
+
+impl<R> Resolve<R> {
+    /// Resolves names within `suffixes` via DNS when `inner` cannot.
+    pub fn new(inner: R, dns: dns::Resolver, suffixes: Vec<dns::Suffix>) -> Self {
+        Self {
+            inner,
+            dns,
+            suffixes: Arc::new(suffixes),
+        }
+    }
+}
+
+impl<R> resolve::Resolve<NameAddr> for Resolve<R>
+where
+    R: resolve::Resolve<NameAddr, Endpoint = Metadata>,
+    R::Future: Future<Error = Unresolvable>,
+{
+    type Endpoint = Metadata;
+    type Future = ResolveFuture<R::Future>;
+    type Resolution = Resolution<R::Resolution>;
+
+    fn resolve(&self, name: &NameAddr) -> Self::Future {
+        let dns = if self.suffixes.iter().any(|s| s.contains(name.name())) {
+            Some(self.dns.clone())
+        } else {
+            None
+        };
+        ResolveFuture {
+            name: name.clone(),
+            dns,
+            state: Resolving::Inner(self.inner.resolve(name)),
+        }
+    }
+}
+
+// === impl ResolveFuture ===
+
+impl<F> Future for ResolveFuture<F>
+where
+    F: Future<Error = Unresolvable>,
+{
+    type Item = Resolution<F::Item>;
+    type Error = Unresolvable;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            self.state = match self.state {
+                Resolving::Inner(ref mut f) => match f.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(res)) => return Ok(Async::Ready(Resolution::Inner(res))),
+                    Err(e) => match self.dns.take() {
+                        Some(dns) => {
+                            debug!("resolving {} via DNS", self.name);
+                            Resolving::Dns(Lookup::new(self.name.clone(), dns))
+                        }
+                        None => return Err(e),
+                    },
+                },
+                Resolving::Dns(ref mut lookup) => match lookup.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(ref addrs)) if addrs.addrs.is_empty() => {
+                        debug!("no addresses found for {}", self.name);
+                        return Err(Unresolvable::new());
+                    }
+                    Ok(Async::Ready(addrs)) => {
+                        let mut res = DnsResolution {
+                            name: self.name.clone(),
+                            dns: lookup.dns.clone(),
+                            endpoints: IndexSet::new(),
+                            updates: VecDeque::new(),
+                            state: Refresh::ValidUntil(Delay::new(addrs.valid_until)),
+                        };
+                        res.update(addrs.addrs);
+                        return Ok(Async::Ready(Resolution::Dns(res)));
+                    }
+                    Err(e) => {
+                        debug!("failed to resolve {}: {}", self.name, e);
+                        return Err(Unresolvable::new());
+                    }
+                },
+            };
+        }
+    }
+}
+
+// === impl Resolution ===
+
+impl<R> resolve::Resolution for Resolution<R>
+where
+    R: resolve::Resolution<Endpoint = Metadata>,
+{
+    type Endpoint = Metadata;
+    type Error = R::Error;
+
+    fn poll(&mut self) -> Poll<Update<Self::Endpoint>, Self::Error> {
+        match self {
+            Resolution::Inner(res) => res.poll(),
+            Resolution::Dns(res) => Ok(res.poll()),
+        }
+    }
+}
+
+// === impl DnsResolution ===
+
+impl DnsResolution {
+    fn poll(&mut self) -> Async<Update<Metadata>> {
+        loop {
+            if let Some(update) = self.updates.pop_front() {
+                return Async::Ready(update);
+            }
+
+            self.state = match self.state {
+                Refresh::ValidUntil(ref mut delay) => {
+                    match delay.poll().expect("timer must not fail") {
+                        Async::NotReady => return Async::NotReady,
+                        Async::Ready(()) => {
+                            trace!("re-resolving {}", self.name);
+                            Refresh::Lookup(Lookup::new(self.name.clone(), self.dns.clone()))
+                        }
+                    }
+                }
+                Refresh::Lookup(ref mut lookup) => match lookup.poll() {
+                    Ok(Async::NotReady) => return Async::NotReady,
+                    Ok(Async::Ready(addrs)) => {
+                        let valid_until = addrs.valid_until;
+                        self.update(addrs.addrs);
+                        Refresh::ValidUntil(Delay::new(valid_until))
+                    }
+                    Err(e) => {
+                        // If the name no longer has any records, its
+                        // endpoints are removed. Otherwise, the last known
+                        // endpoints are retained until the lookup succeeds.
+                        let valid_until = match e.kind() {
+                            dns::ResolveErrorKind::NoRecordsFound { valid_until, .. } => {
+                                debug!("no records found for {}", self.name);
+                                self.update(IndexSet::new());
+                                *valid_until
+                            }
+                            _ => {
+                                debug!("failed to refresh {}: {}", self.name, e);
+                                None
+                            }
+                        };
+                        let valid_until = valid_until
+                            .unwrap_or_else(|| clock::now() + ERROR_TTL)
+                            .max(clock::now() + ERROR_TTL);
+                        Refresh::ValidUntil(Delay::new(valid_until))
+                    }
+                },
+            };
+        }
+    }
+
+    /// Publishes the difference between `addrs` and the current endpoints.
+    fn update(&mut self, addrs: IndexSet<SocketAddr>) {
+        for addr in self.endpoints.difference(&addrs) {
+            debug!("removing {} from {}", addr, self.name);
+            self.updates.push_back(Update::Remove(*addr));
+        }
+        for addr in addrs.difference(&self.endpoints) {
+            debug!("adding {} to {}", addr, self.name);
+            self.updates
+                .push_back(Update::Add(*addr, Metadata::empty()));
+        }
+        self.endpoints = addrs;
+    }
+}
+
+// === impl Lookup ===
+
+impl Lookup {
+    fn new(name: NameAddr, dns: dns::Resolver) -> Self {
+        let state = match srv_name(name.name()) {
+            Some(srv) => LookupState::Srv(dns.resolve_srv(&srv)),
+            None => LookupState::Targets {
+                targets: future::join_all(vec![Target {
+                    port: name.port(),
+                    future: dns.resolve_all_ips(name.name()),
+                }]),
+                valid_until: None,
+            },
+        };
+        Self { name, dns, state }
+    }
+}
+
+/// Returns the name of the SRV records that describe `name`'s ports, if it
+/// is a valid name.
+fn srv_name(name: &dns::Name) -> Option<dns::Name> {
+    let srv = format!("{}.{}", SRV_SERVICE, name);
+    dns::Name::try_from(srv.as_bytes()).ok()
+}
+
+/// Selects the names and ports whose addresses are resolved for `name`.
+///
+/// If `name` has no SRV targets, its own addresses are used with the
+/// requested port.
+fn targets(name: &NameAddr, srv: Option<dns::SrvList>) -> Vec<(dns::Name, u16)> {
+    let port = name.port();
+    let mut targets = srv.map(|srv| srv.targets).unwrap_or_default();
+    // If the name is served on several ports, prefer those that match the
+    // requested port.
+    if targets.iter().any(|&(_, p)| p == port) {
+        targets.retain(|&(_, p)| p == port);
+    }
+    if targets.is_empty() {
+        targets.push((name.name().clone(), port));
+    }
+    targets
+}
+
+impl Future for Lookup {
+    type Item = Addrs;
+    type Error = dns::ResolveError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            self.state = match self.state {
+                LookupState::Srv(ref mut f) => {
+                    let srv = match f.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(srv)) => Some(srv),
+                        Err(e) => {
+                            trace!("no SRV records for {}: {}", self.name, e);
+                            None
+                        }
+                    };
+                    let valid_until = srv.as_ref().map(|srv| srv.valid_until);
+
+                    let dns = &self.dns;
+                    let targets = targets(&self.name, srv)
+                        .into_iter()
+                        .map(|(name, port)| Target {
+                            port,
+                            future: dns.resolve_all_ips(&name),
+                        })
+                        .collect();
+                    LookupState::Targets {
+                        targets: future::join_all(targets),
+                        valid_until,
+                    }
+                }
+                LookupState::Targets {
+                    ref mut targets,
+                    valid_until,
+                } => {
+                    let results = match targets.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(results)) => results,
+                        Err(never) => match never {},
+                    };
+                    return merge(&self.name, results, valid_until).map(Async::Ready);
+                }
+            };
+        }
+    }
+}
+
+/// Combines the addresses of each target, ignoring targets that failed to
+/// resolve. Fails only if every target failed.
+fn merge(
+    name: &NameAddr,
+    results: Vec<Result<Addrs, dns::ResolveError>>,
+    valid_until: Option<Instant>,
+) -> Result<Addrs, dns::ResolveError> {
+    let mut addrs = Addrs {
+        addrs: IndexSet::new(),
+        valid_until: valid_until.unwrap_or_else(|| clock::now() + MIN_TTL),
+    };
+    let mut resolved = false;
+    let mut error = None;
+    for result in results {
+        match result {
+            Ok(target) => {
+                resolved = true;
+                addrs.addrs.extend(target.addrs);
+                addrs.valid_until = addrs.valid_until.min(target.valid_until);
+            }
+            Err(e) => {
+                debug!("failed to resolve a target of {}: {}", name, e);
+                error = Some(e);
+            }
+        }
+    }
+    match error {
+        Some(e) if !resolved => Err(e),
+        _ => {
+            addrs.valid_until = addrs.valid_until.max(clock::now() + MIN_TTL);
+            Ok(addrs)
+        }
+    }
+}
+
+// === impl Target ===
+
+/// Failures are returned as items so that one target's failure does not fail
+/// the other targets' lookups.
+impl Future for Target {
+    type Item = Result<Addrs, dns::ResolveError>;
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let list = match self.future.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(list)) => list,
+            Err(e) => return Ok(Async::Ready(Err(e))),
+        };
+        let port = self.port;
+        Ok(Async::Ready(Ok(Addrs {
+            addrs: list
+                .ips
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, port))
+                .collect(),
+            valid_until: list.valid_until,
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::resolve::Resolve as _;
+    use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
+
+    /// Never resolves any name.
+    struct Unresolved;
+
+    struct NoUpdates;
+
+    impl resolve::Resolve<NameAddr> for Unresolved {
+        type Endpoint = Metadata;
+        type Future = future::FutureResult<NoUpdates, Unresolvable>;
+        type Resolution = NoUpdates;
+
+        fn resolve(&self, _: &NameAddr) -> Self::Future {
+            future::err(Unresolvable::new())
+        }
+    }
+
+    impl resolve::Resolution for NoUpdates {
+        type Endpoint = Metadata;
+        type Error = Never;
+
+        fn poll(&mut self) -> Poll<Update<Metadata>, Never> {
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn resolver() -> dns::Resolver {
+        dns::Resolver::new(ResolverConfig::default(), ResolverOpts::default()).0
+    }
+
+    fn name(s: &str) -> dns::Name {
+        dns::Name::try_from(s.as_bytes()).unwrap()
+    }
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn srv(targets: &[(&str, u16)]) -> Option<dns::SrvList> {
+        Some(dns::SrvList {
+            targets: targets.iter().map(|&(n, p)| (name(n), p)).collect(),
+            valid_until: clock::now(),
+        })
+    }
+
+    fn updates(res: &mut DnsResolution) -> Vec<String> {
+        let mut updates = Vec::new();
+        while let Some(update) = res.updates.pop_front() {
+            updates.push(match update {
+                Update::Add(addr, _) => format!("+{}", addr),
+                Update::Remove(addr) => format!("-{}", addr),
+            });
+        }
+        updates
+    }
+
+    #[test]
+    fn publishes_changes_in_addresses() {
+        let mut res = DnsResolution {
+            name: NameAddr::from_str("web.example.com:8080").unwrap(),
+            dns: resolver(),
+            endpoints: IndexSet::new(),
+            updates: VecDeque::new(),
+            state: Refresh::ValidUntil(Delay::new(clock::now() + MIN_TTL)),
+        };
+        let (a, b, c) = (
+            addr("10.0.0.1:8080"),
+            addr("10.0.0.2:8080"),
+            addr("10.0.0.3:8080"),
+        );
+
+        res.update(vec![a, b].into_iter().collect());
+        assert_eq!(updates(&mut res), vec!["+10.0.0.1:8080", "+10.0.0.2:8080"]);
+
+        res.update(vec![b, c].into_iter().collect());
+        assert_eq!(updates(&mut res), vec!["-10.0.0.1:8080", "+10.0.0.3:8080"]);
+
+        res.update(vec![c, b].into_iter().collect());
+        assert!(
+            updates(&mut res).is_empty(),
+            "unchanged addresses are not updated"
+        );
+
+        res.update(IndexSet::new());
+        assert_eq!(updates(&mut res), vec!["-10.0.0.3:8080", "-10.0.0.2:8080"]);
+    }
+
+    #[test]
+    fn looks_up_http_srv_records() {
+        assert_eq!(
+            srv_name(&name("web.ns.svc.cluster.local")),
+            Some(name("_http._tcp.web.ns.svc.cluster.local"))
+        );
+    }
+
+    #[test]
+    fn prefers_srv_targets_on_the_requested_port() {
+        let web = NameAddr::from_str("web.example.com:8080").unwrap();
+        let srv = srv(&[
+            ("web-0.example.com", 8080),
+            ("web-0.example.com", 9090),
+            ("web-1.example.com", 8080),
+        ]);
+        assert_eq!(
+            targets(&web, srv),
+            vec![
+                (name("web-0.example.com"), 8080),
+                (name("web-1.example.com"), 8080)
+            ]
+        );
+
+        // When no target is on the requested port, all of them are used.
+        let srv = self::srv(&[("web-0.example.com", 80), ("web-1.example.com", 81)]);
+        assert_eq!(
+            targets(&web, srv),
+            vec![
+                (name("web-0.example.com"), 80),
+                (name("web-1.example.com"), 81)
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_addresses_without_srv_targets() {
+        let web = NameAddr::from_str("web.example.com:8080").unwrap();
+        let expected = vec![(name("web.example.com"), 8080)];
+        assert_eq!(targets(&web, None), expected);
+        assert_eq!(targets(&web, srv(&[])), expected);
+    }
+
+    #[test]
+    fn ignores_targets_that_fail_to_resolve() {
+        let failed = || -> Result<Addrs, dns::ResolveError> {
+            Err(dns::ResolveErrorKind::Message("no records").into())
+        };
+        let web = NameAddr::from_str("web.example.com:8080").unwrap();
+        let a = addr("10.0.0.1:8080");
+        let resolved = Addrs {
+            addrs: Some(a).into_iter().collect(),
+            valid_until: clock::now() + MIN_TTL,
+        };
+
+        let addrs =
+            merge(&web, vec![failed(), Ok(resolved)], None).expect("resolved targets must be used");
+        assert_eq!(addrs.addrs.into_iter().collect::<Vec<_>>(), vec![a]);
+
+        assert!(merge(&web, vec![failed(), failed()], None).is_err());
+    }
+
+    #[test]
+    fn only_resolves_configured_suffixes() {
+        let sfx = dns::Suffix::try_from("example.com").unwrap();
+        let resolve = Resolve::new(Unresolved, resolver(), vec![sfx]);
+
+        let mut other = resolve.resolve(&NameAddr::from_str("web.example.org:80").unwrap());
+        assert!(other.dns.is_none());
+        assert!(
+            other.poll().is_err(),
+            "names outside of suffixes are unresolvable"
+        );
+
+        let web = resolve.resolve(&NameAddr::from_str("web.example.com:80").unwrap());
+        assert!(web.dns.is_some());
+
+        // Without suffixes, nothing is resolved via DNS.
+        let resolve = Resolve::new(Unresolved, resolver(), vec![]);
+        let web = resolve.resolve(&NameAddr::from_str("web.example.com:80").unwrap());
+        assert!(web.dns.is_none());
+    }
+}
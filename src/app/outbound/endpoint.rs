@@ -1,6 +1,8 @@
 use super::super::{dst::Route, L5D_REQUIRE_ID};
-use crate::proxy::http::{identity_from_header, settings};
-use crate::proxy::Source;
+use crate::proxy::http::{
+    identity_from_header, locality, profiles, settings, trace_context, weight,
+};
+use crate::proxy::{resolve::EndpointLabels, Source};
 use crate::resolve::{Metadata, ProtocolHint};
 use crate::transport::{connect, tls};
 use crate::{identity, tap};
@@ -16,6 +18,7 @@ pub struct Endpoint {
     pub addr: SocketAddr,
     pub identity: tls::PeerIdentity,
     pub metadata: Metadata,
+    pub locality: Option<locality::Locality>,
     pub http_settings: settings::Settings,
 }
 
@@ -58,6 +61,7 @@ impl Endpoint {
             dst_concrete: None,
             identity,
             metadata: Metadata::empty(),
+            locality: None,
             http_settings,
         })
     }
@@ -71,6 +75,7 @@ impl From<SocketAddr> for Endpoint {
             dst_concrete: None,
             identity: Conditional::None(tls::ReasonForNoPeerName::NotHttp.into()),
             metadata: Metadata::empty(),
+            locality: None,
             http_settings: settings::Settings::NotHttp,
         }
     }
@@ -89,7 +94,7 @@ impl std::hash::Hash for Endpoint {
         self.addr.hash(state);
         self.identity.hash(state);
         self.http_settings.hash(state);
-        // Ignore metadata.
+        // Ignore metadata and the locality derived from it.
     }
 }
 
@@ -111,6 +116,32 @@ impl settings::HasSettings for Endpoint {
     }
 }
 
+impl profiles::CanGetDestination for Endpoint {
+    fn get_destination(&self) -> Option<&NameAddr> {
+        self.dst_concrete
+            .as_ref()
+            .or_else(|| self.dst_logical.as_ref())
+    }
+}
+
+impl locality::HasLocality for Endpoint {
+    fn locality(&self) -> Option<locality::Locality> {
+        self.locality
+    }
+}
+
+impl weight::HasWeight for Endpoint {
+    fn weight(&self) -> u32 {
+        self.metadata.weight()
+    }
+}
+
+impl EndpointLabels for Endpoint {
+    fn endpoint_labels(&self) -> Option<&IndexMap<String, String>> {
+        Some(self.metadata.labels())
+    }
+}
+
 impl tap::Inspect for Endpoint {
     fn src_addr<B>(&self, req: &http::Request<B>) -> Option<SocketAddr> {
         req.extensions().get::<Source>().map(|s| s.remote)
@@ -146,3 +177,22 @@ impl tap::Inspect for Endpoint {
         true
     }
 }
+
+impl trace_context::SpanLabels for Endpoint {
+    fn span_labels(&self, labels: &mut trace_context::Labels) {
+        labels.insert("direction".into(), "outbound".into());
+        labels.insert("peer.addr".into(), self.addr.to_string());
+        if let Some(ref dst) = self.dst_logical {
+            labels.insert("dst_logical".into(), dst.to_string());
+        }
+        if let Some(ref dst) = self.dst_concrete {
+            labels.insert("dst_concrete".into(), dst.to_string());
+        }
+        if let Conditional::Some(ref id) = self.identity {
+            labels.insert("server_id".into(), id.as_ref().to_owned());
+        }
+        for (k, v) in self.metadata.labels() {
+            labels.insert(format!("dst_{}", k), v.clone());
+        }
+    }
+}
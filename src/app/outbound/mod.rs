@@ -1,18 +1,18 @@
-use super::{classify, config::Config, dst::DstAddr, identity, DispatchDeadline};
+use super::{classify, config::Config, dst::DstAddr, identity, spans, DispatchDeadline};
 use crate::core::listen::ServeConnection;
 use crate::core::resolve::{Resolution, Resolve};
 use crate::proxy::http::{
-    balance, canonicalize, client, fallback, header_from_target, insert, metrics as http_metrics,
-    normalize_uri, profiles, retry, router, settings, strip_header,
+    adaptive_concurrency, balance, canonicalize, client, consistent_hash, fallback, fault,
+    header_from_target, header_rewrite, health_check, insert, locality, metrics as http_metrics,
+    normalize_uri, outlier, profiles, retry, router, settings, strip_header, trace_context, weight,
 };
-use crate::proxy::{self, accept, reconnect, resolve, Server};
+use crate::proxy::{self, accept, reconnect, resolve, server::ForwardConnect, Server};
 use crate::resolve::{Metadata, Unresolvable};
 use crate::transport::Connection;
 use crate::transport::{self, connect, keepalive, tls};
 use crate::{svc, Addr, NameAddr};
 use std::net::SocketAddr;
 use std::time::Duration;
-use tower_grpc::{self as grpc, generic::client::GrpcService};
 use tracing::debug;
 
 #[allow(dead_code)] // TODO #2597
@@ -20,9 +20,11 @@ mod add_remote_ip_on_rsp;
 #[allow(dead_code)] // TODO #2597
 mod add_server_id_on_rsp;
 mod discovery;
+mod dns_fallback;
 mod endpoint;
 mod orig_proto_upgrade;
 mod require_identity_on_endpoint;
+mod tcp;
 
 pub(super) use self::endpoint::Endpoint;
 pub(super) use self::require_identity_on_endpoint::RequireIdentityError;
@@ -30,29 +32,36 @@ pub(super) use self::require_identity_on_endpoint::RequireIdentityError;
 const EWMA_DEFAULT_RTT: Duration = Duration::from_millis(30);
 const EWMA_DECAY: Duration = Duration::from_secs(10);
 
-pub fn server<R, P>(
+pub fn server<R, G>(
     config: &Config,
     local_identity: tls::Conditional<identity::Local>,
     local_addr: SocketAddr,
     resolve: R,
     dns_resolver: crate::dns::Resolver,
-    profiles_client: super::profiles::Client<P>,
+    profiles_client: G,
     tap_layer: crate::tap::Layer,
     handle_time: http_metrics::handle_time::Scope,
     endpoint_http_metrics: super::HttpEndpointMetricsRegistry,
     route_http_metrics: super::HttpRouteMetricsRegistry,
     retry_http_metrics: super::HttpRouteMetricsRegistry,
+    mirror_http_metrics: super::HttpRouteMetricsRegistry,
+    mirror_retry_http_metrics: super::HttpRouteMetricsRegistry,
     transport_metrics: transport::metrics::Registry,
+    outlier_metrics: outlier::Registry<super::metric_labels::BalancerLabels>,
+    weight_metrics: weight::Registry<super::metric_labels::EndpointLabels>,
+    health_check_metrics: health_check::Registry<super::metric_labels::EndpointLabels>,
+    concurrency_metrics: adaptive_concurrency::Registry<super::metric_labels::BalancerLabels>,
+    fault_metrics: fault::Registry<super::metric_labels::RouteLabels>,
+    span_sink: Option<spans::SpanConverter>,
+    debug_state: super::admin::State,
 ) -> impl ServeConnection<Connection>
 where
     R: Resolve<NameAddr, Endpoint = Metadata> + Clone + Send + Sync + 'static,
     R::Future: futures::Future<Error = Unresolvable> + Send,
     R::Resolution: Send,
     <R::Resolution as Resolution>::Error: std::error::Error + Send + Sync + 'static,
-    P: GrpcService<grpc::BoxBody> + Clone + Send + Sync + 'static,
-    P::ResponseBody: Send,
-    <P::ResponseBody as grpc::Body>::Data: Send,
-    P::Future: Send,
+    G: profiles::GetRoutes + Clone + Send + Sync + 'static,
+    G::Stream: Send + 'static,
 {
     let capacity = config.outbound_router_capacity;
     let max_idle_age = config.outbound_router_max_idle_age;
@@ -60,6 +69,15 @@ where
     let profile_suffixes = config.destination_profile_suffixes.clone();
     let canonicalize_timeout = config.dns_canonicalize_timeout;
     let dispatch_timeout = config.outbound_dispatch_timeout;
+    let mirror_max_body_bytes = config.outbound_mirror_max_body_bytes;
+
+    // Holds the health check of each destination, as configured or as
+    // described by the destination's profile.
+    let health_checks = health_check::Checks::new(config.outbound_health_checks.clone());
+
+    // Holds the balancer hash key of each destination, as configured or as
+    // described by the destination's profile.
+    let hash_keys = consistent_hash::HashKeys::new(config.outbound_balance_hash_key.clone());
 
     // Establishes connections to remote peers (for both TCP
     // forwarding and HTTP proxying).
@@ -80,20 +98,25 @@ where
     // A per-`outbound::Endpoint` stack that:
     //
     // 1. Records http metrics  with per-endpoint labels.
-    // 2. Instruments `tap` inspection.
-    // 3. Changes request/response versions when the endpoint
+    // 2. Records a client span for requests with a sampled trace context.
+    // 3. Instruments `tap` inspection.
+    // 4. Changes request/response versions when the endpoint
     //    supports protocol upgrade (and the request may be upgraded).
-    // 4. Appends `l5d-server-id` to responses coming back iff meshed
+    // 5. Appends `l5d-server-id` to responses coming back iff meshed
     //    TLS was used on the connection.
-    // 5. Routes requests to the correct client (based on the
+    // 6. Routes requests to the correct client (based on the
     //    request version and headers).
-    // 6. Strips any `l5d-server-id` that may have been received from
+    // 7. Strips any `l5d-server-id` that may have been received from
     //    the server, before we apply our own.
     let endpoint_stack = svc::builder()
         .layer(require_identity_on_endpoint::layer())
         .layer(http_metrics::layer::<_, classify::Response>(
             endpoint_http_metrics,
         ))
+        .layer(trace_context::layer::<_, classify::Response>(
+            trace_context::Kind::Client,
+            span_sink,
+        ))
         .layer(tap_layer.clone())
         .layer(orig_proto_upgrade::layer())
         // disabled on purpose
@@ -107,27 +130,42 @@ where
     // A per-`dst::Route` layer that uses profile data to configure
     // a per-route layer.
     //
-    // 1. The `classify` module installs a `classify::Response`
+    // 1. Request and response headers are rewritten as configured by
+    //    the target `dst::Route`.
+    // 2. The `classify` module installs a `classify::Response`
     //    extension into each request so that all lower metrics
     //    implementations can use the route-specific configuration.
-    // 2. A timeout is optionally enabled if the target `dst::Route`
+    // 3. Faults are optionally injected if the target `dst::Route`
+    //    configures them. This goes before the timeout so that injected
+    //    delays are not capped by it.
+    // 4. A timeout is optionally enabled if the target `dst::Route`
     //    specifies a timeout. This goes before `retry` to cap
     //    retries.
-    // 3. Retries are optionally enabled depending on if the route
+    // 5. Retries are optionally enabled depending on if the route
     //    is retryable.
-    let dst_route_layer = svc::builder()
-        .buffer_pending(max_in_flight, DispatchDeadline::extract)
-        .layer(classify::layer())
-        .layer(http_metrics::layer::<_, classify::Response>(
-            route_http_metrics,
-        ))
-        .layer(proxy::http::timeout::layer())
-        .layer(retry::layer(retry_http_metrics.clone()))
-        .layer(http_metrics::layer::<_, classify::Response>(
-            retry_http_metrics,
-        ))
-        .layer(insert::target::layer())
-        .into_inner();
+    //
+    // Requests copied to a route's mirrors are routed through an
+    // identical layer that records its own metrics.
+    let route_layer = |route_http_metrics: super::HttpRouteMetricsRegistry,
+                       retry_http_metrics: super::HttpRouteMetricsRegistry| {
+        svc::builder()
+            .buffer_pending(max_in_flight, DispatchDeadline::extract)
+            .layer(header_rewrite::layer())
+            .layer(classify::layer())
+            .layer(http_metrics::layer::<_, classify::Response>(
+                route_http_metrics,
+            ))
+            .layer(fault::layer(fault_metrics.clone()))
+            .layer(proxy::http::timeout::layer())
+            .layer(retry::layer(retry_http_metrics.clone()))
+            .layer(http_metrics::layer::<_, classify::Response>(
+                retry_http_metrics,
+            ))
+            .layer(insert::target::layer())
+            .into_inner()
+    };
+    let dst_route_layer = route_layer(route_http_metrics, retry_http_metrics);
+    let mirror_route_layer = route_layer(mirror_http_metrics, mirror_retry_http_metrics);
 
     // Routes requests to their original destination endpoints. Used as
     // a fallback when service discovery has no endpoints for a destination.
@@ -147,18 +185,60 @@ where
         .into_inner();
 
     // Resolves the target via the control plane and balances requests
-    // over all endpoints returned from the destination service.
+    // over all endpoints returned from the destination service. Names within
+    // `outbound_dns_fallback_suffixes` that the destination service cannot
+    // resolve are resolved via DNS, so that headless services and external
+    // hosts are balanced over all of their addresses. Endpoints are selected
+    // in proportion to the weights that the destination service provides.
+    //
+    // Endpoints that fail too often are ejected from the balancer for a
+    // time, as configured by `outbound_outlier_detection`. If the proxy's
+    // locality is configured, requests are only dispatched to endpoints in
+    // other zones when too few endpoints in the proxy's zone are ready.
+    // If the destination has a hash key, requests are dispatched by a
+    // consistent hash of that key rather than by load. Newly discovered
+    // endpoints are ramped up over `outbound_balance_slow_start`.
+    //
+    // Endpoints of destinations with a health check are periodically
+    // checked through the endpoint stack, and are not used while unhealthy.
     let balancer_layer = svc::builder()
-        .layer(balance::layer(EWMA_DEFAULT_RTT, EWMA_DECAY))
-        .layer(resolve::layer(discovery::Resolve::new(resolve)))
+        .layer(
+            balance::layer(EWMA_DEFAULT_RTT, EWMA_DECAY)
+                .with_hash_keys(hash_keys.clone())
+                .with_slow_start(config.outbound_balance_slow_start),
+        )
+        .layer(locality::layer(config.outbound_locality.clone()))
+        .layer(outlier::layer::<_, classify::Response>(
+            config.outbound_outlier_detection.clone(),
+            outlier_metrics,
+        ))
+        .layer(
+            resolve::layer(
+                discovery::Resolve::new(dns_fallback::Resolve::new(
+                    resolve.clone(),
+                    dns_resolver.clone(),
+                    config.outbound_dns_fallback_suffixes.clone(),
+                ))
+                .with_locality(config.outbound_locality.clone()),
+            )
+            .with_resolutions(debug_state.resolutions()),
+        )
+        .layer(locality::tag_layer())
+        .layer(weight::layer(weight_metrics))
+        .layer(health_check::layer(
+            config.outbound_health_check.clone(),
+            health_checks.clone(),
+            health_check_metrics,
+        ))
         .spawn_ready()
         .into_inner();
 
     let distributor = svc::builder()
         .layer(
             // Attempt to build a balancer. If the service is
-            // unresolvable, fall back to using a router that dispatches
-            // request to the application-selected original destination.
+            // unresolvable, both by the destination service and via DNS,
+            // fall back to using a router that dispatches request to the
+            // application-selected original destination.
             fallback::layer(balancer_layer, orig_dst_router_layer).on_error::<Unresolvable>(),
         )
         .service(endpoint_stack);
@@ -167,15 +247,24 @@ where
     //
     // 1. Adds the `CANONICAL_DST_HEADER` from the `DstAddr`.
     // 2. Determines the profile of the destination and applies
-    //    per-route policy.
-    // 3. Creates a load balancer , configured by resolving the
+    //    per-route policy. Requests are copied to their route's mirrors,
+    //    if any.
+    // 3. Limits the concurrency of requests to each concrete `DstAddr`,
+    //    if configured, adapting the limit to the observed latency.
+    // 4. Creates a load balancer , configured by resolving the
     //   `DstAddr` with a resolver.
     let dst_stack = svc::builder()
         .layer(header_from_target::layer(super::CANONICAL_DST_HEADER))
-        .layer(profiles::router::layer(
-            profile_suffixes,
-            profiles_client,
-            dst_route_layer,
+        .layer(
+            profiles::router::layer(profile_suffixes, profiles_client, dst_route_layer)
+                .with_mirrors(mirror_route_layer, mirror_max_body_bytes)
+                .with_active_routes(debug_state.active_routes())
+                .with_health_checks(health_checks)
+                .with_hash_keys(hash_keys),
+        )
+        .layer(adaptive_concurrency::layer(
+            config.outbound_adaptive_concurrency.clone(),
+            concurrency_metrics,
         ))
         .buffer_pending(max_in_flight, DispatchDeadline::extract)
         .service(distributor);
@@ -199,6 +288,7 @@ where
         .buffer_pending(max_in_flight, DispatchDeadline::extract)
         .service(dst_stack)
         .make();
+    debug_state.register_router("out dst", dst_router.cache_keys());
 
     // Canonicalizes the request-specified `Addr` via DNS, and
     // annotates each request with a refined `Addr` so that it may be
@@ -247,6 +337,7 @@ where
         .layer(strip_header::request::layer(super::L5D_CLIENT_ID))
         .service(addr_stack)
         .make();
+    debug_state.register_router("out addr", addr_router.cache_keys());
 
     // Share a single semaphore across all requests to signal when
     // the proxy is overloaded.
@@ -273,11 +364,40 @@ where
         .layer(transport_metrics.accept("outbound"))
         .layer(keepalive::accept::layer(config.outbound_accept_keepalive));
 
+    // Resolves configured opaque TCP destinations via the control plane
+    // and balances connections over all endpoints returned from the
+    // destination service. Each endpoint is connected to with the
+    // same `connect` stack that is used for TCP forwarding.
+    let tcp_balance = svc::builder()
+        .buffer_pending(max_in_flight, config.outbound_connect_timeout)
+        .layer(proxy::tcp::balance::layer())
+        .layer(
+            resolve::layer(discovery::Resolve::new(resolve))
+                .with_resolutions(debug_state.resolutions()),
+        )
+        .service(proxy::tcp::balance::endpoint(connect.clone()));
+
+    // Establishes a connection for each non-HTTP stream:
+    //
+    // 1. If the stream's SO_ORIGINAL_DST is configured as an opaque TCP
+    // destination, the connection is balanced over the destination's
+    // endpoints.
+    //
+    // 2. Otherwise, or if the destination is unresolvable, a connection
+    // is established to the SO_ORIGINAL_DST.
+    let tcp_connect = tcp::connect(
+        config.outbound_tcp_destinations.clone(),
+        capacity,
+        max_idle_age,
+        tcp_balance,
+        ForwardConnect::<Endpoint, _>::new(connect),
+    );
+
     Server::new(
         "out",
         local_addr,
         accept,
-        connect,
+        tcp_connect,
         server_stack,
         config.h2_settings,
     )
@@ -0,0 +1,273 @@
+use super::super::dst::DstAddr;
+use crate::proxy::{http::settings, Source};
+use crate::resolve::Unresolvable;
+use crate::{logging, svc, Addr, Error, NameAddr};
+use bytes::Buf;
+use futures::{try_ready, Async, Future, Poll};
+use indexmap::IndexMap;
+use linkerd2_router as rt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::debug;
+
+/// Recognizes connections whose original destination address is configured
+/// to be balanced over the endpoints of a logical destination.
+#[derive(Clone, Debug)]
+pub struct Recognize {
+    dsts: Arc<IndexMap<SocketAddr, NameAddr>>,
+}
+
+/// Establishes connections for opaque TCP streams.
+///
+/// If a connection's original destination is recognized, the connection is
+/// dispatched through a `B`-typed balancer for the logical destination.
+/// Otherwise, or if the logical destination is unresolvable, an `F`-typed
+/// service is used to connect to the original destination.
+#[derive(Clone)]
+pub struct Connect<B, F> {
+    recognize: Recognize,
+    balance: B,
+    forward: F,
+}
+
+pub struct ConnectFuture<B, F: svc::Service<Source>> {
+    source: Option<Source>,
+    forward: F,
+    state: State<B, F::Future>,
+}
+
+enum State<B, F> {
+    Balance(B),
+    Fallback,
+    Forward(F),
+}
+
+/// A connection that was either balanced or forwarded.
+#[derive(Debug)]
+pub enum Io<B, F> {
+    Balanced(B),
+    Forwarded(F),
+}
+
+// === impl Recognize ===
+
+impl Recognize {
+    pub fn new(dsts: IndexMap<SocketAddr, NameAddr>) -> Self {
+        Self {
+            dsts: Arc::new(dsts),
+        }
+    }
+}
+
+impl rt::Recognize<Source> for Recognize {
+    type Target = DstAddr;
+
+    fn recognize(&self, source: &Source) -> Option<Self::Target> {
+        let orig_dst = source.orig_dst_if_not_local()?;
+        let name = self.dsts.get(&orig_dst)?;
+        let dst = DstAddr::outbound(Addr::Name(name.clone()), settings::Settings::NotHttp);
+        debug!("outbound tcp dst={:?}", dst);
+        Some(dst)
+    }
+}
+
+// === impl Connect ===
+
+/// Builds a `Connect` that routes recognized connections to balancers built
+/// by `make` and forwards all other connections with `forward`.
+pub fn connect<M, F>(
+    dsts: IndexMap<SocketAddr, NameAddr>,
+    capacity: usize,
+    max_idle_age: Duration,
+    make: M,
+    forward: F,
+) -> Connect<rt::Router<Source, Recognize, M>, F>
+where
+    M: rt::Make<DstAddr> + Clone + Send + Sync + 'static,
+    M::Value: svc::Service<Source> + Clone + Send + 'static,
+    <M::Value as svc::Service<Source>>::Error: Into<Error>,
+    F: svc::Service<Source>,
+{
+    let recognize = Recognize::new(dsts);
+    let (balance, cache_bg) = rt::Router::new(recognize.clone(), make, capacity, max_idle_age);
+    let ctx = logging::Section::Proxy.bg("out tcp");
+    tokio::spawn(ctx.future(cache_bg));
+
+    Connect {
+        recognize,
+        balance,
+        forward,
+    }
+}
+
+impl<B, F> svc::Service<Source> for Connect<B, F>
+where
+    B: svc::Service<Source>,
+    B::Error: Into<Error>,
+    F: svc::Service<Source> + Clone,
+    F::Error: Into<Error>,
+{
+    type Response = Io<B::Response, F::Response>;
+    type Error = Error;
+    type Future = ConnectFuture<B::Future, F>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        try_ready!(self.balance.poll_ready().map_err(Into::into));
+        self.forward.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, source: Source) -> Self::Future {
+        use rt::Recognize as _;
+
+        if self.recognize.recognize(&source).is_none() {
+            return ConnectFuture {
+                source: None,
+                state: State::Forward(self.forward.call(source)),
+                forward: self.forward.clone(),
+            };
+        }
+
+        ConnectFuture {
+            source: Some(source.clone()),
+            state: State::Balance(self.balance.call(source)),
+            forward: self.forward.clone(),
+        }
+    }
+}
+
+// === impl ConnectFuture ===
+
+impl<B, F> Future for ConnectFuture<B, F>
+where
+    B: Future,
+    B::Error: Into<Error>,
+    F: svc::Service<Source>,
+    F::Error: Into<Error>,
+{
+    type Item = Io<B::Item, F::Response>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Balance(ref mut f) => match f.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(io)) => return Ok(Async::Ready(Io::Balanced(io))),
+                    Err(e) => {
+                        let e = e.into();
+                        if !is_unresolvable(&*e) {
+                            return Err(e);
+                        }
+                        debug!("unresolvable; forwarding to the original destination");
+                        State::Fallback
+                    }
+                },
+                State::Fallback => {
+                    try_ready!(self.forward.poll_ready().map_err(Into::into));
+                    let source = self.source.take().expect("source must only be taken once");
+                    State::Forward(self.forward.call(source))
+                }
+                State::Forward(ref mut f) => {
+                    let io = try_ready!(f.poll().map_err(Into::into));
+                    return Ok(Async::Ready(Io::Forwarded(io)));
+                }
+            };
+        }
+    }
+}
+
+fn is_unresolvable(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.is::<Unresolvable>() || e.source().map(is_unresolvable).unwrap_or(false)
+}
+
+// === impl Io ===
+
+impl<B: io::Read, F: io::Read> io::Read for Io<B, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Io::Balanced(ref mut io) => io.read(buf),
+            Io::Forwarded(ref mut io) => io.read(buf),
+        }
+    }
+}
+
+impl<B: io::Write, F: io::Write> io::Write for Io<B, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Io::Balanced(ref mut io) => io.write(buf),
+            Io::Forwarded(ref mut io) => io.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Io::Balanced(ref mut io) => io.flush(),
+            Io::Forwarded(ref mut io) => io.flush(),
+        }
+    }
+}
+
+impl<B: AsyncRead, F: AsyncRead> AsyncRead for Io<B, F> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        match self {
+            Io::Balanced(ref io) => io.prepare_uninitialized_buffer(buf),
+            Io::Forwarded(ref io) => io.prepare_uninitialized_buffer(buf),
+        }
+    }
+}
+
+impl<B: AsyncWrite, F: AsyncWrite> AsyncWrite for Io<B, F> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self {
+            Io::Balanced(ref mut io) => io.shutdown(),
+            Io::Forwarded(ref mut io) => io.shutdown(),
+        }
+    }
+
+    fn write_buf<T: Buf>(&mut self, buf: &mut T) -> Poll<usize, io::Error> {
+        match self {
+            Io::Balanced(ref mut io) => io.write_buf(buf),
+            Io::Forwarded(ref mut io) => io.write_buf(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tls;
+    use crate::Conditional;
+    use linkerd2_router::Recognize as _;
+
+    fn source(orig_dst: SocketAddr) -> Source {
+        Source::for_test(
+            ([10, 1, 1, 1], 5432).into(),
+            ([127, 0, 0, 1], 4140).into(),
+            Some(orig_dst),
+            Conditional::None(tls::ReasonForNoIdentity::Disabled),
+        )
+    }
+
+    #[test]
+    fn recognizes_configured_orig_dst() {
+        let name = NameAddr::from_str("redis.ns.svc.cluster.local:6379").unwrap();
+        let mut dsts = IndexMap::new();
+        dsts.insert(([10, 2, 2, 2], 6379).into(), name.clone());
+        let recognize = Recognize::new(dsts);
+
+        let dst = recognize
+            .recognize(&source(([10, 2, 2, 2], 6379).into()))
+            .expect("orig dst must be recognized");
+        assert_eq!(dst.dst_logical(), &Addr::Name(name));
+
+        assert!(recognize
+            .recognize(&source(([10, 2, 2, 2], 6380).into()))
+            .is_none());
+        assert!(recognize
+            .recognize(&source(([10, 3, 3, 3], 6379).into()))
+            .is_none());
+    }
+}
@@ -1,5 +1,6 @@
+use super::config::{parse_duration, parse_percent};
 use crate::api::destination as api;
-use crate::proxy::http::{profiles, retry::Budget};
+use crate::proxy::http::{fault, header_rewrite, profiles, retry::Budget};
 use crate::NameAddr;
 use crate::Never;
 use futures::sync::{mpsc, oneshot};
@@ -164,9 +165,13 @@ where
                         .into_iter()
                         .filter_map(convert_dst_override)
                         .collect();
+                    // TODO: The Destination API does not yet describe health
+                    // checks or balancer hash keys.
                     match tx.start_send(profiles::Routes {
                         routes,
                         dst_overrides,
+                        health_check: None,
+                        balance_hash_key: None,
                     }) {
                         Ok(AsyncSink::Ready) => {} // continue
                         Ok(AsyncSink::NotReady(_)) => {
@@ -255,21 +260,385 @@ fn convert_route(
     retry_budget: Option<&Arc<Budget>>,
 ) -> Option<(profiles::RequestMatch, profiles::Route)> {
     let req_match = orig.condition.and_then(convert_req_match)?;
-    let rsp_classes = orig
-        .response_classes
+    let (config_labels, labels): (Vec<_>, Vec<_>) = orig
+        .metrics_labels
         .into_iter()
-        .filter_map(convert_rsp_class)
+        .partition(|(k, _)| is_config_label(k));
+    let config_labels = convert_config_labels(config_labels);
+    let rsp_classes = convert_class_labels(&config_labels)
+        .into_iter()
+        .chain(
+            orig.response_classes
+                .into_iter()
+                .filter_map(convert_rsp_class),
+        )
         .collect();
-    let mut route = profiles::Route::new(orig.metrics_labels.into_iter(), rsp_classes);
+    let value_matches = convert_value_matches(&config_labels);
+    let req_match = if value_matches.is_empty() {
+        req_match
+    } else {
+        let ms = Some(req_match).into_iter().chain(value_matches);
+        profiles::RequestMatch::All(ms.collect())
+    };
+    let mut route = profiles::Route::new(labels.into_iter(), rsp_classes);
     if orig.is_retryable {
         set_route_retry(&mut route, retry_budget);
     }
     if let Some(timeout) = orig.timeout {
         set_route_timeout(&mut route, timeout.into());
     }
+    if let Some(fault) = convert_fault(&config_labels) {
+        route.set_fault(fault);
+    }
+    if let Some(rewrite) = convert_header_rewrite(&config_labels) {
+        route.set_header_rewrite(rewrite);
+    }
+    route.set_mirrors(convert_mirrors(&config_labels));
+    if convert_requires_jwt(&config_labels) {
+        route.set_requires_jwt();
+    }
     Some((req_match, route))
 }
 
+// The Destination API does not describe several kinds of route
+// configuration, so they are configured with route labels in the reserved
+// `config.linkerd.io/` namespace. These labels configure the proxy rather
+// than describe the route, so they are not included in the route's metric
+// labels. The labels described below are named relative to the namespace,
+// e.g. `config.linkerd.io/fault_delay`. Unrecognized labels in the namespace
+// are ignored.
+const CONFIG_LABEL_PREFIX: &str = "config.linkerd.io/";
+
+/// Route labels that configure the proxy rather than describe the route.
+fn is_config_label(k: &str) -> bool {
+    k.starts_with(CONFIG_LABEL_PREFIX)
+}
+
+/// Strips the namespace from configuration labels, ignoring those that are
+/// not recognized.
+fn convert_config_labels(labels: Vec<(String, String)>) -> Vec<(String, String)> {
+    labels
+        .into_iter()
+        .filter_map(|(k, v)| {
+            let name = &k[CONFIG_LABEL_PREFIX.len()..];
+            let known = [
+                MATCH_HEADER_LABEL_PREFIX,
+                MATCH_QUERY_LABEL_PREFIX,
+                CLASS_LABEL_PREFIX,
+                FAULT_LABEL_PREFIX,
+                REQUEST_HEADER_LABEL_PREFIX,
+                RESPONSE_HEADER_LABEL_PREFIX,
+            ]
+            .iter()
+            .any(|p| name.starts_with(p));
+            if !known && name != MIRROR_LABEL && name != JWT_LABEL {
+                warn!("unknown route configuration label: {}", k);
+                return None;
+            }
+            Some((name.to_owned(), v))
+        })
+        .collect()
+}
+
+// Header and query parameter matches are configured with these labels. A
+// route only matches requests that also match each of its labels:
+//
+// - `match_header.<name>` matches a request header;
+// - `match_query.<name>` matches a query parameter.
+//
+// An empty label value matches any value that is present. A value prefixed by
+// `~` is a regular expression that must match the entire value. Otherwise,
+// the value must match exactly.
+const MATCH_HEADER_LABEL_PREFIX: &str = "match_header.";
+const MATCH_QUERY_LABEL_PREFIX: &str = "match_query.";
+
+fn convert_value_matches(labels: &[(String, String)]) -> Vec<profiles::RequestMatch> {
+    let mut matches = Vec::new();
+    for (k, v) in labels {
+        let m = if k.starts_with(MATCH_HEADER_LABEL_PREFIX) {
+            let name = &k[MATCH_HEADER_LABEL_PREFIX.len()..];
+            http::header::HeaderName::from_bytes(name.as_bytes())
+                .ok()
+                .and_then(|name| {
+                    let value = convert_value_match(v)?;
+                    Some(profiles::RequestMatch::Header { name, value })
+                })
+        } else if k.starts_with(MATCH_QUERY_LABEL_PREFIX) {
+            let name = &k[MATCH_QUERY_LABEL_PREFIX.len()..];
+            if name.is_empty() {
+                None
+            } else {
+                convert_value_match(v).map(|value| profiles::RequestMatch::Query {
+                    name: name.to_owned(),
+                    value,
+                })
+            }
+        } else {
+            continue;
+        };
+
+        match m {
+            Some(m) => matches.push(m),
+            // A route whose match cannot be parsed must not match more
+            // requests than it was configured to, so it matches none.
+            None => {
+                warn!("invalid request match label: {}={}", k, v);
+                matches.push(profiles::RequestMatch::Any(Vec::new()));
+            }
+        }
+    }
+    matches
+}
+
+fn convert_value_match(v: &str) -> Option<profiles::ValueMatch> {
+    if v.is_empty() {
+        return Some(profiles::ValueMatch::Present);
+    }
+    if v.starts_with('~') {
+        return anchored_regex(&v[1..]).map(profiles::ValueMatch::Regex);
+    }
+    Some(profiles::ValueMatch::Exact(v.to_owned()))
+}
+
+// Response classes that match on response headers or gRPC statuses are
+// configured with these labels:
+//
+// - `class_failure_grpc_status` and `class_success_grpc_status` match a gRPC
+//   status (e.g. `14`) or an inclusive range of statuses (e.g. `1-16`);
+// - `class_failure_header.<name>` and `class_success_header.<name>` match a
+//   response header, as `match_header.<name>` matches a request header.
+//
+// These classes are matched before those that the Destination API describes,
+// and failure classes are matched before success classes.
+const CLASS_LABEL_PREFIX: &str = "class_";
+
+fn convert_class_labels(labels: &[(String, String)]) -> Vec<profiles::ResponseClass> {
+    let mut failures = Vec::new();
+    let mut successes = Vec::new();
+    for (k, v) in labels {
+        if !k.starts_with(CLASS_LABEL_PREFIX) {
+            continue;
+        }
+        let k = &k[CLASS_LABEL_PREFIX.len()..];
+        let (classes, is_failure, m) = if k.starts_with("failure_") {
+            (&mut failures, true, &k["failure_".len()..])
+        } else if k.starts_with("success_") {
+            (&mut successes, false, &k["success_".len()..])
+        } else {
+            warn!("invalid response class label: {}{}", CLASS_LABEL_PREFIX, k);
+            continue;
+        };
+
+        let m = if m == "grpc_status" {
+            let mut codes = v.trim().splitn(2, '-').map(|c| c.trim().parse::<u32>());
+            match (codes.next(), codes.next()) {
+                (Some(Ok(min)), None) => {
+                    Some(profiles::ResponseMatch::GrpcStatus { min, max: min })
+                }
+                (Some(Ok(min)), Some(Ok(max))) if min <= max => {
+                    Some(profiles::ResponseMatch::GrpcStatus { min, max })
+                }
+                _ => None,
+            }
+        } else if m.starts_with("header.") {
+            let name = &m["header.".len()..];
+            http::header::HeaderName::from_bytes(name.as_bytes())
+                .ok()
+                .and_then(|name| {
+                    let value = convert_value_match(v)?;
+                    Some(profiles::ResponseMatch::Header { name, value })
+                })
+        } else {
+            None
+        };
+
+        match m {
+            Some(m) => classes.push(profiles::ResponseClass::new(is_failure, m)),
+            None => warn!(
+                "invalid response class label: {}{}={}",
+                CLASS_LABEL_PREFIX, k, v
+            ),
+        }
+    }
+
+    failures.extend(successes);
+    failures
+}
+
+// Fault injection is configured with these labels:
+//
+// - `fault_delay_percent`: the percentage of requests to delay;
+// - `fault_delay`: a duration (e.g. `100ms`), or a range of durations
+//   (e.g. `100ms-1s`) from which each delay is chosen at random;
+// - `fault_abort_percent`: the percentage of requests to abort;
+// - `fault_abort_http_status` or `fault_abort_grpc_status`: the status with
+//   which aborted requests fail.
+const FAULT_LABEL_PREFIX: &str = "fault_";
+
+// Header rewrites are configured with these labels, each of which names an
+// operation and a header, separated by a `.`:
+//
+// - `request_header_add.<name>` and `response_header_add.<name>` append the
+//   label's value to the header;
+// - `request_header_set.<name>` and `response_header_set.<name>` replace the
+//   header with the label's value;
+// - `request_header_remove.<name>` and `response_header_remove.<name>` remove
+//   the header; the label's value is ignored.
+const REQUEST_HEADER_LABEL_PREFIX: &str = "request_header_";
+const RESPONSE_HEADER_LABEL_PREFIX: &str = "response_header_";
+
+fn convert_header_rewrite(labels: &[(String, String)]) -> Option<header_rewrite::Rewrite> {
+    let mut rewrite = header_rewrite::Rewrite::default();
+    for (k, v) in labels {
+        let (ops, op) = if k.starts_with(REQUEST_HEADER_LABEL_PREFIX) {
+            (
+                &mut rewrite.request,
+                &k[REQUEST_HEADER_LABEL_PREFIX.len()..],
+            )
+        } else if k.starts_with(RESPONSE_HEADER_LABEL_PREFIX) {
+            (
+                &mut rewrite.response,
+                &k[RESPONSE_HEADER_LABEL_PREFIX.len()..],
+            )
+        } else {
+            continue;
+        };
+
+        let mut parts = op.splitn(2, '.');
+        let op = parts.next();
+        let name = match parts
+            .next()
+            .and_then(|n| http::header::HeaderName::from_bytes(n.as_bytes()).ok())
+        {
+            Some(name) => name,
+            None => {
+                warn!("invalid header rewrite label: {}", k);
+                continue;
+            }
+        };
+        if op == Some("remove") {
+            ops.remove.push(name);
+            continue;
+        }
+        let value = match http::header::HeaderValue::from_str(v) {
+            Ok(value) => value,
+            Err(_) => {
+                warn!("invalid header rewrite value: {}={}", k, v);
+                continue;
+            }
+        };
+        match op {
+            Some("add") => ops.add.push((name, value)),
+            Some("set") => ops.set.push((name, value)),
+            _ => warn!("invalid header rewrite label: {}", k),
+        }
+    }
+
+    if rewrite.request.is_empty() && rewrite.response.is_empty() {
+        return None;
+    }
+    Some(rewrite)
+}
+
+fn convert_fault(labels: &[(String, String)]) -> Option<fault::Fault> {
+    let get = |name: &str| {
+        labels
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.trim())
+    };
+
+    let delay = match (get("fault_delay_percent"), get("fault_delay")) {
+        (Some(percent), Some(delay)) => {
+            let mut durations = delay.splitn(2, '-').map(parse_duration);
+            match (parse_percent(percent), durations.next(), durations.next()) {
+                (Ok(percent), Some(Ok(min)), None) => Some(fault::Delay {
+                    percent,
+                    min,
+                    max: min,
+                }),
+                (Ok(percent), Some(Ok(min)), Some(Ok(max))) if min <= max => {
+                    Some(fault::Delay { percent, min, max })
+                }
+                _ => {
+                    warn!("invalid fault delay: {}% {}", percent, delay);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let abort = match get("fault_abort_percent") {
+        Some(percent) => {
+            let status = match (
+                get("fault_abort_http_status"),
+                get("fault_abort_grpc_status"),
+            ) {
+                (Some(s), None) => s
+                    .parse::<u16>()
+                    .ok()
+                    .and_then(|s| http::StatusCode::from_u16(s).ok())
+                    .map(fault::AbortStatus::Http),
+                (None, Some(s)) => s.parse::<u32>().ok().map(fault::AbortStatus::Grpc),
+                _ => None,
+            };
+            match (parse_percent(percent), status) {
+                (Ok(percent), Some(status)) => Some(fault::Abort { percent, status }),
+                _ => {
+                    warn!("invalid fault abort: {:?}", labels);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    if delay.is_none() && abort.is_none() {
+        return None;
+    }
+    Some(fault::Fault { delay, abort })
+}
+
+// Mirror destinations are configured with the `mirror` label, whose value is
+// a comma-separated list of authorities (e.g.
+// `web-shadow.ns.svc.cluster.local:80`). Each request that matches the route
+// is copied to each of these authorities.
+const MIRROR_LABEL: &str = "mirror";
+
+fn convert_mirrors(labels: &[(String, String)]) -> Vec<NameAddr> {
+    let mut mirrors = Vec::new();
+    let values = labels
+        .iter()
+        .filter(|(k, _)| k == MIRROR_LABEL)
+        .map(|(_, v)| v);
+    for authority in values.flat_map(|v| v.split(',')) {
+        let authority = authority.trim();
+        if authority.is_empty() {
+            continue;
+        }
+        match NameAddr::from_str(authority) {
+            Ok(addr) => {
+                if !mirrors.contains(&addr) {
+                    mirrors.push(addr);
+                }
+            }
+            Err(_) => warn!("invalid mirror authority: {}", authority),
+        }
+    }
+    mirrors
+}
+
+// Routes that require a JWT on inbound requests are configured with a `jwt`
+// label whose value is `required`.
+const JWT_LABEL: &str = "jwt";
+
+fn convert_requires_jwt(labels: &[(String, String)]) -> bool {
+    labels
+        .iter()
+        .any(|(k, v)| k == JWT_LABEL && v == "required")
+}
+
 fn convert_dst_override(orig: api::WeightedDst) -> Option<profiles::WeightedAddr> {
     if orig.weight == 0 {
         return None;
@@ -320,17 +689,7 @@ fn convert_req_match(orig: api::RequestMatch) -> Option<profiles::RequestMatch>
             profiles::RequestMatch::Not(Box::new(m))
         }
         api::request_match::Match::Path(api::PathMatch { regex }) => {
-            let regex = regex.trim();
-            let re = match (regex.starts_with('^'), regex.ends_with('$')) {
-                (true, true) => Regex::new(regex).ok()?,
-                (hd_anchor, tl_anchor) => {
-                    let hd = if hd_anchor { "" } else { "^" };
-                    let tl = if tl_anchor { "" } else { "$" };
-                    let re = format!("{}{}{}", hd, regex, tl);
-                    Regex::new(&re).ok()?
-                }
-            };
-            profiles::RequestMatch::Path(re)
+            profiles::RequestMatch::Path(anchored_regex(&regex)?)
         }
         api::request_match::Match::Method(mm) => {
             let m = mm.r#type.and_then(|m| m.try_as_http().ok())?;
@@ -341,6 +700,20 @@ fn convert_req_match(orig: api::RequestMatch) -> Option<profiles::RequestMatch>
     Some(m)
 }
 
+/// Parses a regular expression that must match an entire value.
+pub(super) fn anchored_regex(regex: &str) -> Option<Regex> {
+    let regex = regex.trim();
+    match (regex.starts_with('^'), regex.ends_with('$')) {
+        (true, true) => Regex::new(regex).ok(),
+        (hd_anchor, tl_anchor) => {
+            let hd = if hd_anchor { "" } else { "^" };
+            let tl = if tl_anchor { "" } else { "$" };
+            let re = format!("{}{}{}", hd, regex, tl);
+            Regex::new(&re).ok()
+        }
+    }
+}
+
 fn convert_rsp_class(orig: api::ResponseClass) -> Option<profiles::ResponseClass> {
     let c = orig.condition.and_then(convert_rsp_match)?;
     Some(profiles::ResponseClass::new(orig.is_failure, c))
@@ -447,4 +820,262 @@ mod tests {
             true
         }
     }
+
+    #[test]
+    fn header_rewrite_from_labels() {
+        let labels = vec![
+            ("request_header_set.x-env".to_string(), "canary".to_string()),
+            (
+                "response_header_remove.x-internal".to_string(),
+                "".to_string(),
+            ),
+            ("response_header_add.x-tag".to_string(), "a".to_string()),
+            ("request_header_replace.x-env".to_string(), "b".to_string()),
+            ("fault_delay".to_string(), "1s".to_string()),
+        ];
+        let rewrite = convert_header_rewrite(&labels).expect("must have a rewrite");
+        assert_eq!(
+            rewrite.request,
+            header_rewrite::Ops {
+                set: vec![(
+                    http::header::HeaderName::from_static("x-env"),
+                    http::header::HeaderValue::from_static("canary"),
+                )],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            rewrite.response,
+            header_rewrite::Ops {
+                add: vec![(
+                    http::header::HeaderName::from_static("x-tag"),
+                    http::header::HeaderValue::from_static("a"),
+                )],
+                remove: vec![http::header::HeaderName::from_static("x-internal")],
+                ..Default::default()
+            }
+        );
+        assert!(is_config_label(
+            "config.linkerd.io/request_header_set.x-env"
+        ));
+        assert!(!is_config_label("request_header_set.x-env"));
+    }
+
+    #[test]
+    fn request_matches_from_labels() {
+        let route = |labels: &[(&str, &str)]| {
+            let orig = api::Route {
+                condition: Some(api::RequestMatch {
+                    r#match: Some(api::request_match::Match::Path(api::PathMatch {
+                        regex: "/api/.*".into(),
+                    })),
+                }),
+                metrics_labels: labels
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            };
+            convert_route(orig, None).expect("route must convert")
+        };
+        let req = |uri: &str, tenant: Option<&str>| {
+            let mut req = http::Request::builder();
+            req.uri(uri);
+            if let Some(tenant) = tenant {
+                req.header("x-tenant", tenant);
+            }
+            req.body(()).unwrap()
+        };
+
+        let (m, r) = route(&[
+            ("config.linkerd.io/match_header.x-tenant", "gold"),
+            ("config.linkerd.io/match_query.debug", ""),
+            ("config.linkerd.io/unknown", "x"),
+            ("match_header.x-other", "gold"),
+            ("tier", "gold"),
+        ]);
+        assert_eq!(
+            r.labels().len(),
+            2,
+            "configuration labels must not be metric labels"
+        );
+        assert_eq!(
+            r.labels().get("match_header.x-other").map(String::as_str),
+            Some("gold"),
+            "labels outside of the namespace must be metric labels"
+        );
+        assert!(m.is_match(&req("/api/v1?debug", Some("gold"))));
+        assert!(m.is_match(&req("/api/v1?a=b&debug=1", Some("gold"))));
+        assert!(!m.is_match(&req("/api/v1?debug", Some("silver"))));
+        assert!(!m.is_match(&req("/api/v1?debug", None)));
+        assert!(!m.is_match(&req("/api/v1", Some("gold"))));
+        assert!(!m.is_match(&req("/other?debug", Some("gold"))));
+
+        let (m, _) = route(&[("config.linkerd.io/match_header.x-tenant", "~gold|silver")]);
+        assert!(m.is_match(&req("/api/v1", Some("silver"))));
+        assert!(!m.is_match(&req("/api/v1", Some("goldish"))));
+
+        let (m, _) = route(&[("config.linkerd.io/match_query.v", "2")]);
+        assert!(m.is_match(&req("/api/v1?v=2", None)));
+        assert!(!m.is_match(&req("/api/v1?v=22", None)));
+
+        // Invalid matches never match.
+        let (m, _) = route(&[("config.linkerd.io/match_header.x-tenant", "~(")]);
+        assert!(!m.is_match(&req("/api/v1", Some("("))));
+        let (m, _) = route(&[("config.linkerd.io/match_query.", "x")]);
+        assert!(!m.is_match(&req("/api/v1?=x", None)));
+    }
+
+    #[test]
+    fn response_classes_from_labels() {
+        let labels = vec![
+            ("class_success_grpc_status".to_string(), "0".to_string()),
+            ("class_failure_grpc_status".to_string(), "1-16".to_string()),
+            ("class_failure_header.x-error".to_string(), "".to_string()),
+            ("class_failure_grpc_status".to_string(), "16-1".to_string()),
+            ("class_other".to_string(), "x".to_string()),
+            ("tier".to_string(), "gold".to_string()),
+        ];
+        let classes = convert_class_labels(&labels);
+        assert_eq!(classes.len(), 3);
+        assert!(classes[0].is_failure() && classes[1].is_failure());
+        assert!(!classes[2].is_failure());
+        assert!(is_config_label(
+            "config.linkerd.io/class_failure_grpc_status"
+        ));
+        assert!(!is_config_label("class_of_service"));
+
+        let rsp = |grpc_status: &str, error: Option<&str>| {
+            let mut rsp = http::Response::builder();
+            rsp.header("grpc-status", grpc_status);
+            if let Some(error) = error {
+                rsp.header("x-error", error);
+            }
+            rsp.body(()).unwrap()
+        };
+        let class = |rsp: &http::Response<()>| {
+            classes
+                .iter()
+                .find(|c| c.is_match(rsp))
+                .map(|c| c.is_failure())
+        };
+        assert_eq!(class(&rsp("0", None)), Some(false));
+        assert_eq!(class(&rsp("14", None)), Some(true));
+        assert_eq!(class(&rsp("0", Some("any"))), Some(true));
+        assert_eq!(class(&rsp("17", None)), None);
+    }
+
+    #[test]
+    fn fault_from_labels() {
+        let labels = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(convert_fault(&labels(&[])), None);
+        assert_eq!(
+            convert_fault(&labels(&[
+                ("fault_delay_percent", "10"),
+                ("fault_delay", "100ms-1s"),
+                ("fault_abort_percent", "5"),
+                ("fault_abort_grpc_status", "14"),
+            ])),
+            Some(fault::Fault {
+                delay: Some(fault::Delay {
+                    percent: 10,
+                    min: Duration::from_millis(100),
+                    max: Duration::from_secs(1),
+                }),
+                abort: Some(fault::Abort {
+                    percent: 5,
+                    status: fault::AbortStatus::Grpc(14),
+                }),
+            })
+        );
+        assert_eq!(
+            convert_fault(&labels(&[
+                ("fault_abort_percent", "100"),
+                ("fault_abort_http_status", "503"),
+            ])),
+            Some(fault::Fault {
+                delay: None,
+                abort: Some(fault::Abort {
+                    percent: 100,
+                    status: fault::AbortStatus::Http(http::StatusCode::SERVICE_UNAVAILABLE),
+                }),
+            })
+        );
+        assert_eq!(
+            convert_fault(&labels(&[
+                ("fault_delay_percent", "101"),
+                ("fault_delay", "1s"),
+            ])),
+            None
+        );
+    }
+
+    #[test]
+    fn mirrors_from_labels() {
+        let route = |mirror: Option<&str>| {
+            let mut orig = api::Route {
+                condition: Some(api::RequestMatch {
+                    r#match: Some(api::request_match::Match::Path(api::PathMatch {
+                        regex: "/.*".into(),
+                    })),
+                }),
+                ..Default::default()
+            };
+            if let Some(m) = mirror {
+                let label = format!("{}{}", CONFIG_LABEL_PREFIX, MIRROR_LABEL);
+                orig.metrics_labels.insert(label, m.into());
+            }
+            let (_, route) = convert_route(orig, None).expect("route must convert");
+            route
+        };
+        let addr = |a: &str| NameAddr::from_str(a).unwrap();
+
+        assert!(route(None).mirrors().is_empty());
+        let r = route(Some(
+            "a.ns.svc.cluster.local:80, b.ns.svc.cluster.local:8080,\
+             a.ns.svc.cluster.local:80,not an authority,",
+        ));
+        assert_eq!(
+            r.mirrors(),
+            &[
+                addr("a.ns.svc.cluster.local:80"),
+                addr("b.ns.svc.cluster.local:8080"),
+            ][..]
+        );
+        assert!(r.labels().is_empty(), "mirrors must not be metric labels");
+        assert!(is_config_label("config.linkerd.io/mirror"));
+        assert!(!is_config_label(MIRROR_LABEL));
+    }
+
+    #[test]
+    fn requires_jwt_from_labels() {
+        let route = |jwt: Option<&str>| {
+            let mut orig = api::Route {
+                condition: Some(api::RequestMatch {
+                    r#match: Some(api::request_match::Match::Path(api::PathMatch {
+                        regex: "/.*".into(),
+                    })),
+                }),
+                ..Default::default()
+            };
+            if let Some(v) = jwt {
+                let label = format!("{}{}", CONFIG_LABEL_PREFIX, JWT_LABEL);
+                orig.metrics_labels.insert(label, v.into());
+            }
+            let (_, route) = convert_route(orig, None).expect("route must convert");
+            route
+        };
+
+        assert!(!route(None).requires_jwt());
+        assert!(!route(Some("optional")).requires_jwt());
+        let r = route(Some("required"));
+        assert!(r.requires_jwt());
+        assert!(r.labels().is_empty(), "jwt must not be a metric label");
+    }
 }
@@ -0,0 +1,113 @@
+use crate::opencensus::proto::trace as oc;
+use crate::proxy::http::trace_context::{Kind, Span, SpanSink};
+use crate::Error;
+use futures::sync::mpsc;
+
+/// The number of spans that may be buffered while the collector is
+/// unavailable.
+const BUFFER_CAPACITY: usize = 1_000;
+
+/// Converts proxy spans to OpenCensus spans and sends them to the exporter.
+#[derive(Clone, Debug)]
+pub struct SpanConverter {
+    tx: mpsc::Sender<oc::Span>,
+}
+
+/// Returns a `SpanConverter` that sends spans to the returned receiver.
+pub fn channel() -> (SpanConverter, mpsc::Receiver<oc::Span>) {
+    let (tx, rx) = mpsc::channel(BUFFER_CAPACITY);
+    (SpanConverter { tx }, rx)
+}
+
+impl SpanSink for SpanConverter {
+    fn try_send(&mut self, span: Span) -> Result<(), Error> {
+        let kind = match span.kind {
+            Kind::Server => oc::span::SpanKind::Server,
+            Kind::Client => oc::span::SpanKind::Client,
+        };
+
+        let span = oc::Span {
+            trace_id: span.trace_id.as_bytes().to_vec(),
+            span_id: span.span_id.as_bytes().to_vec(),
+            parent_span_id: span.parent_id.as_bytes().to_vec(),
+            name: Some(span.span_name.into()),
+            kind: kind as i32,
+            start_time: Some(span.start.into()),
+            end_time: Some(span.end.into()),
+            attributes: Some(oc::span::Attributes::from_labels(span.labels)),
+        };
+
+        self.tx.try_send(span).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::http::trace_context::{Id, Labels};
+    use futures::Stream;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn span(kind: Kind) -> Span {
+        let mut labels = Labels::new();
+        labels.insert("http.path".into(), "/foo".into());
+        let start = UNIX_EPOCH + Duration::from_secs(1);
+        Span {
+            trace_id: Id::from_hex("0af7651916cd43dd8448eb211c80319c", 16).unwrap(),
+            span_id: Id::from_hex("00f067aa0ba902b7", 8).unwrap(),
+            parent_id: Id::from_hex("b7ad6b7169203331", 8).unwrap(),
+            span_name: "/foo".into(),
+            kind,
+            start,
+            end: start + Duration::from_millis(5),
+            labels,
+        }
+    }
+
+    #[test]
+    fn converts_spans() {
+        let (mut converter, rx) = channel();
+        converter.try_send(span(Kind::Server)).unwrap();
+        converter.try_send(span(Kind::Client)).unwrap();
+        drop(converter);
+
+        let spans = rx.wait().collect::<Result<Vec<_>, ()>>().unwrap();
+        assert_eq!(spans.len(), 2);
+
+        let server = &spans[0];
+        assert_eq!(
+            server.trace_id,
+            vec![
+                0x0a, 0xf7, 0x65, 0x19, 0x16, 0xcd, 0x43, 0xdd, 0x84, 0x48, 0xeb, 0x21, 0x1c, 0x80,
+                0x31, 0x9c
+            ]
+        );
+        assert_eq!(
+            server.span_id,
+            vec![0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]
+        );
+        assert_eq!(
+            server.parent_span_id,
+            vec![0xb7, 0xad, 0x6b, 0x71, 0x69, 0x20, 0x33, 0x31]
+        );
+        assert_eq!(server.name.as_ref().map(|n| n.value.as_str()), Some("/foo"));
+        assert_eq!(server.kind, oc::span::SpanKind::Server as i32);
+        let start = server.start_time.as_ref().unwrap();
+        assert_eq!((start.seconds, start.nanos), (1, 0));
+        let end = server.end_time.as_ref().unwrap();
+        assert_eq!((end.seconds, end.nanos), (1, 5_000_000));
+        assert_eq!(
+            server.attributes.as_ref().unwrap().attribute_map["http.path"],
+            oc::AttributeValue::from("/foo".to_string())
+        );
+
+        assert_eq!(spans[1].kind, oc::span::SpanKind::Client as i32);
+    }
+
+    #[test]
+    fn fails_when_the_exporter_is_gone() {
+        let (mut converter, rx) = channel();
+        drop(rx);
+        assert!(converter.try_send(span(Kind::Server)).is_err());
+    }
+}
@@ -0,0 +1,694 @@
+//! Discovers destinations from a local file rather than the control plane.
+//!
+//! The file is a JSON or YAML document that maps authorities to their
+//! endpoints and profile routes. YAML documents are limited to the subset
+//! described in `crate::yaml`. For example:
+//!
+//! ```json
+//! {
+//!   "destinations": {
+//!     "web.default.svc.cluster.local:8080": {
+//!       "endpoints": [{
+//!         "addr": "10.1.2.3:8080",
+//!         "weight": 10000,
+//!         "identity": "web.default.serviceaccount.identity.linkerd.cluster.local",
+//!         "protocol_hint": "h2",
+//!         "labels": {"pod": "web-0"}
+//!       }],
+//!       "routes": [{
+//!         "condition": {"method": "GET", "path": "/api/.*"},
+//!         "labels": {"route": "api"},
+//!         "timeout_ms": 500,
+//!         "mirrors": ["web-shadow.default.svc.cluster.local:8080"]
+//!       }],
+//!       "dst_overrides": [{"authority": "web-v2.default.svc.cluster.local:8080", "weight": 10000}],
+//!       "health_check": "http:/healthz",
+//!       "balance_hash_key": "header:x-user-id"
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! A destination's `health_check` is one of `http:<path>`, `grpc`, or
+//! `grpc:<service>`. Its `balance_hash_key` is one of `source-ip`,
+//! `header:<name>`, or `cookie:<name>`, and overrides the proxy's
+//! `LINKERD2_PROXY_OUTBOUND_BALANCE_HASH_KEY` for the destination.
+//!
+//! All fields but `addr` and `authority` are optional. The file is polled for
+//! changes; each change is published to active resolutions as endpoint
+//! additions and removals, and to profile watches as new routes. A change
+//! that cannot be parsed is ignored.
+
+use super::file_watch::Watch;
+use crate::core::resolve::{self, Resolve, Update};
+use crate::json::Value;
+use crate::proxy::http::{consistent_hash, health_check, profiles};
+use crate::resolve::{Metadata, ProtocolHint, Unresolvable};
+use crate::yaml;
+use crate::{identity, NameAddr, Never};
+use futures::{future, Async, Future, Poll, Stream};
+use indexmap::IndexMap;
+use std::collections::VecDeque;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, error, trace};
+
+/// The default endpoint weight, matching the Destination service's.
+const DEFAULT_WEIGHT: u32 = 10_000;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub path: PathBuf,
+    pub poll_interval: Duration,
+
+    /// The destinations read as the proxy was configured.
+    pub table: Table,
+}
+
+/// The destinations described by a discovery file.
+#[derive(Clone, Debug, Default)]
+pub struct Table(Arc<IndexMap<NameAddr, Destination>>);
+
+#[derive(Debug, Default)]
+struct Destination {
+    endpoints: IndexMap<SocketAddr, Metadata>,
+    routes: Vec<(profiles::RequestMatch, profiles::Route)>,
+    dst_overrides: Vec<profiles::WeightedAddr>,
+    health_check: Option<health_check::Check>,
+    balance_hash_key: Option<consistent_hash::HashKey>,
+}
+
+/// Resolves endpoints and watches routes from the discovery file.
+#[derive(Clone)]
+pub struct Discovery(watch::Receiver<Table>);
+
+/// Polls the discovery file, publishing its contents as they change.
+pub struct Daemon {
+    path: PathBuf,
+    table: watch::Sender<Table>,
+    watch: Watch,
+}
+
+/// Publishes a destination's endpoint changes.
+pub struct Resolution {
+    dst: NameAddr,
+    table: watch::Receiver<Table>,
+    endpoints: IndexMap<SocketAddr, Metadata>,
+    pending: VecDeque<Update<Metadata>>,
+}
+
+/// Publishes a destination's routes each time the discovery file changes.
+pub struct RoutesStream {
+    dst: NameAddr,
+    table: watch::Receiver<Table>,
+    initial: bool,
+}
+
+/// Discovers destinations via the control plane or, if configured, a file.
+#[derive(Clone)]
+pub enum Discover<C> {
+    Control(C),
+    Static(Discovery),
+}
+
+/// A resolution or route stream from either discovery source.
+pub enum Discovered<C, S> {
+    Control(C),
+    Static(S),
+}
+
+pub fn new(config: Config) -> (Discovery, Daemon) {
+    let (tx, rx) = watch::channel(config.table);
+    // The file was read as the proxy was configured.
+    let mut watch = Watch::new(vec![config.path.clone()], config.poll_interval);
+    let modified = watch.modified();
+    watch.loaded(modified);
+    let daemon = Daemon {
+        path: config.path,
+        table: tx,
+        watch,
+    };
+    (Discovery(rx), daemon)
+}
+
+// === impl Table ===
+
+impl Table {
+    /// Parses a JSON or YAML discovery file.
+    pub fn parse(doc: &[u8]) -> Result<Self, String> {
+        let doc = Value::parse(doc)
+            .or_else(|| yaml::parse(doc))
+            .ok_or_else(|| "invalid JSON or YAML".to_owned())?;
+
+        let mut dsts = IndexMap::new();
+        match doc.get("destinations") {
+            None => {}
+            Some(Value::Object(entries)) => {
+                for (authority, dst) in entries.iter() {
+                    let name = parse_name_addr(authority)?;
+                    let dst = Destination::from_json(dst)
+                        .map_err(|e| format!("destination {}: {}", authority, e))?;
+                    dsts.insert(name, dst);
+                }
+            }
+            Some(_) => return Err("destinations must be an object".into()),
+        }
+
+        Ok(Table(Arc::new(dsts)))
+    }
+
+    fn endpoints(&self, dst: &NameAddr) -> IndexMap<SocketAddr, Metadata> {
+        self.0
+            .get(dst)
+            .map(|d| d.endpoints.clone())
+            .unwrap_or_default()
+    }
+
+    fn routes(&self, dst: &NameAddr) -> profiles::Routes {
+        match self.0.get(dst) {
+            Some(d) => profiles::Routes {
+                routes: d.routes.clone(),
+                dst_overrides: d.dst_overrides.clone(),
+                health_check: d.health_check.clone(),
+                balance_hash_key: d.balance_hash_key.clone(),
+            },
+            None => profiles::Routes {
+                routes: Vec::new(),
+                dst_overrides: Vec::new(),
+                health_check: None,
+                balance_hash_key: None,
+            },
+        }
+    }
+}
+
+// === impl Destination ===
+
+impl Destination {
+    fn from_json(dst: &Value) -> Result<Self, String> {
+        let mut endpoints = IndexMap::new();
+        for ep in array(dst, "endpoints")? {
+            let addr = str_field(ep, "addr")?
+                .ok_or("endpoint must have an addr")?
+                .parse::<SocketAddr>()
+                .map_err(|e| format!("invalid endpoint addr: {}", e))?;
+            endpoints.insert(addr, endpoint_metadata(ep)?);
+        }
+
+        let routes = array(dst, "routes")?
+            .iter()
+            .map(route)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut dst_overrides = Vec::new();
+        for o in array(dst, "dst_overrides")? {
+            let authority = str_field(o, "authority")?.ok_or("override must have an authority")?;
+            dst_overrides.push(profiles::WeightedAddr {
+                addr: parse_name_addr(authority)?,
+                weight: u32_field(o, "weight")?.unwrap_or(DEFAULT_WEIGHT),
+            });
+        }
+
+        let health_check = match str_field(dst, "health_check")? {
+            None => None,
+            Some(check) => Some(
+                check
+                    .parse()
+                    .map_err(|e| format!("invalid health check {}: {}", check, e))?,
+            ),
+        };
+
+        let balance_hash_key = match str_field(dst, "balance_hash_key")? {
+            None => None,
+            Some(key) => Some(
+                key.parse()
+                    .map_err(|e| format!("invalid balance hash key {}: {}", key, e))?,
+            ),
+        };
+
+        Ok(Destination {
+            endpoints,
+            routes,
+            dst_overrides,
+            health_check,
+            balance_hash_key,
+        })
+    }
+}
+
+fn endpoint_metadata(ep: &Value) -> Result<Metadata, String> {
+    let weight = u32_field(ep, "weight")?.unwrap_or(DEFAULT_WEIGHT);
+    let identity = match str_field(ep, "identity")? {
+        None => None,
+        Some(id) => Some(
+            identity::Name::from_hostname(id.as_bytes())
+                .map_err(|_| format!("invalid identity: {}", id))?,
+        ),
+    };
+    let protocol_hint = match str_field(ep, "protocol_hint")? {
+        None | Some("unknown") => ProtocolHint::Unknown,
+        Some("h2") => ProtocolHint::Http2,
+        Some(hint) => return Err(format!("invalid protocol hint: {}", hint)),
+    };
+    Ok(Metadata::new(labels(ep)?, protocol_hint, identity, weight))
+}
+
+fn route(r: &Value) -> Result<(profiles::RequestMatch, profiles::Route), String> {
+    let mut matches = Vec::new();
+    if let Some(cond) = r.get("condition") {
+        if let Some(method) = str_field(cond, "method")? {
+            let method = http::Method::from_bytes(method.as_bytes())
+                .map_err(|_| format!("invalid method: {}", method))?;
+            matches.push(profiles::RequestMatch::Method(method));
+        }
+        if let Some(path) = str_field(cond, "path")? {
+            // Like the Destination service's path regexes, these must match
+            // the entire path.
+            let re = super::profiles::anchored_regex(path)
+                .ok_or_else(|| format!("invalid path regex: {}", path))?;
+            matches.push(profiles::RequestMatch::Path(re));
+        }
+    }
+
+    let mut route = profiles::Route::new(labels(r)?.into_iter(), Vec::new());
+    if let Some(ms) = u32_field(r, "timeout_ms")? {
+        route.set_timeout(Duration::from_millis(u64::from(ms)));
+    }
+    let mirrors = array(r, "mirrors")?
+        .iter()
+        .map(|m| {
+            m.as_str()
+                .ok_or_else(|| "mirror must be a string".to_owned())
+                .and_then(parse_name_addr)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    route.set_mirrors(mirrors);
+    Ok((profiles::RequestMatch::All(matches), route))
+}
+
+fn parse_name_addr(s: &str) -> Result<NameAddr, String> {
+    NameAddr::from_str(s).map_err(|_| format!("invalid authority: {}", s))
+}
+
+fn array<'a>(v: &'a Value, name: &str) -> Result<&'a [Value], String> {
+    match v.get(name) {
+        None => Ok(&[]),
+        Some(Value::Array(vs)) => Ok(vs),
+        Some(_) => Err(format!("{} must be an array", name)),
+    }
+}
+
+fn str_field<'a>(v: &'a Value, name: &str) -> Result<Option<&'a str>, String> {
+    match v.get(name) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(format!("{} must be a string", name)),
+    }
+}
+
+fn u32_field(v: &Value, name: &str) -> Result<Option<u32>, String> {
+    match v.get(name).map(|n| n.as_f64()) {
+        None => Ok(None),
+        Some(Some(n)) if n >= 0.0 && n <= f64::from(u32::max_value()) && n.fract() == 0.0 => {
+            Ok(Some(n as u32))
+        }
+        Some(_) => Err(format!("{} must be a non-negative integer", name)),
+    }
+}
+
+fn labels(v: &Value) -> Result<IndexMap<String, String>, String> {
+    match v.get("labels") {
+        None => Ok(IndexMap::new()),
+        Some(Value::Object(labels)) => labels
+            .iter()
+            .map(|(k, v)| match v.as_str() {
+                Some(v) => Ok((k.clone(), v.to_owned())),
+                None => Err(format!("label {} must be a string", k)),
+            })
+            .collect(),
+        Some(_) => Err("labels must be an object".into()),
+    }
+}
+
+// === impl Discovery ===
+
+impl Resolve<NameAddr> for Discovery {
+    type Endpoint = Metadata;
+    type Resolution = Resolution;
+    type Future = future::FutureResult<Resolution, Unresolvable>;
+
+    /// Resolves destinations that are listed in the discovery file.
+    fn resolve(&self, dst: &NameAddr) -> Self::Future {
+        let table = self.0.clone();
+        if !table.get_ref().0.contains_key(dst) {
+            trace!("{} is not in the discovery file", dst);
+            return future::err(Unresolvable::new());
+        }
+
+        let mut resolution = Resolution {
+            dst: dst.clone(),
+            table,
+            endpoints: IndexMap::new(),
+            pending: VecDeque::new(),
+        };
+        let endpoints = resolution.table.get_ref().endpoints(dst);
+        resolution.update(endpoints);
+        future::ok(resolution)
+    }
+}
+
+impl profiles::GetRoutes for Discovery {
+    type Stream = RoutesStream;
+
+    fn get_routes(&self, dst: &NameAddr) -> Option<Self::Stream> {
+        Some(RoutesStream {
+            dst: dst.clone(),
+            table: self.0.clone(),
+            initial: true,
+        })
+    }
+}
+
+// === impl Resolution ===
+
+impl Resolution {
+    /// Queues the changes needed to move from the current endpoints to
+    /// `endpoints`.
+    fn update(&mut self, endpoints: IndexMap<SocketAddr, Metadata>) {
+        for addr in self.endpoints.keys() {
+            if !endpoints.contains_key(addr) {
+                self.pending.push_back(Update::Remove(*addr));
+            }
+        }
+        for (addr, meta) in endpoints.iter() {
+            if self.endpoints.get(addr) != Some(meta) {
+                self.pending.push_back(Update::Add(*addr, meta.clone()));
+            }
+        }
+        self.endpoints = endpoints;
+    }
+}
+
+impl resolve::Resolution for Resolution {
+    type Endpoint = Metadata;
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Update<Metadata>, Never> {
+        loop {
+            if let Some(update) = self.pending.pop_front() {
+                return Ok(Async::Ready(update));
+            }
+
+            let endpoints = match self.table.poll_ref() {
+                Ok(Async::Ready(Some(table))) => table.endpoints(&self.dst),
+                // If the daemon is gone, the endpoints will never change.
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) | Err(_) => {
+                    return Ok(Async::NotReady);
+                }
+            };
+            self.update(endpoints);
+        }
+    }
+}
+
+// === impl RoutesStream ===
+
+impl Stream for RoutesStream {
+    type Item = profiles::Routes;
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.initial {
+            self.initial = false;
+            return Ok(Async::Ready(Some(self.table.get_ref().routes(&self.dst))));
+        }
+
+        match self.table.poll_ref() {
+            Ok(Async::Ready(Some(table))) => Ok(Async::Ready(Some(table.routes(&self.dst)))),
+            Ok(Async::Ready(None)) | Ok(Async::NotReady) | Err(_) => Ok(Async::NotReady),
+        }
+    }
+}
+
+// === impl Daemon ===
+
+impl Daemon {
+    fn load(&self) -> Option<Table> {
+        let doc = fs::read(&self.path)
+            .map_err(|e| error!("Failed to read {}: {}", self.path.display(), e))
+            .ok()?;
+        Table::parse(&doc)
+            .map_err(|e| error!("Invalid discovery file {}: {}", self.path.display(), e))
+            .ok()
+    }
+}
+
+impl Future for Daemon {
+    type Item = ();
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let modified = match self.watch.poll() {
+                Ok(Async::Ready(modified)) => modified,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => {
+                    error!("Discovery file can no longer be watched: {}", e);
+                    return Ok(Async::Ready(()));
+                }
+            };
+
+            trace!("discovery file changed");
+            if let Some(table) = self.load() {
+                debug!("loaded {} destinations", table.0.len());
+                if self.table.broadcast(table).is_err() {
+                    return Ok(Async::Ready(()));
+                }
+                self.watch.loaded(modified);
+            }
+        }
+    }
+}
+
+// === impl Discover ===
+
+impl<C> Resolve<NameAddr> for Discover<C>
+where
+    C: Resolve<NameAddr, Endpoint = Metadata>,
+    C::Future: Future<Error = Unresolvable>,
+{
+    type Endpoint = Metadata;
+    type Resolution = Discovered<C::Resolution, Resolution>;
+    type Future = future::Either<
+        future::Map<C::Future, fn(C::Resolution) -> Self::Resolution>,
+        future::Map<
+            future::FutureResult<Resolution, Unresolvable>,
+            fn(Resolution) -> Self::Resolution,
+        >,
+    >;
+
+    fn resolve(&self, dst: &NameAddr) -> Self::Future {
+        match self {
+            Discover::Control(c) => {
+                future::Either::A(c.resolve(dst).map(Discovered::Control as fn(_) -> _))
+            }
+            Discover::Static(d) => {
+                future::Either::B(d.resolve(dst).map(Discovered::Static as fn(_) -> _))
+            }
+        }
+    }
+}
+
+impl<C> profiles::GetRoutes for Discover<C>
+where
+    C: profiles::GetRoutes,
+{
+    type Stream = Discovered<C::Stream, RoutesStream>;
+
+    fn get_routes(&self, dst: &NameAddr) -> Option<Self::Stream> {
+        match self {
+            Discover::Control(c) => c.get_routes(dst).map(Discovered::Control),
+            Discover::Static(d) => d.get_routes(dst).map(Discovered::Static),
+        }
+    }
+}
+
+// === impl Discovered ===
+
+impl<C> resolve::Resolution for Discovered<C, Resolution>
+where
+    C: resolve::Resolution<Endpoint = Metadata>,
+{
+    type Endpoint = Metadata;
+    type Error = C::Error;
+
+    fn poll(&mut self) -> Poll<Update<Metadata>, Self::Error> {
+        match self {
+            Discovered::Control(c) => c.poll(),
+            Discovered::Static(s) => s.poll().map_err(|never| match never {}),
+        }
+    }
+}
+
+impl<C> Stream for Discovered<C, RoutesStream>
+where
+    C: Stream<Item = profiles::Routes, Error = Never>,
+{
+    type Item = profiles::Routes;
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self {
+            Discovered::Control(c) => c.poll(),
+            Discovered::Static(s) => s.poll(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::resolve::Resolution as _;
+
+    fn table(json: &str) -> Table {
+        Table::parse(json.as_bytes()).expect("table must be valid")
+    }
+
+    #[test]
+    fn parses_destinations() {
+        let t = table(
+            r#"{"destinations": {"web.ns.svc.cluster.local:8080": {
+                "endpoints": [
+                    {"addr": "10.1.1.1:8080", "weight": 5000, "protocol_hint": "h2",
+                     "identity": "web.ns.serviceaccount.identity.linkerd.cluster.local",
+                     "labels": {"pod": "web-0"}},
+                    {"addr": "10.1.1.2:8080"}
+                ],
+                "routes": [{"condition": {"method": "GET", "path": "/api/.*"},
+                            "labels": {"route": "api"}, "timeout_ms": 500,
+                            "mirrors": ["web-shadow.ns.svc.cluster.local:8080"]}],
+                "dst_overrides": [{"authority": "web-v2.ns.svc.cluster.local:8080"}],
+                "health_check": "grpc",
+                "balance_hash_key": "cookie:session"
+            }}}"#,
+        );
+
+        let dst = NameAddr::from_str("web.ns.svc.cluster.local:8080").unwrap();
+        let endpoints = t.endpoints(&dst);
+        assert_eq!(endpoints.len(), 2);
+        let ep = &endpoints[&"10.1.1.1:8080".parse::<SocketAddr>().unwrap()];
+        assert_eq!(ep.protocol_hint(), ProtocolHint::Http2);
+        assert_eq!(ep.labels().get("pod").map(String::as_str), Some("web-0"));
+        assert!(ep.identity().is_some());
+
+        let routes = t.routes(&dst);
+        assert_eq!(routes.routes.len(), 1);
+        assert_eq!(
+            routes.routes[0].1.timeout(),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(routes.dst_overrides[0].weight, DEFAULT_WEIGHT);
+        assert_eq!(routes.routes[0].1.mirrors().len(), 1);
+        assert_eq!(
+            routes.health_check,
+            Some(health_check::Check::Grpc(String::new()))
+        );
+        assert_eq!(
+            routes.balance_hash_key,
+            Some(consistent_hash::HashKey::Cookie("session".into()))
+        );
+
+        assert!(Table::parse(br#"{"destinations": {"web": {}}}"#).is_err());
+        assert!(Table::parse(br#"{"destinations": {"web:80": {"endpoints": [{}]}}}"#).is_err());
+        assert!(Table::parse(
+            br#"{"destinations": {"web:80": {"endpoints": [{"addr": "10.1.1.1:80", "weight": -1}]}}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_yaml_destinations() {
+        let t = Table::parse(
+            b"destinations:
+  web.ns.svc.cluster.local:8080:
+    endpoints:
+    - addr: 10.1.1.1:8080
+      weight: 5000
+      labels: {\"pod\": \"web-0\"}
+    routes:
+    - condition: {\"path\": \"/api/.*\"}
+      timeout_ms: 500
+",
+        )
+        .expect("table must be valid");
+
+        let dst = NameAddr::from_str("web.ns.svc.cluster.local:8080").unwrap();
+        let endpoints = t.endpoints(&dst);
+        let ep = &endpoints[&"10.1.1.1:8080".parse::<SocketAddr>().unwrap()];
+        assert_eq!(ep.labels().get("pod").map(String::as_str), Some("web-0"));
+
+        let routes = t.routes(&dst);
+        assert_eq!(routes.routes.len(), 1);
+        assert_eq!(
+            routes.routes[0].1.timeout(),
+            Some(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn resolutions_publish_changes() {
+        let path = PathBuf::from("discovery.json");
+        let t = table(
+            r#"{"destinations": {"web.ns.svc.cluster.local:8080": {"endpoints": [
+                {"addr": "10.1.1.1:8080"}, {"addr": "10.1.1.2:8080"}
+            ]}}}"#,
+        );
+        let (discovery, daemon) = new(Config {
+            path,
+            poll_interval: Duration::from_secs(10),
+            table: t,
+        });
+
+        let dst = NameAddr::from_str("web.ns.svc.cluster.local:8080").unwrap();
+        let other = NameAddr::from_str("other.ns.svc.cluster.local:8080").unwrap();
+        assert!(discovery.resolve(&other).wait().is_err());
+
+        let mut resolution = discovery.resolve(&dst).wait().expect("must resolve");
+        assert_eq!(
+            updates(&mut resolution),
+            vec!["+10.1.1.1:8080", "+10.1.1.2:8080"]
+        );
+
+        let t = table(
+            r#"{"destinations": {"web.ns.svc.cluster.local:8080": {"endpoints": [
+                {"addr": "10.1.1.2:8080", "weight": 1}, {"addr": "10.1.1.3:8080"}
+            ]}}}"#,
+        );
+        daemon.table.broadcast(t).unwrap();
+        assert_eq!(
+            updates(&mut resolution),
+            vec!["-10.1.1.1:8080", "+10.1.1.2:8080", "+10.1.1.3:8080"]
+        );
+    }
+
+    /// Drains a resolution's pending updates.
+    fn updates(resolution: &mut Resolution) -> Vec<String> {
+        let mut updates = Vec::new();
+        future::poll_fn(|| {
+            while let Async::Ready(up) = resolution.poll()? {
+                updates.push(match up {
+                    Update::Add(addr, _) => format!("+{}", addr),
+                    Update::Remove(addr) => format!("-{}", addr),
+                });
+            }
+            Ok::<_, Never>(Async::Ready(()))
+        })
+        .wait()
+        .unwrap();
+        updates
+    }
+}
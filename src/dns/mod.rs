@@ -7,7 +7,10 @@ use std::{fmt, net};
 use tracing::trace;
 pub use trust_dns_resolver::config::ResolverOpts;
 pub use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
-use trust_dns_resolver::{config::ResolverConfig, system_conf, AsyncResolver, BackgroundLookupIp};
+use trust_dns_resolver::{
+    config::ResolverConfig, lookup::SrvLookupFuture, system_conf, AsyncResolver, BackgroundLookup,
+    BackgroundLookupIp,
+};
 
 #[derive(Clone)]
 pub struct Resolver {
@@ -28,6 +31,10 @@ pub struct IpAddrFuture(logging::ContextualFuture<Ctx, BackgroundLookupIp>);
 
 pub struct RefineFuture(logging::ContextualFuture<Ctx, BackgroundLookupIp>);
 
+pub struct IpListFuture(logging::ContextualFuture<Ctx, BackgroundLookupIp>);
+
+pub struct SrvFuture(logging::ContextualFuture<Ctx, BackgroundLookup<SrvLookupFuture>>);
+
 struct Ctx(Name);
 
 pub struct Refine {
@@ -35,6 +42,20 @@ pub struct Refine {
     pub valid_until: Instant,
 }
 
+/// All of the addresses resolved for a name.
+#[derive(Clone, Debug)]
+pub struct IpList {
+    pub ips: Vec<net::IpAddr>,
+    pub valid_until: Instant,
+}
+
+/// The targets of a name's SRV records.
+#[derive(Clone, Debug)]
+pub struct SrvList {
+    pub targets: Vec<(Name, u16)>,
+    pub valid_until: Instant,
+}
+
 impl fmt::Display for Ctx {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(f, "dns={}", self.0)
@@ -92,6 +113,19 @@ impl Resolver {
         let f = self.resolver.lookup_ip(name.as_ref());
         RefineFuture(logging::context_future(Ctx(name.clone()), f))
     }
+
+    /// Resolves all of the A and AAAA records for `name`.
+    pub fn resolve_all_ips(&self, name: &Name) -> IpListFuture {
+        let f = self.resolver.lookup_ip(name.as_ref());
+        IpListFuture(logging::context_future(Ctx(name.clone()), f))
+    }
+
+    /// Resolves the SRV records for `name`, returning each record's target
+    /// and port.
+    pub fn resolve_srv(&self, name: &Name) -> SrvFuture {
+        let f = self.resolver.lookup_srv(name.as_ref());
+        SrvFuture(logging::context_future(Ctx(name.clone()), f))
+    }
 }
 
 /// Note: `AsyncResolver` does not implement `Debug`, so we must manually
@@ -134,6 +168,43 @@ impl Future for RefineFuture {
     }
 }
 
+impl Future for IpListFuture {
+    type Item = IpList;
+    type Error = ResolveError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let lookup = try_ready!(self.0.poll());
+        let list = IpList {
+            ips: lookup.iter().collect(),
+            valid_until: lookup.valid_until(),
+        };
+        Ok(Async::Ready(list))
+    }
+}
+
+impl Future for SrvFuture {
+    type Item = SrvList;
+    type Error = ResolveError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let lookup = try_ready!(self.0.poll());
+        let targets = lookup
+            .iter()
+            .filter_map(|srv| {
+                // Targets that are not valid DNS names (e.g. `.`, which
+                // indicates that the service is unavailable) are ignored.
+                let name = Name::try_from(srv.target().to_ascii().as_bytes()).ok()?;
+                Some((name, srv.port()))
+            })
+            .collect();
+        let list = SrvList {
+            targets,
+            valid_until: lookup.valid_until(),
+        };
+        Ok(Async::Ready(list))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Name, Suffix};
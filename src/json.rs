@@ -0,0 +1,393 @@
+//! Just enough JSON parsing to read JWTs, key sets, and discovery files.
+
+use indexmap::IndexMap;
+
+/// Bounds the nesting of untrusted documents.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(IndexMap<String, Value>),
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+// === impl Value ===
+
+impl Value {
+    /// Parses a complete JSON document.
+    pub fn parse(input: &[u8]) -> Option<Self> {
+        let mut parser = Parser { input, pos: 0 };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos != input.len() {
+            return None;
+        }
+        Some(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(ref obj) => obj.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+// === impl Parser ===
+
+impl<'a> Parser<'a> {
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        self.skip_whitespace();
+        match *self.input.get(self.pos)? {
+            b'{' => self.object(depth),
+            b'[' => self.array(depth),
+            b'"' => self.string().map(Value::String),
+            b't' => self.literal("true", Value::Bool(true)),
+            b'f' => self.literal("false", Value::Bool(false)),
+            b'n' => self.literal("null", Value::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Option<Value> {
+        self.pos += 1;
+        let mut obj = IndexMap::new();
+
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Some(Value::Object(obj));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(b':') {
+                return None;
+            }
+            let value = self.value(depth + 1)?;
+            // Documents with duplicate keys are ambiguous, so they're
+            // rejected rather than resolved in favor of either value.
+            if obj.insert(key, value).is_some() {
+                return None;
+            }
+
+            self.skip_whitespace();
+            if self.eat(b'}') {
+                return Some(Value::Object(obj));
+            }
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Option<Value> {
+        self.pos += 1;
+        let mut arr = Vec::new();
+
+        self.skip_whitespace();
+        if self.eat(b']') {
+            return Some(Value::Array(arr));
+        }
+
+        loop {
+            arr.push(self.value(depth + 1)?);
+
+            self.skip_whitespace();
+            if self.eat(b']') {
+                return Some(Value::Array(arr));
+            }
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if !self.eat(b'"') {
+            return None;
+        }
+
+        let mut buf = Vec::new();
+        loop {
+            match *self.input.get(self.pos)? {
+                b'"' => {
+                    self.pos += 1;
+                    return String::from_utf8(buf).ok();
+                }
+                b'\\' => {
+                    self.pos += 1;
+                    let c = match *self.input.get(self.pos)? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.escaped_char()?,
+                        _ => return None,
+                    };
+                    self.pos += 1;
+                    let mut utf8 = [0; 4];
+                    buf.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                }
+                b if b < 0x20 => return None,
+                b => {
+                    self.pos += 1;
+                    buf.push(b);
+                }
+            }
+        }
+    }
+
+    /// Reads the code point of a `\uXXXX` escape (or a surrogate pair of
+    /// them), leaving the position on its final digit.
+    fn escaped_char(&mut self) -> Option<char> {
+        let hi = self.hex4()?;
+        if hi < 0xd800 || hi > 0xdfff {
+            return ::std::char::from_u32(hi);
+        }
+        if hi > 0xdbff || self.input.get(self.pos + 1..self.pos + 3)? != b"\\u" {
+            return None;
+        }
+        self.pos += 2;
+        let lo = self.hex4()?;
+        if lo < 0xdc00 || lo > 0xdfff {
+            return None;
+        }
+        ::std::char::from_u32(0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00))
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = self.input.get(self.pos + 1..self.pos + 5)?;
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        let digits = ::std::str::from_utf8(digits).ok()?;
+        let n = u32::from_str_radix(digits, 16).ok()?;
+        self.pos += 4;
+        Some(n)
+    }
+
+    /// Reads a number, which must be of the form `-?int(.digits)?(e[+-]?digits)?`
+    /// where `int` has no leading zeros.
+    fn number(&mut self) -> Option<Value> {
+        let start = self.pos;
+        self.eat(b'-');
+        if !self.eat(b'0') && self.digits() == 0 {
+            return None;
+        }
+        if self.eat(b'.') && self.digits() == 0 {
+            return None;
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            if !self.eat(b'+') {
+                self.eat(b'-');
+            }
+            if self.digits() == 0 {
+                return None;
+            }
+        }
+
+        let n = ::std::str::from_utf8(&self.input[start..self.pos]).ok()?;
+        n.parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .map(Value::Number)
+    }
+
+    /// Skips a run of digits, returning its length.
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.input.get(self.pos) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn literal(&mut self, lit: &str, value: Value) -> Option<Value> {
+        let end = self.pos + lit.len();
+        if self.input.get(self.pos..end)? != lit.as_bytes() {
+            return None;
+        }
+        self.pos = end;
+        Some(value)
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.input.get(self.pos) == Some(&b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.input.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_documents() {
+        let doc = br#" {"sub": "a\"b\u00e9\ud83d\ude00", "n": -1.5e2, "ok": true,
+                        "aud": ["x", null, {}], "nested": {"k": []}} "#;
+        let value = Value::parse(doc).expect("document must parse");
+        assert_eq!(
+            value.get("sub").and_then(Value::as_str),
+            Some("a\"b\u{e9}\u{1f600}")
+        );
+        assert_eq!(value.get("n").and_then(Value::as_f64), Some(-150.0));
+        assert_eq!(value.get("ok"), Some(&Value::Bool(true)));
+        assert_eq!(
+            value.get("aud"),
+            Some(&Value::Array(vec![
+                Value::String("x".into()),
+                Value::Null,
+                Value::Object(IndexMap::new()),
+            ]))
+        );
+
+        assert_eq!(Value::parse(b"{\"a\":1,}"), None);
+        assert_eq!(Value::parse(b"{\"a\":1} x"), None);
+        assert_eq!(Value::parse(b"\"\\ud83d\""), None);
+        assert_eq!(Value::parse(b"+1"), None);
+        let deep = format!("{}{}", "[".repeat(64), "]".repeat(64));
+        assert_eq!(Value::parse(deep.as_bytes()), None);
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        let cases: &[&[u8]] = &[
+            b"",
+            b" ",
+            b"{",
+            b"}",
+            b"[1,]",
+            b"[,1]",
+            b"{\"a\"}",
+            b"{\"a\" 1}",
+            b"{a:1}",
+            b"{'a':1}",
+            b"{\"a\":1 \"b\":2}",
+            b"\"unterminated",
+            b"\"tab\there\"",
+            b"\"\\x\"",
+            b"\"\xff\"",
+            b"tru",
+            b"nul",
+            b"True",
+            b"[1][2]",
+        ];
+        for case in cases {
+            assert_eq!(
+                Value::parse(case),
+                None,
+                "{:?}",
+                String::from_utf8_lossy(case)
+            );
+        }
+    }
+
+    #[test]
+    fn parses_escapes_and_surrogates() {
+        let s = |doc: &[u8]| Value::parse(doc).and_then(|v| v.as_str().map(String::from));
+
+        assert_eq!(
+            s(br#""\"\\\/\b\f\n\r\t""#),
+            Some("\"\\/\u{8}\u{c}\n\r\t".into())
+        );
+        assert_eq!(
+            s(br#""\u0041\u00E9\u20ac""#),
+            Some("A\u{e9}\u{20ac}".into())
+        );
+        assert_eq!(s(br#""\uD834\uDD1E""#), Some("\u{1d11e}".into()));
+        assert_eq!(s("\"\u{e9}\"".as_bytes()), Some("\u{e9}".into()));
+
+        // Escapes must have exactly four hex digits.
+        assert_eq!(s(br#""\u41""#), None);
+        assert_eq!(s(br#""\u+041""#), None);
+        assert_eq!(s(br#""\u004G""#), None);
+        // Surrogates must be paired, high then low.
+        assert_eq!(s(br#""\ude00""#), None);
+        assert_eq!(s(br#""\ud83d\u0041""#), None);
+        assert_eq!(s(br#""\ud83dx""#), None);
+        assert_eq!(s(br#""\ude00\ud83d""#), None);
+    }
+
+    #[test]
+    fn rejects_duplicate_keys() {
+        assert_eq!(Value::parse(br#"{"alg":"ES256","alg":"none"}"#), None);
+        assert_eq!(Value::parse(br#"{"a":{"b":1,"b":1}}"#), None);
+        assert!(Value::parse(br#"{"a":{"b":1},"b":{"a":1}}"#).is_some());
+    }
+
+    #[test]
+    fn parses_numbers() {
+        let n = |doc: &[u8]| Value::parse(doc).and_then(|v| v.as_f64());
+
+        assert_eq!(n(b"0"), Some(0.0));
+        assert_eq!(n(b"-0"), Some(0.0));
+        assert_eq!(n(b"1585000000"), Some(1_585_000_000.0));
+        assert_eq!(n(b"1.25"), Some(1.25));
+        assert_eq!(n(b"1e3"), Some(1000.0));
+        assert_eq!(n(b"1E+3"), Some(1000.0));
+        assert_eq!(n(b"25e-1"), Some(2.5));
+
+        let invalid: &[&[u8]] = &[
+            b"-",
+            b"01",
+            b"-01",
+            b"1.",
+            b".1",
+            b"1.e3",
+            b"1e",
+            b"1e+",
+            b"--1",
+            b"0x10",
+            b"1_000",
+            b"NaN",
+            b"Infinity",
+            b"1e999",
+        ];
+        for case in invalid {
+            assert_eq!(n(case), None, "{:?}", String::from_utf8_lossy(case));
+        }
+    }
+}
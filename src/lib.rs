@@ -13,12 +13,15 @@ use linkerd2_task as task;
 
 pub mod app;
 mod dns;
+mod json;
 pub mod logging;
+pub mod opencensus;
 mod proxy;
 mod svc;
 mod tap;
 pub mod telemetry;
 pub mod transport;
+mod yaml;
 
 pub use self::logging::trace;
 pub use self::transport::SoOriginalDst;
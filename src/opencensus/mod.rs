@@ -0,0 +1,319 @@
+//! Exports spans to an OpenCensus agent.
+//!
+//! Spans are read from a channel and are streamed to the agent in batches.
+//! While the agent is unavailable, spans accumulate in the channel; once the
+//! channel is full, spans are dropped by their producers.
+
+use self::proto::agent::{
+    client::TraceService, ExportTraceServiceRequest, ExportTraceServiceResponse,
+};
+use self::proto::{common::Node, trace::Span};
+use crate::Never;
+use futures::sync::mpsc;
+use futures::{stream, try_ready, Async, Future, Poll, Stream};
+use std::time::Duration;
+use tokio_timer::{clock, Delay};
+use tower_grpc::{self as grpc, generic::client::GrpcService, BoxBody};
+use tracing::{debug, trace, warn};
+
+pub mod proto;
+
+/// The maximum number of spans sent in a single export message.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// Drives the export of spans to an OpenCensus agent.
+pub struct SpanExporter<T>
+where
+    T: GrpcService<BoxBody>,
+{
+    client: TraceService<T>,
+    node: Node,
+    backoff: Duration,
+    spans: mpsc::Receiver<Span>,
+    state: State<T>,
+}
+
+type Requests = stream::MapErr<mpsc::Receiver<ExportTraceServiceRequest>, fn(()) -> grpc::Status>;
+
+enum State<T>
+where
+    T: GrpcService<BoxBody>,
+{
+    Idle,
+    Backoff(Delay),
+    Sending(Export<T>),
+}
+
+struct Export<T>
+where
+    T: GrpcService<BoxBody>,
+{
+    tx: mpsc::Sender<ExportTraceServiceRequest>,
+    /// The node is sent with the first request on each stream.
+    node: Option<Node>,
+    response: Response<T>,
+}
+
+enum Response<T>
+where
+    T: GrpcService<BoxBody>,
+{
+    Waiting(grpc::client::streaming::ResponseFuture<ExportTraceServiceResponse, T::Future>),
+    Streaming(grpc::Streaming<ExportTraceServiceResponse, T::ResponseBody>),
+}
+
+/// Describes why an export stream completed.
+enum Closed {
+    /// All span producers have been dropped.
+    Spans,
+    /// The agent ended the export stream.
+    Export,
+}
+
+// === impl SpanExporter ===
+
+impl<T> SpanExporter<T>
+where
+    T: GrpcService<BoxBody>,
+{
+    pub fn new(client: T, node: Node, backoff: Duration, spans: mpsc::Receiver<Span>) -> Self {
+        Self {
+            client: TraceService::new(client),
+            node,
+            backoff,
+            spans,
+            state: State::Idle,
+        }
+    }
+}
+
+impl<T> Future for SpanExporter<T>
+where
+    T: GrpcService<BoxBody>,
+{
+    type Item = ();
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Idle => {
+                    match self.client.poll_ready() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(())) => {}
+                        Err(e) => {
+                            warn!("span collector unavailable: {}", e);
+                            self.state = State::Backoff(Delay::new(clock::now() + self.backoff));
+                            continue;
+                        }
+                    }
+
+                    let (tx, rx) = mpsc::channel(0);
+                    let canceled: fn(()) -> grpc::Status =
+                        |()| grpc::Status::new(grpc::Code::Cancelled, "span exporter dropped");
+                    let requests: Requests = rx.map_err(canceled);
+                    debug!("opening span export stream");
+                    let rsp = self.client.export(grpc::Request::new(requests));
+                    State::Sending(Export {
+                        tx,
+                        node: Some(self.node.clone()),
+                        response: Response::Waiting(rsp),
+                    })
+                }
+                State::Sending(ref mut export) => {
+                    match export.poll(&mut self.spans) {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(Closed::Spans)) => {
+                            debug!("span producers dropped; exporter stopping");
+                            return Ok(Async::Ready(()));
+                        }
+                        Ok(Async::Ready(Closed::Export)) => {
+                            debug!("span export stream closed");
+                        }
+                        Err(e) => {
+                            warn!("span export stream failed: {}", e);
+                        }
+                    }
+                    State::Backoff(Delay::new(clock::now() + self.backoff))
+                }
+                State::Backoff(ref mut delay) => match delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(_) | Ok(Async::Ready(())) => State::Idle,
+                },
+            };
+        }
+    }
+}
+
+// === impl Export ===
+
+impl<T> Export<T>
+where
+    T: GrpcService<BoxBody>,
+{
+    fn poll(&mut self, spans: &mut mpsc::Receiver<Span>) -> Poll<Closed, grpc::Status> {
+        // Drive the response so that the stream's failure is noticed.
+        if let Async::Ready(()) = self.response.poll()? {
+            return Ok(Async::Ready(Closed::Export));
+        }
+
+        loop {
+            match self.tx.poll_ready() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(())) => {}
+                // The request stream was dropped by the client.
+                Err(_) => return Ok(Async::Ready(Closed::Export)),
+            }
+
+            let req = match poll_request(spans, &mut self.node) {
+                Async::NotReady => return Ok(Async::NotReady),
+                Async::Ready(None) => return Ok(Async::Ready(Closed::Spans)),
+                Async::Ready(Some(req)) => req,
+            };
+            if self.tx.try_send(req).is_err() {
+                return Ok(Async::Ready(Closed::Export));
+            }
+        }
+    }
+}
+
+/// Batches the spans that are ready into an export request.
+///
+/// Returns ready with `None` once all span producers have been dropped and
+/// all spans have been exported.
+fn poll_request(
+    spans: &mut mpsc::Receiver<Span>,
+    node: &mut Option<Node>,
+) -> Async<Option<ExportTraceServiceRequest>> {
+    let mut batch = Vec::new();
+    let mut spans_closed = false;
+    while batch.len() < MAX_BATCH_SIZE {
+        match spans.poll() {
+            Ok(Async::Ready(Some(span))) => batch.push(span),
+            Ok(Async::Ready(None)) | Err(()) => {
+                spans_closed = true;
+                break;
+            }
+            Ok(Async::NotReady) => break,
+        }
+    }
+
+    if batch.is_empty() {
+        if spans_closed {
+            return Async::Ready(None);
+        }
+        return Async::NotReady;
+    }
+
+    trace!("exporting {} spans", batch.len());
+    Async::Ready(Some(ExportTraceServiceRequest {
+        node: node.take(),
+        spans: batch,
+    }))
+}
+
+// === impl Response ===
+
+impl<T> Response<T>
+where
+    T: GrpcService<BoxBody>,
+{
+    /// Polls the export response, returning ready when the stream has ended.
+    fn poll(&mut self) -> Poll<(), grpc::Status> {
+        loop {
+            *self = match *self {
+                Response::Waiting(ref mut f) => {
+                    let rsp = try_ready!(f.poll());
+                    trace!("span export stream established");
+                    Response::Streaming(rsp.into_inner())
+                }
+                Response::Streaming(ref mut s) => match try_ready!(s.poll()) {
+                    Some(_) => continue,
+                    None => return Ok(Async::Ready(())),
+                },
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future, Sink};
+
+    fn span(n: u8) -> Span {
+        Span {
+            span_id: vec![n],
+            ..Span::default()
+        }
+    }
+
+    fn node() -> Node {
+        Node {
+            service_info: Some(proto::common::ServiceInfo {
+                name: "linkerd-proxy".into(),
+            }),
+            ..Node::default()
+        }
+    }
+
+    /// Polls for requests until no spans are ready.
+    fn poll_requests(
+        spans: &mut mpsc::Receiver<Span>,
+        node: &mut Option<Node>,
+    ) -> (Vec<ExportTraceServiceRequest>, bool) {
+        future::lazy(|| {
+            let mut reqs = Vec::new();
+            loop {
+                match poll_request(spans, node) {
+                    Async::Ready(Some(req)) => reqs.push(req),
+                    Async::Ready(None) => return Ok::<_, ()>((reqs, true)),
+                    Async::NotReady => return Ok((reqs, false)),
+                }
+            }
+        })
+        .wait()
+        .unwrap()
+    }
+
+    #[test]
+    fn batches_ready_spans() {
+        let (tx, mut rx) = mpsc::channel(1_000);
+        let mut tx = tx.wait();
+        for n in 0..250 {
+            tx.send(span(n as u8)).unwrap();
+        }
+
+        let mut node = Some(node());
+        let (reqs, closed) = poll_requests(&mut rx, &mut node);
+        assert!(!closed, "producers have not been dropped");
+        assert_eq!(
+            reqs.iter().map(|r| r.spans.len()).collect::<Vec<_>>(),
+            vec![MAX_BATCH_SIZE, MAX_BATCH_SIZE, 50]
+        );
+        assert_eq!(reqs[0].spans[0], span(0));
+        assert_eq!(reqs[2].spans[49], span(249));
+
+        // The node is only sent with the first request on a stream.
+        assert_eq!(reqs[0].node, Some(self::node()));
+        assert!(reqs[1..].iter().all(|r| r.node.is_none()));
+        assert_eq!(node, None);
+
+        tx.send(span(0)).unwrap();
+        drop(tx);
+        let (reqs, closed) = poll_requests(&mut rx, &mut node);
+        assert_eq!(reqs.len(), 1, "remaining spans are exported");
+        assert!(reqs[0].node.is_none());
+        assert!(closed, "producers have been dropped");
+    }
+
+    #[test]
+    fn waits_for_spans() {
+        let (_tx, mut rx) = mpsc::channel::<Span>(1);
+        let mut node = Some(node());
+        let (reqs, closed) = poll_requests(&mut rx, &mut node);
+        assert!(reqs.is_empty());
+        assert!(!closed);
+        assert_eq!(node, Some(self::node()), "node is retained until sent");
+    }
+}
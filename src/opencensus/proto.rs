@@ -0,0 +1,257 @@
+//! A subset of the OpenCensus agent protocol.
+//!
+//! Only the messages and fields that the proxy emits are described here;
+//! collectors ignore fields that are omitted.
+
+pub mod common {
+    use std::collections::HashMap;
+
+    /// Identifies the process that emits spans.
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Node {
+        #[prost(message, optional, tag = "3")]
+        pub service_info: Option<ServiceInfo>,
+        #[prost(map = "string, string", tag = "4")]
+        pub attributes: HashMap<String, String>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct ServiceInfo {
+        #[prost(string, tag = "1")]
+        pub name: String,
+    }
+}
+
+pub mod trace {
+    use std::collections::HashMap;
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Span {
+        #[prost(bytes, tag = "1")]
+        pub trace_id: Vec<u8>,
+        #[prost(bytes, tag = "2")]
+        pub span_id: Vec<u8>,
+        #[prost(bytes, tag = "3")]
+        pub parent_span_id: Vec<u8>,
+        #[prost(message, optional, tag = "4")]
+        pub name: Option<TruncatableString>,
+        #[prost(enumeration = "span::SpanKind", tag = "14")]
+        pub kind: i32,
+        #[prost(message, optional, tag = "5")]
+        pub start_time: Option<::prost_types::Timestamp>,
+        #[prost(message, optional, tag = "6")]
+        pub end_time: Option<::prost_types::Timestamp>,
+        #[prost(message, optional, tag = "7")]
+        pub attributes: Option<span::Attributes>,
+    }
+
+    pub mod span {
+        use super::AttributeValue;
+        use std::collections::HashMap;
+
+        #[derive(Clone, PartialEq, ::prost::Message)]
+        pub struct Attributes {
+            #[prost(map = "string, message", tag = "1")]
+            pub attribute_map: HashMap<String, AttributeValue>,
+            #[prost(int32, tag = "2")]
+            pub dropped_attributes_count: i32,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+        #[repr(i32)]
+        pub enum SpanKind {
+            Unspecified = 0,
+            Server = 1,
+            Client = 2,
+        }
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct AttributeValue {
+        #[prost(oneof = "attribute_value::Value", tags = "1")]
+        pub value: Option<attribute_value::Value>,
+    }
+
+    pub mod attribute_value {
+        use super::TruncatableString;
+
+        #[derive(Clone, PartialEq, ::prost::Oneof)]
+        pub enum Value {
+            #[prost(message, tag = "1")]
+            StringValue(TruncatableString),
+        }
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct TruncatableString {
+        #[prost(string, tag = "1")]
+        pub value: String,
+        #[prost(int32, tag = "2")]
+        pub truncated_byte_count: i32,
+    }
+
+    impl From<String> for TruncatableString {
+        fn from(value: String) -> Self {
+            Self {
+                value,
+                truncated_byte_count: 0,
+            }
+        }
+    }
+
+    impl From<String> for AttributeValue {
+        fn from(value: String) -> Self {
+            Self {
+                value: Some(attribute_value::Value::StringValue(value.into())),
+            }
+        }
+    }
+
+    impl span::Attributes {
+        pub fn from_labels<I: IntoIterator<Item = (String, String)>>(labels: I) -> Self {
+            let attribute_map = labels
+                .into_iter()
+                .map(|(k, v)| (k, v.into()))
+                .collect::<HashMap<_, _>>();
+            Self {
+                attribute_map,
+                dropped_attributes_count: 0,
+            }
+        }
+    }
+}
+
+pub mod agent {
+    use super::{common, trace};
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct ExportTraceServiceRequest {
+        #[prost(message, optional, tag = "1")]
+        pub node: Option<common::Node>,
+        #[prost(message, repeated, tag = "2")]
+        pub spans: Vec<trace::Span>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct ExportTraceServiceResponse {}
+
+    pub mod client {
+        use super::{ExportTraceServiceRequest, ExportTraceServiceResponse};
+        use futures::{Poll, Stream};
+        use http::uri::PathAndQuery;
+        use tower_grpc::client::{streaming, Encodable, Grpc};
+        use tower_grpc::generic::client::GrpcService;
+        use tower_grpc::{Request, Status};
+
+        /// A client for the OpenCensus agent's `TraceService`.
+        #[derive(Clone, Debug)]
+        pub struct TraceService<T> {
+            inner: Grpc<T>,
+        }
+
+        impl<T> TraceService<T> {
+            pub fn new(inner: T) -> Self {
+                Self {
+                    inner: Grpc::new(inner),
+                }
+            }
+
+            pub fn poll_ready<R>(&mut self) -> Poll<(), Status>
+            where
+                T: GrpcService<R>,
+            {
+                self.inner.poll_ready()
+            }
+
+            /// Opens a stream over which spans are exported.
+            pub fn export<R, B>(
+                &mut self,
+                request: Request<B>,
+            ) -> streaming::ResponseFuture<ExportTraceServiceResponse, T::Future>
+            where
+                T: GrpcService<R>,
+                B: Stream<Item = ExportTraceServiceRequest>,
+                B: Encodable<R>,
+            {
+                let path = PathAndQuery::from_static(
+                    "/opencensus.proto.agent.trace.v1.TraceService/Export",
+                );
+                self.inner.streaming(request, path)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{agent, common, trace};
+    use prost::Message;
+
+    fn encode<M: Message>(msg: &M) -> Vec<u8> {
+        let mut buf = Vec::new();
+        msg.encode(&mut buf).expect("must encode");
+        buf
+    }
+
+    /// Checks that fields are encoded with the tags that the OpenCensus
+    /// protocol defines. Fields are encoded in tag order.
+    #[test]
+    fn span_wire_format() {
+        let span = trace::Span {
+            trace_id: vec![1],
+            span_id: vec![2],
+            parent_span_id: vec![3],
+            name: Some("a".to_string().into()),
+            kind: trace::span::SpanKind::Client as i32,
+            start_time: None,
+            end_time: None,
+            attributes: Some(trace::span::Attributes::from_labels(vec![(
+                "k".to_string(),
+                "v".to_string(),
+            )])),
+        };
+        assert_eq!(
+            encode(&span),
+            vec![
+                0x0a, 1, 1, // trace_id = 1
+                0x12, 1, 2, // span_id = 2
+                0x1a, 1, 3, // parent_span_id = 3
+                0x22, 3, 0x0a, 1, b'a', // name = 4 { value = 1 }
+                0x3a, 12, // attributes = 7
+                0x0a, 10, // attribute_map = 1
+                0x0a, 1, b'k', // key = 1
+                0x12, 5, // value = 2
+                0x0a, 3, 0x0a, 1, b'v', // string_value = 1 { value = 1 }
+                0x70, 2, // kind = 14
+            ]
+        );
+    }
+
+    #[test]
+    fn export_request_wire_format() {
+        let req = agent::ExportTraceServiceRequest {
+            node: Some(common::Node {
+                service_info: Some(common::ServiceInfo {
+                    name: "p".to_string(),
+                }),
+                ..Default::default()
+            }),
+            spans: vec![trace::Span {
+                span_id: vec![2],
+                ..Default::default()
+            }],
+        };
+        assert_eq!(
+            encode(&req),
+            vec![
+                0x0a, 5, // node = 1
+                0x1a, 3, 0x0a, 1, b'p', // service_info = 3 { name = 1 }
+                0x12, 3, // spans = 2
+                0x12, 1, 2, // span_id = 2
+            ]
+        );
+
+        let decoded = agent::ExportTraceServiceRequest::decode(encode(&req)).expect("must decode");
+        assert_eq!(decoded, req);
+    }
+}
@@ -0,0 +1,647 @@
+//! Inbound authorization by client identity.
+//!
+//! A `Policy` describes, for each inbound port, whether plaintext connections
+//! are authorized or whether clients must authenticate via mTLS, optionally
+//! with one of a set of identities. Unauthorized TCP connections are refused
+//! before they are forwarded; unauthorized HTTP requests fail with an
+//! `Unauthorized` error.
+
+use crate::dns;
+use crate::identity;
+use crate::metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics, Metric};
+use crate::proxy::Source;
+use crate::transport::tls;
+use crate::{svc, Conditional, Error};
+use futures::{future, try_ready, Future, Poll};
+use http;
+use indexmap::IndexMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+use tokio_timer::clock;
+use tracing::debug;
+
+metrics! {
+    inbound_authz_allow_total: Counter {
+        "Total count of inbound connections and requests that were authorized"
+    },
+    inbound_authz_deny_total: Counter {
+        "Total count of inbound connections and requests that were not authorized"
+    }
+}
+
+/// Authorizes inbound connections by port.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    ports: IndexMap<u16, PortPolicy>,
+    default: PortPolicy,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PortPolicy {
+    /// All connections are authorized, whether or not they are secured by
+    /// mTLS.
+    AllowPlaintext,
+
+    /// Only clients that authenticate via mTLS are authorized. If any
+    /// identities are specified, the client's identity must match one of them.
+    RequireIdentity(Vec<Match>),
+}
+
+/// Matches a client identity.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Match {
+    Exact(identity::Name),
+    Suffix(dns::Suffix),
+}
+
+/// Indicates that a connection was not authorized.
+#[derive(Clone, Debug)]
+pub struct Unauthorized {
+    port: u16,
+    tls_peer: tls::PeerIdentity,
+}
+
+/// Creates a registry and report for authorization metrics.
+///
+/// Metrics for a port and client are dropped once they have not been updated
+/// for `retain_idle` and no connection refers to them.
+pub fn new(retain_idle: Duration) -> (Registry, Report) {
+    let inner = Arc::new(Mutex::new(IndexMap::new()));
+    let report = Report {
+        by_key: inner.clone(),
+        retain_idle,
+    };
+    (Registry(inner), report)
+}
+
+type ByKey = IndexMap<Key, Arc<Mutex<Counts>>>;
+
+/// Holds authorization metrics for all ports and clients.
+#[derive(Clone, Debug)]
+pub struct Registry(Arc<Mutex<ByKey>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted authorization
+/// metrics.
+#[derive(Clone, Debug)]
+pub struct Report {
+    by_key: Arc<Mutex<ByKey>>,
+    retain_idle: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    port: u16,
+    client_id: Option<identity::Name>,
+}
+
+#[derive(Debug)]
+struct Counts {
+    allow: Counter,
+    deny: Counter,
+    last_update: Instant,
+}
+
+/// Authorizes each HTTP connection's requests.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    policy: Option<Arc<Policy>>,
+    registry: Registry,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    policy: Option<Arc<Policy>>,
+    registry: Registry,
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    authz: Option<Authz>,
+    inner: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    authz: Option<Authz>,
+    inner: S,
+}
+
+/// Refuses unauthorized TCP connections.
+#[derive(Clone, Debug)]
+pub struct ConnectLayer {
+    policy: Option<Arc<Policy>>,
+    registry: Registry,
+}
+
+#[derive(Clone, Debug)]
+pub struct Connect<C> {
+    policy: Option<Arc<Policy>>,
+    registry: Registry,
+    inner: C,
+}
+
+/// The outcome of authorizing a connection.
+#[derive(Clone, Debug)]
+struct Authz {
+    result: Result<(), Unauthorized>,
+    counts: Arc<Mutex<Counts>>,
+}
+
+// === impl Policy ===
+
+impl Policy {
+    pub fn new(default: PortPolicy, ports: IndexMap<u16, PortPolicy>) -> Self {
+        Self { ports, default }
+    }
+
+    fn port_policy(&self, port: u16) -> &PortPolicy {
+        self.ports.get(&port).unwrap_or(&self.default)
+    }
+
+    fn authorize(&self, source: &Source) -> Result<(), Unauthorized> {
+        let port = port(source);
+        let authorized = match (self.port_policy(port), source.tls_peer.as_ref()) {
+            (PortPolicy::AllowPlaintext, _) => true,
+            (PortPolicy::RequireIdentity(_), Conditional::None(_)) => false,
+            (PortPolicy::RequireIdentity(matches), Conditional::Some(id)) => {
+                matches.is_empty() || matches.iter().any(|m| m.matches(id))
+            }
+        };
+
+        if authorized {
+            Ok(())
+        } else {
+            Err(Unauthorized {
+                port,
+                tls_peer: source.tls_peer.clone(),
+            })
+        }
+    }
+}
+
+/// Connections without an original destination are authorized by the port
+/// on which they were accepted.
+fn port(source: &Source) -> u16 {
+    source.orig_dst.unwrap_or(source.local).port()
+}
+
+// === impl Match ===
+
+impl Match {
+    pub fn matches(&self, id: &identity::Name) -> bool {
+        match self {
+            Match::Exact(name) => name == id,
+            Match::Suffix(sfx) => dns::Name::try_from(id.as_ref().as_bytes())
+                .map(|n| sfx.contains(&n))
+                .unwrap_or(false),
+        }
+    }
+}
+
+// === impl Unauthorized ===
+
+impl fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.tls_peer {
+            Conditional::Some(ref id) => write!(
+                f,
+                "client {} is not authorized to connect to port {}",
+                id.as_ref(),
+                self.port
+            ),
+            Conditional::None(ref reason) => write!(
+                f,
+                "unauthenticated client is not authorized to connect to port {} ({})",
+                self.port, reason
+            ),
+        }
+    }
+}
+
+impl error::Error for Unauthorized {}
+
+#[cfg(test)]
+impl Unauthorized {
+    pub fn for_test(port: u16, tls_peer: tls::PeerIdentity) -> Self {
+        Self { port, tls_peer }
+    }
+}
+
+// === impl Registry ===
+
+impl Registry {
+    fn authorize(&self, policy: &Policy, source: &Source) -> Authz {
+        let key = Key {
+            port: port(source),
+            client_id: source.tls_peer.value().cloned(),
+        };
+        let counts = match self.0.lock() {
+            Ok(mut by_key) => by_key
+                .entry(key)
+                .or_insert_with(|| Arc::new(Mutex::new(Counts::new())))
+                .clone(),
+            Err(_) => Arc::new(Mutex::new(Counts::new())),
+        };
+
+        let result = policy.authorize(source);
+        if let Err(ref e) = result {
+            debug!("{}", e);
+        }
+        Authz { result, counts }
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut inner = match self.by_key.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if let Some(epoch) = clock::now().checked_sub(self.retain_idle) {
+            retain_since(&mut inner, epoch);
+        }
+
+        if inner.is_empty() {
+            return Ok(());
+        }
+
+        inbound_authz_allow_total.fmt_help(f)?;
+        fmt_by(&inner, f, inbound_authz_allow_total, |c| &c.allow)?;
+
+        inbound_authz_deny_total.fmt_help(f)?;
+        fmt_by(&inner, f, inbound_authz_deny_total, |c| &c.deny)?;
+
+        Ok(())
+    }
+}
+
+/// Retains metrics that are either referenced by a connection or have been
+/// updated since `epoch`.
+fn retain_since(by_key: &mut ByKey, epoch: Instant) {
+    by_key.retain(|_, c| {
+        Arc::strong_count(&c) > 1 || c.lock().map(|c| c.last_update >= epoch).unwrap_or(false)
+    })
+}
+
+fn fmt_by<F, M>(
+    inner: &ByKey,
+    f: &mut fmt::Formatter<'_>,
+    metric: Metric<'_, M>,
+    get_metric: F,
+) -> fmt::Result
+where
+    F: Fn(&Counts) -> &M,
+    M: FmtMetric,
+{
+    for (key, counts) in inner.iter() {
+        if let Ok(counts) = counts.lock() {
+            get_metric(&*counts).fmt_metric_labeled(f, metric.name, key)?;
+        }
+    }
+
+    Ok(())
+}
+
+// === impl Key ===
+
+impl FmtLabels for Key {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "target_port=\"{}\"", self.port)?;
+        if let Some(ref id) = self.client_id {
+            write!(f, ",client_id=\"{}\"", id.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+// === impl Counts ===
+
+impl Counts {
+    fn new() -> Self {
+        Self {
+            allow: Counter::default(),
+            deny: Counter::default(),
+            last_update: clock::now(),
+        }
+    }
+}
+
+// === impl Authz ===
+
+impl Authz {
+    fn check(&self) -> Result<(), Unauthorized> {
+        if let Ok(mut counts) = self.counts.lock() {
+            counts.last_update = clock::now();
+            match self.result {
+                Ok(()) => counts.allow.incr(),
+                Err(_) => counts.deny.incr(),
+            }
+        }
+        self.result.clone()
+    }
+}
+
+// === impl Layer ===
+
+/// Authorizes HTTP requests if `policy` is set.
+pub fn layer(policy: Option<Policy>, registry: Registry) -> Layer {
+    Layer {
+        policy: policy.map(Arc::new),
+        registry,
+    }
+}
+
+impl<M> svc::Layer<M> for Layer {
+    type Service = Stack<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            policy: self.policy.clone(),
+            registry: self.registry.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Service<Source> for Stack<M>
+where
+    M: svc::Service<Source>,
+{
+    type Response = Service<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, source: Source) -> Self::Future {
+        let authz = self
+            .policy
+            .as_ref()
+            .map(|p| self.registry.authorize(p, &source));
+        let inner = self.inner.call(source);
+        MakeFuture { authz, inner }
+    }
+}
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = Service<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        let authz = self.authz.take();
+        Ok(Service { authz, inner }.into())
+    }
+}
+
+// === impl Service ===
+
+impl<S, B> svc::Service<http::Request<B>> for Service<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::FutureResult<S::Response, Error>,
+        future::MapErr<S::Future, fn(S::Error) -> Error>,
+    >;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if let Some(ref authz) = self.authz {
+            if let Err(e) = authz.check() {
+                return future::Either::A(future::err(e.into()));
+            }
+        }
+
+        future::Either::B(self.inner.call(req).map_err(Into::into))
+    }
+}
+
+// === impl ConnectLayer ===
+
+/// Refuses unauthorized TCP connections if `policy` is set.
+pub fn connect_layer(policy: Option<Policy>, registry: Registry) -> ConnectLayer {
+    ConnectLayer {
+        policy: policy.map(Arc::new),
+        registry,
+    }
+}
+
+impl<C> svc::Layer<C> for ConnectLayer {
+    type Service = Connect<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        Connect {
+            policy: self.policy.clone(),
+            registry: self.registry.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Connect ===
+
+impl<C> svc::Service<Source> for Connect<C>
+where
+    C: svc::Service<Source>,
+    C::Error: Into<Error>,
+{
+    type Response = C::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::FutureResult<C::Response, Error>,
+        future::MapErr<C::Future, fn(C::Error) -> Error>,
+    >;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, source: Source) -> Self::Future {
+        if let Some(ref policy) = self.policy {
+            if let Err(e) = self.registry.authorize(policy, &source).check() {
+                return future::Either::A(future::err(e.into()));
+            }
+        }
+
+        future::Either::B(self.inner.call(source).map_err(Into::into))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::{Layer as _, Service as _};
+    use crate::Never;
+    use std::net::SocketAddr;
+
+    const FOO: Option<&str> = Some("foo.ns1.serviceaccount.identity.linkerd.cluster.local");
+
+    fn source(port: u16, client_id: Option<&str>) -> Source {
+        let tls_peer = match client_id {
+            Some(id) => Conditional::Some(identity::Name::from_hostname(id.as_bytes()).unwrap()),
+            None => Conditional::None(tls::ReasonForNoPeerName::NotProvidedByRemote.into()),
+        };
+        let local = SocketAddr::from(([127, 0, 0, 1], 4143));
+        let orig_dst = SocketAddr::from(([10, 1, 1, 1], port));
+        Source::for_test(local, local, Some(orig_dst), tls_peer)
+    }
+
+    #[test]
+    fn authorizes_by_port_and_identity() {
+        let foo =
+            identity::Name::from_hostname(b"foo.ns1.serviceaccount.identity.linkerd.cluster.local")
+                .unwrap();
+        let ns2 =
+            dns::Suffix::try_from("ns2.serviceaccount.identity.linkerd.cluster.local").unwrap();
+        let mut ports = IndexMap::new();
+        ports.insert(
+            8080,
+            PortPolicy::RequireIdentity(vec![Match::Exact(foo), Match::Suffix(ns2)]),
+        );
+        ports.insert(9090, PortPolicy::RequireIdentity(vec![]));
+        let policy = Policy::new(PortPolicy::AllowPlaintext, ports);
+
+        let foo = Some("foo.ns1.serviceaccount.identity.linkerd.cluster.local");
+        let bar = Some("bar.ns1.serviceaccount.identity.linkerd.cluster.local");
+        let baz = Some("baz.ns2.serviceaccount.identity.linkerd.cluster.local");
+
+        assert!(policy.authorize(&source(80, None)).is_ok());
+        assert!(policy.authorize(&source(8080, None)).is_err());
+        assert!(policy.authorize(&source(8080, foo)).is_ok());
+        assert!(policy.authorize(&source(8080, bar)).is_err());
+        assert!(policy.authorize(&source(8080, baz)).is_ok());
+        assert!(policy.authorize(&source(9090, None)).is_err());
+        assert!(policy.authorize(&source(9090, bar)).is_ok());
+    }
+
+    /// Requires identity on all ports but 80.
+    fn require_identity_except_80() -> Policy {
+        let mut ports = IndexMap::new();
+        ports.insert(80, PortPolicy::AllowPlaintext);
+        Policy::new(PortPolicy::RequireIdentity(vec![]), ports)
+    }
+
+    fn is_unauthorized(e: Error) -> bool {
+        e.downcast_ref::<Unauthorized>().is_some()
+    }
+
+    #[test]
+    fn port_policies_override_the_default() {
+        let policy = require_identity_except_80();
+        assert!(policy.authorize(&source(80, None)).is_ok());
+        assert!(policy.authorize(&source(80, FOO)).is_ok());
+        assert!(policy.authorize(&source(8080, None)).is_err());
+        assert!(policy.authorize(&source(8080, FOO)).is_ok());
+    }
+
+    #[test]
+    fn refuses_unauthorized_connections() {
+        let (registry, _) = new(Duration::from_secs(60));
+        let mut connect = connect_layer(Some(require_identity_except_80()), registry)
+            .layer(svc::mk(|_: Source| future::ok::<(), Never>(())));
+
+        let e = connect.call(source(8080, None)).wait().unwrap_err();
+        assert!(is_unauthorized(e));
+        assert!(connect.call(source(8080, FOO)).wait().is_ok());
+        assert!(connect.call(source(80, None)).wait().is_ok());
+    }
+
+    #[test]
+    fn fails_unauthorized_requests() {
+        let (registry, _) = new(Duration::from_secs(60));
+        let mut stack =
+            layer(Some(require_identity_except_80()), registry).layer(svc::mk(|_: Source| {
+                future::ok::<_, Never>(svc::mk(|_: http::Request<()>| {
+                    future::ok::<_, Never>(http::Response::new(()))
+                }))
+            }));
+
+        // Connections are accepted so that each request may fail.
+        let mut svc = stack.call(source(8080, None)).wait().unwrap();
+        let e = svc.call(http::Request::new(())).wait().unwrap_err();
+        assert!(is_unauthorized(e));
+
+        let mut svc = stack.call(source(8080, FOO)).wait().unwrap();
+        assert!(svc.call(http::Request::new(())).wait().is_ok());
+    }
+
+    #[test]
+    fn authorizes_everything_without_a_policy() {
+        let (registry, report) = new(Duration::from_secs(60));
+        let mut connect =
+            connect_layer(None, registry).layer(svc::mk(|_: Source| future::ok::<(), Never>(())));
+        assert!(connect.call(source(8080, None)).wait().is_ok());
+        assert_eq!(report.as_display().to_string(), "");
+    }
+
+    #[test]
+    fn reports_counts_by_port_and_client() {
+        let (registry, report) = new(Duration::from_secs(60));
+        let mut connect = connect_layer(Some(require_identity_except_80()), registry)
+            .layer(svc::mk(|_: Source| future::ok::<(), Never>(())));
+        for src in vec![
+            source(8080, None),
+            source(8080, FOO),
+            source(8080, FOO),
+            source(80, None),
+        ] {
+            let _ = connect.call(src).wait();
+        }
+
+        assert_eq!(
+            report.as_display().to_string(),
+            "# HELP inbound_authz_allow_total Total count of inbound connections and requests \
+             that were authorized\n\
+             # TYPE inbound_authz_allow_total counter\n\
+             inbound_authz_allow_total{target_port=\"8080\"} 0\n\
+             inbound_authz_allow_total{target_port=\"8080\",client_id=\"\
+             foo.ns1.serviceaccount.identity.linkerd.cluster.local\"} 2\n\
+             inbound_authz_allow_total{target_port=\"80\"} 1\n\
+             # HELP inbound_authz_deny_total Total count of inbound connections and requests \
+             that were not authorized\n\
+             # TYPE inbound_authz_deny_total counter\n\
+             inbound_authz_deny_total{target_port=\"8080\"} 1\n\
+             inbound_authz_deny_total{target_port=\"8080\",client_id=\"\
+             foo.ns1.serviceaccount.identity.linkerd.cluster.local\"} 0\n\
+             inbound_authz_deny_total{target_port=\"80\"} 0\n",
+        );
+    }
+
+    #[test]
+    fn evicts_idle_counts() {
+        let policy = require_identity_except_80();
+        let (registry, report) = new(Duration::from_secs(60));
+
+        let before_update = clock::now();
+        let authz = registry.authorize(&policy, &source(8080, FOO));
+        assert!(authz.check().is_ok());
+        let after_update = clock::now() + Duration::from_secs(1);
+
+        let mut by_key = report.by_key.lock().unwrap();
+        retain_since(&mut by_key, after_update);
+        assert_eq!(by_key.len(), 1, "counts should not be evicted while in use");
+
+        drop(authz);
+        retain_since(&mut by_key, before_update);
+        assert_eq!(by_key.len(), 1, "counts should not be evicted while active");
+
+        retain_since(&mut by_key, after_update);
+        assert_eq!(by_key.len(), 0, "idle counts should be evicted");
+    }
+}
@@ -0,0 +1,623 @@
+//! Adaptive concurrency limiting for HTTP requests.
+//!
+//! Each target (i.e. destination) has a concurrency limit that is adjusted
+//! as responses are observed: the limit grows by one while responses are as
+//! fast as the minimum recently-observed latency (within a tolerance), and it
+//! shrinks multiplicatively when responses are slower than that or fail. The
+//! limit is always kept within the configured bounds.
+//!
+//! Requests that arrive while a target is at its limit wait until another
+//! request completes or the limit grows.
+
+use crate::metrics::{metrics, FmtLabels, FmtMetric, FmtMetrics, Gauge, Metric};
+use crate::{svc, Error};
+use futures::task::{self, Task};
+use futures::{try_ready, Async, Future, Poll};
+use http;
+use indexmap::IndexMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_timer::clock;
+use tracing::{debug, trace};
+
+metrics! {
+    adaptive_concurrency_limit: Gauge {
+        "Current concurrency limit of a destination"
+    },
+    adaptive_concurrency_queue_depth: Gauge {
+        "Number of requests waiting for capacity under a destination's concurrency limit"
+    }
+}
+
+/// Responses slower than this multiple of the minimum observed latency
+/// indicate that the target is congested.
+const LATENCY_TOLERANCE: u32 = 2;
+
+/// The minimum observed latency is reset to the minimum of each window, so
+/// that the limit adapts when a target's baseline latency changes.
+const MIN_LATENCY_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The lowest concurrency limit. Each target starts at this limit.
+    pub min_limit: usize,
+
+    /// The highest concurrency limit.
+    pub max_limit: usize,
+}
+
+/// Builds a registry whose metrics are evicted once they have been idle for
+/// `retain_idle` and no target's limiter refers to them.
+pub fn new<K: Hash + Eq>(retain_idle: Duration) -> (Registry<K>, Report<K>) {
+    let by_key = Arc::new(Mutex::new(IndexMap::new()));
+    let report = Report {
+        by_key: by_key.clone(),
+        retain_idle,
+    };
+    (Registry(by_key), report)
+}
+
+type ByKey<K> = IndexMap<K, Arc<Mutex<Metrics>>>;
+
+/// Holds concurrency limit metrics for each target.
+#[derive(Debug)]
+pub struct Registry<K: Hash + Eq>(Arc<Mutex<ByKey<K>>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted concurrency limit
+/// metrics.
+#[derive(Debug)]
+pub struct Report<K: Hash + Eq> {
+    by_key: Arc<Mutex<ByKey<K>>>,
+    retain_idle: Duration,
+}
+
+#[derive(Debug)]
+struct Metrics {
+    last_update: Instant,
+    limit: Gauge,
+    queue_depth: Gauge,
+}
+
+#[derive(Debug)]
+pub struct Layer<K: Hash + Eq> {
+    config: Option<Arc<Config>>,
+    registry: Registry<K>,
+}
+
+#[derive(Debug)]
+pub struct Stack<M, K: Hash + Eq> {
+    config: Option<Arc<Config>>,
+    registry: Registry<K>,
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    limiter: Option<Arc<Limiter>>,
+    inner: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    limiter: Arc<Limiter>,
+    inner: S,
+}
+
+pub struct ResponseFuture<S, B, F> {
+    limiter: Arc<Limiter>,
+    state: State<S, B, F>,
+}
+
+enum State<S, B, F> {
+    Waiting {
+        queued: Option<Queued>,
+        permit: Option<Permit>,
+        service: S,
+        request: Option<http::Request<B>>,
+    },
+    Responding {
+        _permit: Permit,
+        started_at: Instant,
+        future: F,
+    },
+}
+
+/// The limit shared by all requests to a target.
+#[derive(Debug)]
+struct Limiter {
+    config: Arc<Config>,
+    metrics: Arc<Mutex<Metrics>>,
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    limit: usize,
+    in_flight: usize,
+    waiters: Vec<Task>,
+    min_latency: Option<Duration>,
+    window_min_latency: Option<Duration>,
+    window_ends_at: Instant,
+}
+
+/// Counts a request in the queue depth until it is dropped.
+struct Queued(Arc<Limiter>);
+
+/// Counts a request against the limit until it is dropped.
+struct Permit(Arc<Limiter>);
+
+// === impl Registry ===
+
+impl<K: Hash + Eq> Registry<K> {
+    fn get_or_default(&self, key: K) -> Arc<Mutex<Metrics>> {
+        match self.0.lock() {
+            Ok(mut inner) => inner.entry(key).or_insert_with(Default::default).clone(),
+            // Metrics are not recorded if the registry is poisoned.
+            Err(_) => Default::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> Clone for Registry<K> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+// === impl Report ===
+
+impl<K: Hash + Eq> Clone for Report<K> {
+    fn clone(&self) -> Self {
+        Report {
+            by_key: self.by_key.clone(),
+            retain_idle: self.retain_idle,
+        }
+    }
+}
+
+impl<K: FmtLabels + Hash + Eq> FmtMetrics for Report<K> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut inner = match self.by_key.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if let Some(epoch) = clock::now().checked_sub(self.retain_idle) {
+            retain_since(&mut inner, epoch);
+        }
+
+        if inner.is_empty() {
+            return Ok(());
+        }
+
+        adaptive_concurrency_limit.fmt_help(f)?;
+        fmt_by(&inner, f, adaptive_concurrency_limit, |m| &m.limit)?;
+
+        adaptive_concurrency_queue_depth.fmt_help(f)?;
+        fmt_by(&inner, f, adaptive_concurrency_queue_depth, |m| {
+            &m.queue_depth
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Retains the metrics of targets that (1) still exist or (2) have been
+/// updated since `epoch`.
+fn retain_since<K: Hash + Eq>(by_key: &mut ByKey<K>, epoch: Instant) {
+    by_key.retain(|_, m| {
+        Arc::strong_count(&m) > 1 || m.lock().map(|m| m.last_update >= epoch).unwrap_or(false)
+    })
+}
+
+fn fmt_by<K, F, M>(
+    inner: &ByKey<K>,
+    f: &mut fmt::Formatter<'_>,
+    metric: Metric<'_, M>,
+    get_metric: F,
+) -> fmt::Result
+where
+    K: FmtLabels + Hash + Eq,
+    F: Fn(&Metrics) -> &M,
+    M: FmtMetric,
+{
+    for (key, m) in inner.iter() {
+        if let Ok(m) = m.lock() {
+            get_metric(&*m).fmt_metric_labeled(f, metric.name, key)?;
+        }
+    }
+
+    Ok(())
+}
+
+// === impl Metrics ===
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            last_update: clock::now(),
+            limit: Gauge::default(),
+            queue_depth: Gauge::default(),
+        }
+    }
+}
+
+// === impl Layer ===
+
+/// Limits the concurrency of each target's requests if `config` is set.
+pub fn layer<K: Hash + Eq>(config: Option<Config>, registry: Registry<K>) -> Layer<K> {
+    Layer {
+        config: config.map(Arc::new),
+        registry,
+    }
+}
+
+impl<K: Hash + Eq> Clone for Layer<K> {
+    fn clone(&self) -> Self {
+        Layer {
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<M, K: Hash + Eq> svc::Layer<M> for Layer<K> {
+    type Service = Stack<M, K>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M: Clone, K: Hash + Eq> Clone for Stack<M, K> {
+    fn clone(&self) -> Self {
+        Stack {
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T, M, K> svc::Service<T> for Stack<M, K>
+where
+    T: Clone,
+    K: Hash + Eq + From<T>,
+    M: svc::Service<T>,
+{
+    type Response = svc::Either<Service<M::Response>, M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let limiter = self.config.as_ref().map(|config| {
+            let metrics = self.registry.get_or_default(target.clone().into());
+            Arc::new(Limiter::new(config.clone(), metrics))
+        });
+        let inner = self.inner.call(target);
+
+        MakeFuture { limiter, inner }
+    }
+}
+
+// === impl MakeFuture ===
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = svc::Either<Service<F::Item>, F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+
+        let svc = if let Some(limiter) = self.limiter.take() {
+            svc::Either::A(Service { limiter, inner })
+        } else {
+            svc::Either::B(inner)
+        };
+        Ok(svc.into())
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>> + Clone,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = ResponseFuture<S, A, S::Future>;
+
+    /// Requests are always accepted; the inner service's readiness is
+    /// polled once the request has acquired a permit.
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        ResponseFuture {
+            state: State::Waiting {
+                queued: Some(Queued::new(self.limiter.clone())),
+                permit: None,
+                service: self.inner.clone(),
+                request: Some(req),
+            },
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<S, A, B> Future for ResponseFuture<S, A, S::Future>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+{
+    type Item = http::Response<B>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Waiting {
+                    ref mut queued,
+                    ref mut permit,
+                    ref mut service,
+                    ref mut request,
+                } => {
+                    if permit.is_none() {
+                        match Permit::acquire(&self.limiter) {
+                            Some(p) => {
+                                *permit = Some(p);
+                                queued.take();
+                            }
+                            None => return Ok(Async::NotReady),
+                        }
+                    }
+
+                    try_ready!(service.poll_ready().map_err(Into::into));
+                    let req = request.take().expect("polled after complete");
+                    State::Responding {
+                        _permit: permit.take().expect("permit must be acquired"),
+                        started_at: clock::now(),
+                        future: service.call(req),
+                    }
+                }
+                State::Responding {
+                    ref started_at,
+                    ref mut future,
+                    ..
+                } => {
+                    let res = future.poll().map_err(Into::into);
+                    match res {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(_)) => {
+                            self.limiter.record(clock::now() - *started_at, false)
+                        }
+                        Err(_) => self.limiter.record(clock::now() - *started_at, true),
+                    }
+                    return res;
+                }
+            };
+        }
+    }
+}
+
+// === impl Limiter ===
+
+impl Limiter {
+    fn new(config: Arc<Config>, metrics: Arc<Mutex<Metrics>>) -> Self {
+        let limit = config.min_limit;
+        if let Ok(mut m) = metrics.lock() {
+            m.limit = (limit as u64).into();
+        }
+        Self {
+            config,
+            metrics,
+            state: Mutex::new(LimiterState {
+                limit,
+                in_flight: 0,
+                waiters: Vec::new(),
+                min_latency: None,
+                window_min_latency: None,
+                window_ends_at: clock::now() + MIN_LATENCY_WINDOW,
+            }),
+        }
+    }
+
+    /// Adjusts the limit after a request completes.
+    fn record(&self, latency: Duration, failed: bool) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+
+        let now = clock::now();
+        if now >= state.window_ends_at {
+            if let Some(min) = state.window_min_latency.take() {
+                state.min_latency = Some(min);
+            }
+            state.window_ends_at = now + MIN_LATENCY_WINDOW;
+        }
+        state.window_min_latency = Some(
+            state
+                .window_min_latency
+                .map(|min| min.min(latency))
+                .unwrap_or(latency),
+        );
+        let min_latency = state
+            .min_latency
+            .map(|min| min.min(latency))
+            .unwrap_or(latency);
+        state.min_latency = Some(min_latency);
+
+        let limit = if failed || latency > min_latency * LATENCY_TOLERANCE {
+            (state.limit * 9 / 10).max(self.config.min_limit)
+        } else if state.in_flight * 2 >= state.limit {
+            // The limit only grows while it is being used.
+            (state.limit + 1).min(self.config.max_limit)
+        } else {
+            state.limit
+        };
+
+        if limit != state.limit {
+            trace!(
+                "concurrency limit {} => {}; latency={:?}; min={:?}",
+                state.limit,
+                limit,
+                latency,
+                min_latency
+            );
+            if limit > state.limit {
+                for waiter in state.waiters.drain(..) {
+                    waiter.notify();
+                }
+            }
+            state.limit = limit;
+            if let Ok(mut m) = self.metrics.lock() {
+                m.last_update = clock::now();
+                m.limit = (limit as u64).into();
+            }
+        }
+    }
+}
+
+// === impl Queued ===
+
+impl Queued {
+    fn new(limiter: Arc<Limiter>) -> Self {
+        if let Ok(mut m) = limiter.metrics.lock() {
+            m.last_update = clock::now();
+            m.queue_depth.incr();
+        }
+        Queued(limiter)
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        if let Ok(mut m) = self.0.metrics.lock() {
+            m.last_update = clock::now();
+            m.queue_depth.decr();
+        }
+    }
+}
+
+// === impl Permit ===
+
+impl Permit {
+    /// Acquires a permit if the limit allows it. Otherwise, the current task
+    /// is notified when a permit may be available.
+    fn acquire(limiter: &Arc<Limiter>) -> Option<Self> {
+        let mut state = limiter.state.lock().ok()?;
+        if state.in_flight < state.limit {
+            state.in_flight += 1;
+            return Some(Permit(limiter.clone()));
+        }
+
+        debug!("concurrency limit reached: {}", state.limit);
+        state.waiters.push(task::current());
+        None
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.0.state.lock() {
+            state.in_flight -= 1;
+            for waiter in state.waiters.drain(..) {
+                waiter.notify();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(min_limit: usize, max_limit: usize) -> Limiter {
+        let config = Config {
+            min_limit,
+            max_limit,
+        };
+        Limiter::new(Arc::new(config), Default::default())
+    }
+
+    fn limit(l: &Limiter) -> usize {
+        l.state.lock().unwrap().limit
+    }
+
+    #[test]
+    fn grows_while_used_and_fast() {
+        let l = limiter(2, 4);
+        l.record(Duration::from_millis(10), false);
+        assert_eq!(limit(&l), 2, "does not grow while unused");
+
+        l.state.lock().unwrap().in_flight = 2;
+        for _ in 0..4 {
+            l.record(Duration::from_millis(10), false);
+        }
+        assert_eq!(limit(&l), 4, "grows to the maximum");
+        let m = l.metrics.lock().unwrap();
+        assert_eq!(Into::<u64>::into(m.limit), 4);
+    }
+
+    #[test]
+    fn shrinks_when_slow_or_failing() {
+        let l = limiter(5, 100);
+        l.state.lock().unwrap().limit = 50;
+        l.record(Duration::from_millis(10), false);
+        l.record(Duration::from_millis(30), false);
+        assert_eq!(limit(&l), 45);
+
+        l.record(Duration::from_millis(10), true);
+        assert_eq!(limit(&l), 40);
+
+        for _ in 0..100 {
+            l.record(Duration::from_millis(10), true);
+        }
+        assert_eq!(limit(&l), 5, "does not shrink below the minimum");
+    }
+
+    #[test]
+    fn evicts_idle_metrics() {
+        let (registry, report) = new::<&'static str>(Duration::from_secs(60));
+
+        let before_update = clock::now();
+        let metrics = registry.get_or_default("web");
+        let after_update = clock::now() + Duration::from_secs(1);
+
+        let mut by_key = report.by_key.lock().unwrap();
+        retain_since(&mut by_key, after_update);
+        assert_eq!(
+            by_key.len(),
+            1,
+            "metrics should not be evicted while in use"
+        );
+
+        drop(metrics);
+        retain_since(&mut by_key, before_update);
+        assert_eq!(
+            by_key.len(),
+            1,
+            "metrics should not be evicted while active"
+        );
+
+        retain_since(&mut by_key, after_update);
+        assert_eq!(by_key.len(), 0, "idle metrics should be evicted");
+    }
+}
@@ -1,20 +1,30 @@
-use crate::svc;
+use super::consistent_hash::HashKeys;
+use super::profiles::CanGetDestination;
+use super::weight::{HasWeight, WeightedDiscover};
+use crate::{svc, NameAddr};
 use futures::{try_ready, Async, Future, Poll};
 use http;
 use hyper::body::Payload;
 pub use hyper_balance::{PendingUntilFirstData, PendingUntilFirstDataBody};
 use rand::{rngs::SmallRng, FromEntropy};
 use std::{marker::PhantomData, time::Duration};
-pub use tower_balance::p2c::Balance;
 use tower_discover::Discover;
 pub use tower_load::{Load, PeakEwmaDiscover};
 
+pub mod weighted;
+
+/// Balances requests over weighted, load-measured endpoints.
+pub type Balancer<D> =
+    weighted::Balance<PeakEwmaDiscover<WeightedDiscover<D>, PendingUntilFirstData>>;
+
 /// Configures a stack to resolve `T` typed targets to balance requests over
 /// `M`-typed endpoint stacks.
 #[derive(Debug)]
 pub struct Layer<A, B> {
     decay: Duration,
     default_rtt: Duration,
+    hash_keys: HashKeys,
+    slow_start: Option<Duration>,
     rng: SmallRng,
     _marker: PhantomData<fn(A) -> B>,
 }
@@ -24,6 +34,9 @@ pub struct Layer<A, B> {
 pub struct MakeSvc<M, A, B> {
     decay: Duration,
     default_rtt: Duration,
+    hash_keys: HashKeys,
+    dst: Option<NameAddr>,
+    slow_start: Option<Duration>,
     inner: M,
     rng: SmallRng,
     _marker: PhantomData<fn(A) -> B>,
@@ -35,16 +48,33 @@ pub fn layer<A, B>(default_rtt: Duration, decay: Duration) -> Layer<A, B> {
     Layer {
         decay,
         default_rtt,
+        hash_keys: HashKeys::default(),
+        slow_start: None,
         rng: SmallRng::from_entropy(),
         _marker: PhantomData,
     }
 }
 
+impl<A, B> Layer<A, B> {
+    /// Balances requests by a consistent hash of each destination's key in
+    /// `hash_keys`, if it has one.
+    pub fn with_hash_keys(self, hash_keys: HashKeys) -> Self {
+        Self { hash_keys, ..self }
+    }
+
+    /// Ramps up the selection of new endpoints over `slow_start`, if set.
+    pub fn with_slow_start(self, slow_start: Option<Duration>) -> Self {
+        Self { slow_start, ..self }
+    }
+}
+
 impl<A, B> Clone for Layer<A, B> {
     fn clone(&self) -> Self {
         Self {
             decay: self.decay,
             default_rtt: self.default_rtt,
+            hash_keys: self.hash_keys.clone(),
+            slow_start: self.slow_start,
             rng: self.rng.clone(),
             _marker: PhantomData,
         }
@@ -62,6 +92,9 @@ where
         MakeSvc {
             decay: self.decay,
             default_rtt: self.default_rtt,
+            hash_keys: self.hash_keys.clone(),
+            dst: None,
+            slow_start: self.slow_start,
             inner,
             rng: self.rng.clone(),
             _marker: PhantomData,
@@ -76,6 +109,9 @@ impl<M: Clone, A, B> Clone for MakeSvc<M, A, B> {
         MakeSvc {
             decay: self.decay,
             default_rtt: self.default_rtt,
+            hash_keys: self.hash_keys.clone(),
+            dst: self.dst.clone(),
+            slow_start: self.slow_start,
             inner: self.inner.clone(),
             rng: self.rng.clone(),
             _marker: PhantomData,
@@ -85,16 +121,16 @@ impl<M: Clone, A, B> Clone for MakeSvc<M, A, B> {
 
 impl<T, M, A, B> svc::Service<T> for MakeSvc<M, A, B>
 where
+    T: CanGetDestination,
     M: svc::Service<T>,
-    M::Response: Discover,
+    M::Response: Discover + weighted::HasReadiness,
     <M::Response as Discover>::Service:
-        svc::Service<http::Request<A>, Response = http::Response<B>>,
+        svc::Service<http::Request<A>, Response = http::Response<B>> + HasWeight,
     A: Payload,
     B: Payload,
-    Balance<PeakEwmaDiscover<M::Response, PendingUntilFirstData>, http::Request<A>>:
-        svc::Service<http::Request<A>>,
+    Balancer<M::Response>: svc::Service<http::Request<A>>,
 {
-    type Response = Balance<PeakEwmaDiscover<M::Response, PendingUntilFirstData>, http::Request<A>>;
+    type Response = Balancer<M::Response>;
     type Error = M::Error;
     type Future = MakeSvc<M::Future, A, B>;
 
@@ -103,11 +139,15 @@ where
     }
 
     fn call(&mut self, target: T) -> Self::Future {
+        let dst = target.get_destination().cloned();
         let inner = self.inner.call(target);
 
         MakeSvc {
             decay: self.decay,
             default_rtt: self.default_rtt,
+            hash_keys: self.hash_keys.clone(),
+            dst,
+            slow_start: self.slow_start,
             inner,
             rng: self.rng.clone(),
             _marker: PhantomData,
@@ -118,21 +158,34 @@ where
 impl<F, A, B> Future for MakeSvc<F, A, B>
 where
     F: Future,
-    F::Item: Discover,
-    <F::Item as Discover>::Service: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    F::Item: Discover + weighted::HasReadiness,
+    <F::Item as Discover>::Service:
+        svc::Service<http::Request<A>, Response = http::Response<B>> + HasWeight,
     A: Payload,
     B: Payload,
-    Balance<PeakEwmaDiscover<F::Item, PendingUntilFirstData>, http::Request<A>>:
-        svc::Service<http::Request<A>>,
+    Balancer<F::Item>: svc::Service<http::Request<A>>,
 {
-    type Item = Balance<PeakEwmaDiscover<F::Item, PendingUntilFirstData>, http::Request<A>>;
+    type Item = Balancer<F::Item>;
     type Error = F::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         let discover = try_ready!(self.inner.poll());
+        let readiness = discover.readiness();
         let instrument = PendingUntilFirstData::default();
-        let loaded = PeakEwmaDiscover::new(discover, self.default_rtt, self.decay, instrument);
-        let balance = Balance::new(loaded, self.rng.clone());
+        let loaded = PeakEwmaDiscover::new(
+            WeightedDiscover::new(discover),
+            self.default_rtt,
+            self.decay,
+            instrument,
+        );
+        let balance = weighted::Balance::new(
+            loaded,
+            self.hash_keys.clone(),
+            self.dst.take(),
+            self.slow_start,
+            self.rng.clone(),
+        )
+        .with_readiness(readiness);
         Ok(Async::Ready(balance))
     }
 }
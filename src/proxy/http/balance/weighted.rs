@@ -0,0 +1,541 @@
+//! Balances requests over weighted endpoints.
+//!
+//! Requests are dispatched to the less loaded of two ready endpoints, each
+//! selected at random in proportion to its weight. Endpoints with a weight of
+//! 0 are only used when no other endpoints are ready.
+//!
+//! When a slow-start window is configured, the load of a newly discovered
+//! endpoint is inflated when it is compared with another endpoint's, so that
+//! cold endpoints are not flooded while their latency is unknown. The
+//! endpoint's load is compared as if it had only `SLOW_START_MIN_PERCENT` of
+//! its capacity, ramping up linearly to its full capacity over the window.
+//!
+//! When the balancer's destination has a `HashKey`, requests that have a key
+//! are instead dispatched to the first ready endpoint at or after the key's
+//! position on a consistent hash ring, on which each endpoint is placed in
+//! proportion to its weight. The ring is only built once a request is hashed.
+//!
+//! An endpoint that is ready is not polled again until it is called, unless
+//! its `Readiness` is invalidated (e.g. because it depends on the readiness of
+//! other endpoints).
+
+use super::super::consistent_hash::{HashKeys, Ring, VIRTUAL_NODES};
+use super::super::weight::{HasWeight, DEFAULT_WEIGHT};
+use super::Load;
+use crate::{svc, Error, NameAddr};
+use futures::{Async, Poll};
+use indexmap::IndexMap;
+use rand::{rngs::SmallRng, Rng};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_timer::clock;
+use tower_discover::{Change, Discover};
+use tracing::{debug, trace};
+
+/// Bounds the number of points at which a heavily-weighted endpoint is
+/// placed on the hash ring.
+const MAX_VIRTUAL_NODES: u64 = 40 * VIRTUAL_NODES as u64;
+
+/// The percentage of its capacity with which a new endpoint's load is first
+/// compared.
+const SLOW_START_MIN_PERCENT: u64 = 10;
+
+pub struct Balance<D: Discover> {
+    discover: D,
+    hash_keys: HashKeys,
+    dst: Option<NameAddr>,
+    slow_start: Option<Duration>,
+    endpoints: IndexMap<D::Key, Endpoint<D::Service>>,
+    ring: Option<Ring<D::Key>>,
+    rng: SmallRng,
+    readiness: Readiness,
+    polled_generation: usize,
+}
+
+/// Signals that endpoints that were ready may have become unready without
+/// being called, so that the balancer polls them again.
+#[derive(Clone, Debug, Default)]
+pub struct Readiness(Arc<AtomicUsize>);
+
+/// Implemented by `Discover`s whose endpoints' readiness may be invalidated.
+pub trait HasReadiness {
+    fn readiness(&self) -> Readiness;
+}
+
+struct Endpoint<S> {
+    service: S,
+    weight: u32,
+    added: Instant,
+    is_ready: bool,
+}
+
+// === impl Balance ===
+
+impl<D: Discover> Balance<D>
+where
+    D::Key: HasWeight + Hash + Clone,
+{
+    pub fn new(
+        discover: D,
+        hash_keys: HashKeys,
+        dst: Option<NameAddr>,
+        slow_start: Option<Duration>,
+        rng: SmallRng,
+    ) -> Self {
+        Self {
+            discover,
+            hash_keys,
+            dst,
+            slow_start,
+            endpoints: IndexMap::new(),
+            ring: None,
+            rng,
+            readiness: Readiness::default(),
+            polled_generation: 0,
+        }
+    }
+
+    /// Polls ready endpoints again whenever `readiness` is invalidated.
+    pub fn with_readiness(self, readiness: Readiness) -> Self {
+        let polled_generation = readiness.generation();
+        Self {
+            readiness,
+            polled_generation,
+            ..self
+        }
+    }
+
+    fn update_from_discover(&mut self) -> Result<(), Error>
+    where
+        D::Error: Into<Error>,
+    {
+        loop {
+            let change = match self.discover.poll() {
+                Ok(Async::Ready(change)) => change,
+                Ok(Async::NotReady) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            match change {
+                Change::Insert(key, service) => {
+                    let weight = key.weight();
+                    trace!("inserting endpoint; weight={}", weight);
+                    // An endpoint that is replaced (i.e. because its
+                    // metadata changed) is not slow-started again.
+                    let added = self
+                        .endpoints
+                        .get(&key)
+                        .map(|ep| ep.added)
+                        .unwrap_or_else(clock::now);
+                    let endpoint = Endpoint {
+                        service,
+                        weight,
+                        added,
+                        is_ready: false,
+                    };
+                    // If the endpoint is being replaced, its weight may have
+                    // changed.
+                    if let Some(ref mut ring) = self.ring {
+                        ring.remove(&key);
+                        ring.insert(&key, ring_nodes(weight));
+                    }
+                    self.endpoints.insert(key, endpoint);
+                }
+                Change::Remove(key) => {
+                    if let Some(ref mut ring) = self.ring {
+                        ring.remove(&key);
+                    }
+                    self.endpoints.swap_remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Polls all endpoints that are not known to be ready, dropping those
+    /// that fail.
+    ///
+    /// Endpoints that are ready are polled again if their readiness has been
+    /// invalidated since they were last polled.
+    fn poll_endpoints<Req>(&mut self)
+    where
+        D::Service: svc::Service<Req>,
+        <D::Service as svc::Service<Req>>::Error: Into<Error>,
+    {
+        let generation = self.readiness.generation();
+        let invalidated = generation != self.polled_generation;
+        self.polled_generation = generation;
+
+        let mut failed = Vec::new();
+        for (key, endpoint) in self.endpoints.iter_mut() {
+            if endpoint.is_ready && !invalidated {
+                continue;
+            }
+            match endpoint.service.poll_ready() {
+                Ok(ready) => endpoint.is_ready = ready.is_ready(),
+                Err(e) => {
+                    let e: Error = e.into();
+                    debug!("dropping failed endpoint: {}", e);
+                    failed.push(key.clone());
+                }
+            }
+        }
+
+        for key in failed {
+            self.endpoints.swap_remove(&key);
+            if let Some(ref mut ring) = self.ring {
+                ring.remove(&key);
+            }
+        }
+    }
+
+    /// Selects the first ready endpoint at or after `hash` on the ring.
+    fn select_hashed(&mut self, hash: u64) -> Option<usize> {
+        if self.ring.is_none() {
+            let mut ring = Ring::default();
+            for (key, endpoint) in self.endpoints.iter() {
+                ring.insert(key, ring_nodes(endpoint.weight));
+            }
+            self.ring = Some(ring);
+        }
+
+        let ring = self.ring.as_ref()?;
+        ring.iter_from(hash).find_map(|key| {
+            let (idx, _, endpoint) = self.endpoints.get_full(key)?;
+            if endpoint.is_ready {
+                Some(idx)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Selects the less loaded of two ready endpoints, chosen in proportion
+    /// to their weights.
+    fn select_p2c(&mut self) -> Option<usize>
+    where
+        D::Service: Load,
+        <D::Service as Load>::Metric: Into<f64>,
+    {
+        let mut ready = self
+            .endpoints
+            .values()
+            .enumerate()
+            .filter(|(_, ep)| ep.is_ready && ep.weight > 0)
+            .map(|(idx, ep)| (idx, u64::from(ep.weight)))
+            .collect::<Vec<_>>();
+        if ready.is_empty() {
+            ready = self
+                .endpoints
+                .values()
+                .enumerate()
+                .filter(|(_, ep)| ep.is_ready)
+                .map(|(idx, _)| (idx, 1))
+                .collect();
+        }
+
+        match ready.len() {
+            0 => None,
+            1 => Some(ready[0].0),
+            _ => {
+                let a = choose(&mut self.rng, &ready, None);
+                let b = choose(&mut self.rng, &ready, Some(a));
+                let (a, b) = (ready[a].0, ready[b].0);
+                let now = clock::now();
+                let slow_start = self.slow_start;
+                let load = |idx: usize| {
+                    self.endpoints.get_index(idx).map(|(_, ep)| {
+                        let load: f64 = ep.service.load().into();
+                        match slow_start {
+                            Some(window) => load * inflation(now - ep.added, window),
+                            None => load,
+                        }
+                    })
+                };
+                if load(b) < load(a) {
+                    Some(b)
+                } else {
+                    Some(a)
+                }
+            }
+        }
+    }
+}
+
+impl<D, B> svc::Service<http::Request<B>> for Balance<D>
+where
+    D: Discover,
+    D::Key: HasWeight + Hash + Clone,
+    D::Error: Into<Error>,
+    D::Service: svc::Service<http::Request<B>> + Load,
+    <D::Service as svc::Service<http::Request<B>>>::Error: Into<Error>,
+    <D::Service as Load>::Metric: Into<f64>,
+{
+    type Response = <D::Service as svc::Service<http::Request<B>>>::Response;
+    type Error = Error;
+    type Future = futures::future::MapErr<
+        <D::Service as svc::Service<http::Request<B>>>::Future,
+        fn(<D::Service as svc::Service<http::Request<B>>>::Error) -> Error,
+    >;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.update_from_discover()?;
+        self.poll_endpoints::<http::Request<B>>();
+        // Endpoints that became ready may have invalidated the readiness of
+        // endpoints that were polled before them.
+        if self.readiness.generation() != self.polled_generation {
+            self.poll_endpoints::<http::Request<B>>();
+        }
+
+        if self.endpoints.values().any(|ep| ep.is_ready) {
+            Ok(Async::Ready(()))
+        } else {
+            trace!("no ready endpoints");
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        // Requests without a key, or whose key does not map onto a ready
+        // endpoint (i.e. because all ready endpoints have a weight of 0),
+        // are balanced by load.
+        let hashed = self
+            .hash_keys
+            .hash_request(self.dst.as_ref(), &req)
+            .and_then(|hash| self.select_hashed(hash));
+        let idx = match hashed {
+            Some(idx) => idx,
+            None => self.select_p2c().expect("called before ready"),
+        };
+
+        let (_, endpoint) = self
+            .endpoints
+            .get_index_mut(idx)
+            .expect("selected endpoint must exist");
+        // The endpoint must be polled again before it is used.
+        endpoint.is_ready = false;
+        endpoint.service.call(req).map_err(Into::into)
+    }
+}
+
+// === impl Readiness ===
+
+impl Readiness {
+    pub fn invalidate(&self) {
+        self.0.fetch_add(1, Ordering::Release);
+    }
+
+    fn generation(&self) -> usize {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Returns the number of points at which an endpoint is placed on the ring.
+fn ring_nodes(weight: u32) -> u32 {
+    if weight == 0 {
+        return 0;
+    }
+    let nodes = u64::from(VIRTUAL_NODES) * u64::from(weight) / u64::from(DEFAULT_WEIGHT);
+    nodes.max(1).min(MAX_VIRTUAL_NODES) as u32
+}
+
+/// Returns the factor by which an endpoint's load is multiplied, given how far
+/// through the slow-start `window` it is.
+fn inflation(elapsed: Duration, window: Duration) -> f64 {
+    let window_ms = window.as_millis();
+    let elapsed_ms = elapsed.as_millis();
+    if elapsed_ms >= window_ms {
+        return 1.0;
+    }
+
+    let min = SLOW_START_MIN_PERCENT as f64;
+    let percent = min + (100.0 - min) * elapsed_ms as f64 / window_ms as f64;
+    100.0 / percent
+}
+
+/// Chooses the position of a candidate in proportion to its weight, skipping
+/// the candidate at `except`.
+fn choose<R: Rng>(rng: &mut R, candidates: &[(usize, u64)], except: Option<usize>) -> usize {
+    let total = candidates
+        .iter()
+        .enumerate()
+        .filter(|&(pos, _)| Some(pos) != except)
+        .map(|(_, &(_, weight))| weight)
+        .sum::<u64>();
+
+    let mut n = rng.gen_range(0, total);
+    for (pos, &(_, weight)) in candidates.iter().enumerate() {
+        if Some(pos) == except {
+            continue;
+        }
+        if n < weight {
+            return pos;
+        }
+        n -= weight;
+    }
+
+    unreachable!("selection must be less than the total weight")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::Service as _;
+    use crate::Never;
+    use futures::{future, Future};
+    use rand::{FromEntropy, SeedableRng};
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct Key(usize);
+
+    /// An endpoint with a fixed load that counts the requests it serves.
+    struct Fixed {
+        load: f64,
+        requests: Arc<AtomicUsize>,
+    }
+
+    /// Yields each of a list of endpoints once.
+    struct Endpoints(VecDeque<(Key, Fixed)>);
+
+    impl HasWeight for Key {
+        fn weight(&self) -> u32 {
+            DEFAULT_WEIGHT
+        }
+    }
+
+    impl svc::Service<http::Request<()>> for Fixed {
+        type Response = ();
+        type Error = Never;
+        type Future = future::FutureResult<(), Never>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: http::Request<()>) -> Self::Future {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            future::ok(())
+        }
+    }
+
+    impl Load for Fixed {
+        type Metric = f64;
+
+        fn load(&self) -> f64 {
+            self.load
+        }
+    }
+
+    impl Discover for Endpoints {
+        type Key = Key;
+        type Service = Fixed;
+        type Error = Never;
+
+        fn poll(&mut self) -> Poll<Change<Key, Fixed>, Never> {
+            let change = self
+                .0
+                .pop_front()
+                .map(|(key, svc)| Async::Ready(Change::Insert(key, svc)));
+            Ok(change.unwrap_or(Async::NotReady))
+        }
+    }
+
+    /// Dispatches `n` requests and returns the number served by each endpoint.
+    fn dispatch<D>(balance: &mut Balance<D>, n: usize, requests: &[Arc<AtomicUsize>]) -> Vec<usize>
+    where
+        D: Discover<Key = Key, Service = Fixed, Error = Never>,
+    {
+        for r in requests {
+            r.store(0, Ordering::SeqCst);
+        }
+        for _ in 0..n {
+            assert!(balance.poll_ready().unwrap().is_ready());
+            balance.call(http::Request::new(())).wait().unwrap();
+        }
+        requests.iter().map(|r| r.load(Ordering::SeqCst)).collect()
+    }
+
+    #[test]
+    fn new_endpoints_receive_less_traffic_during_slow_start() {
+        let window = Duration::from_secs(60);
+        let requests = vec![Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0))];
+        // The new endpoint is less loaded than the established one, but not
+        // so much less that its inflated load is lower.
+        let endpoints = Endpoints(
+            vec![
+                (
+                    Key(0),
+                    Fixed {
+                        load: 3.0,
+                        requests: requests[0].clone(),
+                    },
+                ),
+                (
+                    Key(1),
+                    Fixed {
+                        load: 1.0,
+                        requests: requests[1].clone(),
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let mut balance = Balance::new(
+            endpoints,
+            HashKeys::default(),
+            None,
+            Some(window),
+            SmallRng::seed_from_u64(0),
+        );
+        assert!(balance.poll_ready().unwrap().is_ready());
+        balance.endpoints.get_index_mut(0).unwrap().1.added = clock::now() - window;
+
+        assert_eq!(dispatch(&mut balance, 100, &requests), vec![100, 0]);
+
+        // Once the window has elapsed, the endpoint's load is not inflated.
+        balance.endpoints.get_index_mut(1).unwrap().1.added = clock::now() - window;
+        assert_eq!(dispatch(&mut balance, 100, &requests), vec![0, 100]);
+    }
+
+    #[test]
+    fn ring_nodes_are_proportional_to_weight() {
+        assert_eq!(ring_nodes(0), 0);
+        assert_eq!(ring_nodes(1), 1);
+        assert_eq!(ring_nodes(DEFAULT_WEIGHT), VIRTUAL_NODES);
+        assert_eq!(ring_nodes(DEFAULT_WEIGHT * 3), VIRTUAL_NODES * 3);
+        assert_eq!(ring_nodes(u32::max_value()), MAX_VIRTUAL_NODES as u32);
+    }
+
+    #[test]
+    fn inflates_load_during_slow_start() {
+        let window = Duration::from_secs(60);
+        let inflated = |secs| inflation(Duration::from_secs(secs), window);
+        assert!((inflated(0) - 10.0).abs() < 1e-9);
+        assert!((inflated(30) - 100.0 / 55.0).abs() < 1e-9);
+        assert!((inflated(60) - 1.0).abs() < 1e-9);
+        assert!((inflated(90) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn chooses_in_proportion_to_weight() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let candidates = [(0, 1), (1, 3)];
+
+        let mut heavy = 0;
+        for _ in 0..4000 {
+            if choose(&mut rng, &candidates, None) == 1 {
+                heavy += 1;
+            }
+        }
+        assert!(2700 < heavy && heavy < 3300, "heavy={}", heavy);
+
+        let mut rng = SmallRng::from_entropy();
+        for _ in 0..100 {
+            assert_eq!(choose(&mut rng, &candidates, Some(1)), 0);
+        }
+    }
+}
@@ -270,6 +270,7 @@ impl Future for ClientServiceFuture {
                 let mut res = try_ready!(future.poll()).map(|b| HttpBody {
                     body: Some(b),
                     upgrade: upgrade.take(),
+                    mirrors: Vec::new(),
                 });
                 if *is_http_connect {
                     res.extensions_mut().insert(HttpConnect);
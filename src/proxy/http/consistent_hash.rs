@@ -0,0 +1,273 @@
+//! Maps requests onto endpoints by a consistent hash of each request.
+//!
+//! Each endpoint is placed on a hash ring at a number of points, so that when
+//! an endpoint is added or removed, only the keys that hash near its points
+//! are remapped. A request is dispatched to the first ready endpoint at or
+//! after its key's position on the ring.
+//!
+//! A default hash key may be configured for all destinations, and may be
+//! overridden by each destination's profile.
+
+use crate::proxy::Source;
+use crate::NameAddr;
+use http::header::{HeaderName, COOKIE};
+use indexmap::IndexMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// The number of points at which an endpoint with the default weight is
+/// placed on the ring.
+pub(super) const VIRTUAL_NODES: u32 = 100;
+
+/// Determines the part of a request that is hashed to select an endpoint.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HashKey {
+    /// The value of a request header.
+    Header(HeaderName),
+
+    /// The value of a request cookie.
+    Cookie(String),
+
+    /// The IP address of the client that sent the request.
+    SourceIp,
+}
+
+/// Indicates that a `HashKey` could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidHashKey(());
+
+/// The hash key of each destination.
+#[derive(Clone, Debug, Default)]
+pub struct HashKeys {
+    default: Option<HashKey>,
+    profiles: Arc<Mutex<IndexMap<NameAddr, HashKey>>>,
+}
+
+/// Maps hashes onto endpoint keys.
+#[derive(Debug)]
+pub(super) struct Ring<K> {
+    nodes: Vec<(u64, K)>,
+}
+
+// === impl HashKey ===
+
+impl HashKey {
+    pub(super) fn hash_request<B>(&self, req: &http::Request<B>) -> Option<u64> {
+        match self {
+            HashKey::Header(name) => {
+                let value = req.headers().get(name)?;
+                Some(hash(&value.as_bytes()))
+            }
+            HashKey::Cookie(name) => {
+                let value = req
+                    .headers()
+                    .get_all(COOKIE)
+                    .iter()
+                    .filter_map(|v| v.to_str().ok())
+                    .flat_map(|v| v.split(';'))
+                    .filter_map(|pair| {
+                        let mut kv = pair.trim().splitn(2, '=');
+                        match (kv.next(), kv.next()) {
+                            (Some(k), Some(v)) if k == name => Some(v),
+                            _ => None,
+                        }
+                    })
+                    .next()?;
+                Some(hash(&value))
+            }
+            HashKey::SourceIp => {
+                let source = req.extensions().get::<Source>()?;
+                Some(hash(&source.remote.ip()))
+            }
+        }
+    }
+}
+
+impl FromStr for HashKey {
+    type Err = InvalidHashKey;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "source-ip" {
+            return Ok(HashKey::SourceIp);
+        }
+
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("header"), Some(name)) => HeaderName::from_bytes(name.as_bytes())
+                .map(HashKey::Header)
+                .map_err(|_| InvalidHashKey(())),
+            (Some("cookie"), Some(name)) if !name.is_empty() => {
+                Ok(HashKey::Cookie(name.to_owned()))
+            }
+            _ => Err(InvalidHashKey(())),
+        }
+    }
+}
+
+impl fmt::Display for InvalidHashKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("expected source-ip, header:<name>, or cookie:<name>")
+    }
+}
+
+// === impl HashKeys ===
+
+impl HashKeys {
+    pub fn new(default: Option<HashKey>) -> Self {
+        Self {
+            default,
+            profiles: Arc::default(),
+        }
+    }
+
+    /// Overrides the default hash key of `dst` with the hash key described by
+    /// its profile, if any.
+    pub fn set_profile(&self, dst: &NameAddr, hash_key: Option<HashKey>) {
+        if let Ok(mut profiles) = self.profiles.lock() {
+            match hash_key {
+                Some(hash_key) => {
+                    profiles.insert(dst.clone(), hash_key);
+                }
+                None => {
+                    profiles.remove(dst);
+                }
+            }
+        }
+    }
+
+    /// Hashes a request to `dst` by its hash key, if it has one.
+    pub(super) fn hash_request<B>(
+        &self,
+        dst: Option<&NameAddr>,
+        req: &http::Request<B>,
+    ) -> Option<u64> {
+        if let Some(dst) = dst {
+            if let Ok(profiles) = self.profiles.lock() {
+                if let Some(key) = profiles.get(dst) {
+                    return key.hash_request(req);
+                }
+            }
+        }
+        self.default.as_ref()?.hash_request(req)
+    }
+}
+
+// === impl Ring ===
+
+impl<K> Default for Ring<K> {
+    fn default() -> Self {
+        Self { nodes: Vec::new() }
+    }
+}
+
+impl<K: Hash + PartialEq + Clone> Ring<K> {
+    /// Places `key` on the ring at `nodes` points.
+    pub(super) fn insert(&mut self, key: &K, nodes: u32) {
+        for i in 0..nodes {
+            self.nodes.push((hash(&(key, i)), key.clone()));
+        }
+        self.nodes.sort_by_key(|&(h, _)| h);
+    }
+
+    pub(super) fn remove(&mut self, key: &K) {
+        self.nodes.retain(|(_, k)| k != key);
+    }
+
+    /// Iterates over the ring's endpoints, starting at `hash`.
+    pub(super) fn iter_from(&self, hash: u64) -> impl Iterator<Item = &K> + '_ {
+        let start = match self.nodes.binary_search_by_key(&hash, |&(h, _)| h) {
+            Ok(i) | Err(i) => i,
+        };
+        self.nodes[start..]
+            .iter()
+            .chain(self.nodes[..start].iter())
+            .map(|(_, k)| k)
+    }
+}
+
+fn hash<H: Hash + ?Sized>(h: &H) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    h.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaps_few_keys_when_endpoints_change() {
+        let mut ring = Ring::default();
+        for ep in 0..10u32 {
+            ring.insert(&ep, VIRTUAL_NODES);
+        }
+        let before = (0..1000u32)
+            .map(|k| *ring.iter_from(hash(&k)).next().unwrap())
+            .collect::<Vec<_>>();
+
+        ring.remove(&3);
+        let after = (0..1000u32)
+            .map(|k| *ring.iter_from(hash(&k)).next().unwrap())
+            .collect::<Vec<_>>();
+
+        for (b, a) in before.iter().zip(after.iter()) {
+            if *b != 3 {
+                assert_eq!(a, b, "only keys on the removed endpoint may move");
+            } else {
+                assert_ne!(*a, 3);
+            }
+        }
+    }
+
+    #[test]
+    fn hashes_headers_and_cookies() {
+        let req = http::Request::builder()
+            .header("x-user", "alice")
+            .header(COOKIE, "theme=dark; session=abc123")
+            .body(())
+            .unwrap();
+
+        let header = HashKey::Header(HeaderName::from_static("x-user"));
+        assert_eq!(header.hash_request(&req), Some(hash(&b"alice"[..])));
+
+        let cookie = HashKey::Cookie("session".into());
+        assert_eq!(cookie.hash_request(&req), Some(hash("abc123")));
+
+        let missing = HashKey::Cookie("user".into());
+        assert_eq!(missing.hash_request(&req), None);
+        assert_eq!(HashKey::SourceIp.hash_request(&req), None);
+    }
+
+    #[test]
+    fn profiles_override_the_default_hash_key() {
+        let req = http::Request::builder()
+            .header("x-user", "alice")
+            .header("x-tenant", "acme")
+            .body(())
+            .unwrap();
+        let web = NameAddr::from_str("web.ns.svc.cluster.local:8080").unwrap();
+        let api = NameAddr::from_str("api.ns.svc.cluster.local:8080").unwrap();
+
+        let keys = HashKeys::new(Some(HashKey::Header(HeaderName::from_static("x-user"))));
+        keys.set_profile(&web, "header:x-tenant".parse().ok());
+        assert_eq!(
+            keys.hash_request(Some(&web), &req),
+            Some(hash(&b"acme"[..]))
+        );
+        assert_eq!(
+            keys.hash_request(Some(&api), &req),
+            Some(hash(&b"alice"[..]))
+        );
+        assert_eq!(keys.hash_request(None, &req), Some(hash(&b"alice"[..])));
+
+        keys.set_profile(&web, None);
+        assert_eq!(
+            keys.hash_request(Some(&web), &req),
+            Some(hash(&b"alice"[..]))
+        );
+        assert_eq!(HashKeys::default().hash_request(Some(&web), &req), None);
+    }
+}
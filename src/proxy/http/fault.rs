@@ -0,0 +1,532 @@
+//! Fault injection for HTTP requests.
+//!
+//! Targets (i.e. routes) may configure a percentage of requests to be
+//! delayed before they are dispatched and a percentage of requests to be
+//! aborted with an HTTP or gRPC status, so that the effects of latency and
+//! failures may be observed. Injected faults are counted separately from the
+//! responses they produce so that they may be distinguished from real ones.
+
+use crate::metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics, Metric};
+use crate::{svc, Error};
+use futures::{try_ready, Async, Future, Poll};
+use http::{self, header::HeaderValue, StatusCode};
+use indexmap::IndexMap;
+use rand::Rng;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_timer::clock;
+use tracing::debug;
+
+metrics! {
+    route_fault_delayed_total: Counter { "Total count of requests delayed by fault injection" },
+    route_fault_aborted_total: Counter { "Total count of requests aborted by fault injection" }
+}
+
+/// Implemented by targets that may have faults injected.
+pub trait CanInjectFault {
+    type Key: Clone + Hash + Eq;
+
+    fn fault(&self) -> Option<Fault>;
+
+    fn fault_key(&self) -> Self::Key;
+}
+
+/// Describes the faults injected into a target's requests.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Fault {
+    pub delay: Option<Delay>,
+    pub abort: Option<Abort>,
+}
+
+/// Delays a percentage of requests by a duration chosen uniformly between
+/// `min` and `max`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Delay {
+    pub percent: u32,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+/// Aborts a percentage of requests with `status`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Abort {
+    pub percent: u32,
+    pub status: AbortStatus,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AbortStatus {
+    Http(StatusCode),
+    Grpc(u32),
+}
+
+/// A marker set in `http::Response::extensions` when *this* process aborted
+/// the request.
+#[derive(Debug)]
+pub struct FaultInjected(());
+
+/// Builds a registry whose counts are evicted once they have been idle for
+/// `retain_idle` and no route refers to them.
+pub fn new<K: Hash + Eq>(retain_idle: Duration) -> (Registry<K>, Report<K>) {
+    let by_key = Arc::new(Mutex::new(IndexMap::new()));
+    let report = Report {
+        by_key: by_key.clone(),
+        retain_idle,
+    };
+    (Registry(by_key), report)
+}
+
+type ByKey<K> = IndexMap<K, Arc<Mutex<Counts>>>;
+
+/// Holds the fault counts for all targets.
+#[derive(Debug)]
+pub struct Registry<K: Hash + Eq>(Arc<Mutex<ByKey<K>>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted fault metrics.
+#[derive(Debug)]
+pub struct Report<K: Hash + Eq> {
+    by_key: Arc<Mutex<ByKey<K>>>,
+    retain_idle: Duration,
+}
+
+#[derive(Debug)]
+pub struct Layer<K: Hash + Eq> {
+    registry: Registry<K>,
+}
+
+#[derive(Debug)]
+pub struct Stack<M, K: Hash + Eq> {
+    registry: Registry<K>,
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    inject: Option<Inject>,
+    inner: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inject: Option<Inject>,
+    inner: S,
+}
+
+pub enum ResponseFuture<S, B, F> {
+    Delayed {
+        delay: tokio_timer::Delay,
+        service: S,
+        request: Option<http::Request<B>>,
+    },
+    Inner(F),
+    Aborted(Option<StatusAbort>),
+}
+
+/// The response status with which a request is aborted.
+pub struct StatusAbort(AbortStatus);
+
+#[derive(Clone, Debug)]
+struct Inject {
+    fault: Fault,
+    counts: Arc<Mutex<Counts>>,
+}
+
+#[derive(Debug)]
+struct Counts {
+    last_update: Instant,
+    delayed_total: Counter,
+    aborted_total: Counter,
+}
+
+// === impl Registry ===
+
+impl<K: Hash + Eq> Registry<K> {
+    fn counts(&self, key: K) -> Arc<Mutex<Counts>> {
+        self.0
+            .lock()
+            .expect("fault registry lock poisoned")
+            .entry(key)
+            .or_insert_with(Default::default)
+            .clone()
+    }
+}
+
+impl<K: Hash + Eq> Clone for Registry<K> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+// === impl Report ===
+
+impl<K: Hash + Eq> Clone for Report<K> {
+    fn clone(&self) -> Self {
+        Report {
+            by_key: self.by_key.clone(),
+            retain_idle: self.retain_idle,
+        }
+    }
+}
+
+impl<K: FmtLabels + Hash + Eq> FmtMetrics for Report<K> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut inner = match self.by_key.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if let Some(epoch) = clock::now().checked_sub(self.retain_idle) {
+            retain_since(&mut inner, epoch);
+        }
+
+        if inner.is_empty() {
+            return Ok(());
+        }
+
+        route_fault_delayed_total.fmt_help(f)?;
+        fmt_by(&inner, f, route_fault_delayed_total, |c| &c.delayed_total)?;
+
+        route_fault_aborted_total.fmt_help(f)?;
+        fmt_by(&inner, f, route_fault_aborted_total, |c| &c.aborted_total)?;
+
+        Ok(())
+    }
+}
+
+/// Retains the counts of routes that (1) still exist or (2) have injected a
+/// fault since `epoch`.
+fn retain_since<K: Hash + Eq>(by_key: &mut ByKey<K>, epoch: Instant) {
+    by_key.retain(|_, c| {
+        Arc::strong_count(&c) > 1 || c.lock().map(|c| c.last_update >= epoch).unwrap_or(false)
+    })
+}
+
+fn fmt_by<K, F, M>(
+    inner: &ByKey<K>,
+    f: &mut fmt::Formatter<'_>,
+    metric: Metric<'_, M>,
+    get_metric: F,
+) -> fmt::Result
+where
+    K: FmtLabels + Hash + Eq,
+    F: Fn(&Counts) -> &M,
+    M: FmtMetric,
+{
+    for (key, c) in inner.iter() {
+        if let Ok(c) = c.lock() {
+            get_metric(&*c).fmt_metric_labeled(f, metric.name, key)?;
+        }
+    }
+
+    Ok(())
+}
+
+// === impl Counts ===
+
+impl Default for Counts {
+    fn default() -> Self {
+        Self {
+            last_update: clock::now(),
+            delayed_total: Counter::default(),
+            aborted_total: Counter::default(),
+        }
+    }
+}
+
+// === impl Layer ===
+
+/// Injects the faults configured on each target into its requests.
+pub fn layer<K: Hash + Eq>(registry: Registry<K>) -> Layer<K> {
+    Layer { registry }
+}
+
+impl<K: Hash + Eq> Clone for Layer<K> {
+    fn clone(&self) -> Self {
+        Layer {
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<M, K: Hash + Eq> svc::Layer<M> for Layer<K> {
+    type Service = Stack<M, K>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            registry: self.registry.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M: Clone, K: Hash + Eq> Clone for Stack<M, K> {
+    fn clone(&self) -> Self {
+        Stack {
+            registry: self.registry.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T, M> svc::Service<T> for Stack<M, T::Key>
+where
+    T: CanInjectFault,
+    M: svc::Service<T>,
+{
+    type Response = Service<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let inject = target.fault().map(|fault| Inject {
+            fault,
+            counts: self.registry.counts(target.fault_key()),
+        });
+        let inner = self.inner.call(target);
+
+        MakeFuture { inject, inner }
+    }
+}
+
+// === impl MakeFuture ===
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = Service<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        let svc = Service {
+            inject: self.inject.take(),
+            inner,
+        };
+        Ok(svc.into())
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>> + Clone,
+    S::Error: Into<Error>,
+    B: Default,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = ResponseFuture<S, A, S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let inject = match self.inject {
+            Some(ref inject) => inject,
+            None => return ResponseFuture::Inner(self.inner.call(req)),
+        };
+
+        let mut rng = rand::thread_rng();
+
+        if let Some(ref abort) = inject.fault.abort {
+            if rng.gen_range(0, 100) < abort.percent {
+                debug!("aborting request with {:?}", abort.status);
+                if let Ok(mut counts) = inject.counts.lock() {
+                    counts.last_update = clock::now();
+                    counts.aborted_total.incr();
+                }
+                let status = StatusAbort(abort.status.clone());
+                return ResponseFuture::Aborted(Some(status));
+            }
+        }
+
+        if let Some(ref delay) = inject.fault.delay {
+            if rng.gen_range(0, 100) < delay.percent {
+                let duration = if delay.max > delay.min {
+                    let min = delay.min.as_millis() as u64;
+                    let max = delay.max.as_millis() as u64;
+                    Duration::from_millis(rng.gen_range(min, max + 1))
+                } else {
+                    delay.min
+                };
+                debug!("delaying request by {:?}", duration);
+                if let Ok(mut counts) = inject.counts.lock() {
+                    counts.last_update = clock::now();
+                    counts.delayed_total.incr();
+                }
+                // The delayed request is dispatched on a clone of the inner
+                // service, which must become ready again once the delay
+                // elapses.
+                return ResponseFuture::Delayed {
+                    delay: tokio_timer::Delay::new(clock::now() + duration),
+                    service: self.inner.clone(),
+                    request: Some(req),
+                };
+            }
+        }
+
+        ResponseFuture::Inner(self.inner.call(req))
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<S, A, B> Future for ResponseFuture<S, A, S::Future>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+    B: Default,
+{
+    type Item = http::Response<B>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            *self = match self {
+                ResponseFuture::Inner(f) => return f.poll().map_err(Into::into),
+                ResponseFuture::Aborted(status) => {
+                    let status = status.take().expect("polled after complete");
+                    return Ok(Async::Ready(status.into_response()));
+                }
+                ResponseFuture::Delayed {
+                    delay,
+                    service,
+                    request,
+                } => {
+                    try_ready!(delay.poll());
+                    try_ready!(service.poll_ready().map_err(Into::into));
+                    let req = request.take().expect("polled after complete");
+                    ResponseFuture::Inner(service.call(req))
+                }
+            };
+        }
+    }
+}
+
+// === impl StatusAbort ===
+
+impl StatusAbort {
+    fn into_response<B: Default>(self) -> http::Response<B> {
+        let mut rsp = http::Response::default();
+        match self.0 {
+            AbortStatus::Http(status) => {
+                *rsp.status_mut() = status;
+            }
+            AbortStatus::Grpc(code) => {
+                // gRPC statuses are sent as a trailers-only response.
+                let headers = rsp.headers_mut();
+                headers.insert(
+                    http::header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/grpc+proto"),
+                );
+                headers.insert("grpc-status", HeaderValue::from(code));
+                headers.insert("grpc-message", HeaderValue::from_static("fault injected"));
+            }
+        }
+        rsp.extensions_mut().insert(FaultInjected(()));
+        rsp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::Service as _;
+    use futures::future;
+
+    #[derive(Clone)]
+    struct Ok200;
+
+    impl svc::Service<http::Request<()>> for Ok200 {
+        type Response = http::Response<()>;
+        type Error = Error;
+        type Future = future::FutureResult<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, _: http::Request<()>) -> Self::Future {
+            future::ok(http::Response::default())
+        }
+    }
+
+    fn service(fault: Fault) -> Service<Ok200> {
+        let (registry, _) = new::<()>(Duration::from_secs(60));
+        Service {
+            inject: Some(Inject {
+                fault,
+                counts: registry.counts(()),
+            }),
+            inner: Ok200,
+        }
+    }
+
+    #[test]
+    fn aborts_requests() {
+        let mut svc = service(Fault {
+            delay: None,
+            abort: Some(Abort {
+                percent: 100,
+                status: AbortStatus::Http(StatusCode::SERVICE_UNAVAILABLE),
+            }),
+        });
+        let rsp = svc.call(http::Request::default()).wait().unwrap();
+        assert_eq!(rsp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(rsp.extensions().get::<FaultInjected>().is_some());
+
+        let mut svc = service(Fault {
+            delay: None,
+            abort: Some(Abort {
+                percent: 100,
+                status: AbortStatus::Grpc(14),
+            }),
+        });
+        let rsp = svc.call(http::Request::default()).wait().unwrap();
+        assert_eq!(rsp.status(), StatusCode::OK);
+        assert_eq!(rsp.headers()["grpc-status"], "14");
+        let counts = svc.inject.as_ref().unwrap().counts.lock().unwrap();
+        assert_eq!(counts.aborted_total.value(), 1);
+    }
+
+    #[test]
+    fn passes_through_requests() {
+        let mut svc = service(Fault {
+            delay: None,
+            abort: Some(Abort {
+                percent: 0,
+                status: AbortStatus::Http(StatusCode::SERVICE_UNAVAILABLE),
+            }),
+        });
+        let rsp = svc.call(http::Request::default()).wait().unwrap();
+        assert_eq!(rsp.status(), StatusCode::OK);
+        assert!(rsp.extensions().get::<FaultInjected>().is_none());
+    }
+
+    #[test]
+    fn evicts_idle_counts() {
+        let (registry, report) = new::<&'static str>(Duration::from_secs(60));
+
+        let before_update = clock::now();
+        let counts = registry.counts("route");
+        let after_update = clock::now() + Duration::from_secs(1);
+
+        let mut by_key = report.by_key.lock().unwrap();
+        retain_since(&mut by_key, after_update);
+        assert_eq!(by_key.len(), 1, "counts should not be evicted while in use");
+
+        drop(counts);
+        retain_since(&mut by_key, before_update);
+        assert_eq!(by_key.len(), 1, "counts should not be evicted while active");
+
+        retain_since(&mut by_key, after_update);
+        assert_eq!(by_key.len(), 0, "idle counts should be evicted");
+    }
+}
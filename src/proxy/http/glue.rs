@@ -1,6 +1,7 @@
-use crate::proxy::http::{upgrade::Http11Upgrade, HasH2Reason};
+use crate::proxy::http::{mirror, upgrade::Http11Upgrade, HasH2Reason};
 use crate::transport::tls::HasStatus as HasTlsStatus;
 use crate::{svc, Error};
+use bytes::Bytes;
 use futures::{try_ready, Async, Future, Poll};
 use http;
 use hyper::client::connect as hyper_connect;
@@ -14,6 +15,8 @@ pub struct HttpBody {
     /// to be inserted into the Http11Upgrade half.
     pub(super) body: Option<hyper::Body>,
     pub(super) upgrade: Option<Http11Upgrade>,
+    /// Receives a copy of all data read from this body.
+    pub(super) mirrors: Vec<mirror::Sender>,
 }
 
 /// Glue for a `tower::Service` to used as a `hyper::server::Service`.
@@ -54,25 +57,46 @@ impl Payload for HttpBody {
     }
 
     fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
-        self.body
-            .as_mut()
-            .expect("only taken in drop")
-            .poll_data()
-            .map_err(|e| {
-                debug!("http body error: {}", e);
-                e
-            })
+        let body = self.body.as_mut().expect("only taken in drop");
+        let data = try_ready!(body.poll_data().map_err(|e| {
+            debug!("http body error: {}", e);
+            e
+        }));
+
+        if !self.mirrors.is_empty() {
+            if let Some(ref data) = data {
+                for mirror in self.mirrors.iter_mut() {
+                    mirror.send_data(data.as_ref());
+                }
+            }
+
+            if data.is_none() || body.is_end_stream() {
+                for mirror in self.mirrors.iter_mut() {
+                    mirror.send_eos();
+                }
+            }
+        }
+
+        Ok(Async::Ready(data))
     }
 
     fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
-        self.body
+        let trailers = try_ready!(self
+            .body
             .as_mut()
             .expect("only taken in drop")
             .poll_trailers()
             .map_err(|e| {
                 debug!("http trailers error: {}", e);
                 e
-            })
+            }));
+
+        // Trailers are not copied to mirrors.
+        for mirror in self.mirrors.iter_mut() {
+            mirror.send_eos();
+        }
+
+        Ok(Async::Ready(trailers))
     }
 }
 
@@ -98,6 +122,17 @@ impl Default for HttpBody {
         HttpBody {
             body: Some(hyper::Body::empty()),
             upgrade: None,
+            mirrors: Vec::new(),
+        }
+    }
+}
+
+impl From<Bytes> for HttpBody {
+    fn from(bytes: Bytes) -> HttpBody {
+        HttpBody {
+            body: Some(hyper::Body::from(bytes)),
+            upgrade: None,
+            mirrors: Vec::new(),
         }
     }
 }
@@ -112,6 +147,22 @@ impl super::retry::TryClone for HttpBody {
     }
 }
 
+impl mirror::Tee for HttpBody {
+    fn tee(&mut self, max_bytes: usize) -> Option<Self> {
+        if self.upgrade.is_some() {
+            return None;
+        }
+
+        let (tx, rx) = mirror::channel(max_bytes);
+        self.mirrors.push(tx);
+        Some(HttpBody {
+            body: Some(hyper::Body::wrap_stream(rx)),
+            upgrade: None,
+            mirrors: Vec::new(),
+        })
+    }
+}
+
 impl Drop for HttpBody {
     fn drop(&mut self) {
         // If an HTTP/1 upgrade was wanted, send the upgrade future.
@@ -149,6 +200,7 @@ where
         self.service.call(req.map(|b| HttpBody {
             body: Some(b),
             upgrade: None,
+            mirrors: Vec::new(),
         }))
     }
 }
@@ -0,0 +1,213 @@
+use crate::svc;
+use futures::{try_ready, Future, Poll};
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use std::sync::Arc;
+use tracing::trace;
+
+/// Implement on targets to determine how their headers are rewritten.
+pub trait HasHeaderRewrite {
+    fn header_rewrite(&self) -> Option<Rewrite>;
+}
+
+/// Describes how the headers of requests and responses are rewritten.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rewrite {
+    pub request: Ops,
+    pub response: Ops,
+}
+
+/// Header mutations, applied in the order: remove, set, add.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Ops {
+    /// Headers that are appended to any existing values.
+    pub add: Vec<(HeaderName, HeaderValue)>,
+
+    /// Headers that replace any existing values.
+    pub set: Vec<(HeaderName, HeaderValue)>,
+
+    /// Headers that are removed.
+    pub remove: Vec<HeaderName>,
+}
+
+/// Rewrites the headers of HTTP requests and responses.
+///
+/// The stack target must implement `HasHeaderRewrite`; if the target has no
+/// rewrite, the inner service is used directly.
+pub fn layer() -> Layer {
+    Layer
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer;
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    inner: F,
+    rewrite: Option<Arc<Rewrite>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    rewrite: Arc<Rewrite>,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    rewrite: Arc<Rewrite>,
+}
+
+// === impl Ops ===
+
+impl Ops {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.set.is_empty() && self.remove.is_empty()
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        for name in &self.remove {
+            headers.remove(name);
+        }
+        for (name, value) in &self.set {
+            headers.insert(name.clone(), value.clone());
+        }
+        for (name, value) in &self.add {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<M> for Layer {
+    type Service = Stack<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack { inner }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Service<T> for Stack<M>
+where
+    M: svc::Service<T>,
+    T: HasHeaderRewrite,
+{
+    type Response = svc::Either<Service<M::Response>, M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let rewrite = target
+            .header_rewrite()
+            .filter(|r| !r.request.is_empty() || !r.response.is_empty())
+            .map(Arc::new);
+        let inner = self.inner.call(target);
+
+        MakeFuture { inner, rewrite }
+    }
+}
+
+// === impl MakeFuture ===
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = svc::Either<Service<F::Item>, F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+
+        let svc = if let Some(rewrite) = self.rewrite.take() {
+            svc::Either::A(Service { inner, rewrite })
+        } else {
+            svc::Either::B(inner)
+        };
+        Ok(svc.into())
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        trace!("rewriting request headers: {:?}", self.rewrite.request);
+        self.rewrite.request.apply(req.headers_mut());
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            rewrite: self.rewrite.clone(),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut rsp = try_ready!(self.inner.poll());
+        self.rewrite.response.apply(rsp.headers_mut());
+        Ok(rsp.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_ops_in_order() {
+        let name = |s: &'static str| HeaderName::from_static(s);
+        let value = |s: &'static str| HeaderValue::from_static(s);
+
+        let ops = Ops {
+            add: vec![
+                (name("x-env"), value("canary")),
+                (name("x-tag"), value("b")),
+            ],
+            set: vec![(name("x-tag"), value("a"))],
+            remove: vec![name("x-internal"), name("x-env")],
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(name("x-env"), value("prod"));
+        headers.insert(name("x-internal"), value("secret"));
+        headers.insert(name("x-tag"), value("z"));
+        ops.apply(&mut headers);
+
+        assert!(headers.get("x-internal").is_none());
+        assert_eq!(
+            headers.get_all("x-env").iter().collect::<Vec<_>>(),
+            vec!["canary"]
+        );
+        assert_eq!(
+            headers.get_all("x-tag").iter().collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+    }
+}
@@ -0,0 +1,1068 @@
+//! Actively checks the health of balancer endpoints.
+//!
+//! Each endpoint service is wrapped so that it is not ready while the endpoint
+//! is unhealthy. When an endpoint's destination has a `Check` as the endpoint
+//! is built, a background task periodically sends it either an HTTP `GET` for
+//! a path or a gRPC `grpc.health.v1.Health/Check` call. Checks are sent
+//! through a client built by the inner stack, so they are sent over the same
+//! kind of connection (including mTLS) as other requests to the endpoint and
+//! are instrumented, e.g. by tap, like them.
+//!
+//! An endpoint becomes unhealthy after `Config::unhealthy_threshold`
+//! consecutive failed checks and healthy again after
+//! `Config::healthy_threshold` consecutive successful checks. Endpoints are
+//! considered healthy until they are checked.
+//!
+//! Checks can only be configured by `LINKERD2_PROXY_OUTBOUND_HEALTH_CHECKS`
+//! or by the static discovery file; the Destination service does not yet
+//! describe checks in its profiles. Endpoints
+//! that were built before their destination had a check are not checked until
+//! they are rediscovered.
+//!
+//! Endpoint metrics are reported until their endpoints have been dropped and
+//! they have not been updated for `retain_idle`.
+
+use super::profiles::CanGetDestination;
+use super::settings::HasSettings;
+use crate::metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics, Gauge, Metric};
+use crate::{svc, Error, NameAddr};
+use bytes::{BufMut, Bytes};
+use futures::task::{self, Task};
+use futures::{try_ready, Async, Future, Poll};
+use http::{self, header};
+use hyper::body::Payload;
+use indexmap::IndexMap;
+use prost::Message;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::executor::{DefaultExecutor, Executor};
+use tokio_timer::{clock, Delay};
+use tracing::{debug, trace, warn};
+
+metrics! {
+    endpoint_health_checks_total: Counter {
+        "Total count of active health checks of an endpoint"
+    },
+    endpoint_healthy: Gauge {
+        "Whether an endpoint passes its active health checks (1) or not (0)"
+    }
+}
+
+const USER_AGENT: &str = "linkerd2-proxy/health-check";
+
+const GRPC_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+
+/// Bounds the size of the gRPC response that is read.
+const MAX_GRPC_RESPONSE_BYTES: usize = 4 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The amount of time between checks of an endpoint.
+    pub interval: Duration,
+
+    /// The amount of time after which a check fails.
+    pub timeout: Duration,
+
+    /// Marks a healthy endpoint unhealthy after this many consecutive failed
+    /// checks.
+    pub unhealthy_threshold: u32,
+
+    /// Marks an unhealthy endpoint healthy after this many consecutive
+    /// successful checks.
+    pub healthy_threshold: u32,
+}
+
+/// Describes how a destination's endpoints are checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Check {
+    /// An HTTP `GET` of a path, which succeeds when the response has a 2XX
+    /// status.
+    Http(http::uri::PathAndQuery),
+
+    /// A gRPC health check of the named service, which succeeds when the
+    /// service is `SERVING`. The empty name checks the server as a whole.
+    Grpc(String),
+}
+
+/// Indicates that a `Check` could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidCheck(());
+
+/// The checks of each destination.
+///
+/// Checks are configured statically and may be overridden by each
+/// destination's profile, which only the static discovery file describes.
+#[derive(Clone, Debug, Default)]
+pub struct Checks {
+    configured: Arc<IndexMap<NameAddr, Check>>,
+    profiles: Arc<Mutex<IndexMap<NameAddr, Check>>>,
+}
+
+/// Builds a registry whose metrics are evicted once they have been idle for
+/// `retain_idle` and no endpoint refers to them.
+pub fn new<K: Hash + Eq>(retain_idle: Duration) -> (Registry<K>, Report<K>) {
+    let by_key = Arc::new(Mutex::new(IndexMap::new()));
+    let report = Report {
+        by_key: by_key.clone(),
+        retain_idle,
+    };
+    (Registry(by_key), report)
+}
+
+type ByKey<K> = IndexMap<K, Arc<Mutex<Metrics>>>;
+
+/// Holds health check metrics for each endpoint.
+#[derive(Debug)]
+pub struct Registry<K: Hash + Eq>(Arc<Mutex<ByKey<K>>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted health check
+/// metrics.
+#[derive(Debug)]
+pub struct Report<K: Hash + Eq> {
+    by_key: Arc<Mutex<ByKey<K>>>,
+    retain_idle: Duration,
+}
+
+#[derive(Debug)]
+struct Metrics {
+    last_update: Instant,
+    successes: Counter,
+    failures: Counter,
+    healthy: Gauge,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Outcome {
+    Success,
+    Failure,
+}
+
+/// Wraps endpoint services so that they are not ready while unhealthy.
+#[derive(Debug)]
+pub struct Layer<K: Hash + Eq, B> {
+    config: Arc<Config>,
+    checks: Checks,
+    registry: Registry<K>,
+    _p: PhantomData<fn(B)>,
+}
+
+#[derive(Debug)]
+pub struct Stack<M, K: Hash + Eq, B> {
+    config: Arc<Config>,
+    checks: Checks,
+    registry: Registry<K>,
+    inner: M,
+    _p: PhantomData<fn(B)>,
+}
+
+pub struct MakeFuture<F> {
+    health: Option<Arc<Health>>,
+    inner: F,
+}
+
+/// An endpoint service that is not ready while the endpoint is unhealthy.
+#[derive(Debug)]
+pub struct Checked<S> {
+    health: Arc<Health>,
+    inner: S,
+}
+
+/// The health of an endpoint, shared by its service and its checker.
+#[derive(Debug)]
+struct Health {
+    config: Arc<Config>,
+    state: Mutex<HealthState>,
+}
+
+#[derive(Debug)]
+struct HealthState {
+    healthy: bool,
+    consecutive_successes: u32,
+    consecutive_failures: u32,
+    task: Option<Task>,
+}
+
+/// Periodically checks an endpoint for as long as its service exists.
+struct Checker<T, M, K, B, P>
+where
+    K: Hash + Eq,
+    M: svc::Service<T>,
+    M::Response: svc::Service<http::Request<B>>,
+{
+    dst: NameAddr,
+    checks: Checks,
+    health: Weak<Health>,
+    metrics: LazyMetrics<K>,
+    client: Client<T, M>,
+    next: Delay,
+    checking: Option<Checking<<M::Response as svc::Service<http::Request<B>>>::Future, P>>,
+}
+
+/// Builds a client for an endpoint and holds it between checks.
+struct Client<T, M: svc::Service<T>> {
+    target: T,
+    make: M,
+    making: Option<M::Future>,
+    client: Option<M::Response>,
+}
+
+/// Endpoint metrics are only registered once the endpoint is first checked.
+struct LazyMetrics<K: Hash + Eq> {
+    registry: Registry<K>,
+    key: Option<K>,
+    metrics: Option<Arc<Mutex<Metrics>>>,
+}
+
+struct Checking<F, P> {
+    check: Check,
+    timeout: Delay,
+    state: CheckState<F, P>,
+}
+
+enum CheckState<F, P> {
+    Connecting,
+    Responding(F),
+    Reading { body: P, buf: Vec<u8> },
+}
+
+// === impl Check ===
+
+impl Check {
+    fn request<B: From<Bytes>>(
+        &self,
+        authority: http::uri::Authority,
+        version: http::Version,
+    ) -> http::Request<B> {
+        let (method, path, body) = match self {
+            Check::Http(path) => (http::Method::GET, path.clone(), Bytes::new()),
+            Check::Grpc(service) => (
+                http::Method::POST,
+                http::uri::PathAndQuery::from_static(GRPC_CHECK_PATH),
+                grpc_request(service),
+            ),
+        };
+
+        let mut parts = http::uri::Parts::default();
+        parts.scheme = Some(http::uri::Scheme::HTTP);
+        parts.authority = Some(authority);
+        parts.path_and_query = Some(path);
+        let uri = http::Uri::from_parts(parts).expect("health check URI must be valid");
+        let mut req = http::Request::new(B::from(body));
+        *req.method_mut() = method;
+        *req.uri_mut() = uri;
+        *req.version_mut() = version;
+        req.headers_mut().insert(
+            header::USER_AGENT,
+            header::HeaderValue::from_static(USER_AGENT),
+        );
+        if let Check::Grpc(_) = self {
+            req.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/grpc"),
+            );
+            req.headers_mut()
+                .insert(header::TE, header::HeaderValue::from_static("trailers"));
+        }
+        req
+    }
+}
+
+/// Parses a check from `http:<path>`, `grpc`, or `grpc:<service>`.
+impl FromStr for Check {
+    type Err = InvalidCheck;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "grpc" {
+            return Ok(Check::Grpc(String::new()));
+        }
+
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("http"), Some(path)) if path.starts_with('/') => {
+                http::uri::PathAndQuery::from_str(path)
+                    .map(Check::Http)
+                    .map_err(|_| InvalidCheck(()))
+            }
+            (Some("grpc"), Some(service)) => Ok(Check::Grpc(service.to_owned())),
+            _ => Err(InvalidCheck(())),
+        }
+    }
+}
+
+impl fmt::Display for InvalidCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("expected http:<path>, grpc, or grpc:<service>")
+    }
+}
+
+/// Encodes a framed `grpc.health.v1.HealthCheckRequest`.
+fn grpc_request(service: &str) -> Bytes {
+    let msg = proto::HealthCheckRequest {
+        service: service.to_owned(),
+    };
+    let len = msg.encoded_len();
+
+    let mut frame = Vec::with_capacity(len + 5);
+    frame.push(0); // Uncompressed.
+    frame.extend_from_slice(&(len as u32).to_be_bytes());
+    msg.encode(&mut frame)
+        .expect("health check request must be encoded into a Vec");
+    frame.into()
+}
+
+/// Determines whether a framed `grpc.health.v1.HealthCheckResponse` reports
+/// that the service is serving.
+fn grpc_is_serving(frame: &[u8]) -> bool {
+    use self::proto::health_check_response::ServingStatus;
+
+    if frame.len() < 5 || frame[0] != 0 {
+        return false;
+    }
+    let len = u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize;
+    let msg = match frame.get(5..5 + len) {
+        Some(msg) => msg,
+        None => return false,
+    };
+
+    match proto::HealthCheckResponse::decode(msg) {
+        Ok(rsp) => ServingStatus::from_i32(rsp.status) == Some(ServingStatus::Serving),
+        Err(e) => {
+            debug!("invalid gRPC health check response: {}", e);
+            false
+        }
+    }
+}
+
+fn grpc_status_is_ok(headers: &http::HeaderMap) -> Option<bool> {
+    headers.get("grpc-status").map(|s| s == "0")
+}
+
+// === impl Checks ===
+
+impl Checks {
+    pub fn new(configured: IndexMap<NameAddr, Check>) -> Self {
+        Self {
+            configured: Arc::new(configured),
+            profiles: Arc::default(),
+        }
+    }
+
+    /// Overrides the configured check of `dst` with the check described by
+    /// its profile, if any.
+    pub fn set_profile(&self, dst: &NameAddr, check: Option<Check>) {
+        if let Ok(mut profiles) = self.profiles.lock() {
+            match check {
+                Some(check) => {
+                    profiles.insert(dst.clone(), check);
+                }
+                None => {
+                    profiles.remove(dst);
+                }
+            }
+        }
+    }
+
+    fn get(&self, dst: &NameAddr) -> Option<Check> {
+        let profile = self
+            .profiles
+            .lock()
+            .ok()
+            .and_then(|profiles| profiles.get(dst).cloned());
+        profile.or_else(|| self.configured.get(dst).cloned())
+    }
+}
+
+// === impl Registry ===
+
+impl<K: Hash + Eq> Registry<K> {
+    fn get_or_default(&self, key: K) -> Arc<Mutex<Metrics>> {
+        match self.0.lock() {
+            Ok(mut inner) => inner.entry(key).or_insert_with(Default::default).clone(),
+            // Metrics are not recorded if the registry is poisoned.
+            Err(_) => Default::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> Clone for Registry<K> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+// === impl Report ===
+
+impl<K: Hash + Eq> Clone for Report<K> {
+    fn clone(&self) -> Self {
+        Report {
+            by_key: self.by_key.clone(),
+            retain_idle: self.retain_idle,
+        }
+    }
+}
+
+impl<K: FmtLabels + Hash + Eq> FmtMetrics for Report<K> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut inner = match self.by_key.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if let Some(epoch) = clock::now().checked_sub(self.retain_idle) {
+            retain_since(&mut inner, epoch);
+        }
+
+        if inner.is_empty() {
+            return Ok(());
+        }
+
+        endpoint_health_checks_total.fmt_help(f)?;
+        for (key, m) in inner.iter() {
+            if let Ok(m) = m.lock() {
+                m.successes.fmt_metric_labeled(
+                    f,
+                    endpoint_health_checks_total.name,
+                    (key, Outcome::Success),
+                )?;
+                m.failures.fmt_metric_labeled(
+                    f,
+                    endpoint_health_checks_total.name,
+                    (key, Outcome::Failure),
+                )?;
+            }
+        }
+
+        endpoint_healthy.fmt_help(f)?;
+        fmt_by(&inner, f, endpoint_healthy, |m| &m.healthy)?;
+
+        Ok(())
+    }
+}
+
+/// Retains the metrics of endpoints that (1) are still checked or (2) have
+/// been updated since `epoch`.
+fn retain_since<K: Hash + Eq>(by_key: &mut ByKey<K>, epoch: Instant) {
+    by_key.retain(|_, m| {
+        Arc::strong_count(&m) > 1 || m.lock().map(|m| m.last_update >= epoch).unwrap_or(false)
+    })
+}
+
+fn fmt_by<K, F, M>(
+    inner: &ByKey<K>,
+    f: &mut fmt::Formatter<'_>,
+    metric: Metric<'_, M>,
+    get_metric: F,
+) -> fmt::Result
+where
+    K: FmtLabels + Hash + Eq,
+    F: Fn(&Metrics) -> &M,
+    M: FmtMetric,
+{
+    for (key, m) in inner.iter() {
+        if let Ok(m) = m.lock() {
+            get_metric(&*m).fmt_metric_labeled(f, metric.name, key)?;
+        }
+    }
+
+    Ok(())
+}
+
+// === impl Metrics ===
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            last_update: clock::now(),
+            successes: Counter::default(),
+            failures: Counter::default(),
+            healthy: Gauge::default(),
+        }
+    }
+}
+
+// === impl Outcome ===
+
+impl FmtLabels for Outcome {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Success => f.pad("result=\"success\""),
+            Outcome::Failure => f.pad("result=\"failure\""),
+        }
+    }
+}
+
+// === impl Layer ===
+
+pub fn layer<K, B>(config: Config, checks: Checks, registry: Registry<K>) -> Layer<K, B>
+where
+    K: Hash + Eq,
+    B: From<Bytes>,
+{
+    Layer {
+        config: Arc::new(config),
+        checks,
+        registry,
+        _p: PhantomData,
+    }
+}
+
+impl<K: Hash + Eq, B> Clone for Layer<K, B> {
+    fn clone(&self) -> Self {
+        Layer {
+            config: self.config.clone(),
+            checks: self.checks.clone(),
+            registry: self.registry.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<M, K: Hash + Eq, B> svc::Layer<M> for Layer<K, B> {
+    type Service = Stack<M, K, B>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            config: self.config.clone(),
+            checks: self.checks.clone(),
+            registry: self.registry.clone(),
+            inner,
+            _p: PhantomData,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M: Clone, K: Hash + Eq, B> Clone for Stack<M, K, B> {
+    fn clone(&self) -> Self {
+        Stack {
+            config: self.config.clone(),
+            checks: self.checks.clone(),
+            registry: self.registry.clone(),
+            inner: self.inner.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<T, M, K, B, P> svc::Service<T> for Stack<M, K, B>
+where
+    T: CanGetDestination + HasSettings + Clone + Send + 'static,
+    K: Hash + Eq + From<T> + Send + 'static,
+    M: svc::Service<T> + Clone + Send + 'static,
+    M::Error: Into<Error>,
+    M::Future: Send + 'static,
+    M::Response: svc::Service<http::Request<B>, Response = http::Response<P>> + Send + 'static,
+    <M::Response as svc::Service<http::Request<B>>>::Error: Into<Error>,
+    <M::Response as svc::Service<http::Request<B>>>::Future: Send + 'static,
+    B: From<Bytes> + 'static,
+    P: Payload,
+{
+    type Response = Checked<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let health = Arc::new(Health::new(self.config.clone()));
+
+        // Endpoints are only checked if their destination has a check.
+        let dst = target
+            .get_destination()
+            .filter(|dst| self.checks.get(dst).is_some())
+            .cloned();
+        if let Some(dst) = dst {
+            let checker = Checker::<_, _, _, B, P> {
+                dst,
+                checks: self.checks.clone(),
+                health: Arc::downgrade(&health),
+                metrics: LazyMetrics {
+                    registry: self.registry.clone(),
+                    key: Some(target.clone().into()),
+                    metrics: None,
+                },
+                client: Client {
+                    target: target.clone(),
+                    make: self.inner.clone(),
+                    making: None,
+                    client: None,
+                },
+                next: Delay::new(clock::now() + self.config.interval),
+                checking: None,
+            };
+            if let Err(e) = DefaultExecutor::current().spawn(Box::new(checker)) {
+                warn!("failed to spawn health checker: {:?}", e);
+            }
+        }
+
+        MakeFuture {
+            health: Some(health),
+            inner: self.inner.call(target),
+        }
+    }
+}
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = Checked<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        let health = self.health.take().expect("polled after ready");
+        Ok(Async::Ready(Checked { health, inner }))
+    }
+}
+
+// === impl Checked ===
+
+impl<S, Req> svc::Service<Req> for Checked<S>
+where
+    S: svc::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        if !self.health.poll_healthy() {
+            return Ok(Async::NotReady);
+        }
+
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+// === impl Health ===
+
+impl Health {
+    fn new(config: Arc<Config>) -> Self {
+        Self {
+            config,
+            state: Mutex::new(HealthState {
+                healthy: true,
+                consecutive_successes: 0,
+                consecutive_failures: 0,
+                task: None,
+            }),
+        }
+    }
+
+    /// Returns whether the endpoint is healthy, notifying the current task
+    /// when an unhealthy endpoint becomes healthy.
+    fn poll_healthy(&self) -> bool {
+        match self.state.lock() {
+            Ok(mut state) => {
+                if !state.healthy {
+                    state.task = Some(task::current());
+                }
+                state.healthy
+            }
+            Err(_) => true,
+        }
+    }
+
+    /// Records the outcome of a check, returning whether the endpoint is
+    /// healthy.
+    fn record(&self, success: bool) -> bool {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return true,
+        };
+
+        if success {
+            state.consecutive_failures = 0;
+            state.consecutive_successes += 1;
+            if !state.healthy && state.consecutive_successes >= self.config.healthy_threshold {
+                debug!(
+                    "endpoint is healthy; consecutive_successes={}",
+                    state.consecutive_successes
+                );
+                state.healthy = true;
+                if let Some(task) = state.task.take() {
+                    task.notify();
+                }
+            }
+        } else {
+            state.consecutive_successes = 0;
+            state.consecutive_failures += 1;
+            if state.healthy && state.consecutive_failures >= self.config.unhealthy_threshold {
+                debug!(
+                    "endpoint is unhealthy; consecutive_failures={}",
+                    state.consecutive_failures
+                );
+                state.healthy = false;
+            }
+        }
+
+        state.healthy
+    }
+}
+
+// === impl Checker ===
+
+impl<T, M, K, B, P> Checker<T, M, K, B, P>
+where
+    T: HasSettings + Clone,
+    K: Hash + Eq,
+    M: svc::Service<T>,
+    M::Error: Into<Error>,
+    M::Response: svc::Service<http::Request<B>, Response = http::Response<P>>,
+    <M::Response as svc::Service<http::Request<B>>>::Error: Into<Error>,
+    B: From<Bytes>,
+    P: Payload,
+{
+    /// Drives the current check to completion, succeeding if the endpoint
+    /// is healthy.
+    fn poll_check(&mut self) -> Poll<(), Error> {
+        let checking = self.checking.as_mut().expect("must be checking");
+        if let Ok(Async::Ready(())) = checking.timeout.poll() {
+            return Err("health check timed out".into());
+        }
+
+        loop {
+            checking.state = match checking.state {
+                CheckState::Connecting => {
+                    try_ready!(self.client.poll_ready::<http::Request<B>>());
+                    let version = if self.client.target.http_settings().is_http2() {
+                        http::Version::HTTP_2
+                    } else if let Check::Grpc(_) = checking.check {
+                        return Err("gRPC health checks require HTTP/2".into());
+                    } else {
+                        http::Version::HTTP_11
+                    };
+                    let req = checking.check.request(self.dst.as_authority(), version);
+                    CheckState::Responding(self.client.call(req))
+                }
+                CheckState::Responding(ref mut f) => {
+                    let rsp = try_ready!(f.poll().map_err(Into::<Error>::into));
+                    trace!("health check response: {:?}", rsp.status());
+                    if !rsp.status().is_success() {
+                        return Err(format!("unexpected status: {}", rsp.status()).into());
+                    }
+                    if let Check::Http(_) = checking.check {
+                        return Ok(Async::Ready(()));
+                    }
+                    // A trailers-only response has no message.
+                    if rsp.headers().contains_key("grpc-status") {
+                        return Err("gRPC health check response has no message".into());
+                    }
+                    CheckState::Reading {
+                        body: rsp.into_body(),
+                        buf: Vec::new(),
+                    }
+                }
+                CheckState::Reading {
+                    ref mut body,
+                    ref mut buf,
+                } => {
+                    while let Some(data) = try_ready!(body.poll_data().map_err(Into::<Error>::into))
+                    {
+                        buf.put(data);
+                        if buf.len() > MAX_GRPC_RESPONSE_BYTES {
+                            return Err("gRPC health check response is too large".into());
+                        }
+                    }
+                    let trailers = try_ready!(body.poll_trailers().map_err(Into::<Error>::into));
+                    let ok = trailers
+                        .as_ref()
+                        .and_then(grpc_status_is_ok)
+                        .unwrap_or(false);
+                    if !ok {
+                        return Err("unexpected grpc-status".into());
+                    }
+                    if !grpc_is_serving(&buf) {
+                        return Err("service is not serving".into());
+                    }
+                    return Ok(Async::Ready(()));
+                }
+            };
+        }
+    }
+}
+
+impl<T, M, K, B, P> Future for Checker<T, M, K, B, P>
+where
+    T: HasSettings + Clone,
+    K: Hash + Eq,
+    M: svc::Service<T>,
+    M::Error: Into<Error>,
+    M::Response: svc::Service<http::Request<B>, Response = http::Response<P>>,
+    <M::Response as svc::Service<http::Request<B>>>::Error: Into<Error>,
+    B: From<Bytes>,
+    P: Payload,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            if self.checking.is_some() {
+                let success = match self.poll_check() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) => true,
+                    Err(e) => {
+                        debug!("health check failed: {}", e);
+                        false
+                    }
+                };
+                self.checking = None;
+
+                let health = match self.health.upgrade() {
+                    Some(health) => health,
+                    None => return Ok(Async::Ready(())),
+                };
+                let healthy = health.record(success);
+                self.metrics.record(success, healthy);
+            }
+
+            try_ready!(self.next.poll().map_err(|_| ()));
+            let now = clock::now();
+            let config = match self.health.upgrade() {
+                Some(health) => health.config.clone(),
+                // The endpoint has been dropped.
+                None => return Ok(Async::Ready(())),
+            };
+            self.next.reset(now + config.interval);
+
+            // The destination's check may change, e.g. as its profile is
+            // updated.
+            if let Some(check) = self.checks.get(&self.dst) {
+                trace!("checking {}; check={:?}", self.dst, check);
+                self.checking = Some(Checking {
+                    check,
+                    timeout: Delay::new(now + config.timeout),
+                    state: CheckState::Connecting,
+                });
+            }
+        }
+    }
+}
+
+// === impl Client ===
+
+impl<T, M> Client<T, M>
+where
+    T: Clone,
+    M: svc::Service<T>,
+    M::Error: Into<Error>,
+{
+    fn poll_ready<Req>(&mut self) -> Poll<(), Error>
+    where
+        M::Response: svc::Service<Req>,
+        <M::Response as svc::Service<Req>>::Error: Into<Error>,
+    {
+        loop {
+            if let Some(client) = self.client.as_mut() {
+                return client.poll_ready().map_err(|e| {
+                    // Build a new client for the next check.
+                    self.client = None;
+                    e.into()
+                });
+            }
+
+            if let Some(making) = self.making.as_mut() {
+                let client = match making.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(client)) => client,
+                    Err(e) => {
+                        self.making = None;
+                        return Err(e.into());
+                    }
+                };
+                self.making = None;
+                self.client = Some(client);
+                continue;
+            }
+
+            try_ready!(self.make.poll_ready().map_err(Into::<Error>::into));
+            self.making = Some(self.make.call(self.target.clone()));
+        }
+    }
+
+    fn call<Req>(&mut self, req: Req) -> <M::Response as svc::Service<Req>>::Future
+    where
+        M::Response: svc::Service<Req>,
+    {
+        self.client.as_mut().expect("called before ready").call(req)
+    }
+}
+
+// === impl LazyMetrics ===
+
+impl<K: Hash + Eq> LazyMetrics<K> {
+    fn record(&mut self, success: bool, healthy: bool) {
+        if self.metrics.is_none() {
+            if let Some(key) = self.key.take() {
+                self.metrics = Some(self.registry.get_or_default(key));
+            }
+        }
+
+        if let Some(Ok(mut metrics)) = self.metrics.as_ref().map(|m| m.lock()) {
+            metrics.last_update = clock::now();
+            if success {
+                metrics.successes.incr();
+            } else {
+                metrics.failures.incr();
+            }
+            metrics.healthy = Gauge::from(u64::from(healthy));
+        }
+    }
+}
+
+/// The subset of the `grpc.health.v1` protocol that health checks use.
+// Not all of the accessors derived for these messages are used.
+#[allow(dead_code)]
+mod proto {
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct HealthCheckRequest {
+        #[prost(string, tag = "1")]
+        pub service: String,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct HealthCheckResponse {
+        #[prost(enumeration = "health_check_response::ServingStatus", tag = "1")]
+        pub status: i32,
+    }
+
+    pub mod health_check_response {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+        #[repr(i32)]
+        pub enum ServingStatus {
+            Unknown = 0,
+            Serving = 1,
+            NotServing = 2,
+            ServiceUnknown = 3,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health() -> Health {
+        Health::new(Arc::new(Config {
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(1),
+            unhealthy_threshold: 3,
+            healthy_threshold: 2,
+        }))
+    }
+
+    fn is_healthy(health: &Health) -> bool {
+        health.state.lock().unwrap().healthy
+    }
+
+    #[test]
+    fn becomes_unhealthy_after_failures() {
+        let health = health();
+        assert!(is_healthy(&health));
+
+        health.record(false);
+        health.record(false);
+        health.record(true);
+        health.record(false);
+        health.record(false);
+        assert!(is_healthy(&health));
+
+        assert!(!health.record(false));
+        assert!(!is_healthy(&health));
+    }
+
+    #[test]
+    fn becomes_healthy_after_successes() {
+        let health = health();
+        for _ in 0..3 {
+            health.record(false);
+        }
+        assert!(!is_healthy(&health));
+
+        health.record(true);
+        health.record(false);
+        assert!(!health.record(true));
+
+        assert!(health.record(true));
+        assert!(is_healthy(&health));
+    }
+
+    #[test]
+    fn parses_checks() {
+        assert_eq!(
+            "http:/healthz".parse(),
+            Ok(Check::Http(http::uri::PathAndQuery::from_static(
+                "/healthz"
+            )))
+        );
+        assert_eq!("grpc".parse(), Ok(Check::Grpc(String::new())));
+        assert_eq!("grpc:web.Api".parse(), Ok(Check::Grpc("web.Api".into())));
+        assert!("http:healthz".parse::<Check>().is_err());
+        assert!("tcp".parse::<Check>().is_err());
+    }
+
+    #[test]
+    fn encodes_and_decodes_grpc_messages() {
+        assert_eq!(&grpc_request("")[..], &[0, 0, 0, 0, 0][..]);
+        assert_eq!(
+            &grpc_request("web")[..],
+            &[0, 0, 0, 0, 5, 0x0a, 3, b'w', b'e', b'b'][..]
+        );
+
+        assert!(grpc_is_serving(&[0, 0, 0, 0, 2, 0x08, 1]));
+        assert!(!grpc_is_serving(&[0, 0, 0, 0, 2, 0x08, 2]));
+        assert!(!grpc_is_serving(&[0, 0, 0, 0, 0]), "unknown by default");
+        assert!(!grpc_is_serving(&[0, 0, 0, 0, 3, 0x08, 1]), "truncated");
+        assert!(!grpc_is_serving(&[1, 0, 0, 0, 2, 0x08, 1]), "compressed");
+        assert!(
+            !grpc_is_serving(&[0, 0, 0, 0, 2, 0x08, 7]),
+            "unknown status"
+        );
+        assert!(
+            !grpc_is_serving(&[0, 0, 0, 0, 2, 0x08, 0x80]),
+            "invalid varint"
+        );
+        assert!(
+            grpc_is_serving(&[0, 0, 0, 0, 5, 0x12, 1, b'x', 0x08, 1]),
+            "unknown fields are skipped"
+        );
+    }
+
+    #[test]
+    fn evicts_idle_metrics() {
+        let (registry, report) = new::<&'static str>(Duration::from_secs(60));
+
+        let before_update = clock::now();
+        let metrics = registry.get_or_default("endpoint");
+        let after_update = clock::now() + Duration::from_secs(1);
+
+        let mut by_key = report.by_key.lock().unwrap();
+        retain_since(&mut by_key, after_update);
+        assert_eq!(
+            by_key.len(),
+            1,
+            "metrics should not be evicted while in use"
+        );
+
+        drop(metrics);
+        retain_since(&mut by_key, before_update);
+        assert_eq!(
+            by_key.len(),
+            1,
+            "metrics should not be evicted while active"
+        );
+
+        retain_since(&mut by_key, after_update);
+        assert_eq!(by_key.len(), 0, "idle metrics should be evicted");
+    }
+}
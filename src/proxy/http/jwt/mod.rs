@@ -0,0 +1,939 @@
+//! Validates JWT bearer tokens on requests.
+//!
+//! Requests to targets that require a token must carry an
+//! `Authorization: Bearer` JWT that is signed by a key in the configured
+//! key set and whose `exp`, `nbf`, `iss`, and `aud` claims are acceptable.
+//! Verified claims may be forwarded to the application as headers; these
+//! headers are always stripped from requests so that they cannot be forged.
+//! Requests without an acceptable token fail with an `Unauthenticated` error.
+
+use crate::json::Value;
+use crate::metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics};
+use crate::{svc, Error};
+use futures::{future, try_ready, Future, Poll};
+use http::header::{self, HeaderName, HeaderValue};
+use indexmap::IndexMap;
+use ring::signature;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{error, fmt};
+use tracing::debug;
+
+metrics! {
+    inbound_jwt_accepted_total: Counter {
+        "Total count of inbound requests with a valid JWT"
+    },
+    inbound_jwt_rejected_total: Counter {
+        "Total count of inbound requests rejected for lacking a valid JWT"
+    }
+}
+
+/// Implemented by targets that may require requests to carry a JWT.
+pub trait CanRequireJwt {
+    fn requires_jwt(&self) -> bool;
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The keys that may sign tokens.
+    pub keys: KeySet,
+
+    /// If not empty, tokens must have been issued by one of these issuers.
+    pub issuers: Vec<String>,
+
+    /// If not empty, tokens must be intended for one of these audiences.
+    pub audiences: Vec<String>,
+
+    /// Claims that are forwarded as request headers.
+    pub claim_headers: Vec<(String, HeaderName)>,
+
+    /// The clock skew tolerated when checking `exp` and `nbf`.
+    pub leeway: Duration,
+}
+
+/// A set of public keys, as read from a JWKS document.
+#[derive(Clone, Debug)]
+pub struct KeySet(Arc<Vec<Key>>);
+
+#[derive(Debug)]
+struct Key {
+    kid: Option<String>,
+    alg: Option<Alg>,
+    material: Material,
+}
+
+#[derive(Debug)]
+enum Material {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    EcP256(Vec<u8>),
+    EcP384(Vec<u8>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Alg {
+    RS256,
+    RS384,
+    RS512,
+    ES256,
+    ES384,
+}
+
+/// Indicates that a request did not carry an acceptable token.
+#[derive(Debug)]
+pub struct Unauthenticated(Reason);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Reason {
+    Missing,
+    Malformed,
+    UnknownKey,
+    InvalidSignature,
+    Expired,
+    NotYetValid,
+    InvalidIssuer,
+    InvalidAudience,
+}
+
+pub fn new() -> (Registry, Report) {
+    let inner = Arc::new(Mutex::new(Counts::default()));
+    (Registry(inner.clone()), Report(inner))
+}
+
+#[derive(Clone, Debug)]
+pub struct Registry(Arc<Mutex<Counts>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted token metrics.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Mutex<Counts>>);
+
+#[derive(Debug, Default)]
+struct Counts {
+    accepted: Counter,
+    rejected: IndexMap<Reason, Counter>,
+}
+
+#[derive(Debug)]
+struct Validator {
+    config: Config,
+    registry: Registry,
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer(Option<Arc<Validator>>);
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    validator: Option<Arc<Validator>>,
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    validator: Option<Arc<Validator>>,
+    required: bool,
+    inner: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    validator: Option<Arc<Validator>>,
+    required: bool,
+    inner: S,
+}
+
+// === impl KeySet ===
+
+impl KeySet {
+    /// Reads the signing keys from a JWKS document.
+    ///
+    /// Keys with unsupported types or curves are ignored.
+    pub fn from_json(jwks: &[u8]) -> Option<Self> {
+        let jwks = Value::parse(jwks)?;
+        let keys = match jwks.get("keys") {
+            Some(Value::Array(keys)) => keys,
+            _ => return None,
+        };
+
+        let keys = keys
+            .iter()
+            .filter(|k| {
+                k.get("use")
+                    .map(|u| u.as_str() == Some("sig"))
+                    .unwrap_or(true)
+            })
+            .filter_map(Key::from_jwk)
+            .collect::<Vec<_>>();
+        if keys.is_empty() {
+            return None;
+        }
+        Some(KeySet(Arc::new(keys)))
+    }
+
+    fn verify(&self, alg: Alg, kid: Option<&str>, msg: &[u8], sig: &[u8]) -> Result<(), Reason> {
+        let mut candidates = self
+            .0
+            .iter()
+            .filter(|k| kid.is_none() || k.kid.as_ref().map(String::as_str) == kid)
+            .filter(|k| k.alg.map(|a| a == alg).unwrap_or(true))
+            .peekable();
+        if candidates.peek().is_none() {
+            return Err(Reason::UnknownKey);
+        }
+
+        if candidates.any(|k| k.verify(alg, msg, sig)) {
+            Ok(())
+        } else {
+            Err(Reason::InvalidSignature)
+        }
+    }
+}
+
+// === impl Key ===
+
+impl Key {
+    fn from_jwk(jwk: &Value) -> Option<Self> {
+        let field = |name: &str| jwk.get(name).and_then(Value::as_str);
+        let material = match (field("kty")?, field("crv")) {
+            ("RSA", _) => Material::Rsa {
+                n: base64url(field("n")?)?,
+                e: base64url(field("e")?)?,
+            },
+            ("EC", Some(crv)) => {
+                let mut point = vec![0x04];
+                point.extend(base64url(field("x")?)?);
+                point.extend(base64url(field("y")?)?);
+                match crv {
+                    "P-256" => Material::EcP256(point),
+                    "P-384" => Material::EcP384(point),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+
+        let alg = match field("alg") {
+            Some(alg) => Some(Alg::from_name(alg)?),
+            None => None,
+        };
+
+        Some(Key {
+            kid: field("kid").map(String::from),
+            alg,
+            material,
+        })
+    }
+
+    fn verify(&self, alg: Alg, msg: &[u8], sig: &[u8]) -> bool {
+        let result = match (alg, &self.material) {
+            (Alg::RS256, Material::Rsa { n, e }) => signature::RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, msg, sig),
+            (Alg::RS384, Material::Rsa { n, e }) => signature::RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA384, msg, sig),
+            (Alg::RS512, Material::Rsa { n, e }) => signature::RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA512, msg, sig),
+            (Alg::ES256, Material::EcP256(point)) => {
+                signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(msg, sig)
+            }
+            (Alg::ES384, Material::EcP384(point)) => {
+                signature::UnparsedPublicKey::new(&signature::ECDSA_P384_SHA384_FIXED, point)
+                    .verify(msg, sig)
+            }
+            _ => return false,
+        };
+        result.is_ok()
+    }
+}
+
+// === impl Alg ===
+
+impl Alg {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "RS256" => Some(Alg::RS256),
+            "RS384" => Some(Alg::RS384),
+            "RS512" => Some(Alg::RS512),
+            "ES256" => Some(Alg::ES256),
+            "ES384" => Some(Alg::ES384),
+            _ => None,
+        }
+    }
+}
+
+// === impl Validator ===
+
+impl Validator {
+    fn validate(&self, token: &str, now: SystemTime) -> Result<Value, Reason> {
+        // The signature covers the encoded header and payload exactly as they
+        // appear in the token.
+        let dot = token.rfind('.').ok_or(Reason::Malformed)?;
+        let (signed, sig) = (&token[..dot], &token[dot + 1..]);
+        let mut parts = signed.split('.');
+        let (header, payload) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(h), Some(p), None) => (h, p),
+            _ => return Err(Reason::Malformed),
+        };
+
+        let header = base64url(header)
+            .and_then(|h| Value::parse(&h))
+            .ok_or(Reason::Malformed)?;
+        let alg = header
+            .get("alg")
+            .and_then(Value::as_str)
+            .and_then(Alg::from_name)
+            .ok_or(Reason::UnknownKey)?;
+        let kid = header.get("kid").and_then(Value::as_str);
+        let sig = base64url(sig).ok_or(Reason::Malformed)?;
+        self.config.keys.verify(alg, kid, signed.as_bytes(), &sig)?;
+
+        let claims = match base64url(payload).and_then(|p| Value::parse(&p)) {
+            Some(claims @ Value::Object(_)) => claims,
+            _ => return Err(Reason::Malformed),
+        };
+        self.check_claims(&claims, now)?;
+        Ok(claims)
+    }
+
+    fn check_claims(&self, claims: &Value, now: SystemTime) -> Result<(), Reason> {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as f64)
+            .unwrap_or(0.0);
+        let leeway = self.config.leeway.as_secs() as f64;
+
+        match claims.get("exp") {
+            None => {}
+            Some(exp) => match exp.as_f64() {
+                Some(exp) if now <= exp + leeway => {}
+                Some(_) => return Err(Reason::Expired),
+                None => return Err(Reason::Malformed),
+            },
+        }
+
+        match claims.get("nbf") {
+            None => {}
+            Some(nbf) => match nbf.as_f64() {
+                Some(nbf) if now + leeway >= nbf => {}
+                Some(_) => return Err(Reason::NotYetValid),
+                None => return Err(Reason::Malformed),
+            },
+        }
+
+        if !self.config.issuers.is_empty() {
+            let iss = claims.get("iss").and_then(Value::as_str);
+            if !self.config.issuers.iter().any(|i| Some(i.as_str()) == iss) {
+                return Err(Reason::InvalidIssuer);
+            }
+        }
+
+        if !self.config.audiences.is_empty() {
+            let auds = match claims.get("aud") {
+                Some(Value::String(aud)) => vec![aud.as_str()],
+                Some(Value::Array(auds)) => auds.iter().filter_map(Value::as_str).collect(),
+                _ => vec![],
+            };
+            if !self
+                .config
+                .audiences
+                .iter()
+                .any(|a| auds.contains(&a.as_str()))
+            {
+                return Err(Reason::InvalidAudience);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn authenticate<B>(&self, req: &mut http::Request<B>) -> Result<(), Reason> {
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(bearer)
+            .ok_or(Reason::Missing)?;
+        let claims = self.validate(token, SystemTime::now())?;
+
+        for (claim, name) in &self.config.claim_headers {
+            if let Some(value) = claims.get(claim).and_then(header_value) {
+                req.headers_mut().insert(name.clone(), value);
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&self, result: Result<(), Reason>) -> Result<(), Reason> {
+        if let Ok(mut counts) = self.registry.0.lock() {
+            match result {
+                Ok(()) => counts.accepted.incr(),
+                Err(reason) => counts
+                    .rejected
+                    .entry(reason)
+                    .or_insert_with(Counter::default)
+                    .incr(),
+            }
+        }
+        result
+    }
+}
+
+fn bearer(authorization: &str) -> Option<&str> {
+    let scheme = authorization.get(..7)?;
+    if !scheme.eq_ignore_ascii_case("bearer ") {
+        return None;
+    }
+    Some(authorization[7..].trim())
+}
+
+fn header_value(claim: &Value) -> Option<HeaderValue> {
+    let value = match claim {
+        Value::String(s) => s.clone(),
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => format!("{}", *n as i64),
+        Value::Number(n) => format!("{}", n),
+        Value::Bool(b) => format!("{}", b),
+        Value::Array(values) => values
+            .iter()
+            .map(Value::as_str)
+            .collect::<Option<Vec<_>>>()?
+            .join(","),
+        _ => return None,
+    };
+    HeaderValue::from_str(&value).ok()
+}
+
+/// Decodes unpadded (or correctly padded) URL-safe base64.
+///
+/// Encodings with non-zero trailing bits are rejected so that each value has
+/// exactly one encoding.
+fn base64url(s: &str) -> Option<Vec<u8>> {
+    let unpadded = s.trim_end_matches('=');
+    let padding = s.len() - unpadded.len();
+    if padding != 0 && (padding > 2 || s.len() % 4 != 0) {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(unpadded.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for b in unpadded.bytes() {
+        let v = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | u32::from(v);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    // A single trailing character cannot encode a byte, and the bits that
+    // remain must be zero.
+    if bits >= 6 || acc != 0 {
+        return None;
+    }
+    Some(out)
+}
+
+// === impl Unauthenticated ===
+
+impl fmt::Display for Unauthenticated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request is not authenticated: {}", self.0)
+    }
+}
+
+impl error::Error for Unauthenticated {}
+
+// === impl Reason ===
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Reason::Missing => "missing bearer token",
+            Reason::Malformed => "malformed token",
+            Reason::UnknownKey => "unknown signing key",
+            Reason::InvalidSignature => "invalid signature",
+            Reason::Expired => "token expired",
+            Reason::NotYetValid => "token not yet valid",
+            Reason::InvalidIssuer => "invalid issuer",
+            Reason::InvalidAudience => "invalid audience",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl Reason {
+    fn label(&self) -> &'static str {
+        match self {
+            Reason::Missing => "missing",
+            Reason::Malformed => "malformed",
+            Reason::UnknownKey => "unknown_key",
+            Reason::InvalidSignature => "invalid_signature",
+            Reason::Expired => "expired",
+            Reason::NotYetValid => "not_yet_valid",
+            Reason::InvalidIssuer => "invalid_issuer",
+            Reason::InvalidAudience => "invalid_audience",
+        }
+    }
+}
+
+impl FmtLabels for Reason {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "reason=\"{}\"", self.label())
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if counts.accepted.value() == 0 && counts.rejected.is_empty() {
+            return Ok(());
+        }
+
+        inbound_jwt_accepted_total.fmt_help(f)?;
+        inbound_jwt_accepted_total.fmt_metric(f, counts.accepted)?;
+
+        if !counts.rejected.is_empty() {
+            inbound_jwt_rejected_total.fmt_help(f)?;
+            for (reason, c) in counts.rejected.iter() {
+                c.fmt_metric_labeled(f, inbound_jwt_rejected_total.name, reason)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Layer ===
+
+/// Validates tokens on requests to targets that require them, if `config` is
+/// set.
+pub fn layer(config: Option<Config>, registry: Registry) -> Layer {
+    Layer(config.map(|config| Arc::new(Validator { config, registry })))
+}
+
+impl<M> svc::Layer<M> for Layer {
+    type Service = Stack<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            validator: self.0.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Service<T> for Stack<M>
+where
+    T: CanRequireJwt,
+    M: svc::Service<T>,
+{
+    type Response = Service<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        MakeFuture {
+            validator: self.validator.clone(),
+            required: target.requires_jwt(),
+            inner: self.inner.call(target),
+        }
+    }
+}
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = Service<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        let svc = Service {
+            validator: self.validator.take(),
+            required: self.required,
+            inner,
+        };
+        Ok(svc.into())
+    }
+}
+
+// === impl Service ===
+
+impl<S, B> svc::Service<http::Request<B>> for Service<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::FutureResult<S::Response, Error>,
+        future::MapErr<S::Future, fn(S::Error) -> Error>,
+    >;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(ref validator) = self.validator {
+            for (_, name) in &validator.config.claim_headers {
+                req.headers_mut().remove(name);
+            }
+
+            if self.required {
+                let result = validator.authenticate(&mut req);
+                if let Err(reason) = validator.record(result) {
+                    let e = Unauthenticated(reason);
+                    debug!("{}", e);
+                    return future::Either::A(future::err(e.into()));
+                }
+            }
+        }
+
+        future::Either::B(self.inner.call(req).map_err(Into::into))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    fn encode(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+        let mut out = String::new();
+        for chunk in bytes.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, b)| n | (u32::from(*b) << (16 - 8 * i)));
+            for i in 0..=chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            }
+        }
+        out
+    }
+
+    struct Signer {
+        rng: SystemRandom,
+        key: EcdsaKeyPair,
+    }
+
+    impl Signer {
+        fn new() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                .expect("key must be generated");
+            let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref())
+                .expect("key must be valid");
+            Self { rng, key }
+        }
+
+        fn jwks(&self) -> String {
+            let point = self.key.public_key().as_ref();
+            format!(
+                r#"{{"keys":[{{"kty":"EC","use":"sig","kid":"k1","crv":"P-256","x":"{}","y":"{}"}}]}}"#,
+                encode(&point[1..33]),
+                encode(&point[33..65]),
+            )
+        }
+
+        fn sign(&self, kid: &str, claims: &str) -> String {
+            let header = format!(r#"{{"alg":"ES256","kid":"{}"}}"#, kid);
+            self.sign_with_header(&header, claims)
+        }
+
+        fn sign_with_header(&self, header: &str, claims: &str) -> String {
+            let signed = format!(
+                "{}.{}",
+                encode(header.as_bytes()),
+                encode(claims.as_bytes())
+            );
+            let sig = self
+                .key
+                .sign(&self.rng, signed.as_bytes())
+                .expect("token must be signed");
+            format!("{}.{}", signed, encode(sig.as_ref()))
+        }
+    }
+
+    #[test]
+    fn base64url_round_trips() {
+        let cases: &[&[u8]] = &[b"", b"f", b"fo", b"foo", b"foob", b"\xff\xfe\xfd"];
+        for s in cases {
+            assert_eq!(base64url(&encode(s)).as_ref().map(Vec::as_slice), Some(*s));
+        }
+        assert_eq!(base64url("Zm9v"), Some(b"foo".to_vec()));
+        assert_eq!(base64url("Zg=="), Some(b"f".to_vec()));
+        assert_eq!(base64url("Z"), None);
+        assert_eq!(base64url("Zm+v"), None);
+    }
+
+    #[test]
+    fn base64url_rejects_noncanonical_encodings() {
+        // Trailing bits must be zero.
+        assert_eq!(base64url("Zg"), Some(b"f".to_vec()));
+        assert_eq!(base64url("Zh"), None);
+        assert_eq!(base64url("Zm8"), Some(b"fo".to_vec()));
+        assert_eq!(base64url("Zm9"), None);
+
+        // Padding must complete a quantum.
+        assert_eq!(base64url("Zm8="), Some(b"fo".to_vec()));
+        assert_eq!(base64url("Zg="), None);
+        assert_eq!(base64url("Zg==="), None);
+        assert_eq!(base64url("Zm9v="), None);
+        assert_eq!(base64url("Z==="), None);
+        assert_eq!(base64url("Zg==Zg=="), None);
+
+        // Only the URL-safe alphabet is accepted.
+        assert_eq!(base64url("Zm9v\n"), None);
+        assert_eq!(base64url("Zm9/"), None);
+        assert_eq!(base64url("Zm 9v"), None);
+    }
+
+    #[test]
+    fn validates_tokens() {
+        let signer = Signer::new();
+        let (registry, _) = new();
+        let validator = Validator {
+            config: Config {
+                keys: KeySet::from_json(signer.jwks().as_bytes()).expect("jwks must be valid"),
+                issuers: vec!["https://issuer.example.com".into()],
+                audiences: vec!["billing".into()],
+                claim_headers: vec![],
+                leeway: Duration::from_secs(10),
+            },
+            registry,
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        let valid = r#"{"iss":"https://issuer.example.com","aud":["web","billing"],
+                        "sub":"alice","exp":1000005,"nbf":999995}"#;
+        let claims = validator
+            .validate(&signer.sign("k1", valid), now)
+            .expect("token must be valid");
+        assert_eq!(claims.get("sub").and_then(Value::as_str), Some("alice"));
+
+        let expired = r#"{"iss":"https://issuer.example.com","aud":"billing","exp":999980}"#;
+        assert_eq!(
+            validator.validate(&signer.sign("k1", expired), now).err(),
+            Some(Reason::Expired)
+        );
+
+        let early = r#"{"iss":"https://issuer.example.com","aud":"billing","nbf":1000020}"#;
+        assert_eq!(
+            validator.validate(&signer.sign("k1", early), now).err(),
+            Some(Reason::NotYetValid)
+        );
+
+        let issuer = r#"{"iss":"https://other.example.com","aud":"billing"}"#;
+        assert_eq!(
+            validator.validate(&signer.sign("k1", issuer), now).err(),
+            Some(Reason::InvalidIssuer)
+        );
+
+        let audience = r#"{"iss":"https://issuer.example.com","aud":"web"}"#;
+        assert_eq!(
+            validator.validate(&signer.sign("k1", audience), now).err(),
+            Some(Reason::InvalidAudience)
+        );
+
+        assert_eq!(
+            validator.validate(&signer.sign("k2", valid), now).err(),
+            Some(Reason::UnknownKey)
+        );
+
+        let forged = Signer::new().sign("k1", valid);
+        assert_eq!(
+            validator.validate(&forged, now).err(),
+            Some(Reason::InvalidSignature)
+        );
+
+        assert_eq!(
+            validator.validate("not.a-token", now).err(),
+            Some(Reason::Malformed)
+        );
+    }
+
+    fn validator(signer: &Signer) -> Validator {
+        let (registry, _) = new();
+        Validator {
+            config: Config {
+                keys: KeySet::from_json(signer.jwks().as_bytes()).expect("jwks must be valid"),
+                issuers: vec![],
+                audiences: vec![],
+                claim_headers: vec![],
+                leeway: Duration::from_secs(0),
+            },
+            registry,
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_tokens() {
+        let signer = Signer::new();
+        let validator = validator(&signer);
+        let now = UNIX_EPOCH;
+        let token = signer.sign("k1", r#"{"sub":"alice"}"#);
+        assert!(validator.validate(&token, now).is_ok());
+
+        let dot = token.rfind('.').unwrap();
+        let (signed, sig) = (&token[..dot], &token[dot + 1..]);
+        let malformed = vec![
+            String::new(),
+            "...".into(),
+            signed.to_string(),
+            format!("{}.", token),
+            format!("{}.{}", signed, token),
+            format!(".{}", token),
+            format!("{}.{}=", signed, sig),
+            format!("{}.{}+", signed, &sig[1..]),
+        ];
+        for token in &malformed {
+            assert!(
+                validator.validate(token, now).is_err(),
+                "{:?} must be rejected",
+                token
+            );
+        }
+
+        // Claims must be an object.
+        for claims in &["[]", "\"alice\"", "null", "{\"sub\":\"alice\"", "{}{}"] {
+            assert_eq!(
+                validator.validate(&signer.sign("k1", claims), now).err(),
+                Some(Reason::Malformed),
+                "{}",
+                claims
+            );
+        }
+
+        // Claims must not be ambiguous.
+        let dup = r#"{"sub":"alice","sub":"mallory"}"#;
+        assert_eq!(
+            validator.validate(&signer.sign("k1", dup), now).err(),
+            Some(Reason::Malformed)
+        );
+    }
+
+    #[test]
+    fn verifies_the_encoded_header_and_payload() {
+        let signer = Signer::new();
+        let validator = validator(&signer);
+        let now = UNIX_EPOCH;
+
+        // Re-encoding the claims, even equivalently, invalidates the token.
+        let token = signer.sign("k1", r#"{"sub":"alice"}"#);
+        let mut parts = token.split('.');
+        let (header, sig) = (parts.next().unwrap(), parts.nth(1).unwrap());
+        let spaced = encode(br#"{ "sub":"alice"}"#);
+        assert_eq!(
+            validator
+                .validate(&format!("{}.{}.{}", header, spaced, sig), now)
+                .err(),
+            Some(Reason::InvalidSignature)
+        );
+
+        // As does changing the claims.
+        let mallory = encode(br#"{"sub":"mallory"}"#);
+        assert_eq!(
+            validator
+                .validate(&format!("{}.{}.{}", header, mallory, sig), now)
+                .err(),
+            Some(Reason::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn rejects_unexpected_algorithms() {
+        let signer = Signer::new();
+        let validator = validator(&signer);
+        let now = UNIX_EPOCH;
+        let claims = r#"{"sub":"alice"}"#;
+
+        // Unsigned tokens are never accepted.
+        let unsigned = format!(
+            "{}.{}.",
+            encode(br#"{"alg":"none","kid":"k1"}"#),
+            encode(claims.as_bytes())
+        );
+        assert_eq!(
+            validator.validate(&unsigned, now).err(),
+            Some(Reason::UnknownKey)
+        );
+        let none = signer.sign_with_header(r#"{"alg":"none","kid":"k1"}"#, claims);
+        assert_eq!(
+            validator.validate(&none, now).err(),
+            Some(Reason::UnknownKey)
+        );
+
+        // Public keys are never used as HMAC secrets.
+        let hmac = signer.sign_with_header(r#"{"alg":"HS256","kid":"k1"}"#, claims);
+        assert_eq!(
+            validator.validate(&hmac, now).err(),
+            Some(Reason::UnknownKey)
+        );
+
+        // An algorithm must be used with a key of the matching type.
+        let rsa = signer.sign_with_header(r#"{"alg":"RS256","kid":"k1"}"#, claims);
+        assert_eq!(
+            validator.validate(&rsa, now).err(),
+            Some(Reason::InvalidSignature)
+        );
+        let es384 = signer.sign_with_header(r#"{"alg":"ES384","kid":"k1"}"#, claims);
+        assert_eq!(
+            validator.validate(&es384, now).err(),
+            Some(Reason::InvalidSignature)
+        );
+
+        // The algorithm must be stated exactly once, as a string.
+        for header in &[
+            r#"{"kid":"k1"}"#,
+            r#"{"alg":["ES256"],"kid":"k1"}"#,
+            r#"{"alg":"es256","kid":"k1"}"#,
+        ] {
+            let token = signer.sign_with_header(header, claims);
+            assert_eq!(
+                validator.validate(&token, now).err(),
+                Some(Reason::UnknownKey),
+                "{}",
+                header
+            );
+        }
+        let dup = signer.sign_with_header(r#"{"alg":"ES256","alg":"none","kid":"k1"}"#, claims);
+        assert_eq!(validator.validate(&dup, now).err(), Some(Reason::Malformed));
+
+        // Keys that specify an algorithm are only used with it.
+        let jwks = signer
+            .jwks()
+            .replace(r#""kid":"k1""#, r#""kid":"k1","alg":"ES384""#);
+        let pinned = Validator {
+            config: Config {
+                keys: KeySet::from_json(jwks.as_bytes()).expect("jwks must be valid"),
+                ..validator.config.clone()
+            },
+            registry: validator.registry.clone(),
+        };
+        assert_eq!(
+            pinned.validate(&signer.sign("k1", claims), now).err(),
+            Some(Reason::UnknownKey)
+        );
+    }
+}
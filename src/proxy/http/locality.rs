@@ -0,0 +1,659 @@
+//! Prefers endpoints in the local proxy's zone and region.
+//!
+//! Each endpoint is classified by comparing its `zone` and `region` labels
+//! with the locality configured for the proxy. Endpoints in a less-preferred
+//! locality are not ready while at least `Config::min_endpoints` endpoints in
+//! more-preferred localities are ready, so that the balancer only spills
+//! requests over to other zones when local endpoints are unhealthy (i.e.
+//! failing or ejected), overloaded (i.e. not ready), or too few.
+//!
+//! Whenever an endpoint becomes ready, the balancer's `Readiness` is
+//! invalidated so that endpoints in less-preferred localities that were
+//! admitted while spilling over are polled again and stop receiving requests.
+
+use super::balance::weighted::{HasReadiness, Readiness};
+use super::weight::HasWeight;
+use crate::{svc, Error};
+use futures::{task, try_ready, Async, Future, Poll};
+use indexmap::IndexMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tower_discover::{Change, Discover};
+use tracing::trace;
+
+/// The endpoint label that names an endpoint's zone.
+pub const ZONE_LABEL: &str = "zone";
+
+/// The endpoint label that names an endpoint's region.
+pub const REGION_LABEL: &str = "region";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The zone in which the proxy runs.
+    pub zone: Option<String>,
+
+    /// The region in which the proxy runs.
+    pub region: Option<String>,
+
+    /// The minimum number of ready endpoints in preferred localities below
+    /// which requests spill over to endpoints in other localities.
+    pub min_endpoints: usize,
+}
+
+/// An endpoint's locality relative to the proxy, in order of preference.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Locality {
+    SameZone,
+    SameRegion,
+    Remote,
+}
+
+/// Implemented by endpoint targets that have been classified by locality.
+pub trait HasLocality {
+    fn locality(&self) -> Option<Locality>;
+}
+
+/// Records the locality of each endpoint service so that it may be
+/// prioritized by `layer`.
+pub fn tag_layer() -> TagLayer {
+    TagLayer
+}
+
+/// Prioritizes each discovered endpoint by its locality.
+///
+/// Endpoint services must be produced by a `tag_layer` stack. If the proxy's
+/// locality is not configured, all endpoints are treated alike.
+pub fn layer(config: Option<Config>) -> Layer {
+    Layer {
+        min_endpoints: config.map(|c| c.min_endpoints).unwrap_or(0),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TagLayer;
+
+#[derive(Clone, Debug)]
+pub struct TagStack<M> {
+    inner: M,
+}
+
+pub struct TagFuture<F> {
+    locality: Option<Locality>,
+    inner: F,
+}
+
+/// An endpoint service that is labeled with its locality.
+#[derive(Clone, Debug)]
+pub struct Tagged<S> {
+    locality: Option<Locality>,
+    inner: S,
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    min_endpoints: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct MakeSvc<M> {
+    min_endpoints: usize,
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    min_endpoints: usize,
+    inner: F,
+}
+
+/// A `Discover` that wraps each endpoint in a `Prioritized` service.
+pub struct PrioritizedDiscover<D> {
+    pool: Arc<Pool>,
+    inner: D,
+}
+
+/// An endpoint service that is not ready while enough endpoints in more
+/// preferred localities are ready.
+pub struct Prioritized<S> {
+    pool: Arc<Pool>,
+    locality: Locality,
+    is_ready: bool,
+    inner: S,
+}
+
+/// The state shared by all endpoints of a balancer.
+#[derive(Debug)]
+struct Pool {
+    min_endpoints: usize,
+    ready: Mutex<[usize; 3]>,
+    readiness: Readiness,
+}
+
+// === impl Config ===
+
+impl Config {
+    /// Classifies an endpoint by its labels.
+    pub fn locality(&self, labels: &IndexMap<String, String>) -> Locality {
+        let matches = |key: &str, local: &Option<String>| match local {
+            Some(local) => labels.get(key) == Some(local),
+            None => false,
+        };
+
+        if matches(ZONE_LABEL, &self.zone) {
+            Locality::SameZone
+        } else if matches(REGION_LABEL, &self.region) {
+            Locality::SameRegion
+        } else {
+            Locality::Remote
+        }
+    }
+}
+
+// === impl Locality ===
+
+impl Locality {
+    fn index(self) -> usize {
+        match self {
+            Locality::SameZone => 0,
+            Locality::SameRegion => 1,
+            Locality::Remote => 2,
+        }
+    }
+}
+
+impl fmt::Display for Locality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Locality::SameZone => write!(f, "same_zone"),
+            Locality::SameRegion => write!(f, "same_region"),
+            Locality::Remote => write!(f, "remote"),
+        }
+    }
+}
+
+// === impl TagLayer ===
+
+impl<M> svc::Layer<M> for TagLayer {
+    type Service = TagStack<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        TagStack { inner }
+    }
+}
+
+// === impl TagStack ===
+
+impl<T, M> svc::Service<T> for TagStack<M>
+where
+    T: HasLocality,
+    M: svc::Service<T>,
+{
+    type Response = Tagged<M::Response>;
+    type Error = M::Error;
+    type Future = TagFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        TagFuture {
+            locality: target.locality(),
+            inner: self.inner.call(target),
+        }
+    }
+}
+
+impl<F: Future> Future for TagFuture<F> {
+    type Item = Tagged<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Async::Ready(Tagged {
+            locality: self.locality,
+            inner,
+        }))
+    }
+}
+
+// === impl Tagged ===
+
+impl<S> HasLocality for Tagged<S> {
+    fn locality(&self) -> Option<Locality> {
+        self.locality
+    }
+}
+
+impl<S: HasWeight> HasWeight for Tagged<S> {
+    fn weight(&self) -> u32 {
+        self.inner.weight()
+    }
+}
+
+impl<S, Req> svc::Service<Req> for Tagged<S>
+where
+    S: svc::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<M> for Layer {
+    type Service = MakeSvc<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeSvc {
+            min_endpoints: self.min_endpoints,
+            inner,
+        }
+    }
+}
+
+// === impl MakeSvc ===
+
+impl<T, M> svc::Service<T> for MakeSvc<M>
+where
+    M: svc::Service<T>,
+    M::Response: Discover,
+    <M::Response as Discover>::Service: HasLocality,
+{
+    type Response = PrioritizedDiscover<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        MakeFuture {
+            min_endpoints: self.min_endpoints,
+            inner: self.inner.call(target),
+        }
+    }
+}
+
+impl<F> Future for MakeFuture<F>
+where
+    F: Future,
+    F::Item: Discover,
+{
+    type Item = PrioritizedDiscover<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Async::Ready(PrioritizedDiscover {
+            pool: Arc::new(Pool::new(self.min_endpoints)),
+            inner,
+        }))
+    }
+}
+
+// === impl PrioritizedDiscover ===
+
+impl<D> Discover for PrioritizedDiscover<D>
+where
+    D: Discover,
+    D::Service: HasLocality,
+{
+    type Key = D::Key;
+    type Service = Prioritized<D::Service>;
+    type Error = D::Error;
+
+    fn poll(&mut self) -> Poll<Change<Self::Key, Self::Service>, Self::Error> {
+        let change = match try_ready!(self.inner.poll()) {
+            Change::Insert(key, inner) => {
+                let prioritized = Prioritized {
+                    pool: self.pool.clone(),
+                    // Endpoints without a locality are always preferred.
+                    locality: inner.locality().unwrap_or(Locality::SameZone),
+                    is_ready: false,
+                    inner,
+                };
+                Change::Insert(key, prioritized)
+            }
+            Change::Remove(key) => Change::Remove(key),
+        };
+
+        Ok(Async::Ready(change))
+    }
+}
+
+impl<D> HasReadiness for PrioritizedDiscover<D> {
+    fn readiness(&self) -> Readiness {
+        self.pool.readiness.clone()
+    }
+}
+
+// === impl Prioritized ===
+
+impl<S> Prioritized<S> {
+    fn set_ready(&mut self, is_ready: bool) {
+        if self.is_ready == is_ready {
+            return;
+        }
+        self.is_ready = is_ready;
+
+        if is_ready {
+            self.pool.incr(self.locality);
+        } else {
+            self.pool.decr(self.locality);
+            // Endpoints in less-preferred localities may have been polled
+            // before this endpoint became unready, so the balancer must poll
+            // them again.
+            task::current().notify();
+        }
+    }
+}
+
+impl<S: HasWeight> HasWeight for Prioritized<S> {
+    fn weight(&self) -> u32 {
+        self.inner.weight()
+    }
+}
+
+impl<S, Req> svc::Service<Req> for Prioritized<S>
+where
+    S: svc::Service<Req>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = futures::future::MapErr<S::Future, fn(S::Error) -> Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        if !self.pool.is_eligible(self.locality) {
+            trace!("preferring endpoints closer than {}", self.locality);
+            self.set_ready(false);
+            return Ok(Async::NotReady);
+        }
+
+        match self.inner.poll_ready() {
+            Ok(Async::Ready(())) => {
+                self.set_ready(true);
+                Ok(Async::Ready(()))
+            }
+            Ok(Async::NotReady) => {
+                self.set_ready(false);
+                Ok(Async::NotReady)
+            }
+            Err(e) => {
+                self.set_ready(false);
+                Err(e.into())
+            }
+        }
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req).map_err(Into::into)
+    }
+}
+
+impl<S> Drop for Prioritized<S> {
+    fn drop(&mut self) {
+        if self.is_ready {
+            self.pool.decr(self.locality);
+        }
+    }
+}
+
+// === impl Pool ===
+
+impl Pool {
+    fn new(min_endpoints: usize) -> Self {
+        Self {
+            min_endpoints,
+            ready: Mutex::new([0; 3]),
+            readiness: Readiness::default(),
+        }
+    }
+
+    /// Returns true if too few endpoints in more-preferred localities are
+    /// ready to serve requests.
+    fn is_eligible(&self, locality: Locality) -> bool {
+        let ready = match self.ready.lock() {
+            Ok(ready) => ready,
+            // If the pool is poisoned, all endpoints are used.
+            Err(_) => return true,
+        };
+
+        let preferred: usize = ready[..locality.index()].iter().sum();
+        locality == Locality::SameZone || preferred < self.min_endpoints
+    }
+
+    fn incr(&self, locality: Locality) {
+        if let Ok(mut ready) = self.ready.lock() {
+            ready[locality.index()] += 1;
+        }
+        // Endpoints in less-preferred localities that are ready may no
+        // longer be eligible.
+        self.readiness.invalidate();
+    }
+
+    fn decr(&self, locality: Locality) {
+        if let Ok(mut ready) = self.ready.lock() {
+            let n = &mut ready[locality.index()];
+            *n = n.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::balance::{weighted::Balance, Load};
+    use super::super::consistent_hash::HashKeys;
+    use super::super::weight::DEFAULT_WEIGHT;
+    use super::*;
+    use crate::svc::Service as _;
+    use crate::Never;
+    use futures::future;
+    use rand::{rngs::SmallRng, SeedableRng};
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct Key(usize);
+
+    /// An endpoint with a fixed load that counts the requests it serves.
+    struct Endpoint {
+        load: f64,
+        ready: Arc<AtomicBool>,
+        requests: Arc<AtomicUsize>,
+    }
+
+    /// Yields each of a list of endpoints once.
+    struct Endpoints(VecDeque<(Key, Tagged<Endpoint>)>);
+
+    impl HasWeight for Key {
+        fn weight(&self) -> u32 {
+            DEFAULT_WEIGHT
+        }
+    }
+
+    impl svc::Service<http::Request<()>> for Endpoint {
+        type Response = ();
+        type Error = Never;
+        type Future = future::FutureResult<(), Never>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            if self.ready.load(Ordering::SeqCst) {
+                Ok(Async::Ready(()))
+            } else {
+                Ok(Async::NotReady)
+            }
+        }
+
+        fn call(&mut self, _: http::Request<()>) -> Self::Future {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            future::ok(())
+        }
+    }
+
+    impl Load for Prioritized<Tagged<Endpoint>> {
+        type Metric = f64;
+
+        fn load(&self) -> f64 {
+            self.inner.inner.load
+        }
+    }
+
+    impl Discover for Endpoints {
+        type Key = Key;
+        type Service = Tagged<Endpoint>;
+        type Error = Never;
+
+        fn poll(&mut self) -> Poll<Change<Key, Tagged<Endpoint>>, Never> {
+            let change = self
+                .0
+                .pop_front()
+                .map(|(key, svc)| Async::Ready(Change::Insert(key, svc)));
+            Ok(change.unwrap_or(Async::NotReady))
+        }
+    }
+
+    fn labels(zone: &str, region: &str) -> IndexMap<String, String> {
+        let mut labels = IndexMap::new();
+        labels.insert(ZONE_LABEL.to_owned(), zone.to_owned());
+        labels.insert(REGION_LABEL.to_owned(), region.to_owned());
+        labels
+    }
+
+    #[test]
+    fn classifies_endpoints() {
+        let config = Config {
+            zone: Some("us-east-1a".into()),
+            region: Some("us-east-1".into()),
+            min_endpoints: 1,
+        };
+
+        assert_eq!(
+            config.locality(&labels("us-east-1a", "us-east-1")),
+            Locality::SameZone
+        );
+        assert_eq!(
+            config.locality(&labels("us-east-1b", "us-east-1")),
+            Locality::SameRegion
+        );
+        assert_eq!(
+            config.locality(&labels("us-west-2a", "us-west-2")),
+            Locality::Remote
+        );
+        assert_eq!(config.locality(&IndexMap::new()), Locality::Remote);
+    }
+
+    #[test]
+    fn spills_over_below_min_endpoints() {
+        let pool = Pool::new(2);
+        assert!(pool.is_eligible(Locality::SameZone));
+        assert!(pool.is_eligible(Locality::SameRegion));
+        assert!(pool.is_eligible(Locality::Remote));
+
+        pool.incr(Locality::SameZone);
+        assert!(pool.is_eligible(Locality::SameRegion));
+        assert!(pool.is_eligible(Locality::Remote));
+
+        pool.incr(Locality::SameRegion);
+        assert!(pool.is_eligible(Locality::SameRegion));
+        assert!(!pool.is_eligible(Locality::Remote));
+
+        pool.incr(Locality::SameZone);
+        assert!(!pool.is_eligible(Locality::SameRegion));
+        assert!(!pool.is_eligible(Locality::Remote));
+
+        pool.decr(Locality::SameZone);
+        pool.decr(Locality::SameRegion);
+        assert!(pool.is_eligible(Locality::SameRegion));
+        assert!(pool.is_eligible(Locality::Remote));
+    }
+
+    #[test]
+    fn stops_spilling_over_once_preferred_endpoints_are_ready() {
+        let local_ready = Arc::new(AtomicBool::new(false));
+        let requests = (0..3)
+            .map(|_| Arc::new(AtomicUsize::new(0)))
+            .collect::<Vec<_>>();
+        let endpoint = |i: usize, locality: Locality, ready| {
+            // The local endpoint is more loaded, so the remote endpoints
+            // would be preferred while they are ready.
+            let load = if locality == Locality::SameZone {
+                10.0
+            } else {
+                1.0
+            };
+            let inner = Endpoint {
+                load,
+                ready,
+                requests: requests[i].clone(),
+            };
+            let tagged = Tagged {
+                locality: Some(locality),
+                inner,
+            };
+            (Key(i), tagged)
+        };
+        let endpoints = Endpoints(
+            vec![
+                endpoint(0, Locality::SameZone, local_ready.clone()),
+                endpoint(1, Locality::Remote, Arc::new(AtomicBool::new(true))),
+                endpoint(2, Locality::Remote, Arc::new(AtomicBool::new(true))),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let discover = PrioritizedDiscover {
+            pool: Arc::new(Pool::new(1)),
+            inner: endpoints,
+        };
+        let readiness = discover.readiness();
+        let mut balance = Balance::new(
+            discover,
+            HashKeys::default(),
+            None,
+            None,
+            SmallRng::seed_from_u64(0),
+        )
+        .with_readiness(readiness);
+
+        // Endpoints must be polled from within a task.
+        future::lazy(move || {
+            let mut dispatch = |n: usize| {
+                for r in &requests {
+                    r.store(0, Ordering::SeqCst);
+                }
+                for _ in 0..n {
+                    assert!(balance.poll_ready().unwrap().is_ready());
+                    balance.call(http::Request::new(())).wait().unwrap();
+                }
+                requests
+                    .iter()
+                    .map(|r| r.load(Ordering::SeqCst))
+                    .collect::<Vec<_>>()
+            };
+
+            // While the local endpoint is not ready, requests spill over to
+            // the remote endpoints, one of which remains ready.
+            let spilled = dispatch(1);
+            assert_eq!(spilled[0], 0);
+            assert_eq!(spilled.iter().sum::<usize>(), 1);
+
+            local_ready.store(true, Ordering::SeqCst);
+            assert_eq!(dispatch(10), vec![10, 0, 0]);
+
+            Ok::<(), ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+}
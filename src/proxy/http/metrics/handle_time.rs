@@ -1,4 +1,4 @@
-use crate::metrics::{latency, FmtLabels, FmtMetric, Histogram};
+use crate::metrics::{latency, Bounds, FmtLabels, FmtMetric, Histogram};
 use crate::proxy::http::insert;
 use std::{
     fmt,
@@ -76,8 +76,8 @@ impl insert::Lazy<Tracker> for InsertTracker {
 // ===== impl Scope =====
 
 impl Scope {
-    pub fn new() -> Self {
-        Scope(Arc::new(Shared::new()))
+    pub fn new(bounds: &Bounds) -> Self {
+        Scope(Arc::new(Shared::new(bounds)))
     }
 
     pub fn layer(&self) -> insert::Layer<InsertTracker, Tracker> {
@@ -134,11 +134,11 @@ impl Drop for Tracker {
 impl Shared {
     const INITIAL_RECORDERS: usize = 32;
 
-    fn new() -> Self {
+    fn new(bounds: &Bounds) -> Self {
         let mut counts = Vec::with_capacity(Self::INITIAL_RECORDERS);
         Self::add_counts(&mut counts, Self::INITIAL_RECORDERS);
         Self {
-            histogram: Mutex::new(Histogram::default()), // TODO(eliza): should we change the bounds here?
+            histogram: Mutex::new(Histogram::new(bounds)),
             counts: RwLock::new(counts),
             idle_head: AtomicUsize::new(0),
         }
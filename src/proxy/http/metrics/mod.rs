@@ -1,4 +1,4 @@
-use crate::metrics::{latency, Counter, FmtLabels, Histogram};
+use crate::metrics::{latency, Bounds, Counter, FmtLabels, Histogram};
 use http;
 use indexmap::IndexMap;
 use std::hash::Hash;
@@ -16,12 +16,16 @@ pub use self::service::layer;
 
 pub type SharedRegistry<T, C> = Arc<Mutex<Registry<T, C>>>;
 
-pub fn new<T, C>(retain_idle: Duration) -> (SharedRegistry<T, C>, Report<T, C>)
+/// Builds a registry whose response latency histograms use the given bounds.
+pub fn new<T, C>(
+    retain_idle: Duration,
+    latency_bounds: Bounds,
+) -> (SharedRegistry<T, C>, Report<T, C>)
 where
     T: FmtLabels + Clone + Hash + Eq,
     C: FmtLabels + Hash + Eq,
 {
-    let registry = Arc::new(Mutex::new(Registry::default()));
+    let registry = Arc::new(Mutex::new(Registry::new(latency_bounds)));
     (registry.clone(), Report::new(retain_idle, registry))
 }
 
@@ -32,6 +36,7 @@ where
     C: Hash + Eq,
 {
     by_target: IndexMap<T, Arc<Mutex<RequestMetrics<C>>>>,
+    latency_bounds: Bounds,
 }
 
 pub trait Scoped<T> {
@@ -52,6 +57,7 @@ where
     total: Counter,
     by_retry_skipped: IndexMap<RetrySkipped, Counter>,
     by_status: IndexMap<Option<http::StatusCode>, StatusMetrics<C>>,
+    latency_bounds: Bounds,
 }
 
 #[derive(Debug)]
@@ -59,7 +65,7 @@ struct StatusMetrics<C>
 where
     C: Hash + Eq,
 {
-    latency: Histogram<latency::Ms>,
+    latency: Histogram<latency::Us>,
     by_class: IndexMap<C, ClassMetrics>,
 }
 
@@ -73,23 +79,18 @@ enum RetrySkipped {
     Budget,
 }
 
-impl<T, C> Default for Registry<T, C>
+impl<T, C> Registry<T, C>
 where
     T: Hash + Eq,
     C: Hash + Eq,
 {
-    fn default() -> Self {
+    fn new(latency_bounds: Bounds) -> Self {
         Self {
             by_target: IndexMap::default(),
+            latency_bounds,
         }
     }
-}
 
-impl<T, C> Registry<T, C>
-where
-    T: Hash + Eq,
-    C: Hash + Eq,
-{
     /// Retains metrics for all targets that (1) no longer have an active
     /// reference to the `RequestMetrics` structure and (2) have not been updated since `epoch`.
     fn retain_since(&mut self, epoch: Instant) {
@@ -107,11 +108,13 @@ where
     type Scope = Arc<Mutex<RequestMetrics<C>>>;
 
     fn scoped(&self, target: T) -> Self::Scope {
-        self.lock()
-            .expect("metrics Registry lock")
+        let mut registry = self.lock().expect("metrics Registry lock");
+        let registry = &mut *registry;
+        let bounds = &registry.latency_bounds;
+        registry
             .by_target
             .entry(target)
-            .or_insert_with(|| Arc::new(Mutex::new(RequestMetrics::default())))
+            .or_insert_with(|| Arc::new(Mutex::new(RequestMetrics::new(bounds.clone()))))
             .clone()
     }
 }
@@ -120,26 +123,29 @@ impl<C> RequestMetrics<C>
 where
     C: Hash + Eq,
 {
-    fn incr_retry_skipped(&mut self, reason: RetrySkipped) {
-        self.by_retry_skipped
-            .entry(reason)
-            .or_insert_with(Counter::default)
-            .incr();
-    }
-}
-
-impl<C> Default for RequestMetrics<C>
-where
-    C: Hash + Eq,
-{
-    fn default() -> Self {
+    fn new(latency_bounds: Bounds) -> Self {
         Self {
             last_update: clock::now(),
             total: Counter::default(),
             by_retry_skipped: IndexMap::default(),
             by_status: IndexMap::default(),
+            latency_bounds,
         }
     }
+
+    fn status_metrics(&mut self, status: Option<http::StatusCode>) -> &mut StatusMetrics<C> {
+        let bounds = &self.latency_bounds;
+        self.by_status
+            .entry(status)
+            .or_insert_with(|| StatusMetrics::new(bounds))
+    }
+
+    fn incr_retry_skipped(&mut self, reason: RetrySkipped) {
+        self.by_retry_skipped
+            .entry(reason)
+            .or_insert_with(Counter::default)
+            .incr();
+    }
 }
 
 impl<C> Stats for Arc<Mutex<RequestMetrics<C>>>
@@ -154,13 +160,13 @@ where
     }
 }
 
-impl<C> Default for StatusMetrics<C>
+impl<C> StatusMetrics<C>
 where
     C: Hash + Eq,
 {
-    fn default() -> Self {
+    fn new(latency_bounds: &Bounds) -> Self {
         Self {
-            latency: Histogram::default(),
+            latency: Histogram::new(latency_bounds),
             by_class: IndexMap::default(),
         }
     }
@@ -170,7 +176,8 @@ where
 mod tests {
     #[test]
     fn expiry() {
-        use crate::metrics::FmtLabels;
+        use super::Scoped;
+        use crate::metrics::{latency, FmtLabels};
         use std::fmt;
         use std::time::Duration;
         use tokio_timer::clock;
@@ -200,15 +207,12 @@ mod tests {
         }
 
         let retain_idle_for = Duration::from_secs(1);
-        let (r, report) = super::new::<Target, Class>(retain_idle_for);
-        let mut registry = r.lock().unwrap();
+        let (r, report) =
+            super::new::<Target, Class>(retain_idle_for, latency::default_ms_bounds());
 
         let before_update = clock::now();
-        let metrics = registry
-            .by_target
-            .entry(Target(123))
-            .or_insert_with(|| Default::default())
-            .clone();
+        let metrics = r.scoped(Target(123));
+        let mut registry = r.lock().unwrap();
         assert_eq!(registry.by_target.len(), 1, "target should be registered");
         let after_update = clock::now();
 
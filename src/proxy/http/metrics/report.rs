@@ -232,7 +232,7 @@ impl Scope {
         Metric::new(&self.response_total_key, &Self::RESPONSE_TOTAL_HELP)
     }
 
-    fn response_latency_ms(&self) -> Metric<'_, Histogram<latency::Ms>> {
+    fn response_latency_ms(&self) -> Metric<'_, Histogram<latency::Us>> {
         Metric::new(
             &self.response_latency_ms_key,
             &Self::RESPONSE_LATENCY_MS_HELP,
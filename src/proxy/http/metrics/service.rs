@@ -1,7 +1,8 @@
 use super::super::retry::TryClone;
 use super::classify::{ClassifyEos, ClassifyResponse};
-use super::{ClassMetrics, Registry, RequestMetrics, StatusMetrics};
+use super::{ClassMetrics, Registry, RequestMetrics};
 use crate::{svc, Error};
+use bytes::Bytes;
 use futures::{try_ready, Async, Future, Poll};
 use http;
 use hyper::body::Payload;
@@ -178,12 +179,18 @@ where
     fn call(&mut self, target: T) -> Self::Future {
         trace!("make: target={:?}", target);
         let metrics = match self.registry.lock() {
-            Ok(mut r) => Some(
-                r.by_target
-                    .entry(target.clone().into())
-                    .or_insert_with(|| Arc::new(Mutex::new(RequestMetrics::default())))
-                    .clone(),
-            ),
+            Ok(mut r) => {
+                let r = &mut *r;
+                let bounds = &r.latency_bounds;
+                Some(
+                    r.by_target
+                        .entry(target.clone().into())
+                        .or_insert_with(|| {
+                            Arc::new(Mutex::new(RequestMetrics::new(bounds.clone())))
+                        })
+                        .clone(),
+                )
+            }
             Err(_) => None,
         };
         trace!("make: metrics={}", metrics.is_some());
@@ -400,6 +407,21 @@ where
     }
 }
 
+impl<B, C> From<Bytes> for RequestBody<B, C>
+where
+    B: Payload + From<Bytes>,
+    C: Hash + Eq,
+{
+    /// Builds a body that is not instrumented, e.g. for requests that are
+    /// issued by the proxy itself.
+    fn from(bytes: Bytes) -> Self {
+        RequestBody {
+            metrics: None,
+            inner: B::from(bytes),
+        }
+    }
+}
+
 impl<B, C> Default for ResponseBody<B, C>
 where
     B: Payload + Default,
@@ -438,11 +460,7 @@ where
 
         (*metrics).last_update = now;
 
-        let status_metrics = metrics
-            .by_status
-            .entry(Some(self.status))
-            .or_insert_with(|| StatusMetrics::default());
-
+        let status_metrics = metrics.status_metrics(Some(self.status));
         status_metrics.latency.add(now - self.stream_open_at);
 
         self.latency_recorded = true;
@@ -475,10 +493,7 @@ fn measure_class<C: Hash + Eq>(
 
     (*metrics).last_update = now;
 
-    let status_metrics = metrics
-        .by_status
-        .entry(status)
-        .or_insert_with(|| StatusMetrics::default());
+    let status_metrics = metrics.status_metrics(status);
 
     let class_metrics = status_metrics
         .by_class
@@ -0,0 +1,242 @@
+use super::metrics::handle_time;
+use super::retry::{self, TryClone};
+use crate::proxy::server::Source;
+use bytes::Bytes;
+use futures::{task, Async, Poll, Stream};
+use http;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::{error, fmt};
+use tracing::trace;
+
+/// Implemented by request bodies whose data may be copied to a mirror as it
+/// is read.
+pub trait Tee: Sized {
+    /// Returns a body that yields a copy of this body's data, or `None` if
+    /// this body cannot be mirrored.
+    ///
+    /// The copy fails if more than `max_bytes` are read from this body.
+    fn tee(&mut self, max_bytes: usize) -> Option<Self>;
+}
+
+/// Copies body data from a primary body into a `Receiver`.
+#[derive(Debug)]
+pub struct Sender {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// A stream of body data copied from a primary body.
+#[derive(Debug)]
+pub struct Receiver {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// Indicates that a mirrored body exceeded its buffer limit.
+#[derive(Debug)]
+pub struct BodyTooLarge(usize);
+
+/// Indicates that the primary body was dropped before it completed.
+#[derive(Debug)]
+pub struct Canceled(());
+
+#[derive(Debug)]
+struct Shared {
+    chunks: VecDeque<Bytes>,
+    buffered: usize,
+    max_bytes: usize,
+    state: State,
+    task: Option<task::Task>,
+}
+
+#[derive(Debug)]
+enum State {
+    Open,
+    Eos,
+    TooLarge,
+    Canceled,
+}
+
+/// Clones a request so that it may be dispatched to a mirror.
+///
+/// Bodies that cannot be cloned are copied as they are read, up to
+/// `max_bytes`.
+pub fn clone_request<B>(req: &mut http::Request<B>, max_bytes: usize) -> Option<http::Request<B>>
+where
+    B: Tee + TryClone,
+{
+    let body = match req.body().try_clone() {
+        Some(body) => body,
+        None => req.body_mut().tee(max_bytes)?,
+    };
+
+    let mut clone = retry::clone_with_body(req, body);
+
+    // Mirrored requests must never be forwarded to the original
+    // destination, nor should they count toward the primary request's
+    // handle time.
+    clone.extensions_mut().remove::<Source>();
+    clone.extensions_mut().remove::<handle_time::Tracker>();
+
+    Some(clone)
+}
+
+pub fn channel(max_bytes: usize) -> (Sender, Receiver) {
+    let shared = Arc::new(Mutex::new(Shared {
+        chunks: VecDeque::new(),
+        buffered: 0,
+        max_bytes,
+        state: State::Open,
+        task: None,
+    }));
+    let tx = Sender {
+        shared: shared.clone(),
+    };
+    (tx, Receiver { shared })
+}
+
+// === impl Sender ===
+
+impl Sender {
+    pub fn send_data(&mut self, data: &[u8]) {
+        let mut shared = self.shared.lock().expect("mirror lock poisoned");
+        if let State::Open = shared.state {
+            shared.buffered += data.len();
+            if shared.buffered > shared.max_bytes {
+                trace!("mirrored body exceeds {}B", shared.max_bytes);
+                shared.chunks.clear();
+                shared.state = State::TooLarge;
+            } else {
+                shared.chunks.push_back(Bytes::from(data));
+            }
+            shared.notify();
+        }
+    }
+
+    pub fn send_eos(&mut self) {
+        self.close(State::Eos);
+    }
+
+    fn close(&mut self, state: State) {
+        let mut shared = self.shared.lock().expect("mirror lock poisoned");
+        if let State::Open = shared.state {
+            shared.state = state;
+            shared.notify();
+        }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.close(State::Canceled);
+    }
+}
+
+// === impl Receiver ===
+
+impl Stream for Receiver {
+    type Item = Bytes;
+    type Error = crate::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut shared = self.shared.lock().expect("mirror lock poisoned");
+        if let Some(chunk) = shared.chunks.pop_front() {
+            return Ok(Async::Ready(Some(chunk)));
+        }
+
+        match shared.state {
+            State::Open => {
+                shared.task = Some(task::current());
+                Ok(Async::NotReady)
+            }
+            State::Eos => Ok(Async::Ready(None)),
+            State::TooLarge => Err(BodyTooLarge(shared.max_bytes).into()),
+            State::Canceled => Err(Canceled(()).into()),
+        }
+    }
+}
+
+// === impl Shared ===
+
+impl Shared {
+    fn notify(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+    }
+}
+
+// === impl BodyTooLarge ===
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mirrored body exceeded {} bytes", self.0)
+    }
+}
+
+impl error::Error for BodyTooLarge {}
+
+// === impl Canceled ===
+
+impl fmt::Display for Canceled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "primary body dropped before completion")
+    }
+}
+
+impl error::Error for Canceled {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future, Future};
+
+    fn poll(rx: &mut Receiver) -> Poll<Option<Bytes>, crate::Error> {
+        future::lazy(|| Ok::<_, ()>(rx.poll())).wait().unwrap()
+    }
+
+    #[test]
+    fn copies_data_until_eos() {
+        let (mut tx, mut rx) = channel(8);
+        assert!(poll(&mut rx).unwrap().is_not_ready());
+
+        tx.send_data(b"abcd");
+        tx.send_data(b"efgh");
+        tx.send_eos();
+        drop(tx);
+
+        assert_eq!(
+            poll(&mut rx).unwrap(),
+            Async::Ready(Some(Bytes::from("abcd")))
+        );
+        assert_eq!(
+            poll(&mut rx).unwrap(),
+            Async::Ready(Some(Bytes::from("efgh")))
+        );
+        assert_eq!(poll(&mut rx).unwrap(), Async::Ready(None));
+    }
+
+    #[test]
+    fn fails_when_too_large() {
+        let (mut tx, mut rx) = channel(8);
+        tx.send_data(b"abcd");
+        tx.send_data(b"efghi");
+        tx.send_eos();
+
+        let err = poll(&mut rx).expect_err("body must be too large");
+        assert!(err.is::<BodyTooLarge>());
+    }
+
+    #[test]
+    fn fails_when_canceled() {
+        let (mut tx, mut rx) = channel(8);
+        tx.send_data(b"abcd");
+        drop(tx);
+
+        assert_eq!(
+            poll(&mut rx).unwrap(),
+            Async::Ready(Some(Bytes::from("abcd")))
+        );
+        let err = poll(&mut rx).expect_err("body must be canceled");
+        assert!(err.is::<Canceled>());
+    }
+}
@@ -3,25 +3,38 @@ use crate::Error;
 use http::header::AsHeaderName;
 use http::uri::Authority;
 
+pub mod adaptive_concurrency;
 pub mod add_header;
 pub mod balance;
 pub mod canonicalize;
 pub mod client;
+pub mod consistent_hash;
+pub mod fault;
 pub(super) mod glue;
 pub mod h1;
 pub mod h2;
 pub mod header_from_target;
+pub mod header_rewrite;
+pub mod health_check;
 pub mod insert;
+pub mod jwt;
+pub mod locality;
 pub mod metrics;
+pub mod mirror;
 pub mod normalize_uri;
 pub mod orig_proto;
+pub mod outlier;
 pub mod profiles;
+pub mod rate_limit;
 pub mod retry;
+pub mod route_authz;
 pub mod router;
 pub mod settings;
 pub mod strip_header;
 pub mod timeout;
+pub mod trace_context;
 pub mod upgrade;
+pub mod weight;
 
 pub use self::client::Client;
 pub use self::glue::{ClientUsedTls, HttpBody as Body, HyperServerSvc};
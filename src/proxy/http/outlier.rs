@@ -0,0 +1,879 @@
+//! Ejects failing endpoints from a load balancer.
+//!
+//! Each discovered endpoint service is wrapped so that the classification of
+//! each of its responses is recorded. When an endpoint fails too many requests
+//! consecutively, or fails too large a proportion of requests within a window,
+//! it is ejected: it does not become ready again until the ejection time has
+//! elapsed, so the balancer dispatches requests to the remaining endpoints.
+//!
+//! No more than `Config::max_ejection_percent` of a balancer's endpoints may be
+//! ejected at once.
+
+use super::locality::{HasLocality, Locality};
+use super::metrics::classify::{ClassifyEos, ClassifyResponse};
+use super::weight::HasWeight;
+use crate::metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics, Gauge, Metric};
+use crate::{svc, Error};
+use futures::{try_ready, Async, Future, Poll};
+use http;
+use hyper::body::Payload;
+use indexmap::IndexMap;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_timer::{clock, Delay};
+use tower_discover::{Change, Discover};
+use tracing::debug;
+
+metrics! {
+    outlier_ejections_total: Counter {
+        "Total count of endpoints ejected from a load balancer"
+    },
+    outlier_ejected_endpoints: Gauge {
+        "Number of endpoints currently ejected from a load balancer"
+    }
+}
+
+/// Determines whether a response classification indicates a failure.
+pub trait IsFailure {
+    fn is_failure(&self) -> bool;
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Ejects an endpoint after this many consecutive failures.
+    pub consecutive_failures: Option<u32>,
+
+    /// Ejects an endpoint when its failure rate exceeds a threshold.
+    pub failure_rate: Option<FailureRate>,
+
+    /// The amount of time for which an endpoint is ejected.
+    pub ejection_time: Duration,
+
+    /// The maximum percentage of a balancer's endpoints that may be ejected
+    /// at once.
+    pub max_ejection_percent: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct FailureRate {
+    /// The percentage of failed requests at which an endpoint is ejected.
+    pub percent: u32,
+
+    /// The minimum number of requests that must be observed within a window
+    /// before the failure rate is considered.
+    pub min_requests: u64,
+
+    /// The amount of time over which requests are counted.
+    pub window: Duration,
+}
+
+/// Builds a registry whose metrics are evicted once they have been idle for
+/// `retain_idle` and no balancer refers to them.
+pub fn new<K: Hash + Eq>(retain_idle: Duration) -> (Registry<K>, Report<K>) {
+    let by_key = Arc::new(Mutex::new(IndexMap::new()));
+    let report = Report {
+        by_key: by_key.clone(),
+        retain_idle,
+    };
+    (Registry(by_key), report)
+}
+
+type ByKey<K> = IndexMap<K, Arc<Mutex<Metrics>>>;
+
+/// Holds outlier metrics for each balancer target.
+#[derive(Debug)]
+pub struct Registry<K: Hash + Eq>(Arc<Mutex<ByKey<K>>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted outlier metrics.
+#[derive(Debug)]
+pub struct Report<K: Hash + Eq> {
+    by_key: Arc<Mutex<ByKey<K>>>,
+    retain_idle: Duration,
+}
+
+#[derive(Debug)]
+struct Metrics {
+    last_update: Instant,
+    ejections_total: Counter,
+    ejected_endpoints: Gauge,
+}
+
+/// Wraps discovered endpoints so that failing endpoints are ejected.
+#[derive(Debug)]
+pub struct Layer<K: Hash + Eq, C> {
+    config: Arc<Config>,
+    registry: Registry<K>,
+    _p: PhantomData<fn() -> C>,
+}
+
+#[derive(Debug)]
+pub struct MakeSvc<M, K: Hash + Eq, C> {
+    config: Arc<Config>,
+    registry: Registry<K>,
+    inner: M,
+    _p: PhantomData<fn() -> C>,
+}
+
+pub struct MakeFuture<F, C> {
+    pool: Option<Arc<Pool>>,
+    inner: F,
+    _p: PhantomData<fn() -> C>,
+}
+
+/// A `Discover` that wraps each endpoint in an `Eject` service.
+pub struct EjectDiscover<D, C> {
+    pool: Arc<Pool>,
+    inner: D,
+    _p: PhantomData<fn() -> C>,
+}
+
+/// An endpoint service that is not ready while the endpoint is ejected.
+pub struct Eject<S, C> {
+    endpoint: Arc<Endpoint>,
+    ejected: Option<Delay>,
+    inner: S,
+    _p: PhantomData<fn() -> C>,
+}
+
+pub struct ResponseFuture<F, C> {
+    classify: Option<C>,
+    endpoint: Arc<Endpoint>,
+    inner: F,
+}
+
+pub struct ResponseBody<B, C>
+where
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    classify: Option<(C, Arc<Endpoint>)>,
+    inner: B,
+}
+
+/// The state shared by all endpoints of a balancer.
+#[derive(Debug)]
+struct Pool {
+    config: Arc<Config>,
+    metrics: Arc<Mutex<Metrics>>,
+    counts: Mutex<PoolCounts>,
+}
+
+#[derive(Debug, Default)]
+struct PoolCounts {
+    endpoints: usize,
+    ejected: usize,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    pool: Arc<Pool>,
+    state: Mutex<EndpointState>,
+}
+
+#[derive(Debug)]
+struct EndpointState {
+    consecutive_failures: u32,
+    window_start: Instant,
+    window_requests: u64,
+    window_failures: u64,
+    ejected_until: Option<Instant>,
+}
+
+// === impl Config ===
+
+impl Config {
+    fn is_enabled(&self) -> bool {
+        self.consecutive_failures.is_some() || self.failure_rate.is_some()
+    }
+}
+
+// === impl Registry ===
+
+impl<K: Hash + Eq> Registry<K> {
+    fn get_or_default(&self, key: K) -> Arc<Mutex<Metrics>> {
+        match self.0.lock() {
+            Ok(mut inner) => inner.entry(key).or_insert_with(Default::default).clone(),
+            // Metrics are not recorded if the registry is poisoned.
+            Err(_) => Default::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> Clone for Registry<K> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+// === impl Report ===
+
+impl<K: Hash + Eq> Clone for Report<K> {
+    fn clone(&self) -> Self {
+        Report {
+            by_key: self.by_key.clone(),
+            retain_idle: self.retain_idle,
+        }
+    }
+}
+
+impl<K: FmtLabels + Hash + Eq> FmtMetrics for Report<K> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut inner = match self.by_key.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if let Some(epoch) = clock::now().checked_sub(self.retain_idle) {
+            retain_since(&mut inner, epoch);
+        }
+
+        if inner.is_empty() {
+            return Ok(());
+        }
+
+        outlier_ejections_total.fmt_help(f)?;
+        fmt_by(&inner, f, outlier_ejections_total, |m| &m.ejections_total)?;
+
+        outlier_ejected_endpoints.fmt_help(f)?;
+        fmt_by(&inner, f, outlier_ejected_endpoints, |m| {
+            &m.ejected_endpoints
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Retains the metrics of balancers that (1) still exist or (2) have been
+/// updated since `epoch`.
+fn retain_since<K: Hash + Eq>(by_key: &mut ByKey<K>, epoch: Instant) {
+    by_key.retain(|_, m| {
+        Arc::strong_count(&m) > 1 || m.lock().map(|m| m.last_update >= epoch).unwrap_or(false)
+    })
+}
+
+fn fmt_by<K, F, M>(
+    inner: &ByKey<K>,
+    f: &mut fmt::Formatter<'_>,
+    metric: Metric<'_, M>,
+    get_metric: F,
+) -> fmt::Result
+where
+    K: FmtLabels + Hash + Eq,
+    F: Fn(&Metrics) -> &M,
+    M: FmtMetric,
+{
+    for (key, m) in inner.iter() {
+        if let Ok(m) = m.lock() {
+            get_metric(&*m).fmt_metric_labeled(f, metric.name, key)?;
+        }
+    }
+
+    Ok(())
+}
+
+// === impl Metrics ===
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            last_update: clock::now(),
+            ejections_total: Counter::default(),
+            ejected_endpoints: Gauge::default(),
+        }
+    }
+}
+
+// === impl Layer ===
+
+pub fn layer<K, C>(config: Config, registry: Registry<K>) -> Layer<K, C>
+where
+    K: Hash + Eq,
+    C: ClassifyResponse + Clone + Default + Send + Sync + 'static,
+    C::Class: IsFailure,
+{
+    Layer {
+        config: Arc::new(config),
+        registry,
+        _p: PhantomData,
+    }
+}
+
+impl<K: Hash + Eq, C> Clone for Layer<K, C> {
+    fn clone(&self) -> Self {
+        Layer {
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<M, K: Hash + Eq, C> svc::Layer<M> for Layer<K, C> {
+    type Service = MakeSvc<M, K, C>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeSvc {
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            inner,
+            _p: PhantomData,
+        }
+    }
+}
+
+// === impl MakeSvc ===
+
+impl<M: Clone, K: Hash + Eq, C> Clone for MakeSvc<M, K, C> {
+    fn clone(&self) -> Self {
+        MakeSvc {
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            inner: self.inner.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<T, M, K, C> svc::Service<T> for MakeSvc<M, K, C>
+where
+    T: Clone,
+    K: Hash + Eq + From<T>,
+    M: svc::Service<T>,
+    M::Response: Discover,
+{
+    type Response = EjectDiscover<M::Response, C>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future, C>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let pool = Pool {
+            config: self.config.clone(),
+            metrics: self.registry.get_or_default(target.clone().into()),
+            counts: Mutex::new(PoolCounts::default()),
+        };
+
+        MakeFuture {
+            pool: Some(Arc::new(pool)),
+            inner: self.inner.call(target),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<F, C> Future for MakeFuture<F, C>
+where
+    F: Future,
+    F::Item: Discover,
+{
+    type Item = EjectDiscover<F::Item, C>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        let pool = self.pool.take().expect("polled after ready");
+        Ok(Async::Ready(EjectDiscover {
+            pool,
+            inner,
+            _p: PhantomData,
+        }))
+    }
+}
+
+// === impl EjectDiscover ===
+
+impl<D: Discover, C> Discover for EjectDiscover<D, C> {
+    type Key = D::Key;
+    type Service = Eject<D::Service, C>;
+    type Error = D::Error;
+
+    fn poll(&mut self) -> Poll<Change<Self::Key, Self::Service>, Self::Error> {
+        let change = match try_ready!(self.inner.poll()) {
+            Change::Insert(key, inner) => {
+                let eject = Eject {
+                    endpoint: Arc::new(Endpoint::new(self.pool.clone())),
+                    ejected: None,
+                    inner,
+                    _p: PhantomData,
+                };
+                Change::Insert(key, eject)
+            }
+            Change::Remove(key) => Change::Remove(key),
+        };
+
+        Ok(Async::Ready(change))
+    }
+}
+
+// === impl Eject ===
+
+impl<S, C, A, B> svc::Service<http::Request<A>> for Eject<S, C>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+    C: ClassifyResponse + Clone + Default + Send + Sync + 'static,
+    C::Class: IsFailure,
+{
+    type Response = http::Response<ResponseBody<B, C::ClassifyEos>>;
+    type Error = Error;
+    type Future = ResponseFuture<S::Future, C>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        loop {
+            if let Some(ejected) = self.ejected.as_mut() {
+                if let Ok(Async::NotReady) = ejected.poll() {
+                    return Ok(Async::NotReady);
+                }
+                self.ejected = None;
+            }
+
+            match self.endpoint.ejected_until() {
+                Some(until) if clock::now() < until => {
+                    self.ejected = Some(Delay::new(until));
+                }
+                Some(_) => self.endpoint.readmit(),
+                None => return self.inner.poll_ready().map_err(Into::into),
+            }
+        }
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let classify = req.extensions().get::<C>().cloned().unwrap_or_default();
+
+        ResponseFuture {
+            classify: Some(classify),
+            endpoint: self.endpoint.clone(),
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+impl<S: HasLocality, C> HasLocality for Eject<S, C> {
+    fn locality(&self) -> Option<Locality> {
+        self.inner.locality()
+    }
+}
+
+impl<S: HasWeight, C> HasWeight for Eject<S, C> {
+    fn weight(&self) -> u32 {
+        self.inner.weight()
+    }
+}
+
+impl<F, C, B> Future for ResponseFuture<F, C>
+where
+    F: Future<Item = http::Response<B>>,
+    F::Error: Into<Error>,
+    C: ClassifyResponse,
+    C::Class: IsFailure,
+{
+    type Item = http::Response<ResponseBody<B, C::ClassifyEos>>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = match self.inner.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(rsp)) => rsp,
+            Err(e) => {
+                let e = e.into();
+                if let Some(classify) = self.classify.take() {
+                    self.endpoint.record(classify.error(&e).is_failure());
+                }
+                return Err(e);
+            }
+        };
+
+        let classify = self
+            .classify
+            .take()
+            .map(|c| (c.start(&rsp), self.endpoint.clone()));
+        let (head, inner) = rsp.into_parts();
+        let body = ResponseBody { classify, inner };
+        Ok(Async::Ready(http::Response::from_parts(head, body)))
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B, C> ResponseBody<B, C>
+where
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    fn record_err(&mut self, err: Error) -> Error {
+        if let Some((classify, endpoint)) = self.classify.take() {
+            endpoint.record(classify.error(&err).is_failure());
+        }
+        err
+    }
+}
+
+impl<B, C> Payload for ResponseBody<B, C>
+where
+    B: Payload,
+    B::Error: Into<Error>,
+    C: ClassifyEos + Send + 'static,
+    C::Class: IsFailure,
+{
+    type Data = B::Data;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        self.inner
+            .poll_data()
+            .map_err(|e| self.record_err(e.into()))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        let trailers = try_ready!(self
+            .inner
+            .poll_trailers()
+            .map_err(|e| self.record_err(e.into())));
+
+        if let Some((classify, endpoint)) = self.classify.take() {
+            endpoint.record(classify.eos(trailers.as_ref()).is_failure());
+        }
+
+        Ok(Async::Ready(trailers))
+    }
+}
+
+impl<B, C> http_body::Body for ResponseBody<B, C>
+where
+    B: Payload,
+    B::Error: Into<Error>,
+    C: ClassifyEos + Send + 'static,
+    C::Class: IsFailure,
+{
+    type Data = B::Data;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        Payload::is_end_stream(self)
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        Payload::poll_data(self)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Payload::poll_trailers(self)
+    }
+}
+
+impl<B, C> Default for ResponseBody<B, C>
+where
+    B: Default,
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    fn default() -> Self {
+        Self {
+            classify: None,
+            inner: B::default(),
+        }
+    }
+}
+
+impl<B, C> Drop for ResponseBody<B, C>
+where
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    fn drop(&mut self) {
+        if let Some((classify, endpoint)) = self.classify.take() {
+            endpoint.record(classify.eos(None).is_failure());
+        }
+    }
+}
+
+// === impl Pool ===
+
+impl Pool {
+    /// Marks an endpoint as ejected, if doing so would not exceed the
+    /// maximum ejection percentage.
+    fn try_eject(&self) -> bool {
+        let mut counts = match self.counts.lock() {
+            Ok(counts) => counts,
+            Err(_) => return false,
+        };
+
+        let max = counts.endpoints * self.config.max_ejection_percent as usize / 100;
+        if counts.ejected >= max {
+            return false;
+        }
+        counts.ejected += 1;
+
+        if let Ok(mut metrics) = self.metrics.lock() {
+            metrics.last_update = clock::now();
+            metrics.ejections_total.incr();
+            metrics.ejected_endpoints.incr();
+        }
+
+        true
+    }
+
+    fn readmit(&self) {
+        if let Ok(mut counts) = self.counts.lock() {
+            counts.ejected = counts.ejected.saturating_sub(1);
+        }
+
+        if let Ok(mut metrics) = self.metrics.lock() {
+            metrics.last_update = clock::now();
+            metrics.ejected_endpoints.decr();
+        }
+    }
+}
+
+// === impl Endpoint ===
+
+impl Endpoint {
+    fn new(pool: Arc<Pool>) -> Self {
+        if let Ok(mut counts) = pool.counts.lock() {
+            counts.endpoints += 1;
+        }
+
+        Self {
+            pool,
+            state: Mutex::new(EndpointState {
+                consecutive_failures: 0,
+                window_start: clock::now(),
+                window_requests: 0,
+                window_failures: 0,
+                ejected_until: None,
+            }),
+        }
+    }
+
+    fn ejected_until(&self) -> Option<Instant> {
+        self.state.lock().ok().and_then(|s| s.ejected_until)
+    }
+
+    fn readmit(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.ejected_until.take().is_some() {
+                debug!("readmitting endpoint");
+                state.reset(clock::now());
+                self.pool.readmit();
+            }
+        }
+    }
+
+    fn record(&self, is_failure: bool) {
+        let config = &self.pool.config;
+        if !config.is_enabled() {
+            return;
+        }
+
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+
+        // Responses that complete while the endpoint is ejected were
+        // dispatched before it was ejected.
+        if state.ejected_until.is_some() {
+            return;
+        }
+
+        let now = clock::now();
+        if is_failure {
+            state.consecutive_failures += 1;
+        } else {
+            state.consecutive_failures = 0;
+        }
+
+        let mut eject = config
+            .consecutive_failures
+            .map(|max| state.consecutive_failures >= max)
+            .unwrap_or(false);
+
+        if let Some(ref rate) = config.failure_rate {
+            if now - state.window_start >= rate.window {
+                state.window_start = now;
+                state.window_requests = 0;
+                state.window_failures = 0;
+            }
+
+            state.window_requests += 1;
+            if is_failure {
+                state.window_failures += 1;
+            }
+
+            if state.window_requests >= rate.min_requests
+                && state.window_failures * 100 >= u64::from(rate.percent) * state.window_requests
+            {
+                eject = true;
+            }
+        }
+
+        if eject && self.pool.try_eject() {
+            debug!(
+                "ejecting endpoint; consecutive_failures={} window_failures={}/{}",
+                state.consecutive_failures, state.window_failures, state.window_requests,
+            );
+            state.ejected_until = Some(now + config.ejection_time);
+        }
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        let ejected = self
+            .state
+            .lock()
+            .map(|s| s.ejected_until.is_some())
+            .unwrap_or(false);
+
+        if let Ok(mut counts) = self.pool.counts.lock() {
+            counts.endpoints = counts.endpoints.saturating_sub(1);
+        }
+
+        if ejected {
+            self.pool.readmit();
+        }
+    }
+}
+
+// === impl EndpointState ===
+
+impl EndpointState {
+    fn reset(&mut self, now: Instant) {
+        self.consecutive_failures = 0;
+        self.window_start = now;
+        self.window_requests = 0;
+        self.window_failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(config: Config) -> Arc<Pool> {
+        Arc::new(Pool {
+            config: Arc::new(config),
+            metrics: Default::default(),
+            counts: Mutex::new(PoolCounts::default()),
+        })
+    }
+
+    fn config() -> Config {
+        Config {
+            consecutive_failures: None,
+            failure_rate: None,
+            ejection_time: Duration::from_secs(30),
+            max_ejection_percent: 50,
+        }
+    }
+
+    #[test]
+    fn ejects_after_consecutive_failures() {
+        let pool = pool(Config {
+            consecutive_failures: Some(3),
+            ..config()
+        });
+        let ep0 = Endpoint::new(pool.clone());
+        let _ep1 = Endpoint::new(pool.clone());
+
+        ep0.record(true);
+        ep0.record(true);
+        ep0.record(false);
+        ep0.record(true);
+        ep0.record(true);
+        assert!(ep0.ejected_until().is_none());
+
+        ep0.record(true);
+        assert!(ep0.ejected_until().is_some());
+        assert_eq!(pool.counts.lock().unwrap().ejected, 1);
+
+        ep0.readmit();
+        assert!(ep0.ejected_until().is_none());
+        assert_eq!(pool.counts.lock().unwrap().ejected, 0);
+    }
+
+    #[test]
+    fn ejects_on_failure_rate() {
+        let pool = pool(Config {
+            failure_rate: Some(FailureRate {
+                percent: 50,
+                min_requests: 4,
+                window: Duration::from_secs(60),
+            }),
+            ..config()
+        });
+        let ep0 = Endpoint::new(pool.clone());
+        let _ep1 = Endpoint::new(pool.clone());
+
+        ep0.record(true);
+        ep0.record(false);
+        ep0.record(true);
+        assert!(ep0.ejected_until().is_none(), "too few requests");
+
+        ep0.record(false);
+        assert!(ep0.ejected_until().is_some());
+    }
+
+    #[test]
+    fn max_ejection_percent() {
+        let pool = pool(Config {
+            consecutive_failures: Some(1),
+            ..config()
+        });
+
+        let ep0 = Endpoint::new(pool.clone());
+        ep0.record(true);
+        assert!(
+            ep0.ejected_until().is_none(),
+            "the only endpoint must not be ejected"
+        );
+
+        let ep1 = Endpoint::new(pool.clone());
+        ep0.record(true);
+        ep1.record(true);
+        assert!(ep0.ejected_until().is_some());
+        assert!(ep1.ejected_until().is_none());
+
+        drop(ep0);
+        assert_eq!(pool.counts.lock().unwrap().ejected, 0);
+        assert_eq!(pool.counts.lock().unwrap().endpoints, 1);
+    }
+
+    #[test]
+    fn evicts_idle_metrics() {
+        let (registry, report) = new::<&'static str>(Duration::from_secs(60));
+
+        let before_update = clock::now();
+        let metrics = registry.get_or_default("web");
+        let after_update = clock::now() + Duration::from_secs(1);
+
+        let mut by_key = report.by_key.lock().unwrap();
+        retain_since(&mut by_key, after_update);
+        assert_eq!(
+            by_key.len(),
+            1,
+            "metrics should not be evicted while in use"
+        );
+
+        drop(metrics);
+        retain_since(&mut by_key, before_update);
+        assert_eq!(
+            by_key.len(),
+            1,
+            "metrics should not be evicted while active"
+        );
+
+        retain_since(&mut by_key, after_update);
+        assert_eq!(by_key.len(), 0, "idle metrics should be evicted");
+    }
+}
@@ -1,3 +1,7 @@
+use super::consistent_hash::HashKey;
+use super::fault::Fault;
+use super::header_rewrite::Rewrite;
+use super::health_check::Check;
 use super::retry::Budget;
 use crate::{NameAddr, Never};
 use futures::Stream;
@@ -23,9 +27,13 @@ pub mod recognize;
 /// they exist, or uses the router's target's addr if no `dst_overrides` exist.
 /// The concrete dst router uses the concrete dst as the target for the
 /// underlying stack.
+///
+/// Requests that match a route may also be copied to each of the route's
+/// `mirrors`. Mirrored requests are routed through their own route stacks and
+/// their responses are discarded.
 pub mod router;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct WeightedAddr {
     pub addr: NameAddr,
     pub weight: u32,
@@ -34,6 +42,11 @@ pub struct WeightedAddr {
 pub struct Routes {
     pub routes: Vec<(RequestMatch, Route)>,
     pub dst_overrides: Vec<WeightedAddr>,
+    /// Actively checks the health of the destination's endpoints.
+    pub health_check: Option<Check>,
+    /// Balances requests over the destination's endpoints by a consistent
+    /// hash of this key.
+    pub balance_hash_key: Option<HashKey>,
 }
 
 /// Watches a destination's Routes.
@@ -71,6 +84,10 @@ pub struct Route {
     response_classes: ResponseClasses,
     retries: Option<Retries>,
     timeout: Option<Duration>,
+    fault: Option<Fault>,
+    header_rewrite: Option<Rewrite>,
+    mirrors: Vec<NameAddr>,
+    requires_jwt: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -80,6 +97,26 @@ pub enum RequestMatch {
     Not(Box<RequestMatch>),
     Path(Regex),
     Method(http::Method),
+    Header {
+        name: http::header::HeaderName,
+        value: ValueMatch,
+    },
+    Query {
+        name: String,
+        value: ValueMatch,
+    },
+}
+
+/// Matches a request header or query parameter value.
+///
+/// Query parameter names and values are compared as they appear in the URI,
+/// without percent-decoding.
+#[derive(Clone, Debug)]
+pub enum ValueMatch {
+    /// Matches if a value is present, regardless of its contents.
+    Present,
+    Exact(String),
+    Regex(Regex),
 }
 
 #[derive(Clone, Debug)]
@@ -100,6 +137,16 @@ pub enum ResponseMatch {
         min: http::StatusCode,
         max: http::StatusCode,
     },
+    Header {
+        name: http::header::HeaderName,
+        value: ValueMatch,
+    },
+    /// Matches a `grpc-status` in the response headers or, once the response
+    /// stream has ended, in its trailers.
+    GrpcStatus {
+        min: u32,
+        max: u32,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -128,6 +175,10 @@ impl Route {
             response_classes: ResponseClasses(response_classes.into()),
             retries: None,
             timeout: None,
+            fault: None,
+            header_rewrite: None,
+            mirrors: Vec::new(),
+            requires_jwt: false,
         }
     }
 
@@ -154,15 +205,74 @@ impl Route {
     pub fn set_timeout(&mut self, timeout: Duration) {
         self.timeout = Some(timeout);
     }
+
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    pub fn set_fault(&mut self, fault: Fault) {
+        self.fault = Some(fault);
+    }
+
+    pub fn header_rewrite(&self) -> Option<&Rewrite> {
+        self.header_rewrite.as_ref()
+    }
+
+    pub fn set_header_rewrite(&mut self, rewrite: Rewrite) {
+        self.header_rewrite = Some(rewrite);
+    }
+
+    /// Destinations that receive a copy of each request on this route.
+    pub fn mirrors(&self) -> &[NameAddr] {
+        &self.mirrors
+    }
+
+    pub fn set_mirrors(&mut self, mirrors: Vec<NameAddr>) {
+        self.mirrors = mirrors;
+    }
+
+    /// Whether inbound requests on this route must carry a valid JWT.
+    pub fn requires_jwt(&self) -> bool {
+        self.requires_jwt
+    }
+
+    pub fn set_requires_jwt(&mut self) {
+        self.requires_jwt = true;
+    }
 }
 
 // === impl RequestMatch ===
 
 impl RequestMatch {
-    fn is_match<B>(&self, req: &http::Request<B>) -> bool {
+    pub fn is_match<B>(&self, req: &http::Request<B>) -> bool {
         match self {
             RequestMatch::Method(ref method) => req.method() == *method,
             RequestMatch::Path(ref re) => re.is_match(req.uri().path()),
+            RequestMatch::Header {
+                ref name,
+                ref value,
+            } => req
+                .headers()
+                .get_all(name)
+                .iter()
+                .any(|v| value.is_match(v.as_bytes())),
+            RequestMatch::Query {
+                ref name,
+                ref value,
+            } => req
+                .uri()
+                .query()
+                .into_iter()
+                .flat_map(|q| q.split('&'))
+                .filter_map(|param| {
+                    let mut kv = param.splitn(2, '=');
+                    let k = kv.next()?;
+                    if k != name.as_str() {
+                        return None;
+                    }
+                    Some(kv.next().unwrap_or(""))
+                })
+                .any(|v| value.is_match(v.as_bytes())),
             RequestMatch::Not(ref m) => !m.is_match(req),
             RequestMatch::All(ref ms) => ms.iter().all(|m| m.is_match(req)),
             RequestMatch::Any(ref ms) => ms.iter().any(|m| m.is_match(req)),
@@ -170,6 +280,20 @@ impl RequestMatch {
     }
 }
 
+// === impl ValueMatch ===
+
+impl ValueMatch {
+    fn is_match(&self, value: &[u8]) -> bool {
+        match self {
+            ValueMatch::Present => true,
+            ValueMatch::Exact(ref v) => v.as_bytes() == value,
+            ValueMatch::Regex(ref re) => std::str::from_utf8(value)
+                .map(|v| re.is_match(v))
+                .unwrap_or(false),
+        }
+    }
+}
+
 // === impl ResponseClass ===
 
 impl ResponseClass {
@@ -181,8 +305,33 @@ impl ResponseClass {
         self.is_failure
     }
 
-    pub fn is_match<B>(&self, req: &http::Response<B>) -> bool {
-        self.match_.is_match(req)
+    pub fn is_match<B>(&self, rsp: &http::Response<B>) -> bool {
+        self.match_.is_match(rsp.status(), rsp.headers(), None)
+    }
+
+    /// Matches a response once its stream has ended, using the response's
+    /// trailers, if any.
+    pub fn is_match_eos(
+        &self,
+        status: http::StatusCode,
+        headers: &http::HeaderMap,
+        trailers: Option<&http::HeaderMap>,
+    ) -> bool {
+        self.match_.is_match(status, headers, trailers)
+    }
+
+    /// Indicates whether this class may only be matched once the response
+    /// stream has ended (i.e. because it matches on a gRPC status that may be
+    /// sent as a trailer).
+    pub fn is_eos_dependent(&self) -> bool {
+        self.match_.is_eos_dependent()
+    }
+
+    /// Copies the response headers that this class matches on, so that the
+    /// class may be matched once the response stream has ended without
+    /// retaining all of the response's headers.
+    pub fn copy_matched_headers(&self, from: &http::HeaderMap, to: &mut http::HeaderMap) {
+        self.match_.copy_matched_headers(from, to)
     }
 }
 
@@ -219,18 +368,75 @@ impl fmt::Debug for ResponseClasses {
 // === impl ResponseMatch ===
 
 impl ResponseMatch {
-    fn is_match<B>(&self, req: &http::Response<B>) -> bool {
+    fn is_match(
+        &self,
+        status: http::StatusCode,
+        headers: &http::HeaderMap,
+        trailers: Option<&http::HeaderMap>,
+    ) -> bool {
         match self {
-            ResponseMatch::Status { ref min, ref max } => {
-                *min <= req.status() && req.status() <= *max
+            ResponseMatch::Status { ref min, ref max } => *min <= status && status <= *max,
+            ResponseMatch::Header {
+                ref name,
+                ref value,
+            } => headers
+                .get_all(name)
+                .iter()
+                .any(|v| value.is_match(v.as_bytes())),
+            ResponseMatch::GrpcStatus { min, max } => grpc_status(headers)
+                .or_else(|| trailers.and_then(grpc_status))
+                .map(|code| *min <= code && code <= *max)
+                .unwrap_or(false),
+            ResponseMatch::Not(ref m) => !m.is_match(status, headers, trailers),
+            ResponseMatch::All(ref ms) => ms.iter().all(|m| m.is_match(status, headers, trailers)),
+            ResponseMatch::Any(ref ms) => ms.iter().any(|m| m.is_match(status, headers, trailers)),
+        }
+    }
+
+    fn is_eos_dependent(&self) -> bool {
+        match self {
+            ResponseMatch::GrpcStatus { .. } => true,
+            ResponseMatch::Status { .. } | ResponseMatch::Header { .. } => false,
+            ResponseMatch::Not(ref m) => m.is_eos_dependent(),
+            ResponseMatch::All(ref ms) | ResponseMatch::Any(ref ms) => {
+                ms.iter().any(|m| m.is_eos_dependent())
+            }
+        }
+    }
+
+    fn copy_matched_headers(&self, from: &http::HeaderMap, to: &mut http::HeaderMap) {
+        let name = match self {
+            ResponseMatch::Header { ref name, .. } => name.clone(),
+            ResponseMatch::GrpcStatus { .. } => {
+                http::header::HeaderName::from_static("grpc-status")
             }
-            ResponseMatch::Not(ref m) => !m.is_match(req),
-            ResponseMatch::All(ref ms) => ms.iter().all(|m| m.is_match(req)),
-            ResponseMatch::Any(ref ms) => ms.iter().any(|m| m.is_match(req)),
+            ResponseMatch::Status { .. } => return,
+            ResponseMatch::Not(ref m) => return m.copy_matched_headers(from, to),
+            ResponseMatch::All(ref ms) | ResponseMatch::Any(ref ms) => {
+                for m in ms {
+                    m.copy_matched_headers(from, to);
+                }
+                return;
+            }
+        };
+
+        // Headers may be matched by several classes.
+        if to.contains_key(&name) {
+            return;
+        }
+        for value in from.get_all(&name) {
+            to.append(name.clone(), value.clone());
         }
     }
 }
 
+fn grpc_status(headers: &http::HeaderMap) -> Option<u32> {
+    headers
+        .get("grpc-status")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.parse::<u32>().ok())
+}
+
 // === impl Retries ===
 
 impl Retries {
@@ -274,3 +480,128 @@ impl fmt::Debug for Labels {
         self.0.fmt(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(uri: &str, headers: &[(&'static str, &str)]) -> http::Request<()> {
+        let mut req = http::Request::builder();
+        req.uri(uri);
+        for (k, v) in headers {
+            req.header(*k, *v);
+        }
+        req.body(()).unwrap()
+    }
+
+    fn header(name: &'static str, value: ValueMatch) -> RequestMatch {
+        RequestMatch::Header {
+            name: http::header::HeaderName::from_static(name),
+            value,
+        }
+    }
+
+    fn query(name: &str, value: ValueMatch) -> RequestMatch {
+        RequestMatch::Query {
+            name: name.to_owned(),
+            value,
+        }
+    }
+
+    #[test]
+    fn header_present() {
+        let m = header("x-tenant-id", ValueMatch::Present);
+        assert!(m.is_match(&req("/", &[("x-tenant-id", "")])));
+        assert!(m.is_match(&req("/", &[("x-tenant-id", "foo")])));
+        assert!(!m.is_match(&req("/", &[("x-tenant", "foo")])));
+    }
+
+    #[test]
+    fn header_exact() {
+        let m = header("x-tenant-id", ValueMatch::Exact("foo".into()));
+        assert!(m.is_match(&req("/", &[("x-tenant-id", "foo")])));
+        assert!(!m.is_match(&req("/", &[("x-tenant-id", "foobar")])));
+        assert!(!m.is_match(&req("/", &[])));
+        assert!(
+            m.is_match(&req("/", &[("x-tenant-id", "bar"), ("x-tenant-id", "foo")])),
+            "any value may match"
+        );
+    }
+
+    #[test]
+    fn header_regex() {
+        let re = Regex::new("^application/grpc(\\+.*)?$").unwrap();
+        let m = header("content-type", ValueMatch::Regex(re));
+        assert!(m.is_match(&req("/", &[("content-type", "application/grpc")])));
+        assert!(m.is_match(&req("/", &[("content-type", "application/grpc+proto")])));
+        assert!(!m.is_match(&req("/", &[("content-type", "application/json")])));
+    }
+
+    #[test]
+    fn query_params() {
+        let present = query("debug", ValueMatch::Present);
+        assert!(present.is_match(&req("/?debug", &[])));
+        assert!(present.is_match(&req("/?a=b&debug=1", &[])));
+        assert!(!present.is_match(&req("/?debugging=1", &[])));
+        assert!(!present.is_match(&req("/", &[])));
+
+        let exact = query("tenant", ValueMatch::Exact("foo".into()));
+        assert!(exact.is_match(&req("/?tenant=foo", &[])));
+        assert!(exact.is_match(&req("/?tenant=bar&tenant=foo", &[])));
+        assert!(!exact.is_match(&req("/?tenant=foobar", &[])));
+
+        let re = query("v", ValueMatch::Regex(Regex::new("^[0-9]+$").unwrap()));
+        assert!(re.is_match(&req("/?v=12", &[])));
+        assert!(!re.is_match(&req("/?v=1a", &[])));
+    }
+
+    fn rsp(status: u16, headers: &[(&'static str, &str)]) -> http::Response<()> {
+        let mut rsp = http::Response::builder();
+        rsp.status(status);
+        for (k, v) in headers {
+            rsp.header(*k, *v);
+        }
+        rsp.body(()).unwrap()
+    }
+
+    #[test]
+    fn response_header() {
+        let m = ResponseMatch::Header {
+            name: http::header::HeaderName::from_static("x-error"),
+            value: ValueMatch::Exact("fatal".into()),
+        };
+        let class = ResponseClass::new(true, m);
+        assert!(class.is_match(&rsp(200, &[("x-error", "fatal")])));
+        assert!(!class.is_match(&rsp(200, &[("x-error", "transient")])));
+        assert!(!class.is_match(&rsp(200, &[])));
+        assert!(!class.is_eos_dependent());
+    }
+
+    #[test]
+    fn response_grpc_status() {
+        let class = ResponseClass::new(true, ResponseMatch::GrpcStatus { min: 14, max: 14 });
+        assert!(class.is_eos_dependent());
+
+        // A trailers-only response carries its status in the headers.
+        assert!(class.is_match(&rsp(200, &[("grpc-status", "14")])));
+        assert!(!class.is_match(&rsp(200, &[("grpc-status", "0")])));
+        assert!(!class.is_match(&rsp(200, &[])));
+
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("grpc-status", 14.into());
+        let headers = http::HeaderMap::new();
+        assert!(class.is_match_eos(http::StatusCode::OK, &headers, Some(&trailers)));
+        assert!(!class.is_match_eos(http::StatusCode::OK, &headers, None));
+    }
+
+    #[test]
+    fn combined_with_path() {
+        let m = RequestMatch::All(vec![
+            RequestMatch::Path(Regex::new("^/api/.*$").unwrap()),
+            header("x-tenant-id", ValueMatch::Exact("foo".into())),
+        ]);
+        assert!(m.is_match(&req("/api/users", &[("x-tenant-id", "foo")])));
+        assert!(!m.is_match(&req("/api/users", &[("x-tenant-id", "bar")])));
+        assert!(!m.is_match(&req("/users", &[("x-tenant-id", "foo")])));
+    }
+}
@@ -1,13 +1,18 @@
 use super::recognize::{ConcreteDstRecognize, RouteRecognize};
-use super::{CanGetDestination, GetRoutes, Route, Routes, WeightedAddr, WithAddr, WithRoute};
+use super::{
+    CanGetDestination, GetRoutes, RequestMatch, Route, Routes, WeightedAddr, WithAddr, WithRoute,
+};
 use crate::dns;
+use crate::proxy::http::{consistent_hash, health_check, mirror, retry::TryClone};
+use crate::proxy::introspect;
 use crate::svc;
-use crate::{Error, Never};
-use futures::{Async, Poll, Stream};
+use crate::{Error, NameAddr, Never};
+use futures::{Async, Future, Poll, Stream};
 use http;
 use indexmap::IndexMap;
 use linkerd2_router as rt;
 use std::hash::Hash;
+use std::sync::{Arc, Mutex};
 use tracing::{debug, error};
 
 // A router which routes based on the `dst_overrides` of the profile or, if
@@ -19,6 +24,12 @@ type ConcreteRouter<Target, Svc, Body> =
 type RouteRouter<Target, RouteTarget, Svc, Body> =
     rt::Router<http::Request<Body>, RouteRecognize<Target>, rt::FixedMake<RouteTarget, Svc>>;
 
+type RouteFuture<Target, RouteTarget, Svc, Body> = rt::ResponseFuture<
+    http::Request<Body>,
+    RouteRecognize<Target>,
+    rt::FixedMake<RouteTarget, Svc>,
+>;
+
 pub fn layer<G, Inner, RouteLayer, RouteBody, InnerBody>(
     suffixes: Vec<dns::Suffix>,
     get_routes: G,
@@ -32,15 +43,77 @@ where
         suffixes,
         get_routes,
         route_layer,
+        mirror: None,
+        active_routes: None,
+        health_checks: None,
+        hash_keys: None,
         default_route: Route::default(),
         _p: ::std::marker::PhantomData,
     }
 }
 
+/// Tracks the profile routes applied to each destination.
+pub type ActiveRoutes = introspect::Registry<Mutex<Profile>>;
+
+/// The routes most recently discovered for a destination's profile.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub dst: NameAddr,
+    pub routes: Vec<Route>,
+    pub dst_overrides: Vec<WeightedAddr>,
+}
+
+impl<G, Inner, RouteLayer, RouteBody, InnerBody> Layer<G, Inner, RouteLayer, RouteBody, InnerBody> {
+    /// Copies requests to the mirrors of the routes they match, if any.
+    ///
+    /// Mirrored requests are routed through `route_layer` so that they may be
+    /// instrumented separately from the primary requests. Request bodies are
+    /// copied up to `max_body_bytes`.
+    pub fn with_mirrors(self, route_layer: RouteLayer, max_body_bytes: usize) -> Self {
+        Self {
+            mirror: Some(MirrorConfig {
+                route_layer,
+                max_body_bytes,
+            }),
+            ..self
+        }
+    }
+
+    /// Reports the routes of each discovered profile to `active_routes`.
+    pub fn with_active_routes(self, active_routes: ActiveRoutes) -> Self {
+        Self {
+            active_routes: Some(active_routes),
+            ..self
+        }
+    }
+
+    /// Publishes the health check of each discovered profile to
+    /// `health_checks`.
+    pub fn with_health_checks(self, health_checks: health_check::Checks) -> Self {
+        Self {
+            health_checks: Some(health_checks),
+            ..self
+        }
+    }
+
+    /// Publishes the balancer hash key of each discovered profile to
+    /// `hash_keys`.
+    pub fn with_hash_keys(self, hash_keys: consistent_hash::HashKeys) -> Self {
+        Self {
+            hash_keys: Some(hash_keys),
+            ..self
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Layer<G, Inner, RouteLayer, RouteBody, InnerBody> {
     get_routes: G,
     route_layer: RouteLayer,
+    mirror: Option<MirrorConfig<RouteLayer>>,
+    active_routes: Option<ActiveRoutes>,
+    health_checks: Option<health_check::Checks>,
+    hash_keys: Option<consistent_hash::HashKeys>,
     suffixes: Vec<dns::Suffix>,
     /// This is saved into a field so that the same `Arc`s are used and
     /// cloned, instead of calling `Route::default()` every time.
@@ -53,11 +126,21 @@ pub struct MakeSvc<G, Inner, RouteLayer, RouteBody, InnerBody> {
     inner: Inner,
     get_routes: G,
     route_layer: RouteLayer,
+    mirror: Option<MirrorConfig<RouteLayer>>,
+    active_routes: Option<ActiveRoutes>,
+    health_checks: Option<health_check::Checks>,
+    hash_keys: Option<consistent_hash::HashKeys>,
     suffixes: Vec<dns::Suffix>,
     default_route: Route,
     _p: ::std::marker::PhantomData<fn(RouteBody, InnerBody)>,
 }
 
+#[derive(Clone, Debug)]
+struct MirrorConfig<RouteLayer> {
+    route_layer: RouteLayer,
+    max_body_bytes: usize,
+}
+
 /// The Service consists of a RouteRouter which routes over the route
 /// stack built by the `route_layer`.  The per-route stack is terminated by
 /// a shared `concrete_router`.  The `concrete_router` routes over the
@@ -74,6 +157,11 @@ pub struct MakeSvc<G, Inner, RouteLayer, RouteBody, InnerBody> {
 ///     |inner         | Target = t.withAddr(concrete_dst)
 ///     +--------------+
 /// ```
+///
+/// Each mirror named by the profile's routes is served by its own
+/// RouteRouter, built by the mirror route layer over a ConcreteRouter that
+/// only routes to the mirror's addr. A request is copied to the mirrors of
+/// the route it matches.
 pub struct Service<RouteStream, Target, RouteLayer, RouteMake, Inner, RouteBody, InnerBody>
 where
     Target: WithAddr + WithRoute + Clone + Eq + Hash,
@@ -90,9 +178,16 @@ where
     target: Target,
     inner: Inner,
     route_layer: RouteLayer,
+    mirror: Option<MirrorConfig<RouteLayer>>,
     route_stream: Option<RouteStream>,
     concrete_router: Option<ConcreteRouter<Target, Inner::Value, InnerBody>>,
     router: RouteRouter<Target, Target::Output, RouteMake::Value, RouteBody>,
+    mirrors: IndexMap<NameAddr, RouteRouter<Target, Target::Output, RouteMake::Value, RouteBody>>,
+    /// The profile's routes, if any of them have mirrors.
+    mirror_routes: Vec<(RequestMatch, Route)>,
+    profile: Option<Arc<Mutex<Profile>>>,
+    health_check: Option<(NameAddr, health_check::Checks)>,
+    hash_key: Option<(NameAddr, consistent_hash::HashKeys)>,
     default_route: Route,
 }
 
@@ -109,6 +204,10 @@ where
             inner,
             get_routes: self.get_routes.clone(),
             route_layer: self.route_layer.clone(),
+            mirror: self.mirror.clone(),
+            active_routes: self.active_routes.clone(),
+            health_checks: self.health_checks.clone(),
+            hash_keys: self.hash_keys.clone(),
             suffixes: self.suffixes.clone(),
             default_route: self.default_route.clone(),
             _p: ::std::marker::PhantomData,
@@ -127,6 +226,10 @@ where
             suffixes: self.suffixes.clone(),
             get_routes: self.get_routes.clone(),
             route_layer: self.route_layer.clone(),
+            mirror: self.mirror.clone(),
+            active_routes: self.active_routes.clone(),
+            health_checks: self.health_checks.clone(),
+            hash_keys: self.hash_keys.clone(),
             default_route: self.default_route.clone(),
             _p: ::std::marker::PhantomData,
         }
@@ -185,10 +288,30 @@ where
 
         // Initiate a stream to get route and dst_override updates for this
         // destination.
+        let mut profile = None;
+        let mut health_check = None;
+        let mut hash_key = None;
         let route_stream = match target.get_destination() {
             Some(ref dst) => {
                 if self.suffixes.iter().any(|s| s.contains(dst.name())) {
                     debug!("fetching routes for {:?}", dst);
+                    health_check = self
+                        .health_checks
+                        .as_ref()
+                        .map(|checks| ((*dst).clone(), checks.clone()));
+                    hash_key = self
+                        .hash_keys
+                        .as_ref()
+                        .map(|keys| ((*dst).clone(), keys.clone()));
+                    profile = self.active_routes.as_ref().map(|active_routes| {
+                        let profile = Arc::new(Mutex::new(Profile {
+                            dst: (*dst).clone(),
+                            routes: Vec::new(),
+                            dst_overrides: Vec::new(),
+                        }));
+                        active_routes.register(&profile);
+                        profile
+                    });
                     self.get_routes.get_routes(&dst)
                 } else {
                     debug!("skipping route discovery for dst={:?}", dst);
@@ -205,8 +328,14 @@ where
             target,
             inner: self.inner.clone(),
             route_layer: self.route_layer.clone(),
+            mirror: self.mirror.clone(),
             route_stream,
             router,
+            mirrors: IndexMap::new(),
+            mirror_routes: Vec::new(),
+            profile,
+            health_check,
+            hash_key,
             concrete_router: Some(concrete_router),
             default_route: self.default_route.clone(),
         })
@@ -225,6 +354,10 @@ where
             inner: self.inner.clone(),
             get_routes: self.get_routes.clone(),
             route_layer: self.route_layer.clone(),
+            mirror: self.mirror.clone(),
+            active_routes: self.active_routes.clone(),
+            health_checks: self.health_checks.clone(),
+            hash_keys: self.hash_keys.clone(),
             suffixes: self.suffixes.clone(),
             default_route: self.default_route.clone(),
             _p: ::std::marker::PhantomData,
@@ -248,6 +381,19 @@ where
     Inner::Value: svc::Service<http::Request<InnerBody>> + Clone,
 {
     fn update_routes(&mut self, routes: Routes) {
+        if let Some(Ok(mut profile)) = self.profile.as_ref().map(|p| p.lock()) {
+            profile.routes = routes.routes.iter().map(|(_, r)| r.clone()).collect();
+            profile.dst_overrides = routes.dst_overrides.clone();
+        }
+
+        if let Some((ref dst, ref checks)) = self.health_check {
+            checks.set_profile(dst, routes.health_check.clone());
+        }
+
+        if let Some((ref dst, ref keys)) = self.hash_key {
+            keys.set_profile(dst, routes.balance_hash_key.clone());
+        }
+
         // We must build a new concrete router with a service for each
         // dst_override.  These services are created eagerly.  If a service
         // was present in the previous concrete router, we reuse that
@@ -275,6 +421,45 @@ where
             make.insert(target, service);
         }
 
+        // Mirror services are also held by the concrete router (though it
+        // never routes to them) so that they may be reused across updates.
+        // Each mirror is routed through its own route stack, terminated by a
+        // concrete router that only routes to the mirror.
+        let mut mirrors = IndexMap::new();
+        if let Some(ref config) = self.mirror {
+            let addrs = routes.routes.iter().flat_map(|(_, r)| r.mirrors());
+            for addr in addrs {
+                if mirrors.contains_key(addr) {
+                    continue;
+                }
+                let target = self.target.clone().with_addr(addr.clone());
+                let service = match make.get(&target) {
+                    Some(service) => service.clone(),
+                    None => old_make
+                        .remove(&target)
+                        .unwrap_or_else(|| self.inner.make(&target)),
+                };
+                make.insert(target.clone(), service.clone());
+
+                let mut mirror_make = IndexMap::with_capacity(1);
+                mirror_make.insert(target.clone(), service);
+                let concrete_router = rt::Router::new_fixed(
+                    ConcreteDstRecognize::new(target.clone(), Vec::new()),
+                    mirror_make,
+                );
+
+                let stack = config.route_layer.layer(svc::shared(concrete_router));
+                let router = self.route_router(target, &stack, routes.routes.clone());
+                mirrors.insert(addr.clone(), router);
+            }
+        }
+        self.mirror_routes = if mirrors.is_empty() {
+            Vec::new()
+        } else {
+            routes.routes.clone()
+        };
+        self.mirrors = mirrors;
+
         let concrete_router = rt::Router::new_fixed(
             ConcreteDstRecognize::new(self.target.clone(), routes.dst_overrides),
             make,
@@ -286,32 +471,34 @@ where
         self.concrete_router = Some(concrete_router.clone());
 
         let stack = self.route_layer.layer(svc::shared(concrete_router));
+        self.router = self.route_router(self.target.clone(), &stack, routes.routes);
+    }
 
-        let default_route = self.target.clone().with_route(self.default_route.clone());
+    fn route_router(
+        &self,
+        target: Target,
+        stack: &RouteMake,
+        routes: Vec<(RequestMatch, Route)>,
+    ) -> RouteRouter<Target, Target::Output, RouteMake::Value, RouteBody> {
+        let default_route = target.clone().with_route(self.default_route.clone());
 
         // Create a new fixed router router; we can eagerly make the
         // services and never expire the routes from the profile router
         // cache.
-        let capacity = routes.routes.len() + 1;
+        let capacity = routes.len() + 1;
         let mut make = IndexMap::with_capacity(capacity);
         make.insert(default_route.clone(), stack.make(&default_route));
 
-        for (_, route) in &routes.routes {
-            let route = self.target.clone().with_route(route.clone());
+        for (_, route) in &routes {
+            let route = target.clone().with_route(route.clone());
             let service = stack.make(&route);
             make.insert(route, service);
         }
 
-        let router = rt::Router::new_fixed(
-            RouteRecognize::new(
-                self.target.clone(),
-                routes.routes,
-                self.default_route.clone(),
-            ),
+        rt::Router::new_fixed(
+            RouteRecognize::new(target, routes, self.default_route.clone()),
             make,
-        );
-
-        self.router = router;
+        )
     }
 
     fn poll_route_stream(&mut self) -> Option<Async<Option<Routes>>> {
@@ -337,14 +524,12 @@ where
     Inner::Value: svc::Service<http::Request<InnerBody>> + Clone,
     RouteSvc: svc::Service<http::Request<RouteBody>> + Clone,
     RouteSvc::Error: Into<Error>,
+    RouteBody: mirror::Tee + TryClone,
+    RouteFuture<Target, Target::Output, RouteSvc, RouteBody>: Send + 'static,
 {
     type Response = RouteSvc::Response;
     type Error = Error;
-    type Future = rt::ResponseFuture<
-        http::Request<RouteBody>,
-        RouteRecognize<Target>,
-        rt::FixedMake<Target::Output, RouteSvc>,
-    >;
+    type Future = RouteFuture<Target, Target::Output, RouteSvc, RouteBody>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
         while let Some(Async::Ready(Some(routes))) = self.poll_route_stream() {
@@ -354,7 +539,30 @@ where
         Ok(Async::Ready(()))
     }
 
-    fn call(&mut self, req: http::Request<RouteBody>) -> Self::Future {
+    fn call(&mut self, mut req: http::Request<RouteBody>) -> Self::Future {
+        // Requests are copied to the mirrors of the first route they match,
+        // as the route router would select it.
+        let route = self
+            .mirror_routes
+            .iter()
+            .find(|(condition, _)| condition.is_match(&req))
+            .map(|(_, route)| route);
+        if let (Some(config), Some(route)) = (self.mirror.as_ref(), route) {
+            for addr in route.mirrors() {
+                let router = match self.mirrors.get_mut(addr) {
+                    Some(router) => router,
+                    None => continue,
+                };
+                match mirror::clone_request(&mut req, config.max_body_bytes) {
+                    Some(clone) => {
+                        // The mirror's response is discarded.
+                        tokio::spawn(router.call(clone).then(|_| Ok(())));
+                    }
+                    None => debug!("request cannot be mirrored"),
+                }
+            }
+        }
+
         self.router.call(req)
     }
 }
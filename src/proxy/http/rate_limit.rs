@@ -0,0 +1,798 @@
+//! Token-bucket rate limiting for HTTP requests.
+//!
+//! Requests are counted against a bucket that may be keyed by the stack
+//! target's rate limit key (e.g. a route) and by the TLS identity of the
+//! client that sent the request. Requests that arrive when a bucket is empty
+//! fail with a `RateLimited` error without being dispatched to the inner
+//! service.
+//!
+//! Buckets that have refilled and have not been used for the metrics
+//! retention period are dropped when metrics are reported. When there are too
+//! many keys, the least recently used bucket is dropped.
+
+use crate::identity;
+use crate::metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics, Metric};
+use crate::proxy::Source;
+use crate::{svc, Conditional, Error};
+use futures::{try_ready, Future, Poll};
+use http;
+use indexmap::IndexMap;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+use tokio_timer::clock;
+use tracing::debug;
+
+metrics! {
+    rate_limit_allowed_total: Counter { "Total count of requests allowed by a rate limit" },
+    rate_limit_exceeded_total: Counter { "Total count of requests rejected by a rate limit" }
+}
+
+/// Bounds the number of buckets, and so the cardinality of rate limit
+/// metrics, when clients present many distinct identities.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Implemented by targets that may be rate limited.
+pub trait CanRateLimit {
+    type Key: Clone + Hash + Eq;
+
+    fn rate_limit_key(&self) -> Self::Key;
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The number of requests permitted per second.
+    pub rate: u32,
+
+    /// The number of requests that may be permitted in a burst.
+    pub burst: u32,
+
+    /// Whether each target (i.e. route) has its own bucket.
+    pub by_target: bool,
+
+    /// Whether each client identity has its own bucket.
+    pub by_client_id: bool,
+}
+
+/// Identifies a bucket.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key<K> {
+    target: Option<K>,
+    client_id: Option<identity::Name>,
+}
+
+/// Indicates that a request was rejected by a rate limit.
+#[derive(Debug)]
+pub struct RateLimited(());
+
+/// Builds a registry that drops idle buckets after `retain_idle`.
+pub fn new<K: Hash + Eq>(retain_idle: Duration) -> (Registry<K>, Report<K>) {
+    let inner = Arc::new(Mutex::new(Buckets {
+        by_key: IndexMap::new(),
+        by_use: BTreeMap::new(),
+        next_use: 0,
+        retain_idle,
+    }));
+    (Registry(inner.clone()), Report(inner))
+}
+
+/// Holds the buckets for all rate limit keys.
+#[derive(Debug)]
+pub struct Registry<K: Hash + Eq>(Arc<Mutex<Buckets<K>>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted rate limit metrics.
+#[derive(Debug)]
+pub struct Report<K: Hash + Eq>(Arc<Mutex<Buckets<K>>>);
+
+#[derive(Debug)]
+pub struct Layer<K: Hash + Eq> {
+    config: Option<Arc<Config>>,
+    registry: Registry<K>,
+}
+
+#[derive(Debug)]
+pub struct Stack<M, K: Hash + Eq> {
+    config: Option<Arc<Config>>,
+    registry: Registry<K>,
+    inner: M,
+}
+
+pub struct MakeFuture<F, K: Hash + Eq> {
+    limit: Option<Limit<K>>,
+    inner: F,
+}
+
+#[derive(Debug)]
+pub struct Service<S, K: Hash + Eq> {
+    limit: Option<Limit<K>>,
+    inner: S,
+}
+
+pub struct ResponseFuture<F> {
+    inner: Option<F>,
+}
+
+#[derive(Debug)]
+struct Limit<K: Hash + Eq> {
+    config: Arc<Config>,
+    target: Option<K>,
+    registry: Registry<K>,
+}
+
+#[derive(Debug)]
+struct Buckets<K: Hash + Eq> {
+    by_key: IndexMap<Key<K>, Entry>,
+    /// Orders keys by when their buckets were last used, so that the least
+    /// recently used bucket may be found without scanning all buckets.
+    by_use: BTreeMap<u64, Key<K>>,
+    next_use: u64,
+    retain_idle: Duration,
+}
+
+#[derive(Debug)]
+struct Entry {
+    last_use: u64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// When the bucket will have refilled, if ever.
+    full_at: Option<Instant>,
+    allowed_total: Counter,
+    exceeded_total: Counter,
+}
+
+// === impl Registry ===
+
+impl<K: Hash + Eq> Clone for Registry<K> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+// === impl Report ===
+
+impl<K: Hash + Eq> Clone for Report<K> {
+    fn clone(&self) -> Self {
+        Report(self.0.clone())
+    }
+}
+
+impl<K: FmtLabels + Hash + Eq> FmtMetrics for Report<K> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut inner = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        inner.retain_active(clock::now());
+        let inner = &inner.by_key;
+        if inner.is_empty() {
+            return Ok(());
+        }
+
+        rate_limit_allowed_total.fmt_help(f)?;
+        fmt_by(inner, f, rate_limit_allowed_total, |b| &b.allowed_total)?;
+
+        rate_limit_exceeded_total.fmt_help(f)?;
+        fmt_by(inner, f, rate_limit_exceeded_total, |b| &b.exceeded_total)?;
+
+        Ok(())
+    }
+}
+
+fn fmt_by<K, F, M>(
+    inner: &IndexMap<Key<K>, Entry>,
+    f: &mut fmt::Formatter<'_>,
+    metric: Metric<'_, M>,
+    get_metric: F,
+) -> fmt::Result
+where
+    K: FmtLabels + Hash + Eq,
+    F: Fn(&Bucket) -> &M,
+    M: FmtMetric,
+{
+    for (key, e) in inner.iter() {
+        if let Ok(b) = e.bucket.lock() {
+            get_metric(&*b).fmt_metric_labeled(f, metric.name, key)?;
+        }
+    }
+
+    Ok(())
+}
+
+// === impl Buckets ===
+
+impl<K: Clone + Hash + Eq> Buckets<K> {
+    fn get_or_insert(&mut self, key: Key<K>, config: &Config, now: Instant) -> Arc<Mutex<Bucket>> {
+        let last_use = self.next_use;
+        self.next_use += 1;
+
+        if let Some(entry) = self.by_key.get_mut(&key) {
+            self.by_use.remove(&entry.last_use);
+            self.by_use.insert(last_use, key);
+            entry.last_use = last_use;
+            return entry.bucket.clone();
+        }
+
+        if self.by_key.len() >= MAX_BUCKETS {
+            let lru = self.by_use.keys().next().cloned();
+            if let Some(key) = lru.and_then(|u| self.by_use.remove(&u)) {
+                debug!("evicting least recently used rate limit bucket");
+                self.by_key.swap_remove(&key);
+            }
+        }
+
+        let bucket = Arc::new(Mutex::new(Bucket::new(config, now)));
+        self.by_use.insert(last_use, key.clone());
+        self.by_key.insert(
+            key,
+            Entry {
+                last_use,
+                bucket: bucket.clone(),
+            },
+        );
+        bucket
+    }
+}
+
+impl<K: Hash + Eq> Buckets<K> {
+    /// Drops buckets that have refilled and have not been used for
+    /// `retain_idle`, since a new bucket would behave identically.
+    fn retain_active(&mut self, now: Instant) {
+        let epoch = match now.checked_sub(self.retain_idle) {
+            Some(epoch) => epoch,
+            None => return,
+        };
+
+        let by_use = &mut self.by_use;
+        self.by_key.retain(|_, e| {
+            let active = e
+                .bucket
+                .lock()
+                .map(|b| !b.is_idle_since(epoch, now))
+                .unwrap_or(false);
+            if !active {
+                by_use.remove(&e.last_use);
+            }
+            active
+        });
+    }
+}
+
+// === impl Key ===
+
+impl<K: FmtLabels> FmtLabels for Key<K> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref target) = self.target {
+            target.fmt_labels(f)?;
+            if self.client_id.is_some() {
+                f.pad(",")?;
+            }
+        }
+
+        if let Some(ref id) = self.client_id {
+            write!(f, "client_id=\"{}\"", id.as_ref())?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Layer ===
+
+/// Rate limits requests if `config` is set.
+pub fn layer<K: Hash + Eq>(config: Option<Config>, registry: Registry<K>) -> Layer<K> {
+    Layer {
+        config: config.map(Arc::new),
+        registry,
+    }
+}
+
+impl<K: Hash + Eq> Clone for Layer<K> {
+    fn clone(&self) -> Self {
+        Layer {
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<M, K: Hash + Eq> svc::Layer<M> for Layer<K> {
+    type Service = Stack<M, K>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M: Clone, K: Hash + Eq> Clone for Stack<M, K> {
+    fn clone(&self) -> Self {
+        Stack {
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T, M> svc::Service<T> for Stack<M, T::Key>
+where
+    T: CanRateLimit,
+    M: svc::Service<T>,
+{
+    type Response = Service<M::Response, T::Key>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future, T::Key>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let limit = self.config.as_ref().map(|config| Limit {
+            target: if config.by_target {
+                Some(target.rate_limit_key())
+            } else {
+                None
+            },
+            config: config.clone(),
+            registry: self.registry.clone(),
+        });
+        let inner = self.inner.call(target);
+
+        MakeFuture { limit, inner }
+    }
+}
+
+impl<F: Future, K: Hash + Eq> Future for MakeFuture<F, K> {
+    type Item = Service<F::Item, K>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        let limit = self.limit.take();
+        Ok(Service { limit, inner }.into())
+    }
+}
+
+// === impl Service ===
+
+impl<S: Clone, K: Clone + Hash + Eq> Clone for Service<S, K> {
+    fn clone(&self) -> Self {
+        Service {
+            limit: self.limit.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// The inner service is always readied, so that backpressure is preserved.
+/// Requests are only dispatched to it once they have acquired a token; a
+/// rejected request leaves the inner service ready for the next request.
+impl<S, K, B> svc::Service<http::Request<B>> for Service<S, K>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+    K: Clone + Hash + Eq,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let permitted = self
+            .limit
+            .as_ref()
+            .map(|limit| limit.acquire(&req))
+            .unwrap_or(true);
+        let inner = if permitted {
+            Some(self.inner.call(req))
+        } else {
+            None
+        };
+
+        ResponseFuture { inner }
+    }
+}
+
+impl<F> Future for ResponseFuture<F>
+where
+    F: Future,
+    F::Error: Into<Error>,
+{
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner.as_mut() {
+            Some(f) => f.poll().map_err(Into::into),
+            None => Err(RateLimited(()).into()),
+        }
+    }
+}
+
+// === impl Limit ===
+
+impl<K: Clone + Hash + Eq> Clone for Limit<K> {
+    fn clone(&self) -> Self {
+        Limit {
+            config: self.config.clone(),
+            target: self.target.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq> Limit<K> {
+    /// Takes a token from the request's bucket, returning false if none are
+    /// available.
+    fn acquire<B>(&self, req: &http::Request<B>) -> bool {
+        let client_id = if self.config.by_client_id {
+            req.extensions()
+                .get::<Source>()
+                .and_then(|s| match s.tls_peer {
+                    Conditional::Some(ref id) => Some(id.clone()),
+                    Conditional::None(_) => None,
+                })
+        } else {
+            None
+        };
+        let key = Key {
+            target: self.target.clone(),
+            client_id,
+        };
+
+        let now = clock::now();
+        let bucket = match (self.registry.0).lock() {
+            Ok(mut buckets) => buckets.get_or_insert(key, &self.config, now),
+            // If the registry is poisoned, requests are not limited.
+            Err(_) => return true,
+        };
+
+        let mut bucket = match bucket.lock() {
+            Ok(bucket) => bucket,
+            Err(_) => return true,
+        };
+        let acquired = bucket.acquire(&self.config, now);
+        if !acquired {
+            debug!("rate limit exceeded");
+        }
+        acquired
+    }
+}
+
+// === impl Bucket ===
+
+impl Bucket {
+    fn new(config: &Config, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(config.burst),
+            updated_at: now,
+            full_at: Some(now),
+            allowed_total: Counter::default(),
+            exceeded_total: Counter::default(),
+        }
+    }
+
+    fn is_idle_since(&self, epoch: Instant, now: Instant) -> bool {
+        let is_full = self.full_at.map(|t| t <= now).unwrap_or(false);
+        is_full && self.updated_at < epoch
+    }
+
+    fn acquire(&mut self, config: &Config, now: Instant) -> bool {
+        if now > self.updated_at {
+            let elapsed = now - self.updated_at;
+            let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+            self.tokens =
+                (self.tokens + secs * f64::from(config.rate)).min(f64::from(config.burst));
+            self.updated_at = now;
+        }
+
+        let acquired = if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.allowed_total.incr();
+            true
+        } else {
+            self.exceeded_total.incr();
+            false
+        };
+
+        let missing = f64::from(config.burst) - self.tokens;
+        self.full_at = if missing <= 0.0 {
+            Some(now)
+        } else if config.rate == 0 {
+            None
+        } else {
+            let nanos = missing / f64::from(config.rate) * 1e9;
+            Some(now + Duration::from_nanos(nanos.ceil() as u64))
+        };
+
+        acquired
+    }
+}
+
+// === impl RateLimited ===
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate limit exceeded")
+    }
+}
+
+impl error::Error for RateLimited {}
+
+#[cfg(test)]
+impl RateLimited {
+    pub fn for_test() -> Self {
+        RateLimited(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tls;
+    use futures::{future, Async};
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn config(rate: u32, burst: u32, by_client_id: bool) -> Config {
+        Config {
+            rate,
+            burst,
+            by_target: false,
+            by_client_id,
+        }
+    }
+
+    fn limit(config: Config) -> Limit<()> {
+        let (registry, _) = new(Duration::from_secs(60));
+        Limit {
+            config: Arc::new(config),
+            target: None,
+            registry,
+        }
+    }
+
+    fn request(client_id: Option<&str>) -> http::Request<()> {
+        let tls_peer = match client_id {
+            Some(id) => Conditional::Some(identity::Name::from_hostname(id.as_bytes()).unwrap()),
+            None => Conditional::None(tls::ReasonForNoPeerName::NotProvidedByRemote.into()),
+        };
+        let addr = SocketAddr::from(([127, 0, 0, 1], 4143));
+        let mut req = http::Request::new(());
+        req.extensions_mut()
+            .insert(Source::for_test(addr, addr, None, tls_peer));
+        req
+    }
+
+    /// Counts the number of times it is readied and called.
+    #[derive(Clone, Default)]
+    struct Inner {
+        readied: Arc<AtomicUsize>,
+        called: Arc<AtomicUsize>,
+    }
+
+    impl svc::Service<http::Request<()>> for Inner {
+        type Response = http::Response<()>;
+        type Error = Error;
+        type Future = future::FutureResult<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            self.readied.fetch_add(1, Ordering::SeqCst);
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: http::Request<()>) -> Self::Future {
+            self.called.fetch_add(1, Ordering::SeqCst);
+            future::ok(http::Response::new(()))
+        }
+    }
+
+    #[test]
+    fn bucket_refills_at_rate() {
+        let config = config(2, 2, false);
+        let mut bucket = Bucket::new(&config, clock::now());
+        let t0 = bucket.updated_at;
+
+        assert!(bucket.acquire(&config, t0));
+        assert!(bucket.acquire(&config, t0));
+        assert!(!bucket.acquire(&config, t0), "burst exhausted");
+
+        let t1 = t0 + Duration::from_millis(500);
+        assert!(bucket.acquire(&config, t1), "refilled one token");
+        assert!(!bucket.acquire(&config, t1));
+
+        let t2 = t1 + Duration::from_secs(60);
+        assert!(bucket.acquire(&config, t2));
+        assert!(bucket.acquire(&config, t2));
+        assert!(
+            !bucket.acquire(&config, t2),
+            "refill is capped by the burst"
+        );
+
+        assert_eq!(bucket.allowed_total.value(), 5);
+        assert_eq!(bucket.exceeded_total.value(), 3);
+    }
+
+    #[test]
+    fn bucket_tracks_when_it_is_full() {
+        let config = config(2, 2, false);
+        let t0 = clock::now();
+        let mut bucket = Bucket::new(&config, t0);
+        assert_eq!(bucket.full_at, Some(t0));
+
+        assert!(bucket.acquire(&config, t0));
+        assert_eq!(bucket.full_at, Some(t0 + Duration::from_millis(500)));
+
+        let no_refill = Config { rate: 0, ..config };
+        assert!(bucket.acquire(&no_refill, t0));
+        assert_eq!(bucket.full_at, None, "never refills");
+    }
+
+    #[test]
+    fn limits_each_client_identity_separately() {
+        let limit = limit(config(0, 1, true));
+        let foo = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
+        let bar = "bar.ns1.serviceaccount.identity.linkerd.cluster.local";
+
+        assert!(limit.acquire(&request(Some(foo))));
+        assert!(!limit.acquire(&request(Some(foo))));
+
+        assert!(limit.acquire(&request(Some(bar))), "isolated from foo");
+        assert!(!limit.acquire(&request(Some(bar))));
+
+        assert!(limit.acquire(&request(None)), "unauthenticated bucket");
+        assert!(!limit.acquire(&request(None)));
+
+        let buckets = limit.registry.0.lock().unwrap();
+        assert_eq!(buckets.by_key.len(), 3);
+    }
+
+    #[test]
+    fn shares_a_bucket_when_not_limited_by_client_identity() {
+        let limit = limit(config(0, 1, false));
+        let foo = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
+        let bar = "bar.ns1.serviceaccount.identity.linkerd.cluster.local";
+
+        assert!(limit.acquire(&request(Some(foo))));
+        assert!(!limit.acquire(&request(Some(bar))));
+        assert!(!limit.acquire(&request(None)));
+    }
+
+    #[test]
+    fn evicts_idle_full_buckets() {
+        let retain_idle = Duration::from_secs(60);
+        let (registry, _) = new::<&'static str>(retain_idle);
+        let mut buckets = registry.0.lock().unwrap();
+        let config = config(1, 1, false);
+        let key = |k| Key {
+            target: Some(k),
+            client_id: None,
+        };
+
+        let t0 = clock::now();
+        let idle = buckets.get_or_insert(key("idle"), &config, t0);
+        assert!(idle.lock().unwrap().acquire(&config, t0));
+        let empty = buckets.get_or_insert(
+            key("empty"),
+            &Config {
+                rate: 0,
+                ..config.clone()
+            },
+            t0,
+        );
+        assert!(empty.lock().unwrap().acquire(
+            &Config {
+                rate: 0,
+                ..config.clone()
+            },
+            t0
+        ));
+
+        let t1 = t0 + retain_idle / 2;
+        buckets.retain_active(t1);
+        assert_eq!(buckets.by_key.len(), 2, "buckets are not yet idle");
+
+        let active = buckets.get_or_insert(key("active"), &config, t1);
+        assert!(active.lock().unwrap().acquire(&config, t1));
+
+        buckets.retain_active(t0 + retain_idle + Duration::from_secs(1));
+        assert!(!buckets.by_key.contains_key(&key("idle")));
+        assert!(
+            buckets.by_key.contains_key(&key("empty")),
+            "buckets that have not refilled are retained"
+        );
+        assert!(buckets.by_key.contains_key(&key("active")));
+        assert_eq!(buckets.by_use.len(), buckets.by_key.len());
+    }
+
+    #[test]
+    fn bounds_the_number_of_buckets() {
+        let (registry, _) = new::<usize>(Duration::from_secs(60));
+        let mut buckets = registry.0.lock().unwrap();
+        let config = config(1, 1, false);
+        let key = |k| Key {
+            target: Some(k),
+            client_id: None,
+        };
+
+        let t0 = clock::now();
+        for i in 0..MAX_BUCKETS {
+            let t = t0 + Duration::from_millis(i as u64);
+            buckets.get_or_insert(key(i), &config, t);
+        }
+        assert_eq!(buckets.by_key.len(), MAX_BUCKETS);
+
+        let t = t0 + Duration::from_millis(MAX_BUCKETS as u64);
+        buckets.get_or_insert(key(MAX_BUCKETS), &config, t);
+        assert_eq!(buckets.by_key.len(), MAX_BUCKETS);
+        assert!(
+            !buckets.by_key.contains_key(&key(0)),
+            "least recently used bucket is evicted"
+        );
+        assert!(buckets.by_key.contains_key(&key(MAX_BUCKETS)));
+
+        // Using a bucket makes it the most recently used.
+        buckets.get_or_insert(key(1), &config, t);
+        buckets.get_or_insert(key(MAX_BUCKETS + 1), &config, t);
+        assert!(buckets.by_key.contains_key(&key(1)));
+        assert!(!buckets.by_key.contains_key(&key(2)));
+        assert_eq!(buckets.by_use.len(), MAX_BUCKETS);
+    }
+
+    #[test]
+    fn rejects_without_calling_inner() {
+        let inner = Inner::default();
+        let (readied, called) = (inner.readied.clone(), inner.called.clone());
+        let mut svc = Service {
+            limit: Some(limit(config(0, 1, false))),
+            inner,
+        };
+
+        svc::Service::poll_ready(&mut svc).expect("ready");
+        assert_eq!(readied.load(Ordering::SeqCst), 1, "inner is readied");
+        let rsp = svc::Service::call(&mut svc, request(None)).wait();
+        assert!(rsp.is_ok());
+        assert_eq!(called.load(Ordering::SeqCst), 1);
+
+        svc::Service::poll_ready(&mut svc).expect("ready");
+        assert_eq!(readied.load(Ordering::SeqCst), 2, "inner is readied");
+        let err = svc::Service::call(&mut svc, request(None))
+            .wait()
+            .expect_err("rate limited");
+        assert!(err.is::<RateLimited>());
+        assert_eq!(
+            called.load(Ordering::SeqCst),
+            1,
+            "inner is not called for rejected requests"
+        );
+    }
+
+    #[test]
+    fn readies_inner_when_not_limited() {
+        let inner = Inner::default();
+        let readied = inner.readied.clone();
+        let mut svc = Service::<_, ()> { limit: None, inner };
+
+        svc::Service::poll_ready(&mut svc).expect("ready");
+        assert_eq!(readied.load(Ordering::SeqCst), 1);
+        assert!(svc::Service::call(&mut svc, request(None)).wait().is_ok());
+    }
+}
@@ -189,25 +189,29 @@ where
 
 impl<B: TryClone> TryClone for Request<B> {
     fn try_clone(&self) -> Option<Self> {
-        if let Some(body) = self.body().try_clone() {
-            let mut clone = Request::new(body);
-            *clone.method_mut() = self.method().clone();
-            *clone.uri_mut() = self.uri().clone();
-            *clone.headers_mut() = self.headers().clone();
-            *clone.version_mut() = self.version();
-
-            if let Some(ext) = self.extensions().get::<crate::proxy::server::Source>() {
-                clone.extensions_mut().insert(ext.clone());
-            }
+        self.body()
+            .try_clone()
+            .map(|body| clone_with_body(self, body))
+    }
+}
 
-            // Count retries toward the request's total handle time.
-            if let Some(ext) = self.extensions().get::<handle_time::Tracker>() {
-                clone.extensions_mut().insert(ext.clone());
-            }
+/// Copies a request's head and its proxy-specific extensions into a new
+/// request with the given body.
+pub fn clone_with_body<A, B>(req: &Request<A>, body: B) -> Request<B> {
+    let mut clone = Request::new(body);
+    *clone.method_mut() = req.method().clone();
+    *clone.uri_mut() = req.uri().clone();
+    *clone.headers_mut() = req.headers().clone();
+    *clone.version_mut() = req.version();
+
+    if let Some(ext) = req.extensions().get::<crate::proxy::server::Source>() {
+        clone.extensions_mut().insert(ext.clone());
+    }
 
-            Some(clone)
-        } else {
-            None
-        }
+    // Count retries toward the request's total handle time.
+    if let Some(ext) = req.extensions().get::<handle_time::Tracker>() {
+        clone.extensions_mut().insert(ext.clone());
     }
+
+    clone
 }
@@ -0,0 +1,487 @@
+//! Authorizes inbound HTTP requests by route and client identity.
+//!
+//! Each `Rule` matches requests like a profile route and lists the client
+//! identities that may send them. Rules are evaluated in order and the first
+//! rule that matches a request decides whether it is authorized. Requests
+//! that match no rule are authorized.
+
+use super::profiles::RequestMatch;
+use crate::metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics};
+use crate::proxy::{authz, Source};
+use crate::{svc, Conditional, Error};
+use futures::{future, try_ready, Future, Poll};
+use http;
+use std::sync::{Arc, Mutex};
+use std::{error, fmt};
+use tracing::debug;
+
+metrics! {
+    inbound_route_authz_allow_total: Counter {
+        "Total count of inbound requests that were authorized by a rule"
+    },
+    inbound_route_authz_deny_total: Counter {
+        "Total count of inbound requests that were not authorized by a rule"
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Policy {
+    rules: Arc<Vec<Rule>>,
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    name: String,
+    match_: RequestMatch,
+    identities: Vec<authz::Match>,
+    allow_total: Mutex<Counter>,
+    deny_total: Mutex<Counter>,
+}
+
+/// Indicates that a request was not authorized by a rule.
+#[derive(Debug)]
+pub struct Unauthorized {
+    rule: String,
+    client_id: Option<String>,
+}
+
+/// Implements `FmtMetrics` to render prometheus-formatted rule metrics.
+#[derive(Clone, Debug, Default)]
+pub struct Report(Option<Policy>);
+
+#[derive(Clone, Debug)]
+pub struct Layer(Option<Policy>);
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    policy: Option<Policy>,
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    policy: Option<Policy>,
+    inner: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    policy: Option<Policy>,
+    inner: S,
+}
+
+// === impl Policy ===
+
+impl Policy {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules: Arc::new(rules),
+        }
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    fn authorize<B>(&self, req: &http::Request<B>) -> Result<(), Unauthorized> {
+        let rule = match self.rules.iter().find(|r| r.match_.is_match(req)) {
+            Some(rule) => rule,
+            None => return Ok(()),
+        };
+
+        let client_id = req
+            .extensions()
+            .get::<Source>()
+            .and_then(|s| match s.tls_peer {
+                Conditional::Some(ref id) => Some(id),
+                Conditional::None(_) => None,
+            });
+        let authorized = client_id
+            .map(|id| rule.identities.iter().any(|m| m.matches(id)))
+            .unwrap_or(false);
+
+        if authorized {
+            if let Ok(mut c) = rule.allow_total.lock() {
+                c.incr();
+            }
+            Ok(())
+        } else {
+            if let Ok(mut c) = rule.deny_total.lock() {
+                c.incr();
+            }
+            Err(Unauthorized {
+                rule: rule.name.clone(),
+                client_id: client_id.map(|id| id.as_ref().to_owned()),
+            })
+        }
+    }
+}
+
+// === impl Rule ===
+
+impl Rule {
+    pub fn new(name: String, match_: RequestMatch, identities: Vec<authz::Match>) -> Self {
+        Self {
+            name,
+            match_,
+            identities,
+            allow_total: Mutex::new(Counter::default()),
+            deny_total: Mutex::new(Counter::default()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// === impl Unauthorized ===
+
+impl fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.client_id {
+            Some(ref id) => write!(f, "client {} is not authorized by rule {}", id, self.rule),
+            None => write!(
+                f,
+                "unauthenticated client is not authorized by rule {}",
+                self.rule
+            ),
+        }
+    }
+}
+
+impl error::Error for Unauthorized {}
+
+// === impl Report ===
+
+impl Report {
+    pub fn new(policy: Option<Policy>) -> Self {
+        Report(policy)
+    }
+}
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rules = match self.0 {
+            Some(ref p) if !p.rules.is_empty() => p.rules(),
+            _ => return Ok(()),
+        };
+
+        inbound_route_authz_allow_total.fmt_help(f)?;
+        for rule in rules {
+            if let Ok(c) = rule.allow_total.lock() {
+                c.fmt_metric_labeled(
+                    f,
+                    inbound_route_authz_allow_total.name,
+                    RuleLabel(rule.name()),
+                )?;
+            }
+        }
+
+        inbound_route_authz_deny_total.fmt_help(f)?;
+        for rule in rules {
+            if let Ok(c) = rule.deny_total.lock() {
+                c.fmt_metric_labeled(
+                    f,
+                    inbound_route_authz_deny_total.name,
+                    RuleLabel(rule.name()),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct RuleLabel<'a>(&'a str);
+
+impl<'a> FmtLabels for RuleLabel<'a> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rule=\"{}\"", self.0)
+    }
+}
+
+// === impl Layer ===
+
+/// Authorizes requests if `policy` is set.
+pub fn layer(policy: Option<Policy>) -> Layer {
+    Layer(policy)
+}
+
+impl<M> svc::Layer<M> for Layer {
+    type Service = Stack<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            policy: self.0.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Service<T> for Stack<M>
+where
+    M: svc::Service<T>,
+{
+    type Response = Service<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        MakeFuture {
+            policy: self.policy.clone(),
+            inner: self.inner.call(target),
+        }
+    }
+}
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = Service<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        let policy = self.policy.take();
+        Ok(Service { policy, inner }.into())
+    }
+}
+
+// === impl Service ===
+
+impl<S, B> svc::Service<http::Request<B>> for Service<S>
+where
+    S: svc::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::FutureResult<S::Response, Error>,
+        future::MapErr<S::Future, fn(S::Error) -> Error>,
+    >;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if let Some(ref policy) = self.policy {
+            if let Err(e) = policy.authorize(&req) {
+                debug!("{}", e);
+                return future::Either::A(future::err(e.into()));
+            }
+        }
+
+        future::Either::B(self.inner.call(req).map_err(Into::into))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::{Layer as _, Service as _};
+    use crate::transport::tls;
+    use crate::{dns, identity, Never};
+    use regex::Regex;
+    use std::convert::TryFrom;
+    use std::net::SocketAddr;
+
+    const WEB: &str = "web.ns.serviceaccount.identity.linkerd.cluster.local";
+    const ADMIN: &str = "admin.ops.serviceaccount.identity.linkerd.cluster.local";
+
+    fn path(re: &str) -> RequestMatch {
+        RequestMatch::Path(Regex::new(re).unwrap())
+    }
+
+    fn suffix(s: &str) -> authz::Match {
+        authz::Match::Suffix(dns::Suffix::try_from(s).unwrap())
+    }
+
+    /// Allows any client in the `ops` namespace to use `/admin` and any
+    /// authenticated client to use `/api`.
+    fn policy() -> Policy {
+        Policy::new(vec![
+            Rule::new(
+                "admin".into(),
+                path("^/admin(/.*)?$"),
+                vec![suffix("ops.serviceaccount.identity.linkerd.cluster.local")],
+            ),
+            Rule::new("api".into(), path("^/api(/.*)?$"), vec![suffix(".")]),
+        ])
+    }
+
+    fn request(method: http::Method, path: &str, client_id: Option<&str>) -> http::Request<()> {
+        let tls_peer = match client_id {
+            Some(id) => Conditional::Some(identity::Name::from_hostname(id.as_bytes()).unwrap()),
+            None => Conditional::None(tls::ReasonForNoPeerName::NotProvidedByRemote.into()),
+        };
+        let addr = SocketAddr::from(([127, 0, 0, 1], 4143));
+        let mut req = http::Request::builder()
+            .method(method)
+            .uri(path)
+            .body(())
+            .unwrap();
+        req.extensions_mut()
+            .insert(Source::for_test(addr, addr, None, tls_peer));
+        req
+    }
+
+    #[test]
+    fn first_matching_rule_authorizes() {
+        let billing = "billing.ns.serviceaccount.identity.linkerd.cluster.local";
+        let web = "web.ns.serviceaccount.identity.linkerd.cluster.local";
+
+        let charge = RequestMatch::All(vec![
+            RequestMatch::Method(http::Method::POST),
+            RequestMatch::Path(Regex::new("^/charge$").unwrap()),
+        ]);
+        let id = identity::Name::from_hostname(billing.as_bytes()).unwrap();
+        let policy = Policy::new(vec![Rule::new(
+            "charge".into(),
+            charge,
+            vec![authz::Match::Exact(id)],
+        )]);
+
+        let post = http::Method::POST;
+        assert!(policy
+            .authorize(&request(post.clone(), "/charge", Some(billing)))
+            .is_ok());
+        assert!(policy
+            .authorize(&request(post.clone(), "/charge", Some(web)))
+            .is_err());
+        assert!(policy
+            .authorize(&request(post.clone(), "/charge", None))
+            .is_err());
+        assert!(policy
+            .authorize(&request(post, "/refund", Some(web)))
+            .is_ok());
+        assert!(policy
+            .authorize(&request(http::Method::GET, "/charge", Some(web)))
+            .is_ok());
+
+        let rule = &policy.rules()[0];
+        assert_eq!(rule.allow_total.lock().unwrap().value(), 1);
+        assert_eq!(rule.deny_total.lock().unwrap().value(), 2);
+    }
+
+    #[test]
+    fn unmatched_requests_are_authorized() {
+        let policy = policy();
+        assert!(policy
+            .authorize(&request(http::Method::GET, "/", None))
+            .is_ok());
+        assert!(policy
+            .authorize(&request(http::Method::GET, "/administer", Some(WEB)))
+            .is_ok());
+
+        // Requests that match no rule are not counted.
+        for rule in policy.rules() {
+            assert_eq!(rule.allow_total.lock().unwrap().value(), 0);
+            assert_eq!(rule.deny_total.lock().unwrap().value(), 0);
+        }
+    }
+
+    #[test]
+    fn suffixes_and_wildcards_match_identities() {
+        let policy = policy();
+        let get = http::Method::GET;
+
+        assert!(policy
+            .authorize(&request(get.clone(), "/admin/users", Some(ADMIN)))
+            .is_ok());
+        assert!(policy
+            .authorize(&request(get.clone(), "/admin/users", Some(WEB)))
+            .is_err());
+        assert!(policy
+            .authorize(&request(get.clone(), "/api/users", Some(WEB)))
+            .is_ok());
+        assert!(policy
+            .authorize(&request(get, "/api/users", Some(ADMIN)))
+            .is_ok());
+    }
+
+    #[test]
+    fn unauthenticated_clients_are_not_authorized() {
+        let policy = policy();
+
+        // Even a wildcard requires an identity.
+        let e = policy
+            .authorize(&request(http::Method::GET, "/api", None))
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "unauthenticated client is not authorized by rule api"
+        );
+
+        // Requests without a source are treated as unauthenticated.
+        let req = http::Request::get("/api").body(()).unwrap();
+        assert!(policy.authorize(&req).is_err());
+
+        let e = policy
+            .authorize(&request(http::Method::GET, "/admin", Some(WEB)))
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!("client {} is not authorized by rule admin", WEB)
+        );
+    }
+
+    #[test]
+    fn fails_unauthorized_requests() {
+        let mut stack = layer(Some(policy())).layer(svc::mk(|_: ()| {
+            future::ok::<_, Never>(svc::mk(|_: http::Request<()>| {
+                future::ok::<_, Never>(http::Response::new(()))
+            }))
+        }));
+        let mut svc = stack.call(()).wait().unwrap();
+
+        let e = svc
+            .call(request(http::Method::GET, "/admin", Some(WEB)))
+            .wait()
+            .unwrap_err();
+        assert!(e.downcast_ref::<Unauthorized>().is_some());
+        assert!(svc
+            .call(request(http::Method::GET, "/admin", Some(ADMIN)))
+            .wait()
+            .is_ok());
+    }
+
+    #[test]
+    fn reports_counts_by_rule() {
+        let policy = policy();
+        let report = Report::new(Some(policy.clone()));
+        let get = http::Method::GET;
+        let _ = policy.authorize(&request(get.clone(), "/admin", Some(ADMIN)));
+        let _ = policy.authorize(&request(get.clone(), "/admin", Some(WEB)));
+        let _ = policy.authorize(&request(get.clone(), "/api", Some(WEB)));
+        let _ = policy.authorize(&request(get.clone(), "/api", None));
+        let _ = policy.authorize(&request(get, "/", None));
+
+        assert_eq!(
+            report.as_display().to_string(),
+            "# HELP inbound_route_authz_allow_total Total count of inbound requests that were \
+             authorized by a rule\n\
+             # TYPE inbound_route_authz_allow_total counter\n\
+             inbound_route_authz_allow_total{rule=\"admin\"} 1\n\
+             inbound_route_authz_allow_total{rule=\"api\"} 1\n\
+             # HELP inbound_route_authz_deny_total Total count of inbound requests that were not \
+             authorized by a rule\n\
+             # TYPE inbound_route_authz_deny_total counter\n\
+             inbound_route_authz_deny_total{rule=\"admin\"} 1\n\
+             inbound_route_authz_deny_total{rule=\"api\"} 1\n",
+        );
+
+        assert_eq!(Report::default().as_display().to_string(), "");
+        assert_eq!(
+            Report::new(Some(Policy::new(vec![])))
+                .as_display()
+                .to_string(),
+            ""
+        );
+    }
+}
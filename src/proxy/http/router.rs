@@ -5,7 +5,7 @@ use crate::{Error, Never};
 use futures::Poll;
 use http;
 use linkerd2_router as rt;
-pub use linkerd2_router::{error, Recognize, Router};
+pub use linkerd2_router::{error, CacheKeys, Recognize, Router, RouterCacheKeys};
 use std::fmt;
 use std::marker::PhantomData;
 use std::time::Duration;
@@ -173,6 +173,18 @@ where
 }
 // === impl Service ===
 
+impl<Req, Rec, Mk> Service<Req, Rec, Mk>
+where
+    Rec: Recognize<Req>,
+    Mk: rt::Make<Rec::Target>,
+    Mk::Value: svc::Service<Req>,
+{
+    /// Returns a handle that lists the targets currently held by the router.
+    pub fn cache_keys(&self) -> RouterCacheKeys<Req, Rec, Mk> {
+        self.inner.cache_keys()
+    }
+}
+
 impl<Req, Rec, Mk, B> svc::Service<Req> for Service<Req, Rec, Mk>
 where
     Rec: Recognize<Req> + Send + Sync + 'static,
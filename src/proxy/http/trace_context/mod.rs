@@ -0,0 +1,697 @@
+//! Records spans for requests that carry a sampled trace context.
+//!
+//! When a request carries a sampled B3 or W3C `traceparent` context, the
+//! request's context is updated so that downstream spans are parented by a
+//! new proxy span. The span is completed when its response ends and is then
+//! handed to a `SpanSink` to be exported.
+
+use super::metrics::classify::{ClassifyEos, ClassifyResponse};
+use crate::{svc, Error};
+use futures::{try_ready, Async, Future, Poll};
+use http;
+use hyper::body::Payload;
+use indexmap::IndexMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{debug, trace};
+
+mod propagation;
+
+pub type Labels = IndexMap<String, String>;
+
+/// An opaque trace or span identifier.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Id(Vec<u8>);
+
+/// Trace flags, as defined by the W3C trace context specification.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Flags(u8);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Server,
+    Client,
+}
+
+/// A completed proxy span.
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub trace_id: Id,
+    pub span_id: Id,
+    pub parent_id: Id,
+    pub span_name: String,
+    pub kind: Kind,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub labels: Labels,
+}
+
+/// Receives completed spans.
+pub trait SpanSink {
+    /// Attempts to send a span without blocking.
+    fn try_send(&mut self, span: Span) -> Result<(), Error>;
+}
+
+/// Describes a stack target or a response classification as span labels.
+pub trait SpanLabels {
+    fn span_labels(&self, labels: &mut Labels);
+}
+
+#[derive(Debug)]
+pub struct Layer<S, C> {
+    kind: Kind,
+    sink: Option<S>,
+    _p: PhantomData<fn() -> C>,
+}
+
+#[derive(Debug)]
+pub struct MakeSvc<M, S, C> {
+    kind: Kind,
+    sink: Option<S>,
+    inner: M,
+    _p: PhantomData<fn() -> C>,
+}
+
+pub struct MakeFuture<F, S, C> {
+    kind: Kind,
+    sink: Option<S>,
+    labels: Option<Arc<Labels>>,
+    inner: F,
+    _p: PhantomData<fn() -> C>,
+}
+
+#[derive(Debug)]
+pub struct Service<T, S, C> {
+    kind: Kind,
+    sink: Option<S>,
+    labels: Arc<Labels>,
+    inner: T,
+    _p: PhantomData<fn() -> C>,
+}
+
+pub struct ResponseFuture<F, S, C> {
+    span: Option<(Span, S, C)>,
+    inner: F,
+}
+
+pub struct ResponseBody<B, S, C>
+where
+    S: SpanSink,
+    C: ClassifyEos,
+    C::Class: SpanLabels,
+{
+    span: Option<(Span, S, C)>,
+    inner: B,
+}
+
+// === impl Id ===
+
+impl Id {
+    /// Generates a random 8-byte span id.
+    pub fn new_span_id() -> Self {
+        use rand::Rng;
+
+        let mut bytes = vec![0; 8];
+        rand::thread_rng().fill(&mut bytes[..]);
+        Id(bytes)
+    }
+
+    /// Parses a hex-encoded id of `len` bytes.
+    ///
+    /// Ids of the wrong length and ids that are entirely zero are invalid.
+    pub fn from_hex(s: &str, len: usize) -> Option<Self> {
+        if s.len() != len * 2 || !s.is_ascii() {
+            return None;
+        }
+
+        let bytes = (0..len)
+            .map(|i| u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        if bytes.iter().all(|b| *b == 0) {
+            return None;
+        }
+
+        Some(Id(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in &self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// === impl Flags ===
+
+impl Flags {
+    pub const SAMPLED: Flags = Flags(0x01);
+
+    pub fn is_sampled(&self) -> bool {
+        self.0 & Self::SAMPLED.0 == Self::SAMPLED.0
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}", self.0)
+    }
+}
+
+// === impl Span ===
+
+impl Span {
+    fn complete(mut self, class: impl SpanLabels, sink: &mut impl SpanSink) {
+        self.end = SystemTime::now();
+        class.span_labels(&mut self.labels);
+        trace!("span complete: {:?}", self);
+        if let Err(e) = sink.try_send(self) {
+            debug!("dropping span: {}", e);
+        }
+    }
+}
+
+// === impl Layer ===
+
+/// Records `kind` spans for sampled requests, if a `sink` is configured.
+pub fn layer<S, C>(kind: Kind, sink: Option<S>) -> Layer<S, C>
+where
+    S: SpanSink + Clone,
+    C: ClassifyResponse + Clone + Default + Send + Sync + 'static,
+    C::Class: SpanLabels,
+{
+    Layer {
+        kind,
+        sink,
+        _p: PhantomData,
+    }
+}
+
+impl<S: Clone, C> Clone for Layer<S, C> {
+    fn clone(&self) -> Self {
+        Layer {
+            kind: self.kind,
+            sink: self.sink.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<M, S: Clone, C> svc::Layer<M> for Layer<S, C> {
+    type Service = MakeSvc<M, S, C>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeSvc {
+            kind: self.kind,
+            sink: self.sink.clone(),
+            inner,
+            _p: PhantomData,
+        }
+    }
+}
+
+// === impl MakeSvc ===
+
+impl<M: Clone, S: Clone, C> Clone for MakeSvc<M, S, C> {
+    fn clone(&self) -> Self {
+        MakeSvc {
+            kind: self.kind,
+            sink: self.sink.clone(),
+            inner: self.inner.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<T, M, S, C> svc::Service<T> for MakeSvc<M, S, C>
+where
+    T: SpanLabels,
+    M: svc::Service<T>,
+    S: Clone,
+{
+    type Response = Service<M::Response, S, C>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future, S, C>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        // Labels are only needed when spans may be exported.
+        let labels = self.sink.as_ref().map(|_| {
+            let mut labels = Labels::new();
+            target.span_labels(&mut labels);
+            Arc::new(labels)
+        });
+
+        MakeFuture {
+            kind: self.kind,
+            sink: self.sink.clone(),
+            labels,
+            inner: self.inner.call(target),
+            _p: PhantomData,
+        }
+    }
+}
+
+// === impl MakeFuture ===
+
+impl<F, S, C> Future for MakeFuture<F, S, C>
+where
+    F: Future,
+    S: Clone,
+{
+    type Item = Service<F::Item, S, C>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Async::Ready(Service {
+            kind: self.kind,
+            sink: self.sink.clone(),
+            labels: self.labels.take().unwrap_or_default(),
+            inner,
+            _p: PhantomData,
+        }))
+    }
+}
+
+// === impl Service ===
+
+impl<T: Clone, S: Clone, C> Clone for Service<T, S, C> {
+    fn clone(&self) -> Self {
+        Service {
+            kind: self.kind,
+            sink: self.sink.clone(),
+            labels: self.labels.clone(),
+            inner: self.inner.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<T, S, C, A, B> svc::Service<http::Request<A>> for Service<T, S, C>
+where
+    T: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    T::Error: Into<Error>,
+    S: SpanSink + Clone,
+    C: ClassifyResponse + Clone + Default + Send + Sync + 'static,
+    C::Class: SpanLabels,
+{
+    type Response = http::Response<ResponseBody<B, S, C::ClassifyEos>>;
+    type Error = Error;
+    type Future = ResponseFuture<T::Future, S, C>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        let span = self.sink.as_ref().and_then(|sink| {
+            let context = propagation::unpack_trace_context(&req)?;
+            if !context.flags.is_sampled() {
+                trace!("trace context not sampled");
+                return None;
+            }
+
+            let span_id = propagation::increment_span_id(&mut req, &context);
+
+            let mut labels = (*self.labels).clone();
+            labels.insert("http.method".into(), req.method().to_string());
+            if let Some(authority) = req.uri().authority_part() {
+                labels.insert("http.authority".into(), authority.to_string());
+            }
+            labels.insert("http.path".into(), req.uri().path().to_owned());
+
+            let now = SystemTime::now();
+            let span = Span {
+                trace_id: context.trace_id,
+                span_id,
+                parent_id: context.parent_id,
+                span_name: req.uri().path().to_owned(),
+                kind: self.kind,
+                start: now,
+                end: now,
+                labels,
+            };
+            debug!(
+                "recording span: trace={} span={}",
+                span.trace_id, span.span_id
+            );
+
+            let classify = req.extensions().get::<C>().cloned().unwrap_or_default();
+            Some((span, sink.clone(), classify))
+        });
+
+        ResponseFuture {
+            span,
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, S, C, B> Future for ResponseFuture<F, S, C>
+where
+    F: Future<Item = http::Response<B>>,
+    F::Error: Into<Error>,
+    S: SpanSink,
+    C: ClassifyResponse,
+    C::Class: SpanLabels,
+{
+    type Item = http::Response<ResponseBody<B, S, C::ClassifyEos>>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = match self.inner.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(rsp)) => rsp,
+            Err(e) => {
+                let e = e.into();
+                if let Some((mut span, mut sink, classify)) = self.span.take() {
+                    span.labels.insert("error".into(), e.to_string());
+                    span.complete(classify.error(&e), &mut sink);
+                }
+                return Err(e);
+            }
+        };
+
+        let span = self.span.take().map(|(mut span, sink, classify)| {
+            span.labels
+                .insert("http.status_code".into(), rsp.status().as_str().to_owned());
+            (span, sink, classify.start(&rsp))
+        });
+        let (head, inner) = rsp.into_parts();
+        let body = ResponseBody { span, inner };
+        Ok(Async::Ready(http::Response::from_parts(head, body)))
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B, S, C> ResponseBody<B, S, C>
+where
+    S: SpanSink,
+    C: ClassifyEos,
+    C::Class: SpanLabels,
+{
+    fn complete(&mut self, trailers: Option<&http::HeaderMap>) {
+        if let Some((span, mut sink, classify)) = self.span.take() {
+            span.complete(classify.eos(trailers), &mut sink);
+        }
+    }
+
+    fn record_err(&mut self, err: Error) -> Error {
+        if let Some((mut span, mut sink, classify)) = self.span.take() {
+            span.labels.insert("error".into(), err.to_string());
+            span.complete(classify.error(&err), &mut sink);
+        }
+        err
+    }
+}
+
+impl<B, S, C> Payload for ResponseBody<B, S, C>
+where
+    B: Payload,
+    B::Error: Into<Error>,
+    S: SpanSink + Send + 'static,
+    C: ClassifyEos + Send + 'static,
+    C::Class: SpanLabels,
+{
+    type Data = B::Data;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        self.inner
+            .poll_data()
+            .map_err(|e| self.record_err(e.into()))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        let trailers = try_ready!(self
+            .inner
+            .poll_trailers()
+            .map_err(|e| self.record_err(e.into())));
+        self.complete(trailers.as_ref());
+        Ok(Async::Ready(trailers))
+    }
+}
+
+impl<B, S, C> http_body::Body for ResponseBody<B, S, C>
+where
+    B: Payload,
+    B::Error: Into<Error>,
+    S: SpanSink + Send + 'static,
+    C: ClassifyEos + Send + 'static,
+    C::Class: SpanLabels,
+{
+    type Data = B::Data;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        Payload::is_end_stream(self)
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        Payload::poll_data(self)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Payload::poll_trailers(self)
+    }
+}
+
+impl<B, S, C> Default for ResponseBody<B, S, C>
+where
+    B: Default,
+    S: SpanSink,
+    C: ClassifyEos,
+    C::Class: SpanLabels,
+{
+    fn default() -> Self {
+        Self {
+            span: None,
+            inner: B::default(),
+        }
+    }
+}
+
+impl<B, S, C> Drop for ResponseBody<B, S, C>
+where
+    S: SpanSink,
+    C: ClassifyEos,
+    C::Class: SpanLabels,
+{
+    fn drop(&mut self) {
+        self.complete(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::sync::Mutex;
+
+    const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+    const PARENT_ID: &str = "b7ad6b7169203331";
+
+    #[derive(Clone, Default)]
+    struct Sink(Arc<Mutex<Vec<Span>>>);
+
+    impl SpanSink for Sink {
+        fn try_send(&mut self, span: Span) -> Result<(), Error> {
+            self.0.lock().unwrap().push(span);
+            Ok(())
+        }
+    }
+
+    impl Sink {
+        fn spans(&self) -> Vec<Span> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    struct Classify;
+
+    #[derive(Debug)]
+    enum Class {
+        Eos,
+        Error,
+    }
+
+    impl ClassifyResponse for Classify {
+        type Class = Class;
+        type ClassifyEos = Self;
+
+        fn start<B>(self, _: &http::Response<B>) -> Self {
+            self
+        }
+
+        fn error(self, _: &Error) -> Class {
+            Class::Error
+        }
+    }
+
+    impl ClassifyEos for Classify {
+        type Class = Class;
+
+        fn eos(self, _: Option<&http::HeaderMap>) -> Class {
+            Class::Eos
+        }
+
+        fn error(self, _: &Error) -> Class {
+            Class::Error
+        }
+    }
+
+    impl SpanLabels for Class {
+        fn span_labels(&self, labels: &mut Labels) {
+            let class = match self {
+                Class::Eos => "eos",
+                Class::Error => "error",
+            };
+            labels.insert("classification".into(), class.into());
+        }
+    }
+
+    fn service<T>(sink: &Sink, inner: T) -> Service<T, Sink, Classify> {
+        let mut labels = Labels::new();
+        labels.insert("target".into(), "test".into());
+        Service {
+            kind: Kind::Server,
+            sink: Some(sink.clone()),
+            labels: Arc::new(labels),
+            inner,
+            _p: PhantomData,
+        }
+    }
+
+    fn request(traceparent: Option<String>) -> http::Request<()> {
+        let mut req = http::Request::get("http://foo.test/bar").body(()).unwrap();
+        if let Some(tp) = traceparent {
+            req.headers_mut().insert("traceparent", tp.parse().unwrap());
+        }
+        req
+    }
+
+    /// Responds successfully, recording the `traceparent` of each request.
+    fn ok_svc(
+        traceparents: Arc<Mutex<Vec<Option<String>>>>,
+    ) -> impl svc::Service<
+        http::Request<()>,
+        Response = http::Response<hyper::Body>,
+        Error = Error,
+        Future = future::FutureResult<http::Response<hyper::Body>, Error>,
+    > {
+        svc::mk(move |req: http::Request<()>| {
+            let tp = req
+                .headers()
+                .get("traceparent")
+                .map(|v| v.to_str().unwrap().to_owned());
+            traceparents.lock().unwrap().push(tp);
+            future::ok(http::Response::new(hyper::Body::empty()))
+        })
+    }
+
+    #[test]
+    fn records_spans_for_sampled_requests() {
+        let sink = Sink::default();
+        let traceparents = Arc::new(Mutex::new(Vec::new()));
+        let mut svc = service(&sink, ok_svc(traceparents.clone()));
+
+        let tp = format!("00-{}-{}-01", TRACE_ID, PARENT_ID);
+        let rsp = svc::Service::call(&mut svc, request(Some(tp)))
+            .wait()
+            .expect("response");
+        assert!(sink.spans().is_empty(), "span ends with the response");
+        drop(rsp);
+
+        let spans = sink.spans();
+        assert_eq!(spans.len(), 1);
+        let span = &spans[0];
+        assert_eq!(span.trace_id.to_string(), TRACE_ID);
+        assert_eq!(span.parent_id.to_string(), PARENT_ID);
+        assert_eq!(span.kind, Kind::Server);
+        assert_eq!(span.span_name, "/bar");
+        assert!(span.start <= span.end);
+        assert_eq!(span.labels["target"], "test");
+        assert_eq!(span.labels["http.method"], "GET");
+        assert_eq!(span.labels["http.authority"], "foo.test");
+        assert_eq!(span.labels["http.path"], "/bar");
+        assert_eq!(span.labels["http.status_code"], "200");
+        assert_eq!(span.labels["classification"], "eos");
+
+        // The inner service sees the proxy's span as the parent.
+        let propagated = traceparents.lock().unwrap()[0].clone();
+        assert_eq!(
+            propagated,
+            Some(format!("00-{}-{}-01", TRACE_ID, span.span_id))
+        );
+    }
+
+    #[test]
+    fn ignores_unsampled_requests() {
+        let sink = Sink::default();
+        let traceparents = Arc::new(Mutex::new(Vec::new()));
+        let mut svc = service(&sink, ok_svc(traceparents.clone()));
+
+        let unsampled = format!("00-{}-{}-00", TRACE_ID, PARENT_ID);
+        for req in vec![request(Some(unsampled.clone())), request(None)] {
+            let rsp = svc::Service::call(&mut svc, req).wait();
+            drop(rsp.expect("response"));
+        }
+
+        assert!(sink.spans().is_empty());
+        assert_eq!(
+            *traceparents.lock().unwrap(),
+            vec![Some(unsampled), None],
+            "trace context is not modified"
+        );
+    }
+
+    #[test]
+    fn records_errors() {
+        let sink = Sink::default();
+        let mut svc = service(
+            &sink,
+            svc::mk(|_: http::Request<()>| {
+                future::err::<http::Response<hyper::Body>, Error>("refused".into())
+            }),
+        );
+
+        let tp = format!("00-{}-{}-01", TRACE_ID, PARENT_ID);
+        let err = svc::Service::call(&mut svc, request(Some(tp)))
+            .wait()
+            .err()
+            .expect("must fail");
+        assert_eq!(err.to_string(), "refused");
+
+        let spans = sink.spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].labels["error"], "refused");
+        assert_eq!(spans[0].labels["classification"], "error");
+        assert!(!spans[0].labels.contains_key("http.status_code"));
+    }
+}
@@ -0,0 +1,207 @@
+use super::{Flags, Id};
+use http::header::{HeaderName, HeaderValue};
+use tracing::{debug, warn};
+
+const TRACE_PARENT: &str = "traceparent";
+const B3_TRACE_ID: &str = "x-b3-traceid";
+const B3_SPAN_ID: &str = "x-b3-spanid";
+const B3_PARENT_SPAN_ID: &str = "x-b3-parentspanid";
+const B3_SAMPLED: &str = "x-b3-sampled";
+const B3_FLAGS: &str = "x-b3-flags";
+
+const W3C_VERSION: &str = "00";
+
+/// The trace context carried by a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    propagation: Propagation,
+    pub trace_id: Id,
+    pub parent_id: Id,
+    pub flags: Flags,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Propagation {
+    W3C,
+    B3,
+}
+
+/// Reads a trace context from a request's headers.
+///
+/// A W3C `traceparent` header is preferred to B3 headers.
+pub fn unpack_trace_context<B>(req: &http::Request<B>) -> Option<TraceContext> {
+    unpack_w3c_trace_context(req).or_else(|| unpack_b3_trace_context(req))
+}
+
+/// Replaces the parent span id carried by the request with a new span id,
+/// so that downstream spans are parented by the proxy's span.
+///
+/// Returns the new span id.
+pub fn increment_span_id<B>(req: &mut http::Request<B>, context: &TraceContext) -> Id {
+    let span_id = Id::new_span_id();
+    match context.propagation {
+        Propagation::W3C => {
+            let value = format!(
+                "{}-{}-{}-{}",
+                W3C_VERSION, context.trace_id, span_id, context.flags
+            );
+            set_header(req, TRACE_PARENT, value);
+        }
+        Propagation::B3 => {
+            set_header(req, B3_SPAN_ID, span_id.to_string());
+            set_header(req, B3_PARENT_SPAN_ID, context.parent_id.to_string());
+        }
+    }
+    span_id
+}
+
+fn set_header<B>(req: &mut http::Request<B>, name: &'static str, value: String) {
+    match HeaderValue::from_str(&value) {
+        Ok(value) => {
+            req.headers_mut()
+                .insert(HeaderName::from_static(name), value);
+        }
+        Err(e) => warn!("invalid {} header: {}", name, e),
+    }
+}
+
+fn get_header<B>(req: &http::Request<B>, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_owned())
+}
+
+fn unpack_w3c_trace_context<B>(req: &http::Request<B>) -> Option<TraceContext> {
+    let header = get_header(req, TRACE_PARENT)?;
+    let parts = header.split('-').collect::<Vec<_>>();
+    if parts.len() != 4 || parts[0] != W3C_VERSION {
+        debug!("unsupported traceparent: {}", header);
+        return None;
+    }
+
+    let trace_id = Id::from_hex(parts[1], 16)?;
+    let parent_id = Id::from_hex(parts[2], 8)?;
+    let flags = u8::from_str_radix(parts[3], 16).ok().map(Flags)?;
+    Some(TraceContext {
+        propagation: Propagation::W3C,
+        trace_id,
+        parent_id,
+        flags,
+    })
+}
+
+fn unpack_b3_trace_context<B>(req: &http::Request<B>) -> Option<TraceContext> {
+    let trace_id = get_header(req, B3_TRACE_ID)?;
+    let trace_id = if trace_id.len() == 16 {
+        Id::from_hex(&trace_id, 8)?
+    } else {
+        Id::from_hex(&trace_id, 16)?
+    };
+    let parent_id = Id::from_hex(&get_header(req, B3_SPAN_ID)?, 8)?;
+
+    // A debug flag implies that the request is sampled.
+    let debug = get_header(req, B3_FLAGS).map(|f| f == "1").unwrap_or(false);
+    let sampled = get_header(req, B3_SAMPLED)
+        .map(|s| s == "1" || s == "true")
+        .unwrap_or(false);
+    let flags = if debug || sampled {
+        Flags::SAMPLED
+    } else {
+        Flags::default()
+    };
+
+    Some(TraceContext {
+        propagation: Propagation::B3,
+        trace_id,
+        parent_id,
+        flags,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+    const SPAN_ID: &str = "b7ad6b7169203331";
+
+    fn request(headers: &[(&'static str, &str)]) -> http::Request<()> {
+        let mut req = http::Request::new(());
+        for (name, value) in headers {
+            req.headers_mut()
+                .insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        req
+    }
+
+    #[test]
+    fn w3c_trace_context() {
+        let traceparent = format!("00-{}-{}-01", TRACE_ID, SPAN_ID);
+        let mut req = request(&[(TRACE_PARENT, traceparent.as_str())]);
+
+        let ctx = unpack_trace_context(&req).expect("traceparent must be parsed");
+        assert_eq!(ctx.trace_id.to_string(), TRACE_ID);
+        assert_eq!(ctx.parent_id.to_string(), SPAN_ID);
+        assert!(ctx.flags.is_sampled());
+
+        let span_id = increment_span_id(&mut req, &ctx);
+        assert_ne!(span_id.to_string(), SPAN_ID);
+        assert_eq!(
+            req.headers()[TRACE_PARENT],
+            format!("00-{}-{}-01", TRACE_ID, span_id).as_str()
+        );
+    }
+
+    #[test]
+    fn w3c_unsampled_and_invalid() {
+        let traceparent = format!("00-{}-{}-00", TRACE_ID, SPAN_ID);
+        let ctx = unpack_trace_context(&request(&[(TRACE_PARENT, traceparent.as_str())])).unwrap();
+        assert!(!ctx.flags.is_sampled());
+
+        for invalid in &[
+            format!("01-{}-{}-01", TRACE_ID, SPAN_ID),
+            format!("00-{}-{}-01", SPAN_ID, SPAN_ID),
+            format!("00-{}-{}-01", TRACE_ID, "0000000000000000"),
+            format!("00-{}-01", TRACE_ID),
+        ] {
+            assert_eq!(
+                unpack_trace_context(&request(&[(TRACE_PARENT, invalid.as_str())])),
+                None,
+                "{} must not be parsed",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn b3_trace_context() {
+        let mut req = request(&[
+            (B3_TRACE_ID, SPAN_ID),
+            (B3_SPAN_ID, SPAN_ID),
+            (B3_SAMPLED, "1"),
+        ]);
+
+        let ctx = unpack_trace_context(&req).expect("b3 headers must be parsed");
+        assert_eq!(ctx.trace_id.to_string(), SPAN_ID);
+        assert!(ctx.flags.is_sampled());
+
+        let span_id = increment_span_id(&mut req, &ctx);
+        assert_eq!(req.headers()[B3_SPAN_ID], span_id.to_string().as_str());
+        assert_eq!(req.headers()[B3_PARENT_SPAN_ID], SPAN_ID);
+        assert_eq!(req.headers()[B3_TRACE_ID], SPAN_ID);
+    }
+
+    #[test]
+    fn b3_sampling() {
+        let unsampled = request(&[(B3_TRACE_ID, TRACE_ID), (B3_SPAN_ID, SPAN_ID)]);
+        assert!(!unpack_trace_context(&unsampled).unwrap().flags.is_sampled());
+
+        let debug = request(&[
+            (B3_TRACE_ID, TRACE_ID),
+            (B3_SPAN_ID, SPAN_ID),
+            (B3_FLAGS, "1"),
+        ]);
+        assert!(unpack_trace_context(&debug).unwrap().flags.is_sampled());
+    }
+}
@@ -0,0 +1,479 @@
+//! Weights endpoints by the relative weights provided by service discovery.
+//!
+//! Each endpoint service is wrapped so that its weight may be read by the
+//! balancer. Because the balancer's load-measuring wrappers hide endpoint
+//! services, `WeightedDiscover` also records each endpoint's weight in its
+//! `Discover` key.
+//!
+//! Endpoint weights are reported as gauges until their services have been
+//! dropped and they have not been updated for `retain_idle`.
+
+use crate::metrics::{metrics, FmtLabels, FmtMetric, FmtMetrics, Gauge};
+use crate::svc;
+use futures::{try_ready, Async, Future, Poll};
+use indexmap::IndexMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_timer::clock;
+use tower_discover::{Change, Discover};
+
+metrics! {
+    endpoint_weight: Gauge {
+        "The relative weight of an endpoint in its load balancer, where 10000 is the default"
+    }
+}
+
+/// The weight of an endpoint for which service discovery provides none.
+pub const DEFAULT_WEIGHT: u32 = 10_000;
+
+/// Implemented by endpoint targets and services that have a relative weight.
+///
+/// An endpoint with a weight of 0 should never be preferred over an endpoint
+/// with a non-zero weight.
+pub trait HasWeight {
+    fn weight(&self) -> u32;
+}
+
+/// Builds a registry whose weights are evicted once they have been idle for
+/// `retain_idle` and no endpoint service refers to them.
+pub fn new<K: Hash + Eq>(retain_idle: Duration) -> (Registry<K>, Report<K>) {
+    let by_key = Arc::new(Mutex::new(IndexMap::new()));
+    let report = Report {
+        by_key: by_key.clone(),
+        retain_idle,
+    };
+    (Registry(by_key), report)
+}
+
+type ByKey<K> = IndexMap<K, Arc<Weight>>;
+
+/// Holds the most recent weight of each endpoint.
+#[derive(Debug)]
+pub struct Registry<K: Hash + Eq>(Arc<Mutex<ByKey<K>>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted endpoint weights.
+#[derive(Debug)]
+pub struct Report<K: Hash + Eq> {
+    by_key: Arc<Mutex<ByKey<K>>>,
+    retain_idle: Duration,
+}
+
+#[derive(Debug)]
+struct Weight {
+    weight: u32,
+    last_update: Instant,
+}
+
+/// Wraps endpoint services so that their weights may be read.
+#[derive(Debug)]
+pub struct Layer<K: Hash + Eq> {
+    registry: Registry<K>,
+}
+
+#[derive(Debug)]
+pub struct Stack<M, K: Hash + Eq> {
+    registry: Registry<K>,
+    inner: M,
+}
+
+pub struct MakeFuture<F> {
+    weight: Arc<Weight>,
+    inner: F,
+}
+
+/// An endpoint service that is labeled with its weight.
+///
+/// The endpoint's weight is retained in the registry while the service
+/// exists.
+#[derive(Clone, Debug)]
+pub struct Weighted<S> {
+    weight: Arc<Weight>,
+    inner: S,
+}
+
+/// A `Discover` that records each endpoint's weight in its key.
+pub struct WeightedDiscover<D>(D);
+
+/// A `Discover` key with an endpoint's weight.
+///
+/// Keys are compared and hashed without their weights, so that a changed
+/// weight replaces an endpoint's prior service.
+#[derive(Clone, Debug)]
+pub struct WeightedKey<K> {
+    key: K,
+    weight: u32,
+}
+
+// === impl Registry ===
+
+impl<K: Hash + Eq> Registry<K> {
+    fn set(&self, key: K, weight: u32) -> Arc<Weight> {
+        let weight = Arc::new(Weight {
+            weight,
+            last_update: clock::now(),
+        });
+
+        // Metrics are not recorded if the registry is poisoned.
+        if let Ok(mut by_key) = self.0.lock() {
+            by_key.insert(key, weight.clone());
+        }
+
+        weight
+    }
+}
+
+impl<K: Hash + Eq> Clone for Registry<K> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+// === impl Report ===
+
+impl<K: Hash + Eq> Clone for Report<K> {
+    fn clone(&self) -> Self {
+        Report {
+            by_key: self.by_key.clone(),
+            retain_idle: self.retain_idle,
+        }
+    }
+}
+
+impl<K: FmtLabels + Hash + Eq> FmtMetrics for Report<K> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut inner = match self.by_key.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if let Some(epoch) = clock::now().checked_sub(self.retain_idle) {
+            retain_since(&mut inner, epoch);
+        }
+
+        if inner.is_empty() {
+            return Ok(());
+        }
+
+        endpoint_weight.fmt_help(f)?;
+        for (key, w) in inner.iter() {
+            let gauge = Gauge::from(u64::from(w.weight));
+            gauge.fmt_metric_labeled(f, endpoint_weight.name, key)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Retains the weights of endpoints that (1) still have a service or (2) have
+/// been updated since `epoch`.
+fn retain_since<K: Hash + Eq>(by_key: &mut ByKey<K>, epoch: Instant) {
+    by_key.retain(|_, w| Arc::strong_count(&w) > 1 || w.last_update >= epoch)
+}
+
+// === impl Layer ===
+
+pub fn layer<K: Hash + Eq>(registry: Registry<K>) -> Layer<K> {
+    Layer { registry }
+}
+
+impl<K: Hash + Eq> Clone for Layer<K> {
+    fn clone(&self) -> Self {
+        Layer {
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<M, K: Hash + Eq> svc::Layer<M> for Layer<K> {
+    type Service = Stack<M, K>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        Stack {
+            registry: self.registry.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M: Clone, K: Hash + Eq> Clone for Stack<M, K> {
+    fn clone(&self) -> Self {
+        Stack {
+            registry: self.registry.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T, M, K> svc::Service<T> for Stack<M, K>
+where
+    T: HasWeight + Clone,
+    K: Hash + Eq + From<T>,
+    M: svc::Service<T>,
+{
+    type Response = Weighted<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let weight = self.registry.set(target.clone().into(), target.weight());
+
+        MakeFuture {
+            weight,
+            inner: self.inner.call(target),
+        }
+    }
+}
+
+impl<F: Future> Future for MakeFuture<F> {
+    type Item = Weighted<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Async::Ready(Weighted {
+            weight: self.weight.clone(),
+            inner,
+        }))
+    }
+}
+
+// === impl Weighted ===
+
+impl<S> HasWeight for Weighted<S> {
+    fn weight(&self) -> u32 {
+        self.weight.weight
+    }
+}
+
+impl<S, Req> svc::Service<Req> for Weighted<S>
+where
+    S: svc::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+// === impl WeightedDiscover ===
+
+impl<D> WeightedDiscover<D> {
+    pub fn new(inner: D) -> Self {
+        WeightedDiscover(inner)
+    }
+}
+
+impl<D> Discover for WeightedDiscover<D>
+where
+    D: Discover,
+    D::Service: HasWeight,
+{
+    type Key = WeightedKey<D::Key>;
+    type Service = D::Service;
+    type Error = D::Error;
+
+    fn poll(&mut self) -> Poll<Change<Self::Key, Self::Service>, Self::Error> {
+        let change = match try_ready!(self.0.poll()) {
+            Change::Insert(key, svc) => {
+                let weight = svc.weight();
+                Change::Insert(WeightedKey { key, weight }, svc)
+            }
+            Change::Remove(key) => Change::Remove(WeightedKey {
+                key,
+                weight: DEFAULT_WEIGHT,
+            }),
+        };
+
+        Ok(Async::Ready(change))
+    }
+}
+
+// === impl WeightedKey ===
+
+impl<K> HasWeight for WeightedKey<K> {
+    fn weight(&self) -> u32 {
+        self.weight
+    }
+}
+
+impl<K: PartialEq> PartialEq for WeightedKey<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Eq> Eq for WeightedKey<K> {}
+
+impl<K: Hash> Hash for WeightedKey<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::{Layer as _, Service as _};
+    use crate::Never;
+    use futures::future;
+    use std::collections::VecDeque;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct Addr(&'static str);
+
+    #[derive(Clone, Debug)]
+    struct Target(&'static str, u32);
+
+    /// Yields each of a list of changes once.
+    struct Changes(VecDeque<Change<Addr, Weighted<()>>>);
+
+    impl FmtLabels for Addr {
+        fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "addr=\"{}\"", self.0)
+        }
+    }
+
+    impl From<Target> for Addr {
+        fn from(Target(addr, _): Target) -> Self {
+            Addr(addr)
+        }
+    }
+
+    impl HasWeight for Target {
+        fn weight(&self) -> u32 {
+            self.1
+        }
+    }
+
+    impl Discover for Changes {
+        type Key = Addr;
+        type Service = Weighted<()>;
+        type Error = Never;
+
+        fn poll(&mut self) -> Poll<Change<Self::Key, Self::Service>, Self::Error> {
+            Ok(self
+                .0
+                .pop_front()
+                .map(Async::Ready)
+                .unwrap_or(Async::NotReady))
+        }
+    }
+
+    fn make(registry: &Registry<Addr>, target: Target) -> Weighted<()> {
+        layer(registry.clone())
+            .layer(svc::mk(|_: Target| future::ok::<(), Never>(())))
+            .call(target)
+            .wait()
+            .unwrap()
+    }
+
+    #[test]
+    fn labels_services_with_their_weights() {
+        let (registry, _) = new(Duration::from_secs(60));
+        assert_eq!(make(&registry, Target("a", 0)).weight(), 0);
+        assert_eq!(make(&registry, Target("b", 20_000)).weight(), 20_000);
+    }
+
+    #[test]
+    fn reports_the_latest_weight_of_each_endpoint() {
+        let (registry, report) = new(Duration::from_secs(60));
+        let _a = make(&registry, Target("a", DEFAULT_WEIGHT));
+        let _b = make(&registry, Target("b", 5_000));
+        let _a = make(&registry, Target("a", 0));
+
+        assert_eq!(
+            report.as_display().to_string(),
+            "# HELP endpoint_weight The relative weight of an endpoint in its load balancer, \
+             where 10000 is the default\n\
+             # TYPE endpoint_weight gauge\n\
+             endpoint_weight{addr=\"a\"} 0\n\
+             endpoint_weight{addr=\"b\"} 5000\n",
+        );
+    }
+
+    #[test]
+    fn evicts_idle_weights() {
+        let (registry, report) = new(Duration::from_secs(60));
+
+        let before_update = clock::now();
+        let svc = make(&registry, Target("a", DEFAULT_WEIGHT));
+        let after_update = clock::now() + Duration::from_secs(1);
+
+        let mut by_key = report.by_key.lock().unwrap();
+        retain_since(&mut by_key, after_update);
+        assert_eq!(
+            by_key.len(),
+            1,
+            "weights should not be evicted while in use"
+        );
+
+        drop(svc);
+        retain_since(&mut by_key, before_update);
+        assert_eq!(
+            by_key.len(),
+            1,
+            "weights should not be evicted while active"
+        );
+
+        retain_since(&mut by_key, after_update);
+        assert_eq!(by_key.len(), 0, "idle weights should be evicted");
+    }
+
+    #[test]
+    fn discovered_keys_carry_service_weights() {
+        let (registry, _) = new(Duration::from_secs(60));
+        let mut discover = WeightedDiscover::new(Changes(
+            vec![
+                Change::Insert(Addr("a"), make(&registry, Target("a", 5_000))),
+                Change::Insert(Addr("a"), make(&registry, Target("a", 0))),
+                Change::Remove(Addr("a")),
+            ]
+            .into_iter()
+            .collect(),
+        ));
+
+        let inserted = match discover.poll() {
+            Ok(Async::Ready(Change::Insert(key, svc))) => {
+                assert_eq!(key.key, Addr("a"));
+                assert_eq!(key.weight(), 5_000);
+                assert_eq!(svc.weight(), 5_000);
+                key
+            }
+            _ => panic!("expected an insert"),
+        };
+
+        match discover.poll() {
+            Ok(Async::Ready(Change::Insert(key, svc))) => {
+                assert_eq!(key.weight(), 0);
+                assert_eq!(svc.weight(), 0);
+                assert_eq!(
+                    key, inserted,
+                    "a reweighted endpoint must replace its prior service"
+                );
+            }
+            _ => panic!("expected an insert"),
+        }
+
+        match discover.poll() {
+            Ok(Async::Ready(Change::Remove(key))) => assert_eq!(key, inserted),
+            _ => panic!("expected a removal"),
+        }
+
+        assert!(discover.poll().unwrap().is_not_ready());
+    }
+}
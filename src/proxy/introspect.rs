@@ -0,0 +1,80 @@
+//! Tracks live proxy state so that it may be reported for debugging.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+
+/// Tracks values that are owned elsewhere in the proxy.
+///
+/// Values are held weakly, so they are no longer reported once their owners
+/// drop them.
+pub struct Registry<T>(Arc<Mutex<Vec<Weak<T>>>>);
+
+// === impl Registry ===
+
+impl<T> Registry<T> {
+    pub fn register(&self, value: &Arc<T>) {
+        if let Ok(mut values) = self.0.lock() {
+            values.retain(|v| v.upgrade().is_some());
+            values.push(Arc::downgrade(value));
+        }
+    }
+
+    /// Returns all registered values that are still live.
+    ///
+    /// If the registry's lock is poisoned, no values are returned.
+    pub fn live(&self) -> Vec<Arc<T>> {
+        let mut values = match self.0.lock() {
+            Ok(values) => values,
+            Err(_) => return Vec::new(),
+        };
+        let mut live = Vec::with_capacity(values.len());
+        values.retain(|v| match v.upgrade() {
+            Some(v) => {
+                live.push(v);
+                true
+            }
+            None => false,
+        });
+        live
+    }
+}
+
+impl<T> Clone for Registry<T> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Self {
+        Registry(Arc::new(Mutex::new(Vec::new())))
+    }
+}
+
+impl<T> fmt::Debug for Registry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_values_are_not_live() {
+        let registry = Registry::default();
+        let a = Arc::new(1);
+        let b = Arc::new(2);
+        registry.register(&a);
+        registry.register(&b);
+
+        let live = registry.live();
+        assert_eq!(live.iter().map(|v| **v).collect::<Vec<_>>(), vec![1, 2]);
+        drop(live);
+
+        drop(a);
+        let live = registry.live();
+        assert_eq!(live.iter().map(|v| **v).collect::<Vec<_>>(), vec![2]);
+    }
+}
@@ -1,15 +1,17 @@
 //! Tools for building a transparent TCP/HTTP proxy.
 
 pub mod accept;
+pub mod authz;
 pub mod buffer;
 pub mod grpc;
 pub mod http;
+pub mod introspect;
 pub mod pending;
 mod protocol;
 pub mod reconnect;
 pub mod resolve;
 pub mod server;
-mod tcp;
+pub mod tcp;
 
 pub use self::accept::Accept;
 pub use self::server::{Server, Source};
@@ -1,34 +1,55 @@
+use super::introspect;
 use crate::core::resolve::{Resolution, Resolve, Update};
 use crate::{svc, Error};
 use futures::{stream::FuturesUnordered, try_ready, Async, Future, Poll, Stream};
 use indexmap::IndexMap;
+use std::sync::{Arc, Mutex};
 use std::{fmt, net::SocketAddr};
 use tokio::sync::oneshot;
 pub use tower_discover::Change;
 use tracing::trace;
 
+/// Tracks the endpoints of active resolutions.
+pub type Resolutions = introspect::Registry<Mutex<Resolved>>;
+
 #[derive(Clone, Debug)]
 pub struct Layer<R> {
     resolve: R,
+    resolutions: Option<Resolutions>,
 }
 
 #[derive(Clone, Debug)]
 pub struct MakeSvc<R, M> {
     resolve: R,
+    resolutions: Option<Resolutions>,
     inner: M,
 }
 
+/// Exposes an endpoint's metadata labels so that resolutions may be reported.
+pub trait EndpointLabels {
+    fn endpoint_labels(&self) -> Option<&IndexMap<String, String>>;
+}
+
+/// The endpoints most recently resolved for a target.
+#[derive(Clone, Debug)]
+pub struct Resolved {
+    pub target: String,
+    pub endpoints: IndexMap<SocketAddr, IndexMap<String, String>>,
+}
+
 /// Observes an `R`-typed resolution stream, using an `M`-typed endpoint stack to
 /// build a service for each endpoint.
 pub struct Discover<R: Resolution, M: svc::Service<R::Endpoint>> {
     resolution: R,
     make: M,
     make_futures: MakeFutures<M::Future>,
+    resolved: Option<Arc<Mutex<Resolved>>>,
 }
 
 pub struct DiscoverFuture<F, M> {
     future: F,
     make: M,
+    resolved: Option<Arc<Mutex<Resolved>>>,
 }
 
 struct MakeFutures<F> {
@@ -54,7 +75,20 @@ where
     R: Resolve<T> + Clone,
     R::Endpoint: fmt::Debug,
 {
-    Layer { resolve }
+    Layer {
+        resolve,
+        resolutions: None,
+    }
+}
+
+impl<R> Layer<R> {
+    /// Reports the endpoints of each resolution to `resolutions`.
+    pub fn with_resolutions(self, resolutions: Resolutions) -> Self {
+        Self {
+            resolutions: Some(resolutions),
+            ..self
+        }
+    }
 }
 
 impl<R, M> svc::Layer<M> for Layer<R>
@@ -66,6 +100,7 @@ where
     fn layer(&self, inner: M) -> Self::Service {
         MakeSvc {
             resolve: self.resolve.clone(),
+            resolutions: self.resolutions.clone(),
             inner,
         }
     }
@@ -75,6 +110,7 @@ where
 
 impl<T, R, M> svc::Service<T> for MakeSvc<R, M>
 where
+    T: fmt::Display,
     R: Resolve<T>,
     R::Endpoint: fmt::Debug,
     M: svc::Service<R::Endpoint> + Clone,
@@ -89,9 +125,18 @@ where
 
     fn call(&mut self, target: T) -> Self::Future {
         let future = self.resolve.resolve(&target);
+        let resolved = self.resolutions.as_ref().map(|resolutions| {
+            let resolved = Arc::new(Mutex::new(Resolved {
+                target: target.to_string(),
+                endpoints: IndexMap::new(),
+            }));
+            resolutions.register(&resolved);
+            resolved
+        });
         DiscoverFuture {
             future,
             make: Some(self.inner.clone()),
+            resolved,
         }
     }
 }
@@ -110,7 +155,10 @@ where
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         let resolution = try_ready!(self.future.poll());
         let make = self.make.take().expect("polled after ready");
-        Ok(Async::Ready(Discover::new(resolution, make)))
+        Ok(Async::Ready(Discover {
+            resolved: self.resolved.take(),
+            ..Discover::new(resolution, make)
+        }))
     }
 }
 
@@ -126,6 +174,7 @@ where
             resolution,
             make,
             make_futures: MakeFutures::new(),
+            resolved: None,
         }
     }
 }
@@ -133,7 +182,7 @@ where
 impl<R, M> Discover<R, M>
 where
     R: Resolution,
-    R::Endpoint: fmt::Debug,
+    R::Endpoint: EndpointLabels + fmt::Debug,
     R::Error: Into<Error>,
     M: svc::Service<R::Endpoint>,
     M::Error: Into<Error>,
@@ -149,12 +198,21 @@ where
             trace!("watch: {:?}", update);
             match update {
                 Update::Add(addr, target) => {
+                    if let Some(Ok(mut resolved)) = self.resolved.as_ref().map(|r| r.lock()) {
+                        let labels = target.endpoint_labels().cloned().unwrap_or_default();
+                        resolved.endpoints.insert(addr, labels);
+                    }
+
                     // Start building the service and continue. If a pending
                     // service exists for this addr, it will be canceled.
                     let fut = self.make.call(target);
                     self.make_futures.push(addr, fut);
                 }
                 Update::Remove(addr) => {
+                    if let Some(Ok(mut resolved)) = self.resolved.as_ref().map(|r| r.lock()) {
+                        resolved.endpoints.remove(&addr);
+                    }
+
                     self.make_futures.remove(&addr);
                     return Ok(Async::Ready(Change::Remove(addr)));
                 }
@@ -166,7 +224,7 @@ where
 impl<R, M> tower_discover::Discover for Discover<R, M>
 where
     R: Resolution,
-    R::Endpoint: fmt::Debug,
+    R::Endpoint: EndpointLabels + fmt::Debug,
     R::Error: Into<Error>,
     M: svc::Service<R::Endpoint>,
     M::Error: Into<Error>,
@@ -270,6 +328,12 @@ mod tests {
     use tower_discover::{Change, Discover as _Discover};
     use tower_util::service_fn;
 
+    impl EndpointLabels for () {
+        fn endpoint_labels(&self) -> Option<&IndexMap<String, String>> {
+            None
+        }
+    }
+
     struct Urx<E>(mpsc::Receiver<Update<E>>);
     impl<E> Resolution for Urx<E> {
         type Endpoint = E;
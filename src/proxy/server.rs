@@ -39,17 +39,15 @@ use tracing::{debug, trace};
 ///    buffered until the server can determine whether the streams begins with a
 ///    HTTP/1 or HTTP/2 preamble.
 ///
-/// 5. If the stream is not determined to be HTTP, then the original destination
-///    address is used to transparently forward the TCP stream. A `C`-typed
-///    `Connect` `Stack` is used to build a connection to the destination (i.e.,
-///    instrumented with telemetry, etc).
+/// 5. If the stream is not determined to be HTTP, then the TCP stream is
+///    forwarded transparently. A `C`-typed `Service` is used to build a
+///    connection for the `Source` (i.e., to its original destination via
+///    `ForwardConnect`, instrumented with telemetry, etc).
 ///
 /// 6. Otherwise, an `R`-typed `Service` `Stack` is used to build a service that
 ///    can route HTTP  requests for the `Source`.
-pub struct Server<A, T, C, H, B>
+pub struct Server<A, C, H, B>
 where
-    // Used when forwarding a TCP stream (e.g. with telemetry, timeouts).
-    T: From<SocketAddr>,
     // Prepares a route for each accepted HTTP connection.
     H: MakeService<
             Source,
@@ -63,7 +61,8 @@ where
     h2_settings: H2Settings,
     listen_addr: SocketAddr,
     accept: A,
-    connect: ForwardConnect<T, C>,
+    // Used when forwarding a TCP stream (e.g. with telemetry, timeouts).
+    connect: C,
     make_http: H,
     log: logging::Server,
 }
@@ -82,7 +81,7 @@ pub struct Source {
 ///
 /// Fails to produce a `Connect` if a `Source`'s `orig_dst` is None.
 #[derive(Debug)]
-struct ForwardConnect<T, C>(C, PhantomData<T>);
+pub struct ForwardConnect<T, C>(C, PhantomData<T>);
 
 /// An error indicating an accepted socket did not have an SO_ORIGINAL_DST
 /// address and therefore could not be forwarded.
@@ -147,6 +146,16 @@ impl fmt::Display for Source {
     }
 }
 
+impl<T, C> ForwardConnect<T, C>
+where
+    T: From<SocketAddr>,
+    C: Service<T>,
+{
+    pub fn new(connect: C) -> Self {
+        ForwardConnect(connect, PhantomData)
+    }
+}
+
 impl<T, C> Service<Source> for ForwardConnect<T, C>
 where
     T: From<SocketAddr>,
@@ -188,9 +197,8 @@ impl fmt::Display for NoOriginalDst {
     }
 }
 
-impl<A, T, C, H, B> Server<A, T, C, H, B>
+impl<A, C, H, B> Server<A, C, H, B>
 where
-    T: From<SocketAddr>,
     H: MakeService<
             Source,
             http::Request<HttpBody>,
@@ -209,7 +217,6 @@ where
         make_http: H,
         h2_settings: H2Settings,
     ) -> Self {
-        let connect = ForwardConnect(connect, PhantomData);
         let log = logging::Server::proxy(proxy_name, listen_addr);
         Self {
             http: hyper::server::conn::Http::new(),
@@ -223,15 +230,14 @@ where
     }
 }
 
-impl<A, T, C, H, B> ServeConnection<Connection> for Server<A, T, C, H, B>
+impl<A, C, H, B> ServeConnection<Connection> for Server<A, C, H, B>
 where
     A: Accept<Connection> + Send + 'static,
     A::Io: fmt::Debug + Send + Peek + 'static,
-    T: From<SocketAddr> + Send + 'static,
-    C: Service<T> + Clone + Send + 'static,
+    C: Service<Source> + Clone + Send + 'static,
     C::Response: AsyncRead + AsyncWrite + fmt::Debug + Send + 'static,
     C::Future: Send + 'static,
-    C::Error: Into<Error>,
+    C::Error: fmt::Debug,
     H: MakeService<
             Source,
             http::Request<HttpBody>,
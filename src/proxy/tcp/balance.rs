@@ -0,0 +1,260 @@
+use crate::{svc, Never};
+use bytes::Buf;
+use futures::{future, try_ready, Async, Future, Poll};
+use rand::{rngs::SmallRng, FromEntropy};
+use std::marker::PhantomData;
+use std::{fmt, io};
+use tokio::io::{AsyncRead, AsyncWrite};
+pub use tower_balance::p2c::Balance;
+use tower_discover::Discover;
+use tower_load::Instrument;
+pub use tower_load::PendingRequestsDiscover;
+
+/// Configures a stack to balance `Req`-typed connection requests over the
+/// endpoints discovered for each target.
+///
+/// Unlike the HTTP balancer, which estimates an endpoint's load from response
+/// latency, connections are balanced by the number of connections that are
+/// currently open to each endpoint.
+#[derive(Debug)]
+pub struct Layer<Req> {
+    rng: SmallRng,
+    _marker: PhantomData<fn(Req)>,
+}
+
+/// Resolves `T` typed targets to balance connections over endpoint services.
+#[derive(Debug)]
+pub struct MakeSvc<M, Req> {
+    inner: M,
+    rng: SmallRng,
+    _marker: PhantomData<fn(Req)>,
+}
+
+/// Instruments connections so that an endpoint's load handle is held until
+/// the connection is dropped.
+#[derive(Clone, Debug, Default)]
+pub struct PendingUntilClose(());
+
+/// A connection that holds its endpoint's load handle until it is dropped.
+pub struct Io<T, H> {
+    io: T,
+    _handle: H,
+}
+
+/// Builds an `Endpoint` service for each discovered endpoint.
+#[derive(Clone, Debug)]
+pub struct MakeEndpoint<C> {
+    connect: C,
+}
+
+/// Establishes connections to a single `T`-typed endpoint.
+///
+/// Requests carry no information about where to connect; the endpoint's
+/// target is used for every connection.
+#[derive(Clone, Debug)]
+pub struct Endpoint<C, T> {
+    connect: C,
+    target: T,
+}
+
+// === impl Layer ===
+
+pub fn layer<Req>() -> Layer<Req> {
+    Layer {
+        rng: SmallRng::from_entropy(),
+        _marker: PhantomData,
+    }
+}
+
+impl<Req> Clone for Layer<Req> {
+    fn clone(&self) -> Self {
+        Self {
+            rng: self.rng.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<M, Req> svc::Layer<M> for Layer<Req> {
+    type Service = MakeSvc<M, Req>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeSvc {
+            inner,
+            rng: self.rng.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+// === impl MakeSvc ===
+
+impl<M: Clone, Req> Clone for MakeSvc<M, Req> {
+    fn clone(&self) -> Self {
+        MakeSvc {
+            inner: self.inner.clone(),
+            rng: self.rng.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, M, Req> svc::Service<T> for MakeSvc<M, Req>
+where
+    M: svc::Service<T>,
+    M::Response: Discover,
+    <M::Response as Discover>::Service: svc::Service<Req>,
+    Balance<PendingRequestsDiscover<M::Response, PendingUntilClose>, Req>: svc::Service<Req>,
+{
+    type Response = Balance<PendingRequestsDiscover<M::Response, PendingUntilClose>, Req>;
+    type Error = M::Error;
+    type Future = MakeSvc<M::Future, Req>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let inner = self.inner.call(target);
+
+        MakeSvc {
+            inner,
+            rng: self.rng.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<F, Req> Future for MakeSvc<F, Req>
+where
+    F: Future,
+    F::Item: Discover,
+    <F::Item as Discover>::Service: svc::Service<Req>,
+    Balance<PendingRequestsDiscover<F::Item, PendingUntilClose>, Req>: svc::Service<Req>,
+{
+    type Item = Balance<PendingRequestsDiscover<F::Item, PendingUntilClose>, Req>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let discover = try_ready!(self.inner.poll());
+        let loaded = PendingRequestsDiscover::new(discover, PendingUntilClose::default());
+        let balance = Balance::new(loaded, self.rng.clone());
+        Ok(Async::Ready(balance))
+    }
+}
+
+// === impl PendingUntilClose ===
+
+impl<H, T> Instrument<H, T> for PendingUntilClose
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Output = Io<T, H>;
+
+    fn instrument(&self, handle: H, io: T) -> Self::Output {
+        Io {
+            io,
+            _handle: handle,
+        }
+    }
+}
+
+// === impl Io ===
+
+impl<T: io::Read, H> io::Read for Io<T, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.io.read(buf)
+    }
+}
+
+impl<T: io::Write, H> io::Write for Io<T, H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<T: AsyncRead, H> AsyncRead for Io<T, H> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.io.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<T: AsyncWrite, H> AsyncWrite for Io<T, H> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        self.io.write_buf(buf)
+    }
+}
+
+impl<T: fmt::Debug, H> fmt::Debug for Io<T, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Io").field("io", &self.io).finish()
+    }
+}
+
+// === impl MakeEndpoint ===
+
+pub fn endpoint<C>(connect: C) -> MakeEndpoint<C> {
+    MakeEndpoint { connect }
+}
+
+impl<C: Clone, T> svc::Service<T> for MakeEndpoint<C> {
+    type Response = Endpoint<C, T>;
+    type Error = Never;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into()) // always ready to make an Endpoint
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        future::ok(Endpoint {
+            connect: self.connect.clone(),
+            target,
+        })
+    }
+}
+
+// === impl Endpoint ===
+
+impl<C, T, Req> svc::Service<Req> for Endpoint<C, T>
+where
+    C: svc::Service<T>,
+    T: Clone,
+{
+    type Response = C::Response;
+    type Error = C::Error;
+    type Future = C::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.connect.poll_ready()
+    }
+
+    fn call(&mut self, _: Req) -> Self::Future {
+        self.connect.call(self.target.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svc::Service;
+
+    #[test]
+    fn endpoint_connects_to_its_target() {
+        let connect = svc::mk(|target: u16| future::ok::<_, Never>(target));
+        let mut make = endpoint(connect);
+
+        let mut ep = make.call(1234).wait().unwrap();
+        assert!(Service::<()>::poll_ready(&mut ep).unwrap().is_ready());
+        assert_eq!(ep.call(()).wait().unwrap(), 1234);
+        assert_eq!(ep.call(()).wait().unwrap(), 1234);
+    }
+}
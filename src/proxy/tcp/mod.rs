@@ -5,6 +5,8 @@ use std::{fmt, io};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::{debug, info, trace};
 
+pub mod balance;
+
 /// Attempt to proxy the `server_io` stream to a `T`-typed target.
 ///
 /// If the target is not valid, an error is logged and the server stream is
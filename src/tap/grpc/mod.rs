@@ -1,4 +1,4 @@
 mod match_;
 mod server;
 
-pub use self::server::{Server, Tap};
+pub use self::server::{Server, Session, Sessions, Tap};
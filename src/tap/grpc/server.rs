@@ -1,6 +1,7 @@
 use super::match_::Match;
 use crate::api::{http_types, pb_duration, tap as api};
 use crate::proxy::http::HasH2Reason;
+use crate::proxy::introspect;
 use crate::tap::{iface, Inspect};
 use crate::transport::tls;
 use crate::Conditional;
@@ -19,6 +20,20 @@ use tracing::{debug, trace, warn};
 pub struct Server<T> {
     subscribe: T,
     base_id: Arc<AtomicUsize>,
+    sessions: Sessions,
+}
+
+/// A handle to the server's active tap sessions.
+#[derive(Clone, Debug, Default)]
+pub struct Sessions(introspect::Registry<Shared>);
+
+/// Describes an active tap session.
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub id: u32,
+    pub match_: String,
+    pub limit: usize,
+    pub tapped: usize,
 }
 
 #[derive(Debug)]
@@ -83,7 +98,15 @@ pub struct TapResponsePayload {
 impl<T: iface::Subscribe<Tap>> Server<T> {
     pub(in crate::tap) fn new(subscribe: T) -> Self {
         let base_id = Arc::new(0.into());
-        Self { base_id, subscribe }
+        Self {
+            base_id,
+            subscribe,
+            sessions: Sessions::default(),
+        }
+    }
+
+    pub fn sessions(&self) -> Sessions {
+        self.sessions.clone()
     }
 
     fn invalid_arg(message: String) -> grpc::Status {
@@ -147,6 +170,8 @@ where
             events_tx,
         });
 
+        self.sessions.0.register(&shared);
+
         let tap = Tap {
             shared: Arc::downgrade(&shared),
         };
@@ -188,6 +213,24 @@ impl<F: Future<Item = ()>> Future for ResponseFuture<F> {
     }
 }
 
+// === impl Sessions ===
+
+impl Sessions {
+    /// Describes each tap session that may still tap requests.
+    pub fn snapshot(&self) -> Vec<Session> {
+        self.0
+            .live()
+            .iter()
+            .map(|shared| Session {
+                id: shared.base_id,
+                match_: format!("{:?}", shared.match_),
+                limit: shared.limit,
+                tapped: ::std::cmp::min(shared.count.load(Ordering::Relaxed), shared.limit),
+            })
+            .collect()
+    }
+}
+
 // === impl ResponseStream ===
 
 impl Stream for ResponseStream {
@@ -304,6 +347,9 @@ impl iface::TapResponse for TapResponse {
     type TapPayload = TapResponsePayload;
 
     fn tap<B: Payload>(mut self, rsp: &http::Response<B>) -> TapResponsePayload {
+        // TODO: Export request and response headers (with redaction) and
+        // body samples once the pinned linkerd2-proxy-api describes them in
+        // `ObserveRequest` and the `TapEvent` payloads.
         let response_init_at = clock::now();
         let init = api::tap_event::http::Event::ResponseInit(api::tap_event::http::ResponseInit {
             id: Some(self.tap.id.clone()),
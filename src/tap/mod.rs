@@ -10,6 +10,8 @@ mod daemon;
 mod grpc;
 mod service;
 
+pub use self::grpc::{Session, Sessions};
+
 /// Instruments service stacks so that requests may be tapped.
 pub type Layer = service::Layer<daemon::Register<grpc::Tap>>;
 
@@ -2,7 +2,7 @@ mod io;
 
 pub use self::io::Io;
 use crate::metrics::{
-    latency, metrics, Counter, FmtLabels, FmtMetric, FmtMetrics, Gauge, Histogram, Metric,
+    latency, metrics, Bounds, Counter, FmtLabels, FmtMetric, FmtMetrics, Gauge, Histogram, Metric,
 };
 use crate::{svc, telemetry::Errno, transport::tls};
 use futures::{try_ready, Future, Poll};
@@ -21,19 +21,24 @@ metrics! {
     tcp_write_bytes_total: Counter { "Total count of bytes written to peers" },
 
     tcp_close_total: Counter { "Total count of closed connections" },
-    tcp_connection_duration_ms: Histogram<latency::Ms> { "Connection lifetimes" }
+    tcp_connection_duration_ms: Histogram<latency::Us> { "Connection lifetimes" }
 }
 
-pub fn new() -> (Registry, Report) {
-    let inner = Arc::new(Mutex::new(Inner::default()));
+/// Builds a registry whose connection duration histograms use the given
+/// bounds.
+pub fn new(connection_duration: Bounds) -> (Registry, Report) {
+    let inner = Arc::new(Mutex::new(Inner {
+        by_key: IndexMap::default(),
+        connection_duration,
+    }));
     (Registry(inner.clone()), Report(inner))
 }
 
 /// Implements `FmtMetrics` to render prometheus-formatted metrics for all transports.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Report(Arc<Mutex<Inner>>);
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Registry(Arc<Mutex<Inner>>);
 
 #[derive(Debug)]
@@ -89,7 +94,7 @@ enum Peer {
 ///
 /// TODO We should probaby use AtomicUsize for most of these counters so that
 /// simple increments don't require a lock. Especially for read|write_bytes_total.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct Metrics {
     open_total: Counter,
     open_connections: Gauge,
@@ -97,6 +102,7 @@ struct Metrics {
     read_bytes_total: Counter,
 
     by_eos: IndexMap<Eos, EosMetrics>,
+    connection_duration: Bounds,
 }
 
 /// Describes a classtransport end.
@@ -111,10 +117,10 @@ pub enum Eos {
 }
 
 /// Holds metrics for a class of end-of-stream.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct EosMetrics {
     close_total: Counter,
-    connection_duration: Histogram<latency::Ms>,
+    connection_duration: Histogram<latency::Us>,
 }
 
 /// Tracks the state of a single instance of `Io` throughout its lifetime.
@@ -129,18 +135,21 @@ struct Sensor {
 struct NewSensor(Option<Arc<Mutex<Metrics>>>);
 
 /// Shares state between `Report` and `Registry`.
-#[derive(Debug, Default)]
-struct Inner(IndexMap<Key, Arc<Mutex<Metrics>>>);
+#[derive(Debug)]
+struct Inner {
+    by_key: IndexMap<Key, Arc<Mutex<Metrics>>>,
+    connection_duration: Bounds,
+}
 
 // ===== impl Inner =====
 
 impl Inner {
     fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.by_key.is_empty()
     }
 
     fn iter(&self) -> impl Iterator<Item = (&Key, MutexGuard<'_, Metrics>)> {
-        self.0
+        self.by_key
             .iter()
             .filter_map(|(k, l)| l.lock().ok().map(move |m| (k, m)))
     }
@@ -184,7 +193,25 @@ impl Inner {
     }
 
     fn get_or_default(&mut self, k: Key) -> &Arc<Mutex<Metrics>> {
-        self.0.entry(k).or_insert_with(|| Default::default())
+        let connection_duration = &self.connection_duration;
+        self.by_key
+            .entry(k)
+            .or_insert_with(|| Arc::new(Mutex::new(Metrics::new(connection_duration.clone()))))
+    }
+}
+
+// ===== impl Metrics =====
+
+impl Metrics {
+    fn new(connection_duration: Bounds) -> Self {
+        Self {
+            open_total: Counter::default(),
+            open_connections: Gauge::default(),
+            write_bytes_total: Counter::default(),
+            read_bytes_total: Counter::default(),
+            by_eos: IndexMap::default(),
+            connection_duration,
+        }
     }
 }
 
@@ -411,7 +438,12 @@ impl Sensor {
             if let Ok(mut m) = m.lock() {
                 m.open_connections.decr();
 
-                let class = m.by_eos.entry(eos).or_insert_with(|| EosMetrics::default());
+                let m = &mut *m;
+                let bounds = &m.connection_duration;
+                let class = m.by_eos.entry(eos).or_insert_with(|| EosMetrics {
+                    close_total: Counter::default(),
+                    connection_duration: Histogram::new(bounds),
+                });
                 class.close_total.incr();
                 class.connection_duration.add(duration);
             }
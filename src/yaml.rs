@@ -0,0 +1,359 @@
+//! Just enough YAML parsing to read discovery files.
+//!
+//! Documents are made of block mappings, block sequences, and single-line
+//! scalars, with `#` comments. Flow collections (`{...}` and `[...]`) must be
+//! valid JSON. Anchors, aliases, tags, block scalars, multi-line scalars, and
+//! multiple documents are not supported.
+
+use crate::json::Value;
+use indexmap::IndexMap;
+
+/// Bounds the nesting of untrusted documents.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Copy, Clone, Debug)]
+struct Line<'a> {
+    indent: usize,
+    text: &'a str,
+}
+
+struct Parser<'a> {
+    lines: Vec<Line<'a>>,
+    pos: usize,
+}
+
+/// Parses a complete YAML document.
+pub fn parse(input: &[u8]) -> Option<Value> {
+    let input = ::std::str::from_utf8(input).ok()?;
+
+    let mut lines = Vec::new();
+    for line in input.lines() {
+        let line = strip_comment(line).trim_end();
+        let text = line.trim_start_matches(' ');
+        if text.is_empty() {
+            continue;
+        }
+        // Tabs may not be used for indentation.
+        if text.starts_with('\t') {
+            return None;
+        }
+        let indent = line.len() - text.len();
+        if indent == 0 && (text == "---" || text == "...") {
+            // Only a single, leading document marker is allowed.
+            if !lines.is_empty() || text == "..." {
+                return None;
+            }
+            continue;
+        }
+        lines.push(Line { indent, text });
+    }
+
+    let indent = match lines.first() {
+        Some(line) => line.indent,
+        None => return Some(Value::Null),
+    };
+    let mut parser = Parser { lines, pos: 0 };
+    let value = parser.block(indent, 0)?;
+    if parser.pos != parser.lines.len() {
+        return None;
+    }
+    Some(value)
+}
+
+// === impl Parser ===
+
+impl<'a> Parser<'a> {
+    /// Parses the node that starts on the current line, at `indent`.
+    fn block(&mut self, indent: usize, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        let line = *self.lines.get(self.pos)?;
+        if line.indent != indent {
+            return None;
+        }
+        if is_item(line.text) {
+            return self.sequence(indent, depth);
+        }
+        if split_key(line.text).is_some() {
+            return self.mapping(indent, depth);
+        }
+        self.pos += 1;
+        scalar(line.text)
+    }
+
+    /// Parses a node nested under a line at `indent`, or null if there is
+    /// none.
+    fn nested(&mut self, indent: usize, depth: usize) -> Option<Value> {
+        match self.lines.get(self.pos) {
+            Some(&line) if line.indent > indent => self.block(line.indent, depth + 1),
+            _ => Some(Value::Null),
+        }
+    }
+
+    fn sequence(&mut self, indent: usize, depth: usize) -> Option<Value> {
+        let mut items = Vec::new();
+        while let Some(&line) = self.lines.get(self.pos) {
+            if line.indent != indent || !is_item(line.text) {
+                break;
+            }
+
+            let rest = line.text[1..].trim_start_matches(' ');
+            if rest.is_empty() {
+                self.pos += 1;
+                items.push(self.nested(indent, depth)?);
+            } else {
+                // The rest of the line is parsed as if it started a line of
+                // its own, so that an item may be a compact mapping (e.g.
+                // `- addr: 10.1.2.3:8080`) whose keys are indented to match.
+                let indent = indent + (line.text.len() - rest.len());
+                self.lines[self.pos] = Line { indent, text: rest };
+                items.push(self.block(indent, depth + 1)?);
+            }
+        }
+        Some(Value::Array(items))
+    }
+
+    fn mapping(&mut self, indent: usize, depth: usize) -> Option<Value> {
+        let mut entries = IndexMap::new();
+        while let Some(&line) = self.lines.get(self.pos) {
+            if line.indent < indent {
+                break;
+            }
+            if line.indent > indent || is_item(line.text) {
+                return None;
+            }
+
+            let (key, rest) = split_key(line.text)?;
+            self.pos += 1;
+            let value = if !rest.is_empty() {
+                scalar(rest)?
+            } else {
+                match self.lines.get(self.pos) {
+                    // A sequence may be indented at the same level as its key.
+                    Some(&next) if next.indent == indent && is_item(next.text) => {
+                        self.sequence(indent, depth + 1)?
+                    }
+                    _ => self.nested(indent, depth)?,
+                }
+            };
+            if entries.insert(key, value).is_some() {
+                // Keys must be unique.
+                return None;
+            }
+        }
+        Some(Value::Object(entries))
+    }
+}
+
+fn is_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Splits a `key: value` line into its key and its (possibly empty) value.
+fn split_key(text: &str) -> Option<(String, &str)> {
+    let (key, rest) = match text.as_bytes()[0] {
+        b'"' | b'\'' => {
+            let end = quoted_len(text)?;
+            let key = match scalar(&text[..end])? {
+                Value::String(key) => key,
+                _ => return None,
+            };
+            (key, text[end..].trim_start_matches(' '))
+        }
+        b'{' | b'[' => return None,
+        _ => {
+            let end = text.find(": ").or_else(|| {
+                if text.ends_with(':') {
+                    Some(text.len() - 1)
+                } else {
+                    None
+                }
+            })?;
+            (text[..end].trim_end().to_owned(), &text[end..])
+        }
+    };
+
+    if key.is_empty() || !rest.starts_with(':') {
+        return None;
+    }
+    let value = &rest[1..];
+    if !value.is_empty() && !value.starts_with(' ') {
+        return None;
+    }
+    Some((key, value.trim()))
+}
+
+fn scalar(text: &str) -> Option<Value> {
+    match text.as_bytes()[0] {
+        // JSON's string escapes are a subset of YAML's.
+        b'"' => match Value::parse(text.as_bytes())? {
+            s @ Value::String(_) => Some(s),
+            _ => None,
+        },
+        b'\'' => {
+            if quoted_len(text)? != text.len() {
+                return None;
+            }
+            let inner = &text[1..text.len() - 1];
+            Some(Value::String(inner.replace("''", "'")))
+        }
+        b'{' | b'[' => Value::parse(text.as_bytes()),
+        // Anchors, aliases, tags, block scalars, and reserved indicators.
+        b'&' | b'*' | b'!' | b'|' | b'>' | b'%' | b'@' | b'`' => None,
+        _ => plain(text),
+    }
+}
+
+fn plain(text: &str) -> Option<Value> {
+    if text.contains(": ") {
+        return None;
+    }
+    let value = match text {
+        "~" | "null" | "Null" | "NULL" => Value::Null,
+        "true" | "True" | "TRUE" => Value::Bool(true),
+        "false" | "False" | "FALSE" => Value::Bool(false),
+        _ => match Value::parse(text.as_bytes()) {
+            Some(n @ Value::Number(_)) => n,
+            _ => Value::String(text.to_owned()),
+        },
+    };
+    Some(value)
+}
+
+/// Returns the length of the quoted scalar at the start of `text`, including
+/// its quotes.
+fn quoted_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let quote = bytes[0];
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if quote == b'"' => i += 1,
+            b'\'' if quote == b'\'' && bytes.get(i + 1) == Some(&b'\'') => i += 1,
+            b if b == quote => return Some(i + 1),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Removes a trailing comment from a line.
+fn strip_comment(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let starts_token = i == 0 || b" \t[{,:-".contains(&bytes[i - 1]);
+        match bytes[i] {
+            b'#' if i == 0 || bytes[i - 1] == b' ' || bytes[i - 1] == b'\t' => {
+                return &line[..i];
+            }
+            b'"' | b'\'' if starts_token => match quoted_len(&line[i..]) {
+                Some(len) => i += len,
+                None => return line,
+            },
+            _ => i += 1,
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_documents() {
+        let doc = br#"---
+# Comments are ignored.
+destinations:
+  web.ns.svc.cluster.local:8080:
+    endpoints:
+    - addr: 10.1.2.3:8080  # inline comment
+      weight: 10000
+      labels: {"pod": "web-0"}
+    - addr: 10.1.2.4:8080
+      protocol_hint: 'h2'
+    routes:
+      - condition:
+          path: "/api/.*#"
+        mirrors: ["shadow:80"]
+        timeout_ms: 500
+    health_check: http:/healthz
+    empty:
+    flags: [true, null]
+"#;
+        let value = parse(doc).expect("document must parse");
+        let dst = value
+            .get("destinations")
+            .and_then(|d| d.get("web.ns.svc.cluster.local:8080"))
+            .expect("destination must exist");
+
+        let eps = match dst.get("endpoints") {
+            Some(Value::Array(eps)) => eps,
+            ep => panic!("endpoints must be an array: {:?}", ep),
+        };
+        assert_eq!(eps.len(), 2);
+        assert_eq!(
+            eps[0].get("addr").and_then(Value::as_str),
+            Some("10.1.2.3:8080")
+        );
+        assert_eq!(eps[0].get("weight").and_then(Value::as_f64), Some(10_000.0));
+        assert_eq!(
+            eps[0].get("labels").and_then(|l| l.get("pod")),
+            Some(&Value::String("web-0".into()))
+        );
+        assert_eq!(
+            eps[1].get("protocol_hint").and_then(Value::as_str),
+            Some("h2")
+        );
+
+        let route = match dst.get("routes") {
+            Some(Value::Array(routes)) if routes.len() == 1 => &routes[0],
+            routes => panic!("routes must have one route: {:?}", routes),
+        };
+        assert_eq!(
+            route
+                .get("condition")
+                .and_then(|c| c.get("path"))
+                .and_then(Value::as_str),
+            Some("/api/.*#")
+        );
+        assert_eq!(
+            route.get("mirrors"),
+            Some(&Value::Array(vec![Value::String("shadow:80".into())]))
+        );
+        assert_eq!(route.get("timeout_ms").and_then(Value::as_f64), Some(500.0));
+
+        assert_eq!(
+            dst.get("health_check").and_then(Value::as_str),
+            Some("http:/healthz")
+        );
+        assert_eq!(dst.get("empty"), Some(&Value::Null));
+        assert_eq!(
+            dst.get("flags"),
+            Some(&Value::Array(vec![Value::Bool(true), Value::Null]))
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_documents() {
+        assert_eq!(parse(b""), Some(Value::Null));
+        assert_eq!(parse(b"a: 1\na: 2\n"), None);
+        assert_eq!(parse(b"a: 1\n  b: 2\n"), None);
+        assert_eq!(parse(b"a:\n\t- 1\n"), None);
+        assert_eq!(parse(b"a: &x 1\n"), None);
+        assert_eq!(parse(b"a: |\n  text\n"), None);
+        assert_eq!(parse(b"a: {b: 1}\n"), None);
+        assert_eq!(parse(b"a: b: c\n"), None);
+        assert_eq!(parse(b"a: 1\n---\nb: 2\n"), None);
+        assert_eq!(parse(b"a: 'unterminated\n"), None);
+
+        let deep = (0..64).fold(String::new(), |doc, i| {
+            format!("{}{}k:\n", doc, " ".repeat(i))
+        });
+        assert_eq!(parse(deep.as_bytes()), None);
+    }
+}
@@ -0,0 +1,108 @@
+#![deny(warnings, rust_2018_idioms)]
+#![recursion_limit = "128"]
+
+mod support;
+
+use self::support::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Service {
+    name: &'static str,
+    response_counter: Arc<AtomicUsize>,
+    svc: server::Listening,
+}
+
+impl Service {
+    fn new(name: &'static str) -> Self {
+        let response_counter = Arc::new(AtomicUsize::new(0));
+        let counter = response_counter.clone();
+        let svc = server::http1()
+            .route_fn("/load-profile", |_| {
+                Response::builder().status(201).body("".into()).unwrap()
+            })
+            .route_fn("/", move |_req| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Response::builder().status(200).body(name.into()).unwrap()
+            })
+            .run();
+        Service {
+            name,
+            response_counter,
+            svc,
+        }
+    }
+
+    fn authority(&self) -> String {
+        format!("{}.svc.cluster.local:{}", self.name, self.svc.addr.port())
+    }
+}
+
+fn wait_for_profile_stage(client: &client::Client, metrics: &client::Client, stage: &str) {
+    loop {
+        assert_eq!(client.get("/load-profile"), "");
+        let m = metrics.get("/metrics");
+        let stage_metric = format!("rt_load_profile=\"{}\"", stage);
+        if m.contains(stage_metric.as_str()) {
+            break;
+        }
+
+        ::std::thread::sleep(::std::time::Duration::from_millis(200));
+    }
+}
+
+#[test]
+fn mirrors_requests_to_labeled_destinations() {
+    let _ = trace_init();
+    let ctrl = controller::new_unordered();
+
+    let apex = "apex";
+    let apex_svc = Service::new(apex);
+    let ctrl = ctrl.destination_and_close(&apex_svc.authority(), apex_svc.svc.addr);
+
+    let shadow = "shadow";
+    let shadow_svc = Service::new(shadow);
+    let ctrl = ctrl.destination_and_close(&shadow_svc.authority(), shadow_svc.svc.addr);
+
+    let profile_tx = ctrl.profile_tx(&apex_svc.authority());
+    profile_tx.send(controller::profile(
+        vec![
+            controller::route()
+                .request_path("/load-profile")
+                .label("load_profile", "mirror"),
+            controller::route()
+                .request_any()
+                .label("config.linkerd.io/mirror", &shadow_svc.authority()),
+        ],
+        None,
+        vec![],
+    ));
+
+    let ctrl = ctrl.run();
+    let proxy = proxy::new().controller(ctrl).run();
+
+    let client = client::http1(proxy.outbound, apex_svc.authority());
+    let metrics = client::http1(proxy.metrics, "localhost");
+
+    wait_for_profile_stage(&client, &metrics, "mirror");
+
+    // Responses are always served by the primary destination.
+    let n = 10;
+    for _ in 0..n {
+        assert_eq!(client.get("/"), apex);
+    }
+    assert_eq!(apex_svc.response_counter.load(Ordering::SeqCst), n);
+
+    // Mirrored requests are dispatched in the background.
+    let mut tries = 0;
+    while shadow_svc.response_counter.load(Ordering::SeqCst) < n {
+        tries += 1;
+        assert!(tries < 50, "requests were not mirrored");
+        ::std::thread::sleep(::std::time::Duration::from_millis(100));
+    }
+    assert_eq!(shadow_svc.response_counter.load(Ordering::SeqCst), n);
+
+    // The mirror label is not a metric label.
+    let m = metrics.get("/metrics");
+    assert!(!m.contains("rt_mirror="), "{}", m);
+    assert!(m.contains("route_mirror_response_total"), "{}", m);
+}
@@ -340,3 +340,68 @@ fn timeout() {
         }
     }
 }
+
+#[test]
+fn routes_match_request_headers() {
+    profile_test! {
+        routes: [
+            controller::route()
+                .request_any()
+                .label("config.linkerd.io/match_header.x-tenant", "gold")
+                .label("tier", "gold"),
+            controller::route()
+                .request_any()
+                .label("tier", "default")
+        ],
+        budget: None,
+        with_client: |client: client::Client| {
+            let rsp = client.request(client.request_builder("/1.0/sleep").header("x-tenant", "gold"));
+            assert_eq!(rsp.status(), 200);
+            let rsp = client.request(client.request_builder("/1.0/sleep").header("x-tenant", "silver"));
+            assert_eq!(rsp.status(), 200);
+            assert_eq!(client.get("/1.0/sleep"), "slept");
+        },
+        with_metrics: |metrics: client::Client| {
+            assert_eventually_contains!(
+                metrics.get("/metrics"),
+                "route_request_total{direction=\"outbound\",dst=\"profiles.test.svc.cluster.local:80\",rt_tier=\"gold\"} 1"
+            );
+            assert_eventually_contains!(
+                metrics.get("/metrics"),
+                "route_request_total{direction=\"outbound\",dst=\"profiles.test.svc.cluster.local:80\",rt_tier=\"default\"} 2"
+            );
+        }
+    }
+}
+
+#[test]
+fn routes_match_query_parameters() {
+    profile_test! {
+        routes: [
+            controller::route()
+                .request_any()
+                .label("config.linkerd.io/match_query.tier", "~gold|platinum")
+                .label("tier", "premium"),
+            controller::route()
+                .request_any()
+                .label("tier", "default")
+        ],
+        budget: None,
+        with_client: |client: client::Client| {
+            assert_eq!(client.get("/1.0/sleep?tier=gold"), "slept");
+            assert_eq!(client.get("/1.0/sleep?a=b&tier=platinum"), "slept");
+            assert_eq!(client.get("/1.0/sleep?tier=goldish"), "slept");
+            assert_eq!(client.get("/1.0/sleep"), "slept");
+        },
+        with_metrics: |metrics: client::Client| {
+            assert_eventually_contains!(
+                metrics.get("/metrics"),
+                "route_request_total{direction=\"outbound\",dst=\"profiles.test.svc.cluster.local:80\",rt_tier=\"premium\"} 2"
+            );
+            assert_eventually_contains!(
+                metrics.get("/metrics"),
+                "route_request_total{direction=\"outbound\",dst=\"profiles.test.svc.cluster.local:80\",rt_tier=\"default\"} 2"
+            );
+        }
+    }
+}
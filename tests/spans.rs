@@ -0,0 +1,119 @@
+#![deny(warnings, rust_2018_idioms)]
+#![recursion_limit = "128"]
+
+#[macro_use]
+mod support;
+use self::support::*;
+
+use linkerd2_proxy::opencensus::proto::trace::{attribute_value, span::SpanKind, Span};
+
+const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+const PARENT_ID: &str = "b7ad6b7169203331";
+
+const ENV_TRACE_COLLECTOR_SVC_ADDR: &str = "LINKERD2_PROXY_TRACE_COLLECTOR_SVC_ADDR";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn attribute<'a>(span: &'a Span, key: &str) -> Option<&'a str> {
+    let value = span.attributes.as_ref()?.attribute_map.get(key)?;
+    match value.value {
+        Some(attribute_value::Value::StringValue(ref s)) => Some(s.value.as_str()),
+        None => None,
+    }
+}
+
+fn traceparent(flags: &str) -> String {
+    format!("00-{}-{}-{}", TRACE_ID, PARENT_ID, flags)
+}
+
+#[test]
+fn inbound_sampled_request_exports_span() {
+    let _ = trace_init();
+
+    let collector = collector::new();
+    let collector_srv = collector.run();
+
+    let srv = server::http1()
+        .route_fn("/hello", |req| {
+            // The application's span must be parented by the proxy's span.
+            let traceparent = req.headers()["traceparent"].to_str().unwrap();
+            assert!(traceparent.starts_with(&format!("00-{}-", TRACE_ID)));
+            assert!(!traceparent.contains(PARENT_ID));
+            Response::builder().body(Bytes::from("hello")).unwrap()
+        })
+        .run();
+
+    let mut env = app::config::TestEnv::new();
+    env.put(ENV_TRACE_COLLECTOR_SVC_ADDR, collector_srv.addr.to_string());
+    let proxy = proxy::new().inbound(srv).run_with_test_env(env);
+    let client = client::http1(proxy.inbound, "tracing.test.svc.cluster.local");
+
+    let rsp = client.request(
+        client
+            .request_builder("/hello")
+            .header("traceparent", traceparent("01")),
+    );
+    assert_eq!(rsp.status(), 200);
+
+    assert_eventually!(collector.spans().len() == 1, retries: 100);
+    let span = collector.spans().pop().unwrap();
+    assert_eq!(hex(&span.trace_id), TRACE_ID);
+    assert_eq!(hex(&span.parent_span_id), PARENT_ID);
+    assert_eq!(span.span_id.len(), 8);
+    assert_eq!(span.kind, SpanKind::Server as i32);
+    assert_eq!(span.name.as_ref().map(|n| n.value.as_str()), Some("/hello"));
+    assert!(span.start_time.is_some() && span.end_time.is_some());
+    assert_eq!(attribute(&span, "direction"), Some("inbound"));
+    assert_eq!(attribute(&span, "http.method"), Some("GET"));
+    assert_eq!(attribute(&span, "http.status_code"), Some("200"));
+    assert_eq!(attribute(&span, "classification"), Some("success"));
+}
+
+#[test]
+fn inbound_unsampled_request_exports_no_span() {
+    let _ = trace_init();
+
+    let collector = collector::new();
+    let collector_srv = collector.run();
+
+    let srv = server::http1()
+        .route_fn("/unsampled", |req| {
+            // Unsampled trace contexts are not modified.
+            assert_eq!(req.headers()["traceparent"], traceparent("00").as_str());
+            Response::builder().body(Bytes::from("unsampled")).unwrap()
+        })
+        .route("/sampled", "sampled")
+        .run();
+
+    let mut env = app::config::TestEnv::new();
+    env.put(ENV_TRACE_COLLECTOR_SVC_ADDR, collector_srv.addr.to_string());
+    let proxy = proxy::new().inbound(srv).run_with_test_env(env);
+    let client = client::http1(proxy.inbound, "tracing.test.svc.cluster.local");
+
+    let rsp = client.request(
+        client
+            .request_builder("/unsampled")
+            .header("traceparent", traceparent("00")),
+    );
+    assert_eq!(rsp.status(), 200);
+
+    let rsp = client.request(
+        client
+            .request_builder("/sampled")
+            .header("x-b3-traceid", TRACE_ID)
+            .header("x-b3-spanid", PARENT_ID)
+            .header("x-b3-sampled", "1"),
+    );
+    assert_eq!(rsp.status(), 200);
+
+    // Only the sampled request is exported.
+    assert_eventually!(collector.spans().len() == 1, retries: 100);
+    let span = collector.spans().pop().unwrap();
+    assert_eq!(
+        span.name.as_ref().map(|n| n.value.as_str()),
+        Some("/sampled")
+    );
+    assert_eq!(hex(&span.parent_span_id), PARENT_ID);
+}
@@ -0,0 +1,71 @@
+use crate::support::*;
+
+use bytes::{Buf, BytesMut, IntoBuf};
+use linkerd2_proxy::opencensus::proto::{agent::ExportTraceServiceRequest, trace::Span};
+use prost::Message;
+use std::sync::Mutex;
+
+const EXPORT_PATH: &str = "/opencensus.proto.agent.trace.v1.TraceService/Export";
+
+/// The length of a gRPC message's prefix: a compression flag followed by
+/// the message's length.
+const GRPC_PREFIX_LEN: usize = 5;
+
+/// A stand-in for an OpenCensus agent that records exported spans.
+#[derive(Clone, Debug, Default)]
+pub struct Collector {
+    spans: Arc<Mutex<Vec<Span>>>,
+}
+
+pub fn new() -> Collector {
+    Collector::default()
+}
+
+impl Collector {
+    /// Returns all spans that have been exported to the collector.
+    pub fn spans(&self) -> Vec<Span> {
+        self.spans.lock().unwrap().clone()
+    }
+
+    pub fn run(&self) -> server::Listening {
+        let spans = self.spans.clone();
+        server::http2()
+            .route_async(EXPORT_PATH, move |req| {
+                let spans = spans.clone();
+                req.into_body()
+                    .fold(BytesMut::new(), move |mut buf, chunk| {
+                        buf.extend_from_slice(&chunk);
+                        while let Some(msg) = decode_message(&mut buf) {
+                            let export = ExportTraceServiceRequest::decode(msg)
+                                .expect("export request must decode");
+                            spans.lock().unwrap().extend(export.spans);
+                        }
+                        Ok::<_, ()>(buf)
+                    })
+                    .map_err(|()| "export stream failed")
+                    .map(|_| {
+                        Response::builder()
+                            .header("content-type", "application/grpc")
+                            .body(Bytes::new())
+                            .unwrap()
+                    })
+            })
+            .run()
+    }
+}
+
+/// Removes a complete gRPC message from `buf`, if one has been received.
+fn decode_message(buf: &mut BytesMut) -> Option<Bytes> {
+    if buf.len() < GRPC_PREFIX_LEN {
+        return None;
+    }
+
+    let len = (&buf[1..GRPC_PREFIX_LEN]).into_buf().get_u32_be() as usize;
+    if buf.len() < GRPC_PREFIX_LEN + len {
+        return None;
+    }
+
+    let mut msg = buf.split_to(GRPC_PREFIX_LEN + len);
+    msg.advance(GRPC_PREFIX_LEN);
+    Some(msg.freeze())
+}
@@ -144,6 +144,7 @@ macro_rules! assert_eventually_contains {
 }
 
 pub mod client;
+pub mod collector;
 pub mod controller;
 pub mod identity;
 pub mod proxy;
@@ -141,6 +141,33 @@ fn metrics_endpoint_outbound_request_count() {
     assert_eventually_contains!(metrics.get("/metrics"), "request_total{authority=\"tele.test.svc.cluster.local\",direction=\"outbound\",tls=\"no_identity\",no_tls_reason=\"not_provided_by_service_discovery\"} 1");
 }
 
+#[test]
+fn debug_state_reports_outbound_router_and_resolution() {
+    let _ = trace_init();
+    let srv = server::new().route("/", "hello").run();
+    let srv_addr = srv.addr;
+    let Fixture {
+        client,
+        metrics,
+        proxy: _proxy,
+    } = Fixture::outbound_with_server(srv);
+
+    info!("client.get(/)");
+    assert_eq!(client.get("/"), "hello");
+
+    assert_eventually_contains!(
+        metrics.get("/debug/state"),
+        "\"out addr\":[\"tele.test.svc.cluster.local:80\"]"
+    );
+    assert_eventually_contains!(
+        metrics.get("/debug/state"),
+        &format!(
+            "{{\"target\":\"tele.test.svc.cluster.local:80\",\"endpoints\":[{{\"addr\":\"{}\"",
+            srv_addr
+        )
+    );
+}
+
 mod response_classification {
     use super::support::*;
     use super::Fixture;
@@ -428,6 +455,33 @@ fn metrics_endpoint_outbound_response_latency() {
         "response_latency_ms_count{authority=\"tele.test.svc.cluster.local\",direction=\"outbound\",tls=\"no_identity\",no_tls_reason=\"not_provided_by_service_discovery\",status_code=\"200\"} 4");
 }
 
+#[test]
+fn metrics_endpoint_inbound_response_latency_configured_buckets() {
+    let _ = trace_init();
+
+    let srv = server::new().route("/", "hello").run();
+
+    let mut env = app::config::TestEnv::new();
+    env.put(app::config::ENV_METRICS_LATENCY_RESOLUTION, "us".to_owned());
+    env.put(
+        app::config::ENV_METRICS_RESPONSE_LATENCY_BUCKETS,
+        "0.5,60000".to_owned(),
+    );
+    let proxy = proxy::new().inbound(srv).run_with_test_env(env);
+    let metrics = client::http1(proxy.metrics, "localhost");
+    let client = client::new(proxy.inbound, "tele.test.svc.cluster.local");
+
+    assert_eq!(client.get("/"), "hello");
+
+    // Sub-millisecond bounds are reported as fractions of a millisecond.
+    assert_eventually_contains!(metrics.get("/metrics"),
+        "response_latency_ms_bucket{authority=\"tele.test.svc.cluster.local\",direction=\"inbound\",tls=\"disabled\",status_code=\"200\",le=\"0.5\"}");
+    assert_eventually_contains!(metrics.get("/metrics"),
+        "response_latency_ms_bucket{authority=\"tele.test.svc.cluster.local\",direction=\"inbound\",tls=\"disabled\",status_code=\"200\",le=\"60000\"} 1");
+    assert_eventually_contains!(metrics.get("/metrics"),
+        "response_latency_ms_bucket{authority=\"tele.test.svc.cluster.local\",direction=\"inbound\",tls=\"disabled\",status_code=\"200\",le=\"+Inf\"} 1");
+}
+
 // Tests for destination labels provided by control plane service discovery.
 mod outbound_dst_labels {
     use super::support::*;
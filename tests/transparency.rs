@@ -54,6 +54,46 @@ fn outbound_tcp() {
     assert_eq!(tcp_client.read(), msg2.as_bytes());
 }
 
+#[test]
+fn outbound_tcp_balanced_via_destination() {
+    let _ = trace_init();
+
+    let msg1 = "custom tcp hello";
+    let msg2 = "custom tcp bye";
+
+    let srv = server::tcp()
+        .accept(move |read| {
+            assert_eq!(read, msg1.as_bytes());
+            msg2
+        })
+        .run();
+
+    // The application connects to an address that isn't listening; the
+    // proxy must instead connect to the endpoint returned by discovery.
+    let orig_dst: std::net::SocketAddr = "127.0.0.1:1".parse().unwrap();
+    let mut env = app::config::TestEnv::new();
+    env.put(
+        app::config::ENV_OUTBOUND_TCP_DESTINATIONS,
+        format!("{}=redis.test.svc.cluster.local:6379", orig_dst),
+    );
+
+    let ctrl = controller::new();
+    let dst = ctrl.destination_tx("redis.test.svc.cluster.local:6379");
+    dst.send_addr(srv.addr);
+
+    let proxy = proxy::new()
+        .controller(ctrl.run())
+        .outbound_ip(orig_dst)
+        .run_with_test_env(env);
+
+    let client = client::tcp(proxy.outbound);
+
+    let tcp_client = client.connect();
+
+    tcp_client.write(msg1);
+    assert_eq!(tcp_client.read(), msg2.as_bytes());
+}
+
 #[test]
 fn inbound_tcp() {
     let _ = trace_init();
@@ -180,6 +220,41 @@ fn tcp_with_no_orig_dst() {
     assert_eq!(read, b"");
 }
 
+#[test]
+fn inbound_authz_refuses_unauthenticated_tcp() {
+    let _ = trace_init();
+
+    let srv = server::tcp().accept(move |_| "don't read me").run();
+    let mut env = app::config::TestEnv::new();
+    env.put(app::config::ENV_INBOUND_AUTHZ_POLICY, "*=identity".into());
+    let proxy = proxy::new().inbound(srv).run_with_test_env(env);
+
+    let client = client::tcp(proxy.inbound);
+
+    let tcp_client = client.connect();
+    tcp_client.write("custom tcp hello");
+
+    let read = tcp_client
+        .try_read()
+        // This read might be an error, or an empty vec
+        .unwrap_or_else(|_| Vec::new());
+    assert_eq!(read, b"");
+}
+
+#[test]
+fn inbound_authz_forbids_unauthenticated_http() {
+    let _ = trace_init();
+
+    let srv = server::http1().route("/", "hello h1").run();
+    let mut env = app::config::TestEnv::new();
+    env.put(app::config::ENV_INBOUND_AUTHZ_POLICY, "*=identity".into());
+    let proxy = proxy::new().inbound(srv).run_with_test_env(env);
+    let client = client::http1(proxy.inbound, "transparency.test.svc.cluster.local");
+
+    let res = client.request(client.request_builder("/"));
+    assert_eq!(res.status(), http::StatusCode::FORBIDDEN);
+}
+
 #[test]
 fn tcp_connections_close_if_client_closes() {
     use std::sync::mpsc;